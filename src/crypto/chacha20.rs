@@ -0,0 +1,87 @@
+//! ChaCha20 stream cipher (RFC 8439).
+
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Compute one 64-byte keystream block.
+fn block(key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> [u8; 64] {
+    let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&CONSTANTS);
+    for i in 0..8 {
+        initial[4 + i] = word(&key[i * 4..]);
+    }
+    initial[12] = counter;
+    for i in 0..3 {
+        initial[13 + i] = word(&nonce[i * 4..]);
+    }
+
+    let mut state = initial;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&state[i].wrapping_add(initial[i]).to_le_bytes());
+    }
+    out
+}
+
+/// Encrypt or decrypt `data` in place, starting at block `counter`.
+pub fn chacha20(key: &[u8; 32], nonce: &[u8; 12], counter: u32, data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let keystream = block(key, nonce, counter.wrapping_add(i as u32));
+        for (byte, key_byte) in chunk.iter_mut().zip(keystream) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+/// Keystream of `len` bytes for a zero nonce and counter.
+#[must_use]
+pub fn keystream(key: &[u8; 32], len: usize) -> Vec<u8> {
+    let mut stream = vec![0u8; len];
+    chacha20(key, &[0u8; 12], 0, &mut stream);
+    stream
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::encoding::hex;
+
+    #[test]
+    fn test_rfc8439_encryption_vector() {
+        // RFC 8439 section 2.4.2.
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let mut data = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+tip for the future, sunscreen would be it."
+            .to_vec();
+        chacha20(&key, &nonce, 1, &mut data);
+        assert_eq!(
+            hex::encode(&data),
+            "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0bf91b65c5524733ab8f\
+             593dabcd62b3571639d624e65152ab8f530c359f0861d807ca0dbf500d6a6156a38e088a22b65e52bc\
+             514d16ccf806818ce91ab77937365af90bbf74a35be6b40b8eedf2785e42874d"
+        );
+    }
+}
@@ -0,0 +1,23 @@
+//! Payment Plugin cryptographic primitives.
+//!
+//! This module contains std-only implementations of the primitives the
+//! Lightning protocol requires:
+//! - `sha256` - SHA-256 and double SHA-256 hashing
+//! - `hmac` - HMAC-SHA256
+//! - `chacha20` - ChaCha20 stream cipher
+//! - `ripemd160` - RIPEMD-160 and Bitcoin `hash160`
+//! - `secp256k1` - Keys, ECDSA signing, verification, recovery and ECDH
+//! - `random` - Operating-system backed randomness
+
+pub mod chacha20;
+pub mod hmac;
+pub mod random;
+pub mod ripemd160;
+pub mod secp256k1;
+pub mod sha256;
+
+pub use chacha20::chacha20;
+pub use hmac::hmac_sha256;
+pub use ripemd160::{hash160, ripemd160};
+pub use secp256k1::{PublicKey, RecoverableSignature, Secp256k1Error, SecretKey, Signature};
+pub use sha256::{sha256, sha256d};
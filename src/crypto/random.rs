@@ -0,0 +1,111 @@
+//! Operating-system backed randomness (SSOP compliant).
+//!
+//! Unix targets read `/dev/urandom` and Windows calls `BCryptGenRandom`. Every
+//! other target has no source, so the fallible functions return
+//! [`RandomnessError`] and the infallible ones panic.
+
+use core::fmt;
+use std::{error::Error, io};
+
+use crate::errors::{ErrorDetail, PaymentError};
+
+/// The operating system CSPRNG could not be read.
+#[derive(Debug)]
+pub struct RandomnessError(io::Error);
+
+impl fmt::Display for RandomnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operating system randomness unavailable: {}", self.0)
+    }
+}
+
+impl Error for RandomnessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<RandomnessError> for PaymentError {
+    fn from(err: RandomnessError) -> Self {
+        PaymentError::Configuration(ErrorDetail::caused_by(err.to_string(), err))
+    }
+}
+
+/// Fill `dest` with cryptographically secure random bytes from the operating system.
+///
+/// Fails if the kernel CSPRNG cannot be read; there is no weaker fallback.
+pub fn try_fill_bytes(dest: &mut [u8]) -> Result<(), RandomnessError> {
+    fill_from_os(dest).map_err(RandomnessError)
+}
+
+/// Return `N` cryptographically secure random bytes, or fail as [`try_fill_bytes`].
+pub fn try_random_bytes<const N: usize>() -> Result<[u8; N], RandomnessError> {
+    let mut bytes = [0u8; N];
+    try_fill_bytes(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Fill `dest` with cryptographically secure random bytes.
+///
+/// # Panics
+///
+/// If the operating system CSPRNG is unavailable. Keys, preimages and payment
+/// secrets must never come from a weaker source.
+pub fn fill_bytes(dest: &mut [u8]) {
+    if let Err(err) = try_fill_bytes(dest) {
+        panic!("{err}");
+    }
+}
+
+/// Return `N` cryptographically secure random bytes.
+///
+/// # Panics
+///
+/// If the operating system CSPRNG is unavailable, as [`fill_bytes`].
+#[must_use]
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    fill_bytes(&mut bytes);
+    bytes
+}
+
+#[cfg(unix)]
+fn fill_from_os(dest: &mut [u8]) -> io::Result<()> {
+    use std::io::Read;
+
+    std::fs::File::open("/dev/urandom")?.read_exact(dest)
+}
+
+#[cfg(windows)]
+fn fill_from_os(dest: &mut [u8]) -> io::Result<()> {
+    use core::ffi::c_void;
+
+    /// Use the system-preferred generator rather than an algorithm handle.
+    const BCRYPT_USE_SYSTEM_PREFERRED_RNG: u32 = 0x0000_0002;
+
+    #[link(name = "bcrypt")]
+    unsafe extern "system" {
+        fn BCryptGenRandom(algorithm: *mut c_void, buffer: *mut u8, len: u32, flags: u32) -> i32;
+    }
+
+    for chunk in dest.chunks_mut(u32::MAX as usize) {
+        // SAFETY: `chunk` is valid for writes of `chunk.len()` bytes, which fits in a u32.
+        let status = unsafe {
+            BCryptGenRandom(
+                core::ptr::null_mut(),
+                chunk.as_mut_ptr(),
+                chunk.len() as u32,
+                BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+            )
+        };
+        if status < 0 {
+            return Err(io::Error::other(format!("BCryptGenRandom failed: {status:#010x}")));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn fill_from_os(_dest: &mut [u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "no operating system CSPRNG on this target"))
+}
@@ -0,0 +1,897 @@
+//! secp256k1 elliptic curve keys and ECDSA signatures.
+//!
+//! A self-contained implementation of the operations the payment plugin needs:
+//! key derivation, RFC 6979 deterministic signing, verification, public key
+//! recovery from compact recoverable signatures, and ECDH.
+//!
+//! Field arithmetic is branch-free, and multiplication by a secret scalar (key
+//! derivation, signing nonces, ECDH and key tweaks) always performs the same
+//! steps, using complete projective formulas and masked selection. Verification
+//! and recovery only involve public values and use faster variable-time
+//! Jacobian arithmetic.
+
+use core::{cmp::Ordering, fmt};
+
+use super::{
+    hmac::HmacSha256,
+    random::{self, RandomnessError},
+    sha256::sha256,
+};
+
+/// secp256k1 key and signature errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Secp256k1Error {
+    /// Secret key is zero or not below the curve order.
+    InvalidSecretKey,
+    /// Public key encoding is malformed or not on the curve.
+    InvalidPublicKey,
+    /// Signature encoding or value is invalid.
+    InvalidSignature,
+    /// Recovery id is outside 0..=3.
+    InvalidRecoveryId,
+}
+
+impl fmt::Display for Secp256k1Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSecretKey => write!(f, "invalid secret key"),
+            Self::InvalidPublicKey => write!(f, "invalid public key"),
+            Self::InvalidSignature => write!(f, "invalid signature"),
+            Self::InvalidRecoveryId => write!(f, "invalid recovery id"),
+        }
+    }
+}
+
+/// 256-bit unsigned integer stored as little-endian 64-bit limbs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct U256([u64; 4]);
+
+impl U256 {
+    const ZERO: Self = Self([0, 0, 0, 0]);
+    const ONE: Self = Self([1, 0, 0, 0]);
+
+    fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 24 - i * 8;
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[start..start + 8]);
+            *limb = u64::from_be_bytes(word);
+        }
+        Self(limbs)
+    }
+
+    fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            let start = 24 - i * 8;
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == [0, 0, 0, 0]
+    }
+
+    fn is_odd(&self) -> bool {
+        self.0[0] & 1 == 1
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        (self.0[index / 64] >> (index % 64)) & 1 == 1
+    }
+
+    /// All ones if bit `index` is set, else zero, without branching on it.
+    fn bit_mask(&self, index: usize) -> u64 {
+        0u64.wrapping_sub((self.0[index / 64] >> (index % 64)) & 1)
+    }
+
+    /// `a` where `mask` is all ones, `b` where it is zero.
+    fn select(mask: u64, a: &Self, b: &Self) -> Self {
+        let mut out = [0u64; 4];
+        for (i, limb) in out.iter_mut().enumerate() {
+            *limb = (a.0[i] & mask) | (b.0[i] & !mask);
+        }
+        Self(out)
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+
+    fn overflowing_add(&self, other: &Self) -> (Self, bool) {
+        let mut out = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(u64::from(carry));
+            *limb = sum;
+            carry = c1 | c2;
+        }
+        (Self(out), carry)
+    }
+
+    fn overflowing_sub(&self, other: &Self) -> (Self, bool) {
+        let mut out = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(u64::from(borrow));
+            *limb = diff;
+            borrow = b1 | b2;
+        }
+        (Self(out), borrow)
+    }
+
+    fn mul_wide(&self, other: &Self) -> [u64; 8] {
+        let mut out = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let acc =
+                    u128::from(self.0[i]) * u128::from(other.0[j]) + u128::from(out[i + j]) + carry;
+                out[i + j] = acc as u64;
+                carry = acc >> 64;
+            }
+            out[i + 4] = carry as u64;
+        }
+        out
+    }
+}
+
+/// Prime modulus of the form `2^256 - c`, used for both the field and the group order.
+struct Modulus {
+    m: U256,
+    c: U256,
+}
+
+/// Field prime `p = 2^256 - 2^32 - 977`.
+const P: Modulus = Modulus {
+    m: U256([0xFFFFFFFEFFFFFC2F, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF]),
+    c: U256([0x00000001000003D1, 0, 0, 0]),
+};
+
+/// Group order `n`.
+const N: Modulus = Modulus {
+    m: U256([0xBFD25E8CD0364141, 0xBAAEDCE6AF48A03B, 0xFFFFFFFFFFFFFFFE, 0xFFFFFFFFFFFFFFFF]),
+    c: U256([0x402DA1732FC9BEBF, 0x4551231950B75FC4, 0x0000000000000001, 0]),
+};
+
+/// `n / 2`, the upper bound for low-S signatures.
+const HALF_N: U256 =
+    U256([0xDFE92F46681B20A0, 0x5D576E7357A4501D, 0xFFFFFFFFFFFFFFFF, 0x7FFFFFFFFFFFFFFF]);
+
+const GX: U256 =
+    U256([0x59F2815B16F81798, 0x029BFCDB2DCE28D9, 0x55A06295CE870B07, 0x79BE667EF9DCBBAC]);
+const GY: U256 =
+    U256([0x9C47D08FFB10D4B8, 0xFD17B448A6855419, 0x5DA4FBFC0E1108A8, 0x483ADA7726A3C465]);
+
+/// `3b` for the curve equation `y^2 = x^3 + b`, with `b = 7`.
+const B3: U256 = U256([21, 0, 0, 0]);
+
+/// All ones if `flag` is set, else zero.
+fn mask(flag: bool) -> u64 {
+    0u64.wrapping_sub(u64::from(flag))
+}
+
+impl Modulus {
+    /// Reduce a value below `2m`, which covers every 256-bit value for both moduli.
+    fn reduce(&self, value: U256) -> U256 {
+        let (diff, borrow) = value.overflowing_sub(&self.m);
+        U256::select(mask(borrow), &value, &diff)
+    }
+
+    fn reduce_wide(&self, mut wide: [u64; 8]) -> U256 {
+        // Fold the high half using 2^256 = c (mod m). With c below 2^130 the
+        // high half is gone after four folds, so always do four.
+        for _ in 0..4 {
+            let high = U256([wide[4], wide[5], wide[6], wide[7]]);
+            let folded = high.mul_wide(&self.c);
+            let mut carry = 0u128;
+            for i in 0..8 {
+                let low = if i < 4 { u128::from(wide[i]) } else { 0 };
+                let acc = u128::from(folded[i]) + low + carry;
+                wide[i] = acc as u64;
+                carry = acc >> 64;
+            }
+        }
+        self.reduce(U256([wide[0], wide[1], wide[2], wide[3]]))
+    }
+
+    fn add(&self, a: &U256, b: &U256) -> U256 {
+        let (sum, carry) = a.overflowing_add(b);
+        let (diff, borrow) = sum.overflowing_sub(&self.m);
+        U256::select(mask(carry | !borrow), &diff, &sum)
+    }
+
+    fn sub(&self, a: &U256, b: &U256) -> U256 {
+        let (diff, borrow) = a.overflowing_sub(b);
+        diff.overflowing_add(&U256::select(mask(borrow), &self.m, &U256::ZERO)).0
+    }
+
+    fn neg(&self, a: &U256) -> U256 {
+        self.sub(&U256::ZERO, a)
+    }
+
+    fn mul(&self, a: &U256, b: &U256) -> U256 {
+        self.reduce_wide(a.mul_wide(b))
+    }
+
+    /// Square and always multiply, keeping the product only for set exponent bits.
+    fn pow(&self, base: &U256, exponent: &U256) -> U256 {
+        let mut result = U256::ONE;
+        for i in (0..256).rev() {
+            result = self.mul(&result, &result);
+            let product = self.mul(&result, base);
+            result = U256::select(exponent.bit_mask(i), &product, &result);
+        }
+        result
+    }
+
+    fn inv(&self, a: &U256) -> U256 {
+        let exponent = self.m.overflowing_sub(&U256([2, 0, 0, 0])).0;
+        self.pow(a, &exponent)
+    }
+}
+
+/// Point in Jacobian coordinates; `z == 0` is the point at infinity.
+#[derive(Debug, Clone, Copy)]
+struct Jacobian {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+impl Jacobian {
+    const INFINITY: Self = Self { x: U256::ONE, y: U256::ONE, z: U256::ZERO };
+
+    fn from_affine(x: U256, y: U256) -> Self {
+        Self { x, y, z: U256::ONE }
+    }
+
+    fn is_infinity(&self) -> bool {
+        self.z.is_zero()
+    }
+
+    fn double(&self) -> Self {
+        if self.is_infinity() || self.y.is_zero() {
+            return Self::INFINITY;
+        }
+        let a = P.mul(&self.x, &self.x);
+        let b = P.mul(&self.y, &self.y);
+        let c = P.mul(&b, &b);
+        let xb = P.add(&self.x, &b);
+        let d = P.sub(&P.sub(&P.mul(&xb, &xb), &a), &c);
+        let d = P.add(&d, &d);
+        let e = P.add(&P.add(&a, &a), &a);
+        let f = P.mul(&e, &e);
+        let x3 = P.sub(&f, &P.add(&d, &d));
+        let c8 = {
+            let c2 = P.add(&c, &c);
+            let c4 = P.add(&c2, &c2);
+            P.add(&c4, &c4)
+        };
+        let y3 = P.sub(&P.mul(&e, &P.sub(&d, &x3)), &c8);
+        let yz = P.mul(&self.y, &self.z);
+        let z3 = P.add(&yz, &yz);
+        Self { x: x3, y: y3, z: z3 }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        if self.is_infinity() {
+            return *other;
+        }
+        if other.is_infinity() {
+            return *self;
+        }
+        let z1z1 = P.mul(&self.z, &self.z);
+        let z2z2 = P.mul(&other.z, &other.z);
+        let u1 = P.mul(&self.x, &z2z2);
+        let u2 = P.mul(&other.x, &z1z1);
+        let s1 = P.mul(&P.mul(&self.y, &other.z), &z2z2);
+        let s2 = P.mul(&P.mul(&other.y, &self.z), &z1z1);
+
+        if u1 == u2 {
+            return if s1 == s2 { self.double() } else { Self::INFINITY };
+        }
+
+        let h = P.sub(&u2, &u1);
+        let r = P.sub(&s2, &s1);
+        let h2 = P.mul(&h, &h);
+        let h3 = P.mul(&h, &h2);
+        let u1h2 = P.mul(&u1, &h2);
+        let x3 = P.sub(&P.sub(&P.mul(&r, &r), &h3), &P.add(&u1h2, &u1h2));
+        let y3 = P.sub(&P.mul(&r, &P.sub(&u1h2, &x3)), &P.mul(&s1, &h3));
+        let z3 = P.mul(&P.mul(&h, &self.z), &other.z);
+        Self { x: x3, y: y3, z: z3 }
+    }
+
+    /// Variable-time double-and-add, for public scalars only.
+    fn mul(&self, scalar: &U256) -> Self {
+        let mut result = Self::INFINITY;
+        for i in (0..256).rev() {
+            result = result.double();
+            if scalar.bit(i) {
+                result = result.add(self);
+            }
+        }
+        result
+    }
+
+    fn to_affine(self) -> Option<(U256, U256)> {
+        if self.is_infinity() {
+            return None;
+        }
+        let z_inv = P.inv(&self.z);
+        let z_inv2 = P.mul(&z_inv, &z_inv);
+        let z_inv3 = P.mul(&z_inv2, &z_inv);
+        Some((P.mul(&self.x, &z_inv2), P.mul(&self.y, &z_inv3)))
+    }
+}
+
+fn generator() -> Jacobian {
+    Jacobian::from_affine(GX, GY)
+}
+
+/// Point in homogeneous projective coordinates `(x/z, y/z)`; `z == 0` is the
+/// point at infinity.
+///
+/// Addition uses the complete formulas of Renes, Costello and Batina (2016,
+/// algorithm 7), which hold for every pair of inputs including equal points
+/// and infinity, so it runs the same steps whatever the points are.
+#[derive(Debug, Clone, Copy)]
+struct Projective {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+impl Projective {
+    const INFINITY: Self = Self { x: U256::ZERO, y: U256::ONE, z: U256::ZERO };
+
+    fn from_affine(x: U256, y: U256) -> Self {
+        Self { x, y, z: U256::ONE }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let t0 = P.mul(&self.x, &other.x);
+        let t1 = P.mul(&self.y, &other.y);
+        let t2 = P.mul(&self.z, &other.z);
+        let t3 = P.mul(&P.add(&self.x, &self.y), &P.add(&other.x, &other.y));
+        let t3 = P.sub(&t3, &P.add(&t0, &t1));
+        let t4 = P.mul(&P.add(&self.y, &self.z), &P.add(&other.y, &other.z));
+        let t4 = P.sub(&t4, &P.add(&t1, &t2));
+        let y3 = P.mul(&P.add(&self.x, &self.z), &P.add(&other.x, &other.z));
+        let y3 = P.sub(&y3, &P.add(&t0, &t2));
+        let t0 = P.add(&P.add(&t0, &t0), &t0);
+        let t2 = P.mul(&B3, &t2);
+        let z3 = P.add(&t1, &t2);
+        let t1 = P.sub(&t1, &t2);
+        let y3 = P.mul(&B3, &y3);
+        let x3 = P.sub(&P.mul(&t3, &t1), &P.mul(&t4, &y3));
+        let y3 = P.add(&P.mul(&t1, &z3), &P.mul(&y3, &t0));
+        let z3 = P.add(&P.mul(&z3, &t4), &P.mul(&t0, &t3));
+        Self { x: x3, y: y3, z: z3 }
+    }
+
+    /// `a` where `mask` is all ones, `b` where it is zero.
+    fn select(mask: u64, a: &Self, b: &Self) -> Self {
+        Self {
+            x: U256::select(mask, &a.x, &b.x),
+            y: U256::select(mask, &a.y, &b.y),
+            z: U256::select(mask, &a.z, &b.z),
+        }
+    }
+
+    /// Double and always add, keeping the sum only for set scalar bits.
+    fn mul(&self, scalar: &U256) -> Self {
+        let mut result = Self::INFINITY;
+        for i in (0..256).rev() {
+            result = result.add(&result);
+            let sum = result.add(self);
+            result = Self::select(scalar.bit_mask(i), &sum, &result);
+        }
+        result
+    }
+
+    fn to_affine(self) -> Option<(U256, U256)> {
+        if self.z.is_zero() {
+            return None;
+        }
+        let z_inv = P.inv(&self.z);
+        Some((P.mul(&self.x, &z_inv), P.mul(&self.y, &z_inv)))
+    }
+}
+
+/// Solve `y^2 = x^3 + 7` for the root with the requested parity.
+fn lift_x(x: &U256, odd: bool) -> Option<U256> {
+    if x.compare(&P.m) != Ordering::Less {
+        return None;
+    }
+    let rhs = P.add(&P.mul(&P.mul(x, x), x), &U256([7, 0, 0, 0]));
+    // (p + 1) / 4
+    let exponent =
+        U256([0xFFFFFFFFBFFFFF0C, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x3FFFFFFFFFFFFFFF]);
+    let y = P.pow(&rhs, &exponent);
+    if P.mul(&y, &y) != rhs {
+        return None;
+    }
+    Some(if y.is_odd() == odd { y } else { P.neg(&y) })
+}
+
+/// Interpret a 32-byte message digest as a scalar.
+fn message_scalar(msg: &[u8; 32]) -> U256 {
+    N.reduce(U256::from_be_bytes(msg))
+}
+
+/// secp256k1 secret key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SecretKey(U256);
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+impl SecretKey {
+    /// Parse a secret key from 32 big-endian bytes.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Secp256k1Error> {
+        let bytes: &[u8; 32] = bytes.try_into().map_err(|_| Secp256k1Error::InvalidSecretKey)?;
+        let scalar = U256::from_be_bytes(bytes);
+        if scalar.is_zero() || scalar.compare(&N.m) != Ordering::Less {
+            return Err(Secp256k1Error::InvalidSecretKey);
+        }
+        Ok(Self(scalar))
+    }
+
+    /// Generate a new secret key from the system CSPRNG, failing if it is unavailable.
+    pub fn try_new_random() -> Result<Self, RandomnessError> {
+        loop {
+            let bytes: [u8; 32] = random::try_random_bytes()?;
+            if let Ok(key) = Self::from_slice(&bytes) {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// Generate a new secret key from the system CSPRNG.
+    ///
+    /// # Panics
+    ///
+    /// If the operating system CSPRNG is unavailable, as [`random::fill_bytes`].
+    #[must_use]
+    pub fn new_random() -> Self {
+        Self::try_new_random().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Get the secret key as 32 big-endian bytes.
+    #[must_use]
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.0.to_be_bytes()
+    }
+
+    /// Derive the public key.
+    #[must_use]
+    pub fn public_key(&self) -> PublicKey {
+        let (x, y) = Projective::from_affine(GX, GY).mul(&self.0).to_affine().unwrap_or((GX, GY));
+        PublicKey { x, y }
+    }
+
+    /// Multiply the key by a 32-byte big-endian scalar.
+    pub fn mul_tweak(&self, tweak: &[u8; 32]) -> Result<Self, Secp256k1Error> {
+        let tweak = SecretKey::from_slice(tweak)?;
+        Ok(Self(N.mul(&self.0, &tweak.0)))
+    }
+
+    /// ECDH shared secret with `point`: SHA-256 of the compressed shared point.
+    #[must_use]
+    pub fn ecdh(&self, point: &PublicKey) -> [u8; 32] {
+        sha256(&point.mul_scalar(&self.0).serialize())
+    }
+
+    /// Produce an RFC 6979 deterministic, low-S signature over a 32-byte digest.
+    #[must_use]
+    pub fn sign_ecdsa(&self, msg: &[u8; 32]) -> Signature {
+        self.sign_ecdsa_recoverable(msg).signature
+    }
+
+    /// Produce a low-S signature that also commits to the public key recovery id.
+    #[must_use]
+    pub fn sign_ecdsa_recoverable(&self, msg: &[u8; 32]) -> RecoverableSignature {
+        let z = message_scalar(msg);
+        let mut nonces = Rfc6979::new(&self.secret_bytes(), &z.to_be_bytes());
+
+        loop {
+            let k = nonces.next_scalar();
+            let Some((rx, ry)) = Projective::from_affine(GX, GY).mul(&k).to_affine() else {
+                continue;
+            };
+
+            let r = N.reduce(rx);
+            if r.is_zero() {
+                continue;
+            }
+            let mut recovery_id = u8::from(ry.is_odd());
+            if rx.compare(&N.m) != Ordering::Less {
+                recovery_id |= 2;
+            }
+
+            let s = N.mul(&N.inv(&k), &N.add(&z, &N.mul(&r, &self.0)));
+            if s.is_zero() {
+                continue;
+            }
+            let s = if s.compare(&HALF_N) == Ordering::Greater {
+                recovery_id ^= 1;
+                N.neg(&s)
+            } else {
+                s
+            };
+
+            return RecoverableSignature { signature: Signature { r, s }, recovery_id };
+        }
+    }
+}
+
+/// RFC 6979 HMAC-DRBG nonce generator for SHA-256 and a 256-bit order.
+struct Rfc6979 {
+    k: [u8; 32],
+    v: [u8; 32],
+}
+
+impl Rfc6979 {
+    fn new(secret: &[u8; 32], msg: &[u8; 32]) -> Self {
+        let mut drbg = Self { k: [0u8; 32], v: [1u8; 32] };
+        for separator in [0x00u8, 0x01] {
+            let mut mac = HmacSha256::new(&drbg.k);
+            mac.update(&drbg.v);
+            mac.update(&[separator]);
+            mac.update(secret);
+            mac.update(msg);
+            drbg.k = mac.finalize();
+            drbg.v = hmac(&drbg.k, &drbg.v);
+        }
+        drbg
+    }
+
+    fn next_scalar(&mut self) -> U256 {
+        loop {
+            self.v = hmac(&self.k, &self.v);
+            let candidate = U256::from_be_bytes(&self.v);
+
+            // Step the generator so a rejected candidate or a retry yields a fresh nonce.
+            let mut mac = HmacSha256::new(&self.k);
+            mac.update(&self.v);
+            mac.update(&[0x00]);
+            self.k = mac.finalize();
+            self.v = hmac(&self.k, &self.v);
+
+            if !candidate.is_zero() && candidate.compare(&N.m) == Ordering::Less {
+                return candidate;
+            }
+        }
+    }
+}
+
+fn hmac(key: &[u8; 32], data: &[u8; 32]) -> [u8; 32] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.finalize()
+}
+
+/// secp256k1 public key (a non-infinity curve point).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey {
+    x: U256,
+    y: U256,
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicKey(")?;
+        for byte in self.serialize() {
+            write!(f, "{byte:02x}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl PublicKey {
+    /// Parse a compressed (33-byte) or uncompressed (65-byte) SEC1 public key.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Secp256k1Error> {
+        match (bytes.len(), bytes.first()) {
+            (33, Some(&prefix @ (0x02 | 0x03))) => {
+                let x = U256::from_be_bytes(bytes[1..].try_into().expect("32 bytes"));
+                let y = lift_x(&x, prefix == 0x03).ok_or(Secp256k1Error::InvalidPublicKey)?;
+                Ok(Self { x, y })
+            },
+            (65, Some(0x04)) => {
+                let x = U256::from_be_bytes(bytes[1..33].try_into().expect("32 bytes"));
+                let y = U256::from_be_bytes(bytes[33..].try_into().expect("32 bytes"));
+                if x.compare(&P.m) != Ordering::Less || y.compare(&P.m) != Ordering::Less {
+                    return Err(Secp256k1Error::InvalidPublicKey);
+                }
+                let rhs = P.add(&P.mul(&P.mul(&x, &x), &x), &U256([7, 0, 0, 0]));
+                if P.mul(&y, &y) != rhs {
+                    return Err(Secp256k1Error::InvalidPublicKey);
+                }
+                Ok(Self { x, y })
+            },
+            _ => Err(Secp256k1Error::InvalidPublicKey),
+        }
+    }
+
+    /// Multiply the point by a 32-byte big-endian scalar.
+    pub fn mul_tweak(&self, tweak: &[u8; 32]) -> Result<Self, Secp256k1Error> {
+        let tweak = SecretKey::from_slice(tweak)?;
+        Ok(self.mul_scalar(&tweak.0))
+    }
+
+    /// Multiply by a non-zero scalar below the curve order.
+    fn mul_scalar(&self, scalar: &U256) -> Self {
+        let (x, y) = Projective::from_affine(self.x, self.y)
+            .mul(scalar)
+            .to_affine()
+            .expect("non-zero scalar below the order keeps the point finite");
+        Self { x, y }
+    }
+
+    /// Serialize in 33-byte compressed form.
+    #[must_use]
+    pub fn serialize(&self) -> [u8; 33] {
+        let mut out = [0u8; 33];
+        out[0] = if self.y.is_odd() { 0x03 } else { 0x02 };
+        out[1..].copy_from_slice(&self.x.to_be_bytes());
+        out
+    }
+
+    /// Serialize in 65-byte uncompressed form.
+    #[must_use]
+    pub fn serialize_uncompressed(&self) -> [u8; 65] {
+        let mut out = [0u8; 65];
+        out[0] = 0x04;
+        out[1..33].copy_from_slice(&self.x.to_be_bytes());
+        out[33..].copy_from_slice(&self.y.to_be_bytes());
+        out
+    }
+
+    /// Verify a low-S ECDSA signature over a 32-byte digest.
+    #[must_use]
+    pub fn verify_ecdsa(&self, msg: &[u8; 32], sig: &Signature) -> bool {
+        if sig.r.is_zero() || sig.s.is_zero() || sig.s.compare(&HALF_N) == Ordering::Greater {
+            return false;
+        }
+        let z = message_scalar(msg);
+        let w = N.inv(&sig.s);
+        let u1 = N.mul(&z, &w);
+        let u2 = N.mul(&sig.r, &w);
+        let point = generator().mul(&u1).add(&Jacobian::from_affine(self.x, self.y).mul(&u2));
+        match point.to_affine() {
+            Some((x, _)) => N.reduce(x) == sig.r,
+            None => false,
+        }
+    }
+}
+
+/// ECDSA signature as an `(r, s)` scalar pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    r: U256,
+    s: U256,
+}
+
+impl Signature {
+    /// Parse a 64-byte compact `r || s` signature.
+    pub fn from_compact(bytes: &[u8]) -> Result<Self, Secp256k1Error> {
+        if bytes.len() != 64 {
+            return Err(Secp256k1Error::InvalidSignature);
+        }
+        let r = U256::from_be_bytes(bytes[..32].try_into().expect("32 bytes"));
+        let s = U256::from_be_bytes(bytes[32..].try_into().expect("32 bytes"));
+        if r.is_zero()
+            || s.is_zero()
+            || r.compare(&N.m) != Ordering::Less
+            || s.compare(&N.m) != Ordering::Less
+        {
+            return Err(Secp256k1Error::InvalidSignature);
+        }
+        Ok(Self { r, s })
+    }
+
+    /// Serialize as 64-byte compact `r || s`.
+    #[must_use]
+    pub fn serialize_compact(&self) -> [u8; 64] {
+        let mut out = [0u8; 64];
+        out[..32].copy_from_slice(&self.r.to_be_bytes());
+        out[32..].copy_from_slice(&self.s.to_be_bytes());
+        out
+    }
+
+    /// Parse a strict DER signature (BIP 66), as used in Bitcoin transactions.
+    pub fn from_der(bytes: &[u8]) -> Result<Self, Secp256k1Error> {
+        /// Read one minimally encoded positive DER integer of at most 32 bytes.
+        fn integer(bytes: &[u8]) -> Result<([u8; 32], &[u8]), Secp256k1Error> {
+            let [0x02, len, rest @ ..] = bytes else {
+                return Err(Secp256k1Error::InvalidSignature);
+            };
+            let len = usize::from(*len);
+            if len == 0 || len > 33 || rest.len() < len {
+                return Err(Secp256k1Error::InvalidSignature);
+            }
+            let (value, rest) = rest.split_at(len);
+            let negative = value[0] & 0x80 != 0;
+            let padded = len > 1 && value[0] == 0 && value[1] & 0x80 == 0;
+            if negative || padded {
+                return Err(Secp256k1Error::InvalidSignature);
+            }
+            let value = if len == 33 { &value[1..] } else { value };
+            let mut out = [0u8; 32];
+            out[32 - value.len()..].copy_from_slice(value);
+            Ok((out, rest))
+        }
+
+        let [0x30, len, body @ ..] = bytes else {
+            return Err(Secp256k1Error::InvalidSignature);
+        };
+        if usize::from(*len) != body.len() {
+            return Err(Secp256k1Error::InvalidSignature);
+        }
+        let (r, rest) = integer(body)?;
+        let (s, rest) = integer(rest)?;
+        if !rest.is_empty() {
+            return Err(Secp256k1Error::InvalidSignature);
+        }
+
+        let mut compact = [0u8; 64];
+        compact[..32].copy_from_slice(&r);
+        compact[32..].copy_from_slice(&s);
+        Self::from_compact(&compact)
+    }
+
+    /// Serialize as a DER `SEQUENCE { r INTEGER, s INTEGER }`.
+    #[must_use]
+    pub fn serialize_der(&self) -> Vec<u8> {
+        fn integer(out: &mut Vec<u8>, value: [u8; 32]) {
+            let start = value.iter().position(|&b| b != 0).unwrap_or(31);
+            let value = &value[start..];
+            let pad = value[0] & 0x80 != 0;
+            out.push(0x02);
+            out.push((value.len() + usize::from(pad)) as u8);
+            if pad {
+                out.push(0);
+            }
+            out.extend_from_slice(value);
+        }
+
+        let mut body = Vec::with_capacity(70);
+        integer(&mut body, self.r.to_be_bytes());
+        integer(&mut body, self.s.to_be_bytes());
+        let mut out = Vec::with_capacity(body.len() + 2);
+        out.push(0x30);
+        out.push(body.len() as u8);
+        out.extend_from_slice(&body);
+        out
+    }
+}
+
+/// ECDSA signature together with its public key recovery id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoverableSignature {
+    signature:   Signature,
+    recovery_id: u8,
+}
+
+impl RecoverableSignature {
+    /// Parse a 64-byte compact signature and a recovery id.
+    pub fn from_compact(bytes: &[u8], recovery_id: u8) -> Result<Self, Secp256k1Error> {
+        if recovery_id > 3 {
+            return Err(Secp256k1Error::InvalidRecoveryId);
+        }
+        Ok(Self { signature: Signature::from_compact(bytes)?, recovery_id })
+    }
+
+    /// Serialize as 64-byte compact signature and recovery id.
+    #[must_use]
+    pub fn serialize_compact(&self) -> ([u8; 64], u8) {
+        (self.signature.serialize_compact(), self.recovery_id)
+    }
+
+    /// Get the signature without its recovery id.
+    #[must_use]
+    pub fn to_standard(&self) -> Signature {
+        self.signature
+    }
+
+    /// Recover the public key that produced this signature over `msg`.
+    pub fn recover(&self, msg: &[u8; 32]) -> Result<PublicKey, Secp256k1Error> {
+        let Signature { r, s } = self.signature;
+        let x = if self.recovery_id & 2 != 0 {
+            let (x, overflow) = r.overflowing_add(&N.m);
+            if overflow {
+                return Err(Secp256k1Error::InvalidSignature);
+            }
+            x
+        } else {
+            r
+        };
+        let y = lift_x(&x, self.recovery_id & 1 == 1).ok_or(Secp256k1Error::InvalidSignature)?;
+
+        let z = message_scalar(msg);
+        let r_inv = N.inv(&r);
+        let u1 = N.neg(&N.mul(&z, &r_inv));
+        let u2 = N.mul(&s, &r_inv);
+        let point = generator().mul(&u1).add(&Jacobian::from_affine(x, y).mul(&u2));
+        let (x, y) = point.to_affine().ok_or(Secp256k1Error::InvalidSignature)?;
+        Ok(PublicKey { x, y })
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{crypto::sha256, encoding::hex};
+
+    fn key(hex_str: &str) -> SecretKey {
+        SecretKey::from_slice(&hex::decode(hex_str).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_generator_public_key() {
+        let one = key("0000000000000000000000000000000000000000000000000000000000000001");
+        assert_eq!(
+            hex::encode(&one.public_key().serialize()),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+    }
+
+    #[test]
+    fn test_public_key_roundtrip() {
+        let sk = key("e126f68f7eafcc8b74f54d269fe206be715000f94dac067d1c04a8ca3b2db734");
+        let pk = sk.public_key();
+        assert_eq!(
+            hex::encode(&pk.serialize()),
+            "03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad"
+        );
+        assert_eq!(PublicKey::from_slice(&pk.serialize()).unwrap(), pk);
+        assert_eq!(PublicKey::from_slice(&pk.serialize_uncompressed()).unwrap(), pk);
+    }
+
+    #[test]
+    fn test_sign_verify_recover() {
+        let sk = SecretKey::new_random();
+        let msg = sha256(b"essentia");
+        let sig = sk.sign_ecdsa_recoverable(&msg);
+
+        assert!(sk.public_key().verify_ecdsa(&msg, &sig.to_standard()));
+        assert!(!sk.public_key().verify_ecdsa(&sha256(b"other"), &sig.to_standard()));
+        assert_eq!(sig.recover(&msg).unwrap(), sk.public_key());
+    }
+
+    #[test]
+    fn test_constant_time_mul_matches_double_and_add() {
+        let n_minus_one = N.m.overflowing_sub(&U256::ONE).0;
+        let random = SecretKey::new_random().0;
+        for scalar in [U256::ONE, U256([2, 0, 0, 0]), HALF_N, n_minus_one, random] {
+            assert_eq!(
+                Projective::from_affine(GX, GY).mul(&scalar).to_affine(),
+                generator().mul(&scalar).to_affine()
+            );
+        }
+        let point = Projective::from_affine(GX, GY);
+        assert_eq!(point.add(&Projective::INFINITY).to_affine(), Some((GX, GY)));
+        assert_eq!(point.add(&point).to_affine(), generator().double().to_affine());
+    }
+
+    #[test]
+    fn test_rejects_invalid_secret_key() {
+        assert!(SecretKey::from_slice(&[0u8; 32]).is_err());
+        assert!(SecretKey::from_slice(&[0xFFu8; 32]).is_err());
+    }
+}
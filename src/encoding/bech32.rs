@@ -0,0 +1,152 @@
+//! Bech32 encoding (BIP 173) without the 90 character length limit.
+//!
+//! BOLT11 invoices routinely exceed the BIP 173 length limit, so encoding and
+//! decoding here accept payloads of any length.
+
+use core::fmt;
+
+/// Bech32 alphabet, indexed by 5-bit value.
+pub const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+const CHECKSUM_LEN: usize = 6;
+
+/// Bech32 decoding errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bech32Error {
+    /// No `1` separator between the human-readable part and the data.
+    MissingSeparator,
+    /// Human-readable part is empty or contains invalid characters.
+    InvalidHrp,
+    /// Data part contains a character outside the bech32 alphabet.
+    InvalidChar(char),
+    /// String mixes upper and lower case.
+    MixedCase,
+    /// Data part is shorter than the checksum.
+    TooShort,
+    /// Checksum does not match.
+    InvalidChecksum,
+    /// Padding bits are invalid when regrouping to bytes.
+    InvalidPadding,
+}
+
+impl fmt::Display for Bech32Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSeparator => write!(f, "missing bech32 separator"),
+            Self::InvalidHrp => write!(f, "invalid human-readable part"),
+            Self::InvalidChar(c) => write!(f, "invalid bech32 character '{c}'"),
+            Self::MixedCase => write!(f, "mixed-case bech32 string"),
+            Self::TooShort => write!(f, "bech32 data too short"),
+            Self::InvalidChecksum => write!(f, "invalid bech32 checksum"),
+            Self::InvalidPadding => write!(f, "invalid bech32 padding"),
+        }
+    }
+}
+
+fn polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    let mut chk: u32 = 1;
+    for value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ff_ffff) << 5) ^ u32::from(value);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes().map(|b| b >> 5).chain(core::iter::once(0)).chain(hrp.bytes().map(|b| b & 0x1f))
+}
+
+/// Encode a lowercase human-readable part and 5-bit data groups.
+#[must_use]
+pub fn encode(hrp: &str, data: &[u8]) -> String {
+    let hrp = hrp.to_ascii_lowercase();
+    let checksum_input = hrp_expand(&hrp).chain(data.iter().copied()).chain([0u8; CHECKSUM_LEN]);
+    let checksum = polymod(checksum_input) ^ 1;
+
+    let mut out = String::with_capacity(hrp.len() + 1 + data.len() + CHECKSUM_LEN);
+    out.push_str(&hrp);
+    out.push('1');
+    for &value in data {
+        out.push(CHARSET[usize::from(value & 0x1f)] as char);
+    }
+    for i in 0..CHECKSUM_LEN {
+        let value = (checksum >> (5 * (5 - i))) & 0x1f;
+        out.push(CHARSET[value as usize] as char);
+    }
+    out
+}
+
+/// Decode a bech32 string into its lowercase human-readable part and 5-bit data groups.
+pub fn decode(encoded: &str) -> Result<(String, Vec<u8>), Bech32Error> {
+    let has_lower = encoded.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = encoded.bytes().any(|b| b.is_ascii_uppercase());
+    if has_lower && has_upper {
+        return Err(Bech32Error::MixedCase);
+    }
+    let encoded = encoded.to_ascii_lowercase();
+
+    let separator = encoded.rfind('1').ok_or(Bech32Error::MissingSeparator)?;
+    let (hrp, data) = (&encoded[..separator], &encoded[separator + 1..]);
+    if hrp.is_empty() || hrp.bytes().any(|b| !(33..=126).contains(&b)) {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if data.len() < CHECKSUM_LEN {
+        return Err(Bech32Error::TooShort);
+    }
+
+    let values = data
+        .chars()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|&x| x as char == c)
+                .map(|p| p as u8)
+                .ok_or(Bech32Error::InvalidChar(c))
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    if polymod(hrp_expand(hrp).chain(values.iter().copied())) != 1 {
+        return Err(Bech32Error::InvalidChecksum);
+    }
+
+    let payload = values[..values.len() - CHECKSUM_LEN].to_vec();
+    Ok((hrp.to_string(), payload))
+}
+
+/// Regroup bits between group sizes, e.g. bytes to 5-bit groups.
+///
+/// With `pad` set, a final partial group is zero-padded; otherwise leftover
+/// bits must be zero and fewer than `from` bits long.
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Bech32Error> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max = (1u32 << to) - 1;
+    let mut out = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+
+    for &value in data {
+        let value = u32::from(value);
+        if value >> from != 0 {
+            return Err(Bech32Error::InvalidPadding);
+        }
+        acc = (acc << from) | value;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return Err(Bech32Error::InvalidPadding);
+    }
+    Ok(out)
+}
@@ -0,0 +1,30 @@
+//! Hexadecimal encoding (SSOP compliant).
+
+const HEX_CHARS: &[u8] = b"0123456789abcdef";
+
+/// Convert bytes to a lowercase hex string.
+#[must_use]
+pub fn encode(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for &byte in bytes {
+        hex.push(HEX_CHARS[(byte >> 4) as usize] as char);
+        hex.push(HEX_CHARS[(byte & 0xF) as usize] as char);
+    }
+    hex
+}
+
+/// Parse a hex string into bytes, returning `None` on odd length or invalid digits.
+#[must_use]
+pub fn decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks_exact(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
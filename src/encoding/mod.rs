@@ -0,0 +1,10 @@
+//! Payment Plugin encodings.
+//!
+//! This module contains the text encodings used by the Payment plugin:
+//! - `base64` - Base64 used by PSBTs
+//! - `bech32` - BIP 173 bech32 used by BOLT11 invoices
+//! - `hex` - Hexadecimal byte strings
+
+pub mod base64;
+pub mod bech32;
+pub mod hex;
@@ -4,4 +4,4 @@
 
 mod payment_error;
 
-pub use payment_error::{PaymentError, PaymentResult};
+pub use payment_error::{ErrorDetail, PaymentError, PaymentErrorCode, PaymentResult};
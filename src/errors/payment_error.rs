@@ -1,22 +1,207 @@
 //! Payment error types.
 
-use core::fmt;
+use core::{fmt, ops::Deref};
+use std::error::Error;
+
+use crate::implementation::OnionFailure;
 
 /// Payment operation errors.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum PaymentError {
     /// Channel operation error.
-    Channel(String),
+    Channel(ErrorDetail),
     /// Invoice error.
-    Invoice(String),
+    Invoice(ErrorDetail),
     /// Routing error.
-    Routing(String),
+    Routing(ErrorDetail),
+    /// Failure reported by a hop along the route.
+    HopFailure(Box<OnionFailure>),
+    /// The payee rejected the payment.
+    Rejected(ErrorDetail),
     /// Insufficient funds.
-    InsufficientFunds(String),
+    InsufficientFunds(ErrorDetail),
     /// Payment timeout.
-    Timeout(String),
+    Timeout(ErrorDetail),
     /// Configuration error.
-    Configuration(String),
+    Configuration(ErrorDetail),
+    /// Escrow error.
+    Escrow(ErrorDetail),
+    /// Subscription billing error.
+    Subscription(ErrorDetail),
+}
+
+/// Message of a payment error, with the lower-level error that caused it.
+///
+/// Dereferences to the message, so it reads like the string it replaces.
+#[derive(Debug)]
+pub struct ErrorDetail {
+    message:   String,
+    source:    Option<Box<dyn Error + Send + Sync>>,
+    permanent: bool,
+}
+
+impl ErrorDetail {
+    /// Message caused by `source`.
+    #[must_use]
+    pub fn caused_by(
+        message: impl Into<String>, source: impl Error + Send + Sync + 'static,
+    ) -> Self {
+        Self { message: message.into(), source: Some(Box::new(source)), permanent: false }
+    }
+
+    /// Mark the failure permanent whatever the variant carrying it, because
+    /// the cause would fail the same way again.
+    #[must_use]
+    pub fn permanent(mut self) -> Self {
+        self.permanent = true;
+        self
+    }
+
+    /// The message.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<String> for ErrorDetail {
+    fn from(message: String) -> Self {
+        Self { message, source: None, permanent: false }
+    }
+}
+
+impl From<&str> for ErrorDetail {
+    fn from(message: &str) -> Self {
+        Self::from(message.to_string())
+    }
+}
+
+impl Deref for ErrorDetail {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ErrorDetail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Stable, machine-readable payment error codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PaymentErrorCode {
+    /// [`PaymentError::Channel`].
+    Channel,
+    /// [`PaymentError::Invoice`].
+    Invoice,
+    /// [`PaymentError::Routing`].
+    Routing,
+    /// [`PaymentError::HopFailure`].
+    HopFailure,
+    /// [`PaymentError::Rejected`].
+    Rejected,
+    /// [`PaymentError::InsufficientFunds`].
+    InsufficientFunds,
+    /// [`PaymentError::Timeout`].
+    Timeout,
+    /// [`PaymentError::Configuration`].
+    Configuration,
+    /// [`PaymentError::Escrow`].
+    Escrow,
+    /// [`PaymentError::Subscription`].
+    Subscription,
+}
+
+impl PaymentErrorCode {
+    /// Code string; never changes once published.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Channel => "PAYMENT_CHANNEL",
+            Self::Invoice => "PAYMENT_INVOICE",
+            Self::Routing => "PAYMENT_ROUTING",
+            Self::HopFailure => "PAYMENT_HOP_FAILURE",
+            Self::Rejected => "PAYMENT_REJECTED",
+            Self::InsufficientFunds => "PAYMENT_INSUFFICIENT_FUNDS",
+            Self::Timeout => "PAYMENT_TIMEOUT",
+            Self::Configuration => "PAYMENT_CONFIGURATION",
+            Self::Escrow => "PAYMENT_ESCROW",
+            Self::Subscription => "PAYMENT_SUBSCRIPTION",
+        }
+    }
+}
+
+impl fmt::Display for PaymentErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PaymentError {
+    /// Stable code identifying the kind of error.
+    #[must_use]
+    pub fn code(&self) -> PaymentErrorCode {
+        match self {
+            Self::Channel(_) => PaymentErrorCode::Channel,
+            Self::Invoice(_) => PaymentErrorCode::Invoice,
+            Self::Routing(_) => PaymentErrorCode::Routing,
+            Self::HopFailure(_) => PaymentErrorCode::HopFailure,
+            Self::Rejected(_) => PaymentErrorCode::Rejected,
+            Self::InsufficientFunds(_) => PaymentErrorCode::InsufficientFunds,
+            Self::Timeout(_) => PaymentErrorCode::Timeout,
+            Self::Configuration(_) => PaymentErrorCode::Configuration,
+            Self::Escrow(_) => PaymentErrorCode::Escrow,
+            Self::Subscription(_) => PaymentErrorCode::Subscription,
+        }
+    }
+
+    /// Message and cause, for every variant but [`Self::HopFailure`].
+    #[must_use]
+    pub fn detail(&self) -> Option<&ErrorDetail> {
+        match self {
+            Self::Channel(detail)
+            | Self::Invoice(detail)
+            | Self::Routing(detail)
+            | Self::Rejected(detail)
+            | Self::InsufficientFunds(detail)
+            | Self::Timeout(detail)
+            | Self::Configuration(detail)
+            | Self::Escrow(detail)
+            | Self::Subscription(detail) => Some(detail),
+            Self::HopFailure(_) => None,
+        }
+    }
+
+    /// Whether repeating the same request unchanged may succeed.
+    ///
+    /// Routing failures and timeouts depend on network conditions, as does a
+    /// failure at an intermediate hop, unless their cause is permanent.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Routing(detail) | Self::Timeout(detail) => !detail.permanent,
+            Self::HopFailure(failure) => !failure.attempt_failure().is_permanent(),
+            _ => false,
+        }
+    }
+
+    /// Whether the request can never succeed, however often it is repeated.
+    ///
+    /// Errors that are neither retryable nor permanent, such as insufficient
+    /// funds, can succeed once the caller changes some state first.
+    #[must_use]
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Invoice(_) | Self::Rejected(_) | Self::Configuration(_) | Self::Escrow(_) => true,
+            Self::HopFailure(failure) => failure.attempt_failure().is_permanent(),
+            _ => self.detail().is_some_and(|detail| detail.permanent),
+        }
+    }
 }
 
 impl fmt::Display for PaymentError {
@@ -25,12 +210,81 @@ impl fmt::Display for PaymentError {
             Self::Channel(msg) => write!(f, "Channel error: {msg}"),
             Self::Invoice(msg) => write!(f, "Invoice error: {msg}"),
             Self::Routing(msg) => write!(f, "Routing error: {msg}"),
+            Self::HopFailure(failure) => write!(f, "Routing failure: {failure}"),
+            Self::Rejected(msg) => write!(f, "Payment rejected: {msg}"),
             Self::InsufficientFunds(msg) => write!(f, "Insufficient funds: {msg}"),
             Self::Timeout(msg) => write!(f, "Payment timeout: {msg}"),
             Self::Configuration(msg) => write!(f, "Configuration error: {msg}"),
+            Self::Escrow(msg) => write!(f, "Escrow error: {msg}"),
+            Self::Subscription(msg) => write!(f, "Subscription error: {msg}"),
         }
     }
 }
 
+impl Error for PaymentError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::HopFailure(failure) => Some(failure.as_ref()),
+            _ => self.detail()?.source.as_deref().map(|source| source as &(dyn Error + 'static)),
+        }
+    }
+}
+
+/// Maps onto the workspace's shared error type, keyed by the stable error
+/// code and keeping this error, with its source chain, as the source.
+impl From<PaymentError> for essentia_error::EssentiaError {
+    fn from(err: PaymentError) -> Self {
+        essentia_error::EssentiaError::new(err.code().as_str(), err.to_string()).with_source(err)
+    }
+}
+
 /// Result type for payment operations.
 pub type PaymentResult<T> = Result<T, PaymentError>;
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::implementation::{EscrowError, GossipError, OnionError};
+
+    #[test]
+    fn test_codes_classification_and_sources() {
+        let err = PaymentError::from(EscrowError::NotFound);
+        assert!(matches!(&err, PaymentError::Escrow(msg) if **msg == *"escrow not found"));
+        assert_eq!(err.code().as_str(), "PAYMENT_ESCROW");
+        assert_eq!(err.to_string(), "Escrow error: escrow not found");
+        let source = err.source().and_then(|source| source.downcast_ref::<EscrowError>());
+        assert_eq!(source, Some(&EscrowError::NotFound));
+        assert!(err.is_permanent() && !err.is_retryable());
+
+        // Malformed onions and gossip fail the same way however often they are retried.
+        for err in [
+            PaymentError::from(OnionError::EmptyRoute),
+            PaymentError::from(OnionError::PayloadsTooLarge(1_400)),
+            PaymentError::from(GossipError::InvalidSignature),
+        ] {
+            assert_eq!(err.code(), PaymentErrorCode::Routing);
+            assert!(err.is_permanent() && !err.is_retryable(), "{err}");
+        }
+
+        let err = PaymentError::Routing("no route to payee".into());
+        assert!(err.is_retryable() && !err.is_permanent());
+
+        let err = PaymentError::InsufficientFunds("need 10 sats".into());
+        assert_eq!(err.code().to_string(), "PAYMENT_INSUFFICIENT_FUNDS");
+        assert!(!err.is_retryable() && !err.is_permanent());
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn test_converts_into_shared_error() {
+        let err = PaymentError::from(EscrowError::NotFound);
+        let shared = essentia_error::EssentiaError::from(err);
+        assert_eq!(shared.code, "PAYMENT_ESCROW");
+        assert_eq!(shared.message, "Escrow error: escrow not found");
+        let payment =
+            shared.source.as_deref().and_then(|source| source.downcast_ref::<PaymentError>());
+        assert!(matches!(payment, Some(PaymentError::Escrow(_))));
+        let cause = payment.and_then(Error::source).and_then(|source| source.downcast_ref());
+        assert_eq!(cause, Some(&EscrowError::NotFound));
+    }
+}
@@ -0,0 +1,155 @@
+//! Escrow dispute arbitration.
+//!
+//! Either party can dispute a funded escrow. The arbiter then signs a ruling
+//! that releases the funds, refunds them or splits them by percentage, and the
+//! ruling is carried out through the escrow's backing: a hold invoice is
+//! settled or cancelled, a multisig output is spent by a PSBT the arbiter
+//! co-signs. The dispute, ruling and execution stay on the escrow as its
+//! decision trail.
+
+use core::fmt;
+
+use crate::{
+    crypto::{sha256, PublicKey, SecretKey, Signature},
+    implementation::{psbt::Psbt, transaction::Transaction},
+    types::PaymentHash,
+};
+
+/// Domain tag mixed into every ruling digest.
+const RULING_TAG: &[u8] = b"essentia/escrow-ruling";
+
+/// Escrow party that opened a dispute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisputeParty {
+    /// Party that deposited the funds.
+    Funder,
+    /// Party the funds would be released to.
+    Claimant,
+}
+
+/// Arbiter's decision on a disputed escrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ruling {
+    /// Pay the claimant.
+    Release,
+    /// Return the funds to the funder.
+    Refund,
+    /// Pay the claimant a share and return the rest.
+    Split {
+        /// Claimant's share, 1 to 99 percent.
+        claimant_percent: u8,
+    },
+}
+
+impl Ruling {
+    fn encode(self) -> [u8; 2] {
+        match self {
+            Self::Release => [0, 100],
+            Self::Refund => [1, 0],
+            Self::Split { claimant_percent } => [2, claimant_percent],
+        }
+    }
+}
+
+impl fmt::Display for Ruling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Release => write!(f, "release to claimant"),
+            Self::Refund => write!(f, "refund to funder"),
+            Self::Split { claimant_percent } => {
+                write!(f, "split {claimant_percent}% to claimant")
+            },
+        }
+    }
+}
+
+/// Ruling signed by the arbiter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRuling {
+    /// Escrow the ruling decides.
+    pub escrow_id: [u8; 32],
+    /// Decision.
+    pub ruling:    Ruling,
+    /// Arbiter's explanation.
+    pub rationale: String,
+    /// Unix time of the ruling, in seconds.
+    pub timestamp: u64,
+    /// Arbiter's compressed public key.
+    pub arbiter:   [u8; 33],
+    /// Arbiter's signature over [`SignedRuling::digest`].
+    pub signature: Signature,
+}
+
+impl SignedRuling {
+    /// Sign a ruling with the arbiter key.
+    #[must_use]
+    pub fn sign(
+        escrow_id: [u8; 32], ruling: Ruling, rationale: impl Into<String>, timestamp: u64,
+        arbiter_key: &SecretKey,
+    ) -> Self {
+        let rationale = rationale.into();
+        let digest = Self::compute_digest(&escrow_id, ruling, &rationale, timestamp);
+        Self {
+            escrow_id,
+            ruling,
+            rationale,
+            timestamp,
+            arbiter: arbiter_key.public_key().serialize(),
+            signature: arbiter_key.sign_ecdsa(&digest),
+        }
+    }
+
+    /// Hash the arbiter signs: tag, escrow id, ruling, timestamp and rationale.
+    #[must_use]
+    pub fn digest(&self) -> [u8; 32] {
+        Self::compute_digest(&self.escrow_id, self.ruling, &self.rationale, self.timestamp)
+    }
+
+    /// Check the signature against the embedded arbiter key.
+    #[must_use]
+    pub fn verify(&self) -> bool {
+        PublicKey::from_slice(&self.arbiter)
+            .is_ok_and(|arbiter| arbiter.verify_ecdsa(&self.digest(), &self.signature))
+    }
+
+    fn compute_digest(
+        escrow_id: &[u8; 32], ruling: Ruling, rationale: &str, timestamp: u64,
+    ) -> [u8; 32] {
+        let mut message = RULING_TAG.to_vec();
+        message.extend_from_slice(escrow_id);
+        message.extend_from_slice(&ruling.encode());
+        message.extend_from_slice(&timestamp.to_be_bytes());
+        message.extend_from_slice(rationale.as_bytes());
+        sha256(&message)
+    }
+}
+
+/// How a ruling was carried out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RulingExecution {
+    /// Hold invoice settled, paying the claimant.
+    HoldSettled(PaymentHash),
+    /// Hold invoice cancelled, returning the HTLCs to the funder.
+    HoldCanceled(PaymentHash),
+    /// Signed multisig spend paying out the ruling.
+    MultisigSpend(Transaction),
+}
+
+/// Dispute raised on an escrow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dispute {
+    /// Party that opened the dispute.
+    pub opened_by: DisputeParty,
+    /// Why the dispute was opened.
+    pub reason:    String,
+    /// Supporting evidence.
+    pub evidence:  Option<String>,
+    /// Unix time the dispute was opened, in seconds.
+    pub opened_at: u64,
+    /// Arbiter's ruling, once issued.
+    pub ruling:    Option<SignedRuling>,
+    /// Multisig spend built for the ruling, awaiting signatures.
+    pub spend:     Option<Psbt>,
+    /// How the ruling was carried out, once executed.
+    pub execution: Option<RulingExecution>,
+}
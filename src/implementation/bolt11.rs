@@ -0,0 +1,903 @@
+//! BOLT11 invoice encoding and decoding.
+//!
+//! Invoices are serialized as bech32 with a human-readable part carrying the
+//! network and amount, followed by a 35-bit timestamp, tagged fields and a
+//! 65-byte recoverable signature over the whole payload.
+
+use core::fmt;
+
+use crate::{
+    crypto::{sha256, PublicKey, RecoverableSignature, SecretKey, Signature},
+    encoding::bech32::{self, Bech32Error},
+    errors::{ErrorDetail, PaymentError, PaymentResult},
+    types::{LightningInvoice, Network, PaymentHash, PaymentInvoice},
+};
+
+/// Default invoice expiry when no `x` field is present (seconds).
+pub const DEFAULT_EXPIRY_SECS: u64 = 3600;
+/// Default `min_final_cltv_expiry_delta` when no `c` field is present.
+pub const DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA: u64 = 18;
+
+/// Maximum length of a tagged field in 5-bit groups.
+const MAX_FIELD_LEN: usize = 1023;
+/// Number of 5-bit groups in the timestamp.
+const TIMESTAMP_LEN: usize = 7;
+/// Serialized size of a single route hint hop in bytes.
+const ROUTE_HINT_HOP_LEN: usize = 51;
+/// Number of 5-bit groups in the recoverable signature.
+const SIGNATURE_LEN: usize = 104;
+/// Number of 5-bit groups in a 256-bit hash field.
+const HASH_FIELD_LEN: usize = 52;
+/// Number of 5-bit groups in a 33-byte public key field.
+const PUBKEY_FIELD_LEN: usize = 53;
+
+/// Tagged field types, as bech32 character values.
+mod tag {
+    pub const PAYMENT_HASH: u8 = 1; // p
+    pub const ROUTE_HINT: u8 = 3; // r
+    pub const FEATURES: u8 = 5; // 9
+    pub const EXPIRY: u8 = 6; // x
+    pub const DESCRIPTION: u8 = 13; // d
+    pub const PAYMENT_SECRET: u8 = 16; // s
+    pub const PAYEE_PUBKEY: u8 = 19; // n
+    pub const DESCRIPTION_HASH: u8 = 23; // h
+    pub const MIN_FINAL_CLTV_EXPIRY: u8 = 24; // c
+}
+
+/// Invoice description: inline text (`d`) or its SHA-256 hash (`h`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvoiceDescription {
+    /// Short UTF-8 description.
+    Direct(String),
+    /// Hash of a longer description delivered out of band.
+    Hash([u8; 32]),
+}
+
+/// One hop of a private route hint (`r` field).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteHintHop {
+    /// Public key of the node at the start of the channel.
+    pub src_node_id:                 [u8; 33],
+    /// Short channel ID of the channel.
+    pub short_channel_id:            u64,
+    /// Base fee in millisatoshis.
+    pub fee_base_msat:               u32,
+    /// Proportional fee in millionths.
+    pub fee_proportional_millionths: u32,
+    /// CLTV expiry delta.
+    pub cltv_expiry_delta:           u16,
+}
+
+/// Invoice feature bits (BOLT9), stored little-endian by bit index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InvoiceFeatures {
+    flags: Vec<u8>,
+}
+
+impl InvoiceFeatures {
+    /// `var_onion_optin` feature bit (required).
+    pub const VAR_ONION_OPTIN: usize = 8;
+    /// `payment_secret` feature bit (required).
+    pub const PAYMENT_SECRET: usize = 14;
+    /// `basic_mpp` feature bit (required).
+    pub const BASIC_MPP: usize = 16;
+
+    /// Features this implementation understands in invoices.
+    const KNOWN: [usize; 3] = [Self::VAR_ONION_OPTIN, Self::PAYMENT_SECRET, Self::BASIC_MPP];
+
+    /// Create an empty feature set.
+    #[must_use]
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Set a feature as required (even bit).
+    pub fn set_required(&mut self, bit: usize) {
+        self.set_bit(bit & !1);
+    }
+
+    /// Set a feature as optional (odd bit).
+    pub fn set_optional(&mut self, bit: usize) {
+        self.set_bit(bit | 1);
+    }
+
+    /// Check whether a raw bit is set.
+    #[must_use]
+    pub fn is_set(&self, bit: usize) -> bool {
+        self.flags.get(bit / 8).is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+    }
+
+    /// Check whether a feature is set as either required or optional.
+    #[must_use]
+    pub fn supports(&self, bit: usize) -> bool {
+        self.is_set(bit & !1) || self.is_set(bit | 1)
+    }
+
+    /// Check whether no bits are set.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.flags.iter().all(|&byte| byte == 0)
+    }
+
+    fn set_bit(&mut self, bit: usize) {
+        if self.flags.len() <= bit / 8 {
+            self.flags.resize(bit / 8 + 1, 0);
+        }
+        self.flags[bit / 8] |= 1 << (bit % 8);
+    }
+
+    fn highest_bit(&self) -> Option<usize> {
+        (0..self.flags.len() * 8).rev().find(|&bit| self.is_set(bit))
+    }
+
+    /// Find the first required (even) bit this implementation does not understand.
+    #[must_use]
+    pub fn first_unknown_required(&self) -> Option<usize> {
+        (0..self.flags.len() * 8)
+            .step_by(2)
+            .find(|&bit| self.is_set(bit) && !Self::KNOWN.contains(&bit))
+    }
+
+    /// Parse from big-endian 5-bit groups.
+    fn from_words(words: &[u8]) -> Self {
+        let mut features = Self::empty();
+        for (index, word) in words.iter().rev().enumerate() {
+            for i in 0..5 {
+                if word & (1 << i) != 0 {
+                    features.set_bit(index * 5 + i);
+                }
+            }
+        }
+        features
+    }
+
+    /// Serialize as big-endian 5-bit groups with no leading zero groups.
+    fn to_words(&self) -> Vec<u8> {
+        let Some(highest) = self.highest_bit() else {
+            return Vec::new();
+        };
+        let word_count = highest / 5 + 1;
+        (0..word_count)
+            .rev()
+            .map(|word| (0..5).fold(0u8, |acc, i| acc | (u8::from(self.is_set(word * 5 + i)) << i)))
+            .collect()
+    }
+}
+
+/// Unsigned BOLT11 invoice contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bolt11Invoice {
+    /// Network the invoice is payable on.
+    pub network:                     Network,
+    /// Requested amount in millisatoshis (None for any amount).
+    pub amount_msat:                 Option<u64>,
+    /// Creation time as seconds since the Unix epoch.
+    pub timestamp:                   u64,
+    /// Payment hash (`p`).
+    pub payment_hash:                [u8; 32],
+    /// Payment secret (`s`).
+    pub payment_secret:              Option<[u8; 32]>,
+    /// Description (`d`) or description hash (`h`).
+    pub description:                 InvoiceDescription,
+    /// Expiry in seconds after `timestamp` (`x`).
+    pub expiry_secs:                 Option<u64>,
+    /// Minimum CLTV delta for the final hop (`c`).
+    pub min_final_cltv_expiry_delta: Option<u64>,
+    /// Explicit payee public key (`n`).
+    pub payee_pubkey:                Option<[u8; 33]>,
+    /// Private route hints (`r`), one entry per route.
+    pub route_hints:                 Vec<Vec<RouteHintHop>>,
+    /// Feature bits (`9`).
+    pub features:                    InvoiceFeatures,
+}
+
+impl Bolt11Invoice {
+    /// Create invoice contents with the mandatory fields set.
+    #[must_use]
+    pub fn new(
+        network: Network, amount_msat: Option<u64>, timestamp: u64, payment_hash: [u8; 32],
+        description: InvoiceDescription,
+    ) -> Self {
+        let mut features = InvoiceFeatures::empty();
+        features.set_required(InvoiceFeatures::VAR_ONION_OPTIN);
+
+        Self {
+            network,
+            amount_msat,
+            timestamp,
+            payment_hash,
+            payment_secret: None,
+            description,
+            expiry_secs: None,
+            min_final_cltv_expiry_delta: None,
+            payee_pubkey: None,
+            route_hints: Vec::new(),
+            features,
+        }
+    }
+
+    /// Set the payment secret and mark the `payment_secret` feature required.
+    pub fn set_payment_secret(&mut self, payment_secret: [u8; 32]) {
+        self.payment_secret = Some(payment_secret);
+        self.features.set_required(InvoiceFeatures::PAYMENT_SECRET);
+    }
+
+    /// Get the expiry in seconds, applying the BOLT11 default.
+    #[must_use]
+    pub fn expiry_secs(&self) -> u64 {
+        self.expiry_secs.unwrap_or(DEFAULT_EXPIRY_SECS)
+    }
+
+    /// Get the absolute expiry timestamp.
+    #[must_use]
+    pub fn expires_at(&self) -> u64 {
+        self.timestamp.saturating_add(self.expiry_secs())
+    }
+
+    /// Get the final hop CLTV delta, applying the BOLT11 default.
+    #[must_use]
+    pub fn min_final_cltv_expiry_delta(&self) -> u64 {
+        self.min_final_cltv_expiry_delta.unwrap_or(DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA)
+    }
+
+    /// Get the human-readable part, e.g. `lnbc2500u`.
+    pub fn hrp(&self) -> PaymentResult<String> {
+        let mut hrp = format!("ln{}", self.network.bolt11_prefix());
+        if let Some(amount_msat) = self.amount_msat {
+            hrp.push_str(&encode_amount(amount_msat)?);
+        }
+        Ok(hrp)
+    }
+
+    /// Sign the invoice with the node key and encode it as a BOLT11 string.
+    pub fn encode(&self, node_key: &SecretKey) -> PaymentResult<String> {
+        let hrp = self.hrp()?;
+        let mut data = self.data_words()?;
+
+        let signature = node_key.sign_ecdsa_recoverable(&signing_hash(&hrp, &data));
+        let (compact, recovery_id) = signature.serialize_compact();
+        let mut signature_bytes = compact.to_vec();
+        signature_bytes.push(recovery_id);
+        data.extend(to_words(&signature_bytes));
+
+        Ok(bech32::encode(&hrp, &data))
+    }
+
+    /// Serialize the timestamp and tagged fields as 5-bit groups.
+    fn data_words(&self) -> PaymentResult<Vec<u8>> {
+        if self.timestamp >= 1 << (5 * TIMESTAMP_LEN) {
+            return Err(PaymentError::Invoice("Timestamp out of range".into()));
+        }
+
+        let mut data = Vec::new();
+        for i in (0..TIMESTAMP_LEN).rev() {
+            data.push(((self.timestamp >> (5 * i)) & 0x1f) as u8);
+        }
+
+        push_field(&mut data, tag::PAYMENT_HASH, &to_words(&self.payment_hash))?;
+        if let Some(secret) = &self.payment_secret {
+            push_field(&mut data, tag::PAYMENT_SECRET, &to_words(secret))?;
+        }
+        match &self.description {
+            InvoiceDescription::Direct(text) => {
+                push_field(&mut data, tag::DESCRIPTION, &to_words(text.as_bytes()))?;
+            },
+            InvoiceDescription::Hash(hash) => {
+                push_field(&mut data, tag::DESCRIPTION_HASH, &to_words(hash))?;
+            },
+        }
+        if let Some(pubkey) = &self.payee_pubkey {
+            push_field(&mut data, tag::PAYEE_PUBKEY, &to_words(pubkey))?;
+        }
+        if let Some(expiry) = self.expiry_secs {
+            push_field(&mut data, tag::EXPIRY, &int_to_words(expiry))?;
+        }
+        if let Some(delta) = self.min_final_cltv_expiry_delta {
+            push_field(&mut data, tag::MIN_FINAL_CLTV_EXPIRY, &int_to_words(delta))?;
+        }
+        for route in &self.route_hints {
+            let mut bytes = Vec::with_capacity(route.len() * ROUTE_HINT_HOP_LEN);
+            for hop in route {
+                bytes.extend_from_slice(&hop.src_node_id);
+                bytes.extend_from_slice(&hop.short_channel_id.to_be_bytes());
+                bytes.extend_from_slice(&hop.fee_base_msat.to_be_bytes());
+                bytes.extend_from_slice(&hop.fee_proportional_millionths.to_be_bytes());
+                bytes.extend_from_slice(&hop.cltv_expiry_delta.to_be_bytes());
+            }
+            push_field(&mut data, tag::ROUTE_HINT, &to_words(&bytes))?;
+        }
+        if !self.features.is_empty() {
+            push_field(&mut data, tag::FEATURES, &self.features.to_words())?;
+        }
+
+        Ok(data)
+    }
+}
+
+impl Bolt11Invoice {
+    /// Decode a BOLT11 string, verifying its signature.
+    ///
+    /// Accepts upper or lower case and an optional `lightning:` URI prefix.
+    pub fn decode(encoded: &str) -> Result<SignedBolt11Invoice, Bolt11ParseError> {
+        let encoded = encoded.trim();
+        let encoded = match encoded.get(..10) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("lightning:") => &encoded[10..],
+            _ => encoded,
+        };
+
+        let (hrp, words) = bech32::decode(encoded).map_err(|e| match e {
+            Bech32Error::InvalidChecksum => Bolt11ParseError::BadChecksum,
+            other => Bolt11ParseError::Bech32(other),
+        })?;
+        let (network, amount_msat) = parse_hrp(&hrp)?;
+
+        if words.len() < TIMESTAMP_LEN + SIGNATURE_LEN {
+            return Err(Bolt11ParseError::TooShort);
+        }
+        let (data, signature_words) = words.split_at(words.len() - SIGNATURE_LEN);
+        let timestamp = words_to_int(&data[..TIMESTAMP_LEN]).ok_or(Bolt11ParseError::TooShort)?;
+
+        let mut payment_hash = None;
+        let mut description = None;
+        let mut invoice = Self::new(
+            network,
+            amount_msat,
+            timestamp,
+            [0u8; 32],
+            InvoiceDescription::Hash([0; 32]),
+        );
+        invoice.features = InvoiceFeatures::empty();
+
+        let mut fields = &data[TIMESTAMP_LEN..];
+        while !fields.is_empty() {
+            if fields.len() < 3 {
+                return Err(Bolt11ParseError::TooShort);
+            }
+            let tag = fields[0];
+            let len = (usize::from(fields[1]) << 5) | usize::from(fields[2]);
+            let value = fields.get(3..3 + len).ok_or(Bolt11ParseError::TooShort)?;
+            fields = &fields[3 + len..];
+
+            let malformed = || Bolt11ParseError::MalformedField(tag_char(tag));
+            match tag {
+                // Readers skip hash and key fields of the wrong length.
+                tag::PAYMENT_HASH if len == HASH_FIELD_LEN && payment_hash.is_none() => {
+                    payment_hash = Some(words_to_array(value).ok_or_else(malformed)?);
+                },
+                tag::PAYMENT_SECRET
+                    if len == HASH_FIELD_LEN && invoice.payment_secret.is_none() =>
+                {
+                    invoice.payment_secret = Some(words_to_array(value).ok_or_else(malformed)?);
+                },
+                tag::DESCRIPTION if description.is_none() => {
+                    let bytes = words_to_bytes(value).ok_or_else(malformed)?;
+                    let text = String::from_utf8(bytes).map_err(|_| malformed())?;
+                    description = Some(InvoiceDescription::Direct(text));
+                },
+                tag::DESCRIPTION_HASH if len == HASH_FIELD_LEN && description.is_none() => {
+                    let hash = words_to_array(value).ok_or_else(malformed)?;
+                    description = Some(InvoiceDescription::Hash(hash));
+                },
+                tag::PAYEE_PUBKEY if len == PUBKEY_FIELD_LEN => {
+                    let pubkey: [u8; 33] = words_to_array(value).ok_or_else(malformed)?;
+                    PublicKey::from_slice(&pubkey).map_err(|_| malformed())?;
+                    invoice.payee_pubkey = Some(pubkey);
+                },
+                tag::EXPIRY => {
+                    invoice.expiry_secs = Some(words_to_int(value).ok_or_else(malformed)?);
+                },
+                tag::MIN_FINAL_CLTV_EXPIRY => {
+                    invoice.min_final_cltv_expiry_delta =
+                        Some(words_to_int(value).ok_or_else(malformed)?);
+                },
+                tag::ROUTE_HINT => {
+                    invoice.route_hints.push(parse_route_hint(value).ok_or_else(malformed)?);
+                },
+                tag::FEATURES => {
+                    invoice.features = InvoiceFeatures::from_words(value);
+                },
+                _ => {},
+            }
+        }
+
+        invoice.payment_hash = payment_hash.ok_or(Bolt11ParseError::MissingPaymentHash)?;
+        invoice.description = description.ok_or(Bolt11ParseError::MissingDescription)?;
+        if let Some(bit) = invoice.features.first_unknown_required() {
+            return Err(Bolt11ParseError::UnknownRequiredFeature(bit));
+        }
+
+        let payee_pubkey = verify_signature(&hrp, data, signature_words, invoice.payee_pubkey)?;
+
+        Ok(SignedBolt11Invoice {
+            invoice,
+            payee_pubkey: payee_pubkey.serialize(),
+            encoded: encoded.to_ascii_lowercase(),
+        })
+    }
+
+    /// Decode a BOLT11 string and require it to be payable on `network`.
+    pub fn decode_for_network(
+        encoded: &str, network: Network,
+    ) -> Result<SignedBolt11Invoice, Bolt11ParseError> {
+        let decoded = Self::decode(encoded)?;
+        if decoded.invoice.network != network {
+            return Err(Bolt11ParseError::WrongNetwork {
+                expected: network,
+                found:    decoded.invoice.network,
+            });
+        }
+        Ok(decoded)
+    }
+}
+
+/// BOLT11 invoice with a verified signature and known payee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedBolt11Invoice {
+    /// Decoded invoice contents.
+    pub invoice:      Bolt11Invoice,
+    /// Payee public key, from the `n` field or recovered from the signature.
+    pub payee_pubkey: [u8; 33],
+    /// Normalized (lowercase, no URI prefix) encoded invoice.
+    pub encoded:      String,
+}
+
+impl SignedBolt11Invoice {
+    /// Check whether the invoice has expired at `now` (Unix seconds).
+    #[must_use]
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.invoice.expires_at()
+    }
+
+    /// Amount in satoshis, rounded up so sub-satoshi amounts are never underpaid.
+    #[must_use]
+    pub fn amount_sats(&self) -> Option<u64> {
+        self.invoice.amount_msat.map(|msat| msat.div_ceil(1000))
+    }
+
+    /// Convert into the plugin's Lightning invoice representation.
+    #[must_use]
+    pub fn to_lightning_invoice(&self) -> LightningInvoice {
+        LightningInvoice {
+            payment_hash:   PaymentHash::new(self.invoice.payment_hash),
+            amount_sats:    self.amount_sats(),
+            description:    self.description_text(),
+            expiry:         self.invoice.expires_at(),
+            bolt11:         self.encoded.clone(),
+            payment_secret: self.invoice.payment_secret,
+        }
+    }
+
+    /// Convert into the plugin's generic payment invoice representation.
+    #[must_use]
+    pub fn to_payment_invoice(&self) -> PaymentInvoice {
+        PaymentInvoice {
+            payment_hash: self.invoice.payment_hash,
+            amount:       self.amount_sats(),
+            description:  self.description_text(),
+            expiry:       self.invoice.expires_at(),
+            encoded:      self.encoded.clone(),
+        }
+    }
+
+    /// Description text, or the hex description hash when only `h` is present.
+    fn description_text(&self) -> String {
+        match &self.invoice.description {
+            InvoiceDescription::Direct(text) => text.clone(),
+            InvoiceDescription::Hash(hash) => crate::encoding::hex::encode(hash),
+        }
+    }
+}
+
+/// BOLT11 decoding and validation errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bolt11ParseError {
+    /// Bech32 checksum does not match.
+    BadChecksum,
+    /// Bech32 string is otherwise malformed.
+    Bech32(Bech32Error),
+    /// Human-readable part is not `ln` followed by a known currency prefix.
+    UnknownCurrency(String),
+    /// Invoice is for a different network than expected.
+    WrongNetwork {
+        /// Network the caller operates on.
+        expected: Network,
+        /// Network the invoice was issued for.
+        found:    Network,
+    },
+    /// Amount in the human-readable part is malformed.
+    InvalidAmount(String),
+    /// Payload is truncated.
+    TooShort,
+    /// A tagged field holds a malformed value.
+    MalformedField(char),
+    /// No payment hash field is present.
+    MissingPaymentHash,
+    /// Neither a description nor a description hash is present.
+    MissingDescription,
+    /// A required feature bit is not understood.
+    UnknownRequiredFeature(usize),
+    /// Signature is malformed or does not match the payee.
+    InvalidSignature,
+}
+
+impl fmt::Display for Bolt11ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadChecksum => write!(f, "bad checksum"),
+            Self::Bech32(e) => write!(f, "malformed bech32: {e}"),
+            Self::UnknownCurrency(hrp) => write!(f, "unknown currency prefix '{hrp}'"),
+            Self::WrongNetwork { expected, found } => {
+                write!(f, "invoice is for {found:?}, expected {expected:?}")
+            },
+            Self::InvalidAmount(amount) => write!(f, "invalid amount '{amount}'"),
+            Self::TooShort => write!(f, "invoice data is truncated"),
+            Self::MalformedField(tag) => write!(f, "malformed '{tag}' field"),
+            Self::MissingPaymentHash => write!(f, "missing payment hash"),
+            Self::MissingDescription => write!(f, "missing description"),
+            Self::UnknownRequiredFeature(bit) => write!(f, "unknown required feature bit {bit}"),
+            Self::InvalidSignature => write!(f, "invalid signature"),
+        }
+    }
+}
+
+impl std::error::Error for Bolt11ParseError {}
+
+impl From<Bolt11ParseError> for PaymentError {
+    fn from(err: Bolt11ParseError) -> Self {
+        PaymentError::Invoice(ErrorDetail::caused_by(err.to_string(), err))
+    }
+}
+
+/// Hash committed to by the invoice signature: `SHA256(hrp || data)`.
+fn signing_hash(hrp: &str, data: &[u8]) -> [u8; 32] {
+    let mut preimage = hrp.as_bytes().to_vec();
+    preimage.extend(bech32::convert_bits(data, 5, 8, true).unwrap_or_default());
+    sha256(&preimage)
+}
+
+/// Encode a millisatoshi amount with the shortest exact multiplier.
+fn encode_amount(amount_msat: u64) -> PaymentResult<String> {
+    let pico_btc = amount_msat
+        .checked_mul(10)
+        .ok_or_else(|| PaymentError::Invoice("Amount too large".into()))?;
+
+    for (suffix, divisor) in [('m', 1_000_000_000), ('u', 1_000_000), ('n', 1_000), ('p', 1)] {
+        if pico_btc % divisor == 0 {
+            return Ok(format!("{}{suffix}", pico_btc / divisor));
+        }
+    }
+    unreachable!("pico-bitcoin divides by one")
+}
+
+fn push_field(data: &mut Vec<u8>, tag: u8, words: &[u8]) -> PaymentResult<()> {
+    if words.len() > MAX_FIELD_LEN {
+        return Err(PaymentError::Invoice(
+            format!("Tagged field {} too long: {} groups", tag, words.len()).into(),
+        ));
+    }
+    data.push(tag);
+    data.push((words.len() >> 5) as u8);
+    data.push((words.len() & 0x1f) as u8);
+    data.extend_from_slice(words);
+    Ok(())
+}
+
+fn to_words(bytes: &[u8]) -> Vec<u8> {
+    bech32::convert_bits(bytes, 8, 5, true).unwrap_or_default()
+}
+
+/// Encode an integer as big-endian 5-bit groups without leading zeros.
+fn int_to_words(mut value: u64) -> Vec<u8> {
+    let mut words = Vec::new();
+    while value > 0 {
+        words.push((value & 0x1f) as u8);
+        value >>= 5;
+    }
+    words.reverse();
+    words
+}
+
+/// Split the human-readable part into network and amount.
+fn parse_hrp(hrp: &str) -> Result<(Network, Option<u64>), Bolt11ParseError> {
+    let unknown = || Bolt11ParseError::UnknownCurrency(hrp.to_string());
+    let rest = hrp.strip_prefix("ln").ok_or_else(unknown)?;
+
+    // Longest prefixes first: "bcrt" before "bc", "tbs" before "tb".
+    let network = [Network::Regtest, Network::Bitcoin, Network::Signet, Network::Testnet]
+        .into_iter()
+        .find(|network| {
+            rest.strip_prefix(network.bolt11_prefix()).is_some_and(|amount| {
+                amount.is_empty() || amount.starts_with(|c: char| c.is_ascii_digit())
+            })
+        })
+        .ok_or_else(unknown)?;
+    let amount = &rest[network.bolt11_prefix().len()..];
+
+    if amount.is_empty() {
+        return Ok((network, None));
+    }
+    Ok((network, Some(parse_amount(amount)?)))
+}
+
+/// Parse an amount with optional multiplier into millisatoshis.
+fn parse_amount(amount: &str) -> Result<u64, Bolt11ParseError> {
+    let invalid = || Bolt11ParseError::InvalidAmount(amount.to_string());
+
+    let (digits, pico_per_unit) = match amount.as_bytes().last() {
+        Some(b'm') => (&amount[..amount.len() - 1], 1_000_000_000),
+        Some(b'u') => (&amount[..amount.len() - 1], 1_000_000),
+        Some(b'n') => (&amount[..amount.len() - 1], 1_000),
+        Some(b'p') => (&amount[..amount.len() - 1], 1),
+        _ => (amount, 1_000_000_000_000u64),
+    };
+    if digits.is_empty() || digits.starts_with('0') || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let pico_btc = digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(pico_per_unit))
+        .ok_or_else(invalid)?;
+    // Amounts must be whole millisatoshis.
+    if pico_btc % 10 != 0 {
+        return Err(invalid());
+    }
+    Ok(pico_btc / 10)
+}
+
+fn parse_route_hint(words: &[u8]) -> Option<Vec<RouteHintHop>> {
+    let bytes = words_to_bytes(words)?;
+    if bytes.is_empty() || bytes.len() % ROUTE_HINT_HOP_LEN != 0 {
+        return None;
+    }
+
+    bytes
+        .chunks_exact(ROUTE_HINT_HOP_LEN)
+        .map(|hop| {
+            let src_node_id: [u8; 33] = hop[..33].try_into().ok()?;
+            PublicKey::from_slice(&src_node_id).ok()?;
+            Some(RouteHintHop {
+                src_node_id,
+                short_channel_id: u64::from_be_bytes(hop[33..41].try_into().ok()?),
+                fee_base_msat: u32::from_be_bytes(hop[41..45].try_into().ok()?),
+                fee_proportional_millionths: u32::from_be_bytes(hop[45..49].try_into().ok()?),
+                cltv_expiry_delta: u16::from_be_bytes(hop[49..51].try_into().ok()?),
+            })
+        })
+        .collect()
+}
+
+/// Check the signature and return the payee key it belongs to.
+fn verify_signature(
+    hrp: &str, data: &[u8], signature_words: &[u8], payee_pubkey: Option<[u8; 33]>,
+) -> Result<PublicKey, Bolt11ParseError> {
+    let bytes = bech32::convert_bits(signature_words, 5, 8, false)
+        .map_err(|_| Bolt11ParseError::InvalidSignature)?;
+    let hash = signing_hash(hrp, data);
+
+    match payee_pubkey {
+        // An explicit payee key must be used to validate instead of recovery.
+        Some(pubkey) => {
+            let pubkey =
+                PublicKey::from_slice(&pubkey).map_err(|_| Bolt11ParseError::InvalidSignature)?;
+            let signature = Signature::from_compact(&bytes[..64])
+                .map_err(|_| Bolt11ParseError::InvalidSignature)?;
+            if !pubkey.verify_ecdsa(&hash, &signature) {
+                return Err(Bolt11ParseError::InvalidSignature);
+            }
+            Ok(pubkey)
+        },
+        None => RecoverableSignature::from_compact(&bytes[..64], bytes[64])
+            .and_then(|signature| signature.recover(&hash))
+            .map_err(|_| Bolt11ParseError::InvalidSignature),
+    }
+}
+
+fn tag_char(tag: u8) -> char {
+    bech32::CHARSET[usize::from(tag & 0x1f)] as char
+}
+
+fn words_to_bytes(words: &[u8]) -> Option<Vec<u8>> {
+    bech32::convert_bits(words, 5, 8, false).ok()
+}
+
+fn words_to_array<const N: usize>(words: &[u8]) -> Option<[u8; N]> {
+    words_to_bytes(words)?.try_into().ok()
+}
+
+/// Decode big-endian 5-bit groups into an integer, failing on overflow.
+fn words_to_int(words: &[u8]) -> Option<u64> {
+    words.iter().try_fold(0u64, |acc, &word| {
+        if acc >> 59 != 0 {
+            return None;
+        }
+        Some((acc << 5) | u64::from(word))
+    })
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::encoding::hex;
+
+    fn spec_key() -> SecretKey {
+        let bytes = hex::decode("e126f68f7eafcc8b74f54d269fe206be715000f94dac067d1c04a8ca3b2db734")
+            .unwrap();
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    fn spec_payment_hash() -> [u8; 32] {
+        let bytes = hex::decode("0001020304050607080900010203040506070809000102030405060708090102")
+            .unwrap();
+        bytes.try_into().unwrap()
+    }
+
+    #[test]
+    fn test_encode_spec_donation_invoice() {
+        let mut invoice = Bolt11Invoice::new(
+            Network::Bitcoin,
+            None,
+            1_496_314_658,
+            spec_payment_hash(),
+            InvoiceDescription::Direct("Please consider supporting this project".into()),
+        );
+        invoice.features = InvoiceFeatures::empty();
+
+        assert_eq!(
+            invoice.encode(&spec_key()).unwrap(),
+            "lnbc1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpl2pkx2ctnv5sxxmmwwd5kgetjypeh2ursdae8g6twvus8g6rfwvs8qun0dfjkxaq8rkx3yf5tcsyz3d73gafnh3cax9rn449d9p5uxz9ezhhypd0elx87sjle52x86fux2ypatgddc6k63n7erqz25le42c4u4ecky03ylcqca784w"
+        );
+    }
+
+    #[test]
+    fn test_encode_spec_coffee_invoice() {
+        let mut invoice = Bolt11Invoice::new(
+            Network::Bitcoin,
+            Some(250_000_000),
+            1_496_314_658,
+            spec_payment_hash(),
+            InvoiceDescription::Direct("1 cup coffee".into()),
+        );
+        invoice.features = InvoiceFeatures::empty();
+        invoice.expiry_secs = Some(60);
+
+        assert_eq!(
+            invoice.encode(&spec_key()).unwrap(),
+            "lnbc2500u1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpuaztrnwngzn3kdzw5hydlzf03qdgm2hdq27cqv3agm2awhz5se903vruatfhq77w3ls4evs3ch9zw97j25emudupq63nyw24cg27h2rspfj9srp"
+        );
+    }
+
+    #[test]
+    fn test_amount_multipliers() {
+        assert_eq!(encode_amount(250_000_000).unwrap(), "2500u");
+        assert_eq!(encode_amount(2_000_000_000).unwrap(), "20m");
+        assert_eq!(encode_amount(1_000).unwrap(), "10n");
+        assert_eq!(encode_amount(1).unwrap(), "10p");
+    }
+
+    #[test]
+    fn test_network_prefixes() {
+        let mut invoice = Bolt11Invoice::new(
+            Network::Regtest,
+            Some(1_000),
+            1_496_314_658,
+            spec_payment_hash(),
+            InvoiceDescription::Hash([0u8; 32]),
+        );
+        assert!(invoice.encode(&spec_key()).unwrap().starts_with("lnbcrt10n1"));
+
+        invoice.network = Network::Testnet;
+        assert!(invoice.encode(&spec_key()).unwrap().starts_with("lntb10n1"));
+    }
+
+    #[test]
+    fn test_feature_words() {
+        let mut features = InvoiceFeatures::empty();
+        features.set_required(InvoiceFeatures::VAR_ONION_OPTIN);
+        features.set_required(InvoiceFeatures::PAYMENT_SECRET);
+        // "sgq" in the spec vectors
+        assert_eq!(features.to_words(), vec![16, 8, 0]);
+    }
+
+    #[test]
+    fn test_decode_spec_invoice_recovers_payee() {
+        let decoded = Bolt11Invoice::decode(
+            "lnbc2500u1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpuaztrnwngzn3kdzw5hydlzf03qdgm2hdq27cqv3agm2awhz5se903vruatfhq77w3ls4evs3ch9zw97j25emudupq63nyw24cg27h2rspfj9srp",
+        )
+        .unwrap();
+
+        assert_eq!(decoded.payee_pubkey, spec_key().public_key().serialize());
+        assert_eq!(decoded.invoice.network, Network::Bitcoin);
+        assert_eq!(decoded.invoice.amount_msat, Some(250_000_000));
+        assert_eq!(decoded.invoice.timestamp, 1_496_314_658);
+        assert_eq!(decoded.invoice.payment_hash, spec_payment_hash());
+        assert_eq!(decoded.invoice.description, InvoiceDescription::Direct("1 cup coffee".into()));
+        assert_eq!(decoded.invoice.expiry_secs(), 60);
+        assert_eq!(decoded.to_lightning_invoice().amount_sats, Some(250_000));
+    }
+
+    #[test]
+    fn test_roundtrip_all_fields() {
+        let hop = RouteHintHop {
+            src_node_id:                 spec_key().public_key().serialize(),
+            short_channel_id:            0x0102_0304_0506_0708,
+            fee_base_msat:               1,
+            fee_proportional_millionths: 20,
+            cltv_expiry_delta:           3,
+        };
+        let mut invoice = Bolt11Invoice::new(
+            Network::Signet,
+            Some(1_234_567),
+            1_700_000_000,
+            spec_payment_hash(),
+            InvoiceDescription::Hash(sha256(b"long description")),
+        );
+        invoice.payment_secret = Some([0x11; 32]);
+        invoice.expiry_secs = Some(86_400);
+        invoice.min_final_cltv_expiry_delta = Some(144);
+        invoice.payee_pubkey = Some(spec_key().public_key().serialize());
+        invoice.route_hints = vec![vec![hop, hop]];
+        invoice.features.set_required(InvoiceFeatures::PAYMENT_SECRET);
+        invoice.features.set_optional(InvoiceFeatures::BASIC_MPP);
+
+        let encoded = invoice.encode(&spec_key()).unwrap();
+        let decoded = Bolt11Invoice::decode(&encoded.to_ascii_uppercase()).unwrap();
+        assert_eq!(decoded.invoice, invoice);
+        assert_eq!(decoded.encoded, encoded);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut invoice = Bolt11Invoice::new(
+            Network::Testnet,
+            Some(1_000),
+            1_700_000_000,
+            spec_payment_hash(),
+            InvoiceDescription::Direct("test".into()),
+        );
+        let encoded = invoice.encode(&spec_key()).unwrap();
+
+        let mut corrupted = encoded.clone();
+        corrupted.replace_range(20..21, if &encoded[20..21] == "q" { "p" } else { "q" });
+        assert_eq!(Bolt11Invoice::decode(&corrupted), Err(Bolt11ParseError::BadChecksum));
+
+        assert_eq!(
+            Bolt11Invoice::decode_for_network(&encoded, Network::Bitcoin),
+            Err(Bolt11ParseError::WrongNetwork {
+                expected: Network::Bitcoin,
+                found:    Network::Testnet,
+            })
+        );
+
+        invoice.features.set_required(100);
+        let encoded = invoice.encode(&spec_key()).unwrap();
+        assert_eq!(
+            Bolt11Invoice::decode(&encoded),
+            Err(Bolt11ParseError::UnknownRequiredFeature(100))
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_wrong_payee() {
+        let mut invoice = Bolt11Invoice::new(
+            Network::Bitcoin,
+            None,
+            1_700_000_000,
+            spec_payment_hash(),
+            InvoiceDescription::Direct("test".into()),
+        );
+        invoice.payee_pubkey = Some(SecretKey::new_random().public_key().serialize());
+        let encoded = invoice.encode(&spec_key()).unwrap();
+
+        assert_eq!(Bolt11Invoice::decode(&encoded), Err(Bolt11ParseError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_parse_amount_rejects_sub_msat() {
+        assert_eq!(parse_amount("2500u").unwrap(), 250_000_000);
+        assert_eq!(parse_amount("10p").unwrap(), 1);
+        assert!(parse_amount("1p").is_err());
+        assert!(parse_amount("01m").is_err());
+    }
+}
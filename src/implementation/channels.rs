@@ -1,22 +1,490 @@
 //! Payment channel management.
 
+use core::fmt;
+use std::collections::HashMap;
+
+use essentia_core::time;
+
 use crate::{
-    errors::PaymentResult,
+    crypto::random,
+    errors::{ErrorDetail, PaymentError, PaymentResult},
+    implementation::config::PaymentConfig,
     traits::ChannelProvider,
-    types::{ChannelState, PaymentChannel},
+    types::{
+        ChannelState, Htlc, HtlcDirection, HtlcState, PaymentChannel, PaymentHash, PaymentPreimage,
+    },
 };
 
+/// Funding confirmations required before a channel becomes active.
+pub const DEFAULT_FUNDING_CONFIRMATIONS: u32 = 3;
+/// Smallest channel reserve, in satoshis (the P2WSH dust limit).
+pub const MIN_CHANNEL_RESERVE_SATS: u64 = 354;
+/// Channel reserve as a share of capacity, in parts per million (1%).
+const CHANNEL_RESERVE_PPM: u64 = 10_000;
+
+/// Reasons a channel open is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelOpenError {
+    /// Capacity is below the configured minimum.
+    CapacityBelowMinimum {
+        /// Requested capacity in satoshis.
+        capacity: u64,
+        /// Configured minimum in satoshis.
+        minimum:  u64,
+    },
+    /// Capacity is above the configured maximum.
+    CapacityAboveMaximum {
+        /// Requested capacity in satoshis.
+        capacity: u64,
+        /// Configured maximum in satoshis.
+        maximum:  u64,
+    },
+    /// Amount pushed to the peer exceeds the capacity.
+    PushExceedsCapacity {
+        /// Requested push in satoshis.
+        push:     u64,
+        /// Requested capacity in satoshis.
+        capacity: u64,
+    },
+    /// Pushing would leave the opener below its channel reserve.
+    ReserveNotMet {
+        /// Local balance after the push, in satoshis.
+        local_balance: u64,
+        /// Required reserve in satoshis.
+        reserve:       u64,
+    },
+}
+
+impl fmt::Display for ChannelOpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityBelowMinimum { capacity, minimum } => {
+                write!(f, "capacity {capacity} sats is below the minimum of {minimum} sats")
+            },
+            Self::CapacityAboveMaximum { capacity, maximum } => {
+                write!(f, "capacity {capacity} sats is above the maximum of {maximum} sats")
+            },
+            Self::PushExceedsCapacity { push, capacity } => {
+                write!(f, "push of {push} sats exceeds capacity of {capacity} sats")
+            },
+            Self::ReserveNotMet { local_balance, reserve } => write!(
+                f,
+                "local balance of {local_balance} sats is below the {reserve} sats reserve"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChannelOpenError {}
+
+impl From<ChannelOpenError> for PaymentError {
+    fn from(err: ChannelOpenError) -> Self {
+        PaymentError::Channel(ErrorDetail::caused_by(err.to_string(), err))
+    }
+}
+
+/// Capacity limits applied when opening channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelLimits {
+    /// Minimum channel capacity in satoshis.
+    pub min_capacity: u64,
+    /// Maximum channel capacity in satoshis.
+    pub max_capacity: u64,
+}
+
+impl ChannelLimits {
+    /// Take the limits from the plugin configuration.
+    #[must_use]
+    pub fn from_config(config: &PaymentConfig) -> Self {
+        Self {
+            min_capacity: config.min_channel_capacity,
+            max_capacity: config.max_channel_capacity,
+        }
+    }
+
+    /// Reserve each side must keep in a channel of `capacity` satoshis.
+    #[must_use]
+    pub fn reserve(capacity: u64) -> u64 {
+        let proportional = u128::from(capacity) * u128::from(CHANNEL_RESERVE_PPM) / 1_000_000;
+        (proportional as u64).max(MIN_CHANNEL_RESERVE_SATS).min(capacity)
+    }
+
+    /// Validate an open of `capacity` satoshis pushing `push` to the peer.
+    ///
+    /// Returns the opening local and remote balances.
+    pub fn validate_open(&self, capacity: u64, push: u64) -> Result<(u64, u64), ChannelOpenError> {
+        if capacity < self.min_capacity {
+            return Err(ChannelOpenError::CapacityBelowMinimum {
+                capacity,
+                minimum: self.min_capacity,
+            });
+        }
+        if capacity > self.max_capacity {
+            return Err(ChannelOpenError::CapacityAboveMaximum {
+                capacity,
+                maximum: self.max_capacity,
+            });
+        }
+        let local_balance = capacity
+            .checked_sub(push)
+            .ok_or(ChannelOpenError::PushExceedsCapacity { push, capacity })?;
+        let reserve = Self::reserve(capacity);
+        if local_balance < reserve {
+            return Err(ChannelOpenError::ReserveNotMet { local_balance, reserve });
+        }
+        Ok((local_balance, push))
+    }
+
+    /// Millisatoshis one side of `channel` can still lock in new HTLCs while
+    /// keeping its channel reserve.
+    #[must_use]
+    pub fn available_msat(channel: &PaymentChannel, direction: HtlcDirection) -> u64 {
+        let balance_msat = match direction {
+            HtlcDirection::Offered => channel.local_balance_msat,
+            HtlcDirection::Received => channel.remote_balance_msat,
+        };
+        balance_msat.saturating_sub(Self::reserve(channel.capacity).saturating_mul(1000))
+    }
+}
+
+impl Default for ChannelLimits {
+    fn default() -> Self {
+        Self::from_config(&PaymentConfig::default())
+    }
+}
+
+/// Recorded change of a channel's state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelTransition {
+    /// Channel that changed state.
+    pub channel_id: [u8; 32],
+    /// Previous state, `None` when the channel was created.
+    pub from:       Option<ChannelState>,
+    /// New state.
+    pub to:         ChannelState,
+    /// Unix time of the change, in seconds.
+    pub timestamp:  u64,
+}
+
 /// Channel manager for Lightning Network channels.
 #[derive(Debug)]
 pub struct ChannelManager {
-    channels: Vec<PaymentChannel>,
+    channels:               Vec<PaymentChannel>,
+    /// Funding confirmations seen per channel.
+    confirmations:          HashMap<[u8; 32], u32>,
+    /// Confirmations needed to move a channel from `Opening` to `Active`.
+    required_confirmations: u32,
+    /// Capacity limits for new channels.
+    limits:                 ChannelLimits,
+    /// Every state change, in order.
+    history:                Vec<ChannelTransition>,
+    /// Latest block height seen, used to expire HTLCs.
+    best_block_height:      u32,
 }
 
 impl ChannelManager {
     /// Create a new channel manager.
     #[must_use]
     pub fn new() -> Self {
-        Self { channels: Vec::new() }
+        Self::with_required_confirmations(DEFAULT_FUNDING_CONFIRMATIONS)
+    }
+
+    /// Create a channel manager requiring `confirmations` funding confirmations.
+    #[must_use]
+    pub fn with_required_confirmations(confirmations: u32) -> Self {
+        Self {
+            channels:               Vec::new(),
+            confirmations:          HashMap::new(),
+            required_confirmations: confirmations,
+            limits:                 ChannelLimits::default(),
+            history:                Vec::new(),
+            best_block_height:      0,
+        }
+    }
+
+    /// Create a channel manager enforcing the configured capacity limits.
+    #[must_use]
+    pub fn with_config(config: &PaymentConfig) -> Self {
+        let mut manager = Self::new();
+        manager.limits = ChannelLimits::from_config(config);
+        manager
+    }
+
+    /// Set the capacity limits for new channels.
+    pub fn set_limits(&mut self, limits: ChannelLimits) {
+        self.limits = limits;
+    }
+
+    /// Get the capacity limits for new channels.
+    #[must_use]
+    pub fn limits(&self) -> &ChannelLimits {
+        &self.limits
+    }
+
+    /// Open a channel, pushing `push` satoshis to the peer.
+    pub fn open_channel_with_push(
+        &mut self, peer_pubkey: [u8; 33], capacity: u64, push: u64,
+    ) -> PaymentResult<[u8; 32]> {
+        let (local_balance, remote_balance) = self.limits.validate_open(capacity, push)?;
+
+        let channel_id = random::try_random_bytes()?;
+        self.channels.push(PaymentChannel {
+            channel_id,
+            peer_pubkey,
+            capacity,
+            local_balance_msat: local_balance * 1000,
+            remote_balance_msat: remote_balance * 1000,
+            state: ChannelState::Opening,
+            htlcs: Vec::new(),
+        });
+        self.record(channel_id, None, ChannelState::Opening);
+        Ok(channel_id)
+    }
+
+    /// Look up a channel.
+    #[must_use]
+    pub fn channel(&self, channel_id: &[u8; 32]) -> Option<&PaymentChannel> {
+        self.channels.iter().find(|c| c.channel_id == *channel_id)
+    }
+
+    /// Funding confirmations recorded for a channel.
+    #[must_use]
+    pub fn funding_confirmations(&self, channel_id: &[u8; 32]) -> Option<u32> {
+        self.channel(channel_id)?;
+        Some(self.confirmations.get(channel_id).copied().unwrap_or(0))
+    }
+
+    /// Record the funding transaction's confirmation count.
+    ///
+    /// The channel becomes active once the required depth is reached.
+    pub fn record_funding_confirmations(
+        &mut self, channel_id: &[u8; 32], confirmations: u32,
+    ) -> PaymentResult<ChannelState> {
+        let state = self.state(channel_id)?;
+        if state != ChannelState::Opening {
+            return Err(PaymentError::Channel(
+                format!("Channel is {state:?}, funding is already confirmed").into(),
+            ));
+        }
+
+        self.confirmations.insert(*channel_id, confirmations);
+        if confirmations >= self.required_confirmations {
+            self.transition(channel_id, ChannelState::Active)?;
+        }
+        self.state(channel_id)
+    }
+
+    /// Force close an active channel by broadcasting the latest commitment.
+    pub fn force_close_channel(&mut self, channel_id: &[u8; 32]) -> PaymentResult<()> {
+        self.transition(channel_id, ChannelState::ForceClosed)
+    }
+
+    /// Mark a closing channel closed once its closing transaction confirms.
+    pub fn confirm_channel_closed(&mut self, channel_id: &[u8; 32]) -> PaymentResult<()> {
+        self.transition(channel_id, ChannelState::Closed)
+    }
+
+    /// Move a channel to `next`, rejecting moves the state machine does not allow.
+    pub fn transition(&mut self, channel_id: &[u8; 32], next: ChannelState) -> PaymentResult<()> {
+        let channel = self.channel_mut(channel_id)?;
+
+        let from = channel.state;
+        if !from.can_transition_to(next) {
+            return Err(PaymentError::Channel(
+                format!("Illegal channel state transition {from:?} -> {next:?}").into(),
+            ));
+        }
+        channel.state = next;
+        self.record(*channel_id, Some(from), next);
+        Ok(())
+    }
+
+    /// Latest block height passed to [`Self::expire_htlcs`].
+    #[must_use]
+    pub fn best_block_height(&self) -> u32 {
+        self.best_block_height
+    }
+
+    /// Add an HTLC to an active channel, locking its amount on the adding side.
+    ///
+    /// Offered HTLCs spend from the local balance and received ones from the
+    /// remote balance, so concurrent payments cannot use the same funds. The
+    /// adding side must keep its channel reserve afterwards.
+    pub fn add_htlc(
+        &mut self, channel_id: &[u8; 32], direction: HtlcDirection, amount_msat: u64,
+        payment_hash: PaymentHash, cltv_expiry: u32,
+    ) -> PaymentResult<u64> {
+        if amount_msat == 0 {
+            return Err(PaymentError::Channel("HTLC carries no amount".into()));
+        }
+        if cltv_expiry <= self.best_block_height {
+            return Err(PaymentError::Channel(
+                format!(
+                    "HTLC expiry {cltv_expiry} is not above block height {}",
+                    self.best_block_height
+                )
+                .into(),
+            ));
+        }
+
+        let channel = self.channel_mut(channel_id)?;
+        if channel.state != ChannelState::Active {
+            return Err(PaymentError::Channel(
+                format!("Channel is {:?}, HTLCs need an active channel", channel.state).into(),
+            ));
+        }
+
+        let id = channel.htlcs.iter().filter(|htlc| htlc.direction == direction).count() as u64;
+        let htlc = Htlc {
+            id,
+            direction,
+            amount_msat,
+            payment_hash,
+            cltv_expiry,
+            state: HtlcState::Pending,
+        };
+        let available = ChannelLimits::available_msat(channel, direction);
+        if amount_msat > available {
+            return Err(PaymentError::InsufficientFunds(
+                format!(
+                    "HTLC needs {amount_msat} msat, {direction:?} side can spend {available} msat"
+                )
+                .into(),
+            ));
+        }
+        match direction {
+            HtlcDirection::Offered => channel.local_balance_msat -= amount_msat,
+            HtlcDirection::Received => channel.remote_balance_msat -= amount_msat,
+        }
+        channel.htlcs.push(htlc);
+        Ok(id)
+    }
+
+    /// Look up an HTLC.
+    #[must_use]
+    pub fn htlc(&self, channel_id: &[u8; 32], direction: HtlcDirection, id: u64) -> Option<&Htlc> {
+        self.channel(channel_id)?
+            .htlcs
+            .iter()
+            .find(|htlc| htlc.direction == direction && htlc.id == id)
+    }
+
+    /// Fulfil a pending HTLC, moving its amount to the receiving side.
+    pub fn fulfill_htlc(
+        &mut self, channel_id: &[u8; 32], direction: HtlcDirection, id: u64,
+        preimage: &PaymentPreimage,
+    ) -> PaymentResult<()> {
+        let htlc = self.htlc(channel_id, direction, id).ok_or_else(|| {
+            PaymentError::Channel(format!("Unknown {direction:?} HTLC {id}").into())
+        })?;
+        if !preimage.matches(&htlc.payment_hash) {
+            return Err(PaymentError::Channel(
+                format!("Preimage does not match {direction:?} HTLC {id}").into(),
+            ));
+        }
+        self.resolve_htlc(channel_id, direction, id, HtlcState::Fulfilled)
+    }
+
+    /// Fail a pending HTLC, returning its amount to the offering side.
+    pub fn fail_htlc(
+        &mut self, channel_id: &[u8; 32], direction: HtlcDirection, id: u64,
+    ) -> PaymentResult<()> {
+        self.resolve_htlc(channel_id, direction, id, HtlcState::Failed)
+    }
+
+    /// Record a new block height and expire pending HTLCs whose CLTV expiry it reached.
+    ///
+    /// Returns the expired HTLCs with their channel; their amounts go back to the
+    /// offering side.
+    pub fn expire_htlcs(&mut self, height: u32) -> Vec<([u8; 32], Htlc)> {
+        self.best_block_height = self.best_block_height.max(height);
+
+        let due: Vec<([u8; 32], HtlcDirection, u64)> = self
+            .channels
+            .iter()
+            .flat_map(|channel| {
+                channel
+                    .pending_htlcs()
+                    .filter(|htlc| htlc.cltv_expiry <= height)
+                    .map(|htlc| (channel.channel_id, htlc.direction, htlc.id))
+            })
+            .collect();
+
+        let mut expired = Vec::with_capacity(due.len());
+        for (channel_id, direction, id) in due {
+            if self.resolve_htlc(&channel_id, direction, id, HtlcState::Expired).is_ok() {
+                if let Some(htlc) = self.htlc(&channel_id, direction, id) {
+                    expired.push((channel_id, htlc.clone()));
+                }
+            }
+        }
+        expired
+    }
+
+    /// All recorded state changes, oldest first.
+    #[must_use]
+    pub fn history(&self) -> &[ChannelTransition] {
+        &self.history
+    }
+
+    /// State changes of one channel, oldest first.
+    #[must_use]
+    pub fn channel_history(&self, channel_id: &[u8; 32]) -> Vec<&ChannelTransition> {
+        self.history.iter().filter(|t| t.channel_id == *channel_id).collect()
+    }
+
+    fn state(&self, channel_id: &[u8; 32]) -> PaymentResult<ChannelState> {
+        self.channel(channel_id)
+            .map(|c| c.state)
+            .ok_or_else(|| PaymentError::Channel("Channel not found".into()))
+    }
+
+    fn channel_mut(&mut self, channel_id: &[u8; 32]) -> PaymentResult<&mut PaymentChannel> {
+        self.channels
+            .iter_mut()
+            .find(|c| c.channel_id == *channel_id)
+            .ok_or_else(|| PaymentError::Channel("Channel not found".into()))
+    }
+
+    fn resolve_htlc(
+        &mut self, channel_id: &[u8; 32], direction: HtlcDirection, id: u64, outcome: HtlcState,
+    ) -> PaymentResult<()> {
+        let channel = self.channel_mut(channel_id)?;
+        let htlc = channel
+            .htlcs
+            .iter_mut()
+            .find(|htlc| htlc.direction == direction && htlc.id == id)
+            .ok_or_else(|| {
+                PaymentError::Channel(format!("Unknown {direction:?} HTLC {id}").into())
+            })?;
+        if htlc.state != HtlcState::Pending {
+            return Err(PaymentError::Channel(
+                format!("{direction:?} HTLC {id} is already {:?}", htlc.state).into(),
+            ));
+        }
+        htlc.state = outcome;
+
+        let amount_msat = htlc.amount_msat;
+        // Fulfilled funds go to the receiving side; anything else returns to the sender.
+        let to_local = match outcome {
+            HtlcState::Fulfilled => direction == HtlcDirection::Received,
+            _ => direction == HtlcDirection::Offered,
+        };
+        if to_local {
+            channel.local_balance_msat += amount_msat;
+        } else {
+            channel.remote_balance_msat += amount_msat;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, channel_id: [u8; 32], from: Option<ChannelState>, to: ChannelState) {
+        self.history.push(ChannelTransition {
+            channel_id,
+            from,
+            to,
+            timestamp: time::unix_seconds_sync(),
+        });
     }
 }
 
@@ -36,17 +504,200 @@ impl ChannelProvider for ChannelManager {
     }
 
     fn total_local_balance(&self) -> u64 {
-        self.active_channels().iter().map(|c| c.local_balance).sum()
+        self.active_channels().iter().map(|c| c.local_balance_msat).sum::<u64>() / 1000
     }
 
-    fn open_channel(&mut self, _peer_pubkey: [u8; 33], _capacity: u64) -> PaymentResult<[u8; 32]> {
-        // Placeholder - would initiate channel opening
-        let channel_id = [0u8; 32];
-        Ok(channel_id)
+    fn total_in_flight_msat(&self, direction: HtlcDirection) -> u64 {
+        self.channels.iter().map(|c| c.in_flight_msat(direction)).sum()
     }
 
-    fn close_channel(&mut self, _channel_id: &[u8; 32]) -> PaymentResult<()> {
-        // Placeholder - would initiate cooperative close
-        Ok(())
+    fn open_channel(&mut self, peer_pubkey: [u8; 33], capacity: u64) -> PaymentResult<[u8; 32]> {
+        self.open_channel_with_push(peer_pubkey, capacity, 0)
+    }
+
+    fn close_channel(&mut self, channel_id: &[u8; 32]) -> PaymentResult<()> {
+        self.transition(channel_id, ChannelState::Closing)
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+
+    fn opened(manager: &mut ChannelManager) -> [u8; 32] {
+        manager.open_channel([2; 33], 100_000).unwrap()
+    }
+
+    #[test]
+    fn test_open_waits_for_funding_confirmations() {
+        let mut manager = ChannelManager::new();
+        let id = opened(&mut manager);
+        assert_ne!(id, [0; 32]);
+        assert_eq!(manager.channel(&id).unwrap().state, ChannelState::Opening);
+        assert_eq!(manager.total_local_balance(), 0);
+
+        assert_eq!(manager.record_funding_confirmations(&id, 1).unwrap(), ChannelState::Opening);
+        assert_eq!(manager.funding_confirmations(&id), Some(1));
+        assert_eq!(manager.record_funding_confirmations(&id, 3).unwrap(), ChannelState::Active);
+        assert_eq!(manager.total_local_balance(), 100_000);
+        assert!(manager.record_funding_confirmations(&id, 4).is_err());
+    }
+
+    #[test]
+    fn test_cooperative_close() {
+        let mut manager = ChannelManager::with_required_confirmations(1);
+        let id = opened(&mut manager);
+        assert!(manager.close_channel(&id).is_err());
+
+        manager.record_funding_confirmations(&id, 1).unwrap();
+        manager.close_channel(&id).unwrap();
+        assert_eq!(manager.channel(&id).unwrap().state, ChannelState::Closing);
+        manager.confirm_channel_closed(&id).unwrap();
+
+        let states: Vec<_> = manager.channel_history(&id).iter().map(|t| (t.from, t.to)).collect();
+        assert_eq!(
+            states,
+            vec![
+                (None, ChannelState::Opening),
+                (Some(ChannelState::Opening), ChannelState::Active),
+                (Some(ChannelState::Active), ChannelState::Closing),
+                (Some(ChannelState::Closing), ChannelState::Closed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_validates_capacity_push_and_reserve() {
+        let config = PaymentConfig::default();
+        let mut manager = ChannelManager::with_config(&config);
+
+        let below = config.min_channel_capacity - 1;
+        let above = config.max_channel_capacity + 1;
+        assert!(matches!(
+            manager.limits().validate_open(below, 0),
+            Err(ChannelOpenError::CapacityBelowMinimum { .. })
+        ));
+        assert!(matches!(
+            manager.limits().validate_open(above, 0),
+            Err(ChannelOpenError::CapacityAboveMaximum { .. })
+        ));
+        assert_eq!(
+            manager.limits().validate_open(100_000, 100_001),
+            Err(ChannelOpenError::PushExceedsCapacity { push: 100_001, capacity: 100_000 })
+        );
+        assert_eq!(
+            manager.limits().validate_open(100_000, 99_500),
+            Err(ChannelOpenError::ReserveNotMet { local_balance: 500, reserve: 1_000 })
+        );
+        assert!(manager.open_channel([2; 33], below).is_err());
+        assert!(manager.channels().is_empty());
+
+        let id = manager.open_channel_with_push([2; 33], 100_000, 40_000).unwrap();
+        let channel = manager.channel(&id).unwrap();
+        assert_eq!(
+            (channel.local_balance_msat, channel.remote_balance_msat),
+            (60_000_000, 40_000_000)
+        );
+        assert!(channel.balances_consistent());
+    }
+
+    #[test]
+    fn test_htlcs_lock_and_release_funds() {
+        let mut manager = ChannelManager::with_required_confirmations(1);
+        let id = manager.open_channel_with_push([2; 33], 100_000, 20_000).unwrap();
+        let preimage = PaymentPreimage::new([5; 32]);
+        let hash = preimage.payment_hash();
+        assert!(manager.add_htlc(&id, HtlcDirection::Offered, 1_000, hash, 500).is_err());
+        manager.record_funding_confirmations(&id, 1).unwrap();
+
+        let first = manager.add_htlc(&id, HtlcDirection::Offered, 50_000_000, hash, 500).unwrap();
+        assert_eq!(manager.total_local_balance(), 30_000);
+        assert!(matches!(
+            manager.add_htlc(&id, HtlcDirection::Offered, 40_000_000, hash, 500),
+            Err(PaymentError::InsufficientFunds(_))
+        ));
+        // 1,000 sats of the remaining 30,000 are the opener's reserve.
+        assert!(matches!(
+            manager.add_htlc(&id, HtlcDirection::Offered, 29_500_000, hash, 500),
+            Err(PaymentError::InsufficientFunds(_))
+        ));
+        let second = manager.add_htlc(&id, HtlcDirection::Offered, 10_000_500, hash, 500).unwrap();
+        let received =
+            manager.add_htlc(&id, HtlcDirection::Received, 5_000_000, hash, 450).unwrap();
+        assert_eq!((first, second, received), (0, 1, 0));
+        assert_eq!(manager.total_in_flight_msat(HtlcDirection::Offered), 60_000_500);
+        assert_eq!(manager.total_in_flight_msat(HtlcDirection::Received), 5_000_000);
+        assert!(manager.channel(&id).unwrap().balances_consistent());
+
+        assert!(manager
+            .fulfill_htlc(&id, HtlcDirection::Offered, first, &PaymentPreimage::new([6; 32]))
+            .is_err());
+        manager.fulfill_htlc(&id, HtlcDirection::Offered, first, &preimage).unwrap();
+        manager.fail_htlc(&id, HtlcDirection::Offered, second).unwrap();
+        assert!(manager.fail_htlc(&id, HtlcDirection::Offered, second).is_err());
+
+        let channel = manager.channel(&id).unwrap();
+        assert_eq!(
+            (channel.local_balance_msat, channel.remote_balance_msat),
+            (30_000_000, 65_000_000)
+        );
+        assert_eq!(channel.in_flight_msat(HtlcDirection::Received), 5_000_000);
+        assert!(channel.balances_consistent());
+
+        // Sub-satoshi HTLCs move exactly their amount.
+        let tiny = manager.add_htlc(&id, HtlcDirection::Offered, 1, hash, 500).unwrap();
+        manager.fulfill_htlc(&id, HtlcDirection::Offered, tiny, &preimage).unwrap();
+        let channel = manager.channel(&id).unwrap();
+        assert_eq!(
+            (channel.local_balance_msat, channel.remote_balance_msat),
+            (29_999_999, 65_000_001)
+        );
+        assert!(channel.balances_consistent());
+        assert_eq!(
+            manager.htlc(&id, HtlcDirection::Offered, first).unwrap().state,
+            HtlcState::Fulfilled
+        );
+    }
+
+    #[test]
+    fn test_htlcs_expire_at_cltv_height() {
+        let mut manager = ChannelManager::with_required_confirmations(1);
+        let id = opened(&mut manager);
+        manager.record_funding_confirmations(&id, 1).unwrap();
+        let hash = PaymentPreimage::new([5; 32]).payment_hash();
+        manager.add_htlc(&id, HtlcDirection::Offered, 10_000_000, hash, 500).unwrap();
+        manager.add_htlc(&id, HtlcDirection::Offered, 20_000_000, hash, 600).unwrap();
+
+        assert!(manager.expire_htlcs(499).is_empty());
+        let expired = manager.expire_htlcs(500);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(
+            (expired[0].0, expired[0].1.id, expired[0].1.state),
+            (id, 0, HtlcState::Expired)
+        );
+        assert_eq!(manager.total_local_balance(), 80_000);
+        assert_eq!(manager.total_in_flight_msat(HtlcDirection::Offered), 20_000_000);
+        assert!(manager.add_htlc(&id, HtlcDirection::Offered, 1_000, hash, 500).is_err());
+    }
+
+    #[test]
+    fn test_reserve_bounds() {
+        assert_eq!(ChannelLimits::reserve(20_000), MIN_CHANNEL_RESERVE_SATS);
+        assert_eq!(ChannelLimits::reserve(10_000_000), 100_000);
+        assert_eq!(ChannelLimits::reserve(100), 100);
+    }
+
+    #[test]
+    fn test_force_close_and_illegal_moves() {
+        let mut manager = ChannelManager::with_required_confirmations(1);
+        let id = opened(&mut manager);
+        manager.record_funding_confirmations(&id, 1).unwrap();
+
+        manager.force_close_channel(&id).unwrap();
+        assert!(matches!(manager.close_channel(&id), Err(PaymentError::Channel(_))));
+        assert!(manager.transition(&id, ChannelState::Active).is_err());
+        manager.confirm_channel_closed(&id).unwrap();
+        assert!(manager.confirm_channel_closed(&id).is_err());
+        assert!(manager.close_channel(&[9; 32]).is_err());
     }
 }
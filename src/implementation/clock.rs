@@ -0,0 +1,53 @@
+//! Clock implementations.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use essentia_core::time;
+
+use crate::traits::Clock;
+
+/// Wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        time::unix_seconds_sync()
+    }
+}
+
+/// Clock that only moves when told to.
+///
+/// Clones share the same time, so a test can keep one handle and advance the
+/// clock it injected elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Start the clock at `now` unix seconds.
+    #[must_use]
+    pub fn new(now: u64) -> Self {
+        Self { now: Arc::new(AtomicU64::new(now)) }
+    }
+
+    /// Jump to `now` unix seconds.
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Move forward by `secs` seconds.
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
@@ -1,5 +1,10 @@
 //! Payment plugin configuration.
 
+use crate::types::Network;
+
+/// Default invoice expiry in seconds.
+pub const DEFAULT_INVOICE_EXPIRY: u64 = 3600;
+
 /// Configuration for the payment plugin.
 #[derive(Debug, Clone)]
 pub struct PaymentConfig {
@@ -15,17 +20,20 @@ pub struct PaymentConfig {
     pub payment_timeout:         u64,
     /// Enable automatic channel management.
     pub auto_channel_management: bool,
+    /// Bitcoin network invoices are issued for.
+    pub network:                 Network,
 }
 
 impl Default for PaymentConfig {
     fn default() -> Self {
         Self {
-            max_channel_capacity:    10_000_000, // 0.1 BTC
-            min_channel_capacity:    20_000,     // 20k sats
-            default_invoice_expiry:  3600,       // 1 hour
+            max_channel_capacity:    10_000_000,             // 0.1 BTC
+            min_channel_capacity:    20_000,                 // 20k sats
+            default_invoice_expiry:  DEFAULT_INVOICE_EXPIRY, // 1 hour
             max_payment_retries:     3,
             payment_timeout:         60,
             auto_channel_management: true,
+            network:                 Network::Bitcoin,
         }
     }
 }
@@ -0,0 +1,749 @@
+//! Bounty escrow management.
+//!
+//! An escrow is recorded once the funder's deposit is locked, e.g. when a hold
+//! invoice is accepted or a multisig output confirms, and starts out
+//! [`EscrowStatus::Funded`]. From there it is released to the claimant,
+//! refunded to the funder or disputed; every change keeps the reason and any
+//! evidence given for it. Disputed escrows are only resolved by a signed
+//! arbiter ruling, see [`crate::implementation::arbitration`].
+
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::{
+    crypto::random,
+    encoding::hex,
+    errors::{ErrorDetail, PaymentError},
+    implementation::{
+        arbitration::{Dispute, DisputeParty, Ruling, RulingExecution, SignedRuling},
+        multisig::{MultisigError, MultisigEscrow},
+        psbt::{Psbt, PsbtError},
+        transaction::{OutPoint, Transaction},
+    },
+    types::{EscrowStatus, EscrowType},
+};
+
+/// Seconds in an hour, for deadline queries.
+const SECS_PER_HOUR: u64 = 3600;
+
+/// Reasons an escrow operation is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EscrowError {
+    /// No escrow with the given id.
+    NotFound,
+    /// Escrowed amount is zero.
+    ZeroAmount,
+    /// Funder and claimant are the same party.
+    SameParty,
+    /// Deadline is not in the future.
+    DeadlineInPast {
+        /// Requested deadline, unix seconds.
+        deadline: u64,
+        /// Current time, unix seconds.
+        now:      u64,
+    },
+    /// Multisig escrow keys and redeem script do not agree.
+    InvalidMultiSig(MultisigError),
+    /// Transition needs a reason.
+    MissingReason,
+    /// Status machine does not allow the move.
+    IllegalTransition {
+        /// Current status.
+        from: EscrowStatus,
+        /// Requested status.
+        to:   EscrowStatus,
+    },
+    /// Undisputed escrows can only be released before the deadline.
+    DeadlinePassed {
+        /// Escrow deadline, unix seconds.
+        deadline: u64,
+    },
+    /// Undisputed escrows can only be refunded once the deadline passed.
+    DeadlineNotReached {
+        /// Escrow deadline, unix seconds.
+        deadline: u64,
+    },
+    /// Disputed escrows are resolved by an arbiter ruling.
+    AwaitingRuling,
+    /// Escrow is not disputed.
+    NotDisputed,
+    /// No arbiter is configured for the escrow.
+    NoArbiter,
+    /// Ruling is not signed by the escrow's arbiter.
+    WrongArbiter,
+    /// Ruling signature does not verify.
+    InvalidRulingSignature,
+    /// Dispute already has a ruling.
+    AlreadyRuled,
+    /// Dispute has no ruling yet.
+    NoRuling,
+    /// Ruling was already carried out.
+    AlreadyExecuted,
+    /// Split percentage outside 1..=99.
+    InvalidSplit(u8),
+    /// Only multisig escrows can be split between the parties.
+    SplitNeedsMultiSig,
+    /// Escrow is not backed the way the execution expects.
+    WrongBacking(&'static str),
+    /// Releasing a hold invoice escrow needs the preimage.
+    MissingPreimage,
+    /// Spending transaction could not be built.
+    Psbt(PsbtError),
+    /// No multisig spend was built for the ruling yet.
+    NoSpend,
+    /// Transaction is not the signed spend built for the ruling.
+    SpendMismatch,
+}
+
+impl fmt::Display for EscrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "escrow not found"),
+            Self::ZeroAmount => write!(f, "escrow amount must be positive"),
+            Self::SameParty => write!(f, "funder and claimant must differ"),
+            Self::DeadlineInPast { deadline, now } => {
+                write!(f, "deadline {deadline} is not after the current time {now}")
+            },
+            Self::InvalidMultiSig(err) => write!(f, "invalid multisig escrow: {err}"),
+            Self::MissingReason => write!(f, "escrow transitions need a reason"),
+            Self::IllegalTransition { from, to } => {
+                write!(f, "illegal escrow transition {from:?} -> {to:?}")
+            },
+            Self::DeadlinePassed { deadline } => {
+                write!(f, "deadline {deadline} passed; only a refund or dispute ruling remains")
+            },
+            Self::DeadlineNotReached { deadline } => {
+                write!(f, "refund is only possible after the deadline {deadline}")
+            },
+            Self::AwaitingRuling => write!(f, "disputed escrow awaits an arbiter ruling"),
+            Self::NotDisputed => write!(f, "escrow is not disputed"),
+            Self::NoArbiter => write!(f, "no arbiter configured for the escrow"),
+            Self::WrongArbiter => write!(f, "ruling is not signed by the escrow's arbiter"),
+            Self::InvalidRulingSignature => write!(f, "ruling signature is invalid"),
+            Self::AlreadyRuled => write!(f, "dispute already has a ruling"),
+            Self::NoRuling => write!(f, "dispute has no ruling yet"),
+            Self::AlreadyExecuted => write!(f, "ruling was already executed"),
+            Self::InvalidSplit(percent) => {
+                write!(f, "split of {percent}% must leave both parties a share")
+            },
+            Self::SplitNeedsMultiSig => write!(f, "only multisig escrows can be split"),
+            Self::WrongBacking(backing) => write!(f, "escrow is not backed by a {backing}"),
+            Self::MissingPreimage => write!(f, "releasing a hold invoice needs the preimage"),
+            Self::Psbt(err) => write!(f, "cannot build spend: {err}"),
+            Self::NoSpend => write!(f, "no multisig spend was built for the ruling"),
+            Self::SpendMismatch => {
+                write!(f, "transaction is not the signed spend built for the ruling")
+            },
+        }
+    }
+}
+
+impl std::error::Error for EscrowError {}
+
+impl From<EscrowError> for PaymentError {
+    fn from(err: EscrowError) -> Self {
+        PaymentError::Escrow(ErrorDetail::caused_by(err.to_string(), err))
+    }
+}
+
+/// Recorded change of an escrow's status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscrowTransition {
+    /// Previous status, `None` when the escrow was recorded.
+    pub from:      Option<EscrowStatus>,
+    /// New status.
+    pub to:        EscrowStatus,
+    /// Why the status changed.
+    pub reason:    String,
+    /// Supporting evidence, e.g. a commit hash or review link.
+    pub evidence:  Option<String>,
+    /// Unix time of the change, in seconds.
+    pub timestamp: u64,
+}
+
+/// Terms an escrow is created with.
+#[derive(Debug, Clone)]
+pub struct EscrowTerms {
+    /// Bounty the escrow pays for.
+    pub bounty_id:   String,
+    /// Escrowed amount in satoshis.
+    pub amount_sats: u64,
+    /// Party that deposited the funds.
+    pub funder:      String,
+    /// Party the funds are released to.
+    pub claimant:    String,
+    /// Unix time after which an undisputed escrow can be refunded.
+    pub deadline:    u64,
+    /// How the funds are locked.
+    pub escrow_type: EscrowType,
+}
+
+/// Funds held for a bounty.
+#[derive(Debug, Clone)]
+pub struct Escrow {
+    /// Escrow identifier.
+    pub escrow_id:   [u8; 32],
+    /// Bounty the escrow pays for.
+    pub bounty_id:   String,
+    /// Escrowed amount in satoshis.
+    pub amount_sats: u64,
+    /// Party that deposited the funds.
+    pub funder:      String,
+    /// Party the funds are released to.
+    pub claimant:    String,
+    /// Unix time after which an undisputed escrow can be refunded.
+    pub deadline:    u64,
+    /// How the funds are locked.
+    pub escrow_type: EscrowType,
+    /// Current status.
+    pub status:      EscrowStatus,
+    /// Every status change, oldest first.
+    pub history:     Vec<EscrowTransition>,
+    /// Dispute and its resolution, if one was opened.
+    pub dispute:     Option<Dispute>,
+}
+
+/// Escrow manager for bounty payments.
+#[derive(Debug, Default)]
+pub struct EscrowManager {
+    escrows: HashMap<[u8; 32], Escrow>,
+    arbiter: Option<[u8; 33]>,
+}
+
+impl EscrowManager {
+    /// Create an empty escrow manager.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the arbiter for escrows that do not name one.
+    ///
+    /// Multisig escrows are always ruled on by the arbiter key in their script.
+    pub fn set_arbiter(&mut self, arbiter: [u8; 33]) {
+        self.arbiter = Some(arbiter);
+    }
+
+    /// Key whose rulings decide disputes on the escrow.
+    #[must_use]
+    pub fn arbiter_for(&self, escrow: &Escrow) -> Option<[u8; 33]> {
+        match &escrow.escrow_type {
+            EscrowType::MultiSig { .. } => MultisigEscrow::from_escrow_type(&escrow.escrow_type)
+                .ok()
+                .map(|multisig| *multisig.arbiter()),
+            EscrowType::LightningHold { .. } => self.arbiter,
+        }
+    }
+
+    /// Record a funded escrow for a bounty.
+    pub fn create_escrow(&mut self, terms: EscrowTerms, now: u64) -> Result<[u8; 32], EscrowError> {
+        let EscrowTerms { bounty_id, amount_sats, funder, claimant, deadline, escrow_type } = terms;
+        if amount_sats == 0 {
+            return Err(EscrowError::ZeroAmount);
+        }
+        if funder == claimant {
+            return Err(EscrowError::SameParty);
+        }
+        if deadline <= now {
+            return Err(EscrowError::DeadlineInPast { deadline, now });
+        }
+        if matches!(escrow_type, EscrowType::MultiSig { .. }) {
+            MultisigEscrow::validate(&escrow_type).map_err(EscrowError::InvalidMultiSig)?;
+        }
+
+        let escrow_id = random::random_bytes();
+        self.escrows.insert(
+            escrow_id,
+            Escrow {
+                escrow_id,
+                bounty_id,
+                amount_sats,
+                funder,
+                claimant,
+                deadline,
+                escrow_type,
+                status: EscrowStatus::Funded,
+                history: vec![EscrowTransition {
+                    from:      None,
+                    to:        EscrowStatus::Funded,
+                    reason:    "funded".to_string(),
+                    evidence:  None,
+                    timestamp: now,
+                }],
+                dispute: None,
+            },
+        );
+        Ok(escrow_id)
+    }
+
+    /// Look up an escrow.
+    #[must_use]
+    pub fn escrow(&self, escrow_id: &[u8; 32]) -> Option<&Escrow> {
+        self.escrows.get(escrow_id)
+    }
+
+    /// Escrows held for a bounty, oldest deadline first.
+    #[must_use]
+    pub fn escrows_for_bounty(&self, bounty_id: &str) -> Vec<&Escrow> {
+        self.sorted(|escrow| escrow.bounty_id == bounty_id)
+    }
+
+    /// Escrows with the given status, oldest deadline first.
+    #[must_use]
+    pub fn escrows_with_status(&self, status: EscrowStatus) -> Vec<&Escrow> {
+        self.sorted(|escrow| escrow.status == status)
+    }
+
+    /// Undisputed escrows whose deadline falls within the next `hours`.
+    #[must_use]
+    pub fn expiring_within(&self, now: u64, hours: u64) -> Vec<&Escrow> {
+        let until = now.saturating_add(hours.saturating_mul(SECS_PER_HOUR));
+        self.sorted(|escrow| {
+            escrow.status == EscrowStatus::Funded
+                && escrow.deadline > now
+                && escrow.deadline <= until
+        })
+    }
+
+    /// Undisputed escrows whose deadline has passed, ready to be refunded.
+    #[must_use]
+    pub fn expired(&self, now: u64) -> Vec<&Escrow> {
+        self.sorted(|escrow| escrow.status == EscrowStatus::Funded && escrow.deadline <= now)
+    }
+
+    /// Release the funds to the claimant before the deadline.
+    pub fn release(
+        &mut self, escrow_id: &[u8; 32], reason: &str, evidence: Option<&str>, now: u64,
+    ) -> Result<(), EscrowError> {
+        let escrow = self.escrow(escrow_id).ok_or(EscrowError::NotFound)?;
+        if escrow.status == EscrowStatus::Disputed {
+            return Err(EscrowError::AwaitingRuling);
+        }
+        if escrow.status == EscrowStatus::Funded && now >= escrow.deadline {
+            return Err(EscrowError::DeadlinePassed { deadline: escrow.deadline });
+        }
+        self.transition(escrow_id, EscrowStatus::Released, reason, evidence, now)
+    }
+
+    /// Refund the funds to the funder once the deadline has passed.
+    pub fn refund(
+        &mut self, escrow_id: &[u8; 32], reason: &str, evidence: Option<&str>, now: u64,
+    ) -> Result<(), EscrowError> {
+        let escrow = self.escrow(escrow_id).ok_or(EscrowError::NotFound)?;
+        if escrow.status == EscrowStatus::Disputed {
+            return Err(EscrowError::AwaitingRuling);
+        }
+        if escrow.status == EscrowStatus::Funded && now < escrow.deadline {
+            return Err(EscrowError::DeadlineNotReached { deadline: escrow.deadline });
+        }
+        self.transition(escrow_id, EscrowStatus::Refunded, reason, evidence, now)
+    }
+
+    /// Dispute the escrow, leaving the outcome to the arbiter.
+    pub fn dispute(
+        &mut self, escrow_id: &[u8; 32], opened_by: DisputeParty, reason: &str,
+        evidence: Option<&str>, now: u64,
+    ) -> Result<(), EscrowError> {
+        self.transition(escrow_id, EscrowStatus::Disputed, reason, evidence, now)?;
+        if let Some(escrow) = self.escrows.get_mut(escrow_id) {
+            escrow.dispute = Some(Dispute {
+                opened_by,
+                reason: reason.to_string(),
+                evidence: evidence.map(str::to_string),
+                opened_at: now,
+                ruling: None,
+                spend: None,
+                execution: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Record the arbiter's signed ruling on a disputed escrow.
+    pub fn record_ruling(&mut self, ruling: SignedRuling) -> Result<(), EscrowError> {
+        let escrow = self.escrow(&ruling.escrow_id).ok_or(EscrowError::NotFound)?;
+        let dispute = escrow.dispute.as_ref().ok_or(EscrowError::NotDisputed)?;
+        if dispute.ruling.is_some() {
+            return Err(EscrowError::AlreadyRuled);
+        }
+        if self.arbiter_for(escrow).ok_or(EscrowError::NoArbiter)? != ruling.arbiter {
+            return Err(EscrowError::WrongArbiter);
+        }
+        if !ruling.verify() {
+            return Err(EscrowError::InvalidRulingSignature);
+        }
+        if let Ruling::Split { claimant_percent } = ruling.ruling {
+            if !(1..=99).contains(&claimant_percent) {
+                return Err(EscrowError::InvalidSplit(claimant_percent));
+            }
+            if !matches!(escrow.escrow_type, EscrowType::MultiSig { .. }) {
+                return Err(EscrowError::SplitNeedsMultiSig);
+            }
+        }
+
+        if let Some(dispute) =
+            self.escrows.get_mut(&ruling.escrow_id).and_then(|escrow| escrow.dispute.as_mut())
+        {
+            dispute.ruling = Some(ruling);
+        }
+        Ok(())
+    }
+
+    /// Ruling that still has to be carried out.
+    pub fn pending_ruling(&self, escrow_id: &[u8; 32]) -> Result<&SignedRuling, EscrowError> {
+        let escrow = self.escrow(escrow_id).ok_or(EscrowError::NotFound)?;
+        let dispute = escrow.dispute.as_ref().ok_or(EscrowError::NotDisputed)?;
+        if dispute.execution.is_some() {
+            return Err(EscrowError::AlreadyExecuted);
+        }
+        dispute.ruling.as_ref().ok_or(EscrowError::NoRuling)
+    }
+
+    /// Build the spend carrying out the ruling on a multisig escrow funded by
+    /// `funding`.
+    ///
+    /// Returns the spend for the arbiter and the favoured party to sign;
+    /// `fee_sats` comes out of the escrowed amount before any split. The
+    /// dispute stays open until [`Self::complete_multisig_ruling`] records the
+    /// signed transaction; building again replaces the spend.
+    pub fn execute_multisig_ruling(
+        &mut self, escrow_id: &[u8; 32], funding: OutPoint, fee_sats: u64,
+    ) -> Result<Psbt, EscrowError> {
+        let ruling = self.pending_ruling(escrow_id)?.ruling;
+        let escrow = self.escrow(escrow_id).ok_or(EscrowError::NotFound)?;
+        let multisig = MultisigEscrow::from_escrow_type(&escrow.escrow_type)
+            .map_err(|_| EscrowError::WrongBacking("multisig output"))?;
+        let amount_sats = escrow.amount_sats;
+
+        let psbt = match ruling {
+            Ruling::Release => multisig.release_psbt(funding, amount_sats, fee_sats),
+            Ruling::Refund => multisig.refund_psbt(funding, amount_sats, fee_sats),
+            Ruling::Split { claimant_percent } => {
+                multisig.split_psbt(funding, amount_sats, fee_sats, claimant_percent)
+            },
+        }
+        .map_err(EscrowError::Psbt)?;
+        if let Some(dispute) =
+            self.escrows.get_mut(escrow_id).and_then(|escrow| escrow.dispute.as_mut())
+        {
+            dispute.spend = Some(psbt.clone());
+        }
+        Ok(psbt)
+    }
+
+    /// Close a multisig dispute once the spend built for its ruling is signed.
+    ///
+    /// `transaction` must be that spend with every input's witness filled in.
+    pub fn complete_multisig_ruling(
+        &mut self, escrow_id: &[u8; 32], transaction: &Transaction, now: u64,
+    ) -> Result<(), EscrowError> {
+        self.pending_ruling(escrow_id)?;
+        let spend = self
+            .escrow(escrow_id)
+            .and_then(|escrow| escrow.dispute.as_ref())
+            .and_then(|dispute| dispute.spend.as_ref())
+            .ok_or(EscrowError::NoSpend)?;
+        if transaction.txid() != spend.unsigned_tx.txid()
+            || transaction.input.iter().any(|input| input.witness.is_empty())
+        {
+            return Err(EscrowError::SpendMismatch);
+        }
+        self.complete_ruling(escrow_id, RulingExecution::MultisigSpend(transaction.clone()), now)
+    }
+
+    /// Close a dispute once its ruling was carried out.
+    ///
+    /// The status change records the rationale, with the ruling signature as
+    /// evidence.
+    pub(crate) fn complete_ruling(
+        &mut self, escrow_id: &[u8; 32], execution: RulingExecution, now: u64,
+    ) -> Result<(), EscrowError> {
+        let ruling = self.pending_ruling(escrow_id)?.clone();
+        let to = match ruling.ruling {
+            Ruling::Refund => EscrowStatus::Refunded,
+            Ruling::Release | Ruling::Split { .. } => EscrowStatus::Released,
+        };
+        let reason = format!("arbiter ruling, {}: {}", ruling.ruling, ruling.rationale);
+        let signature = hex::encode(&ruling.signature.serialize_compact());
+        self.transition(escrow_id, to, &reason, Some(&signature), now)?;
+
+        if let Some(dispute) =
+            self.escrows.get_mut(escrow_id).and_then(|escrow| escrow.dispute.as_mut())
+        {
+            dispute.execution = Some(execution);
+        }
+        Ok(())
+    }
+
+    fn transition(
+        &mut self, escrow_id: &[u8; 32], to: EscrowStatus, reason: &str, evidence: Option<&str>,
+        now: u64,
+    ) -> Result<(), EscrowError> {
+        if reason.trim().is_empty() {
+            return Err(EscrowError::MissingReason);
+        }
+        let escrow = self.escrows.get_mut(escrow_id).ok_or(EscrowError::NotFound)?;
+        let from = escrow.status;
+        if !from.can_transition_to(to) {
+            return Err(EscrowError::IllegalTransition { from, to });
+        }
+
+        escrow.status = to;
+        escrow.history.push(EscrowTransition {
+            from: Some(from),
+            to,
+            reason: reason.to_string(),
+            evidence: evidence.map(str::to_string),
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    fn sorted(&self, filter: impl Fn(&Escrow) -> bool) -> Vec<&Escrow> {
+        let mut escrows: Vec<&Escrow> = self.escrows.values().filter(|e| filter(e)).collect();
+        escrows.sort_by_key(|escrow| (escrow.deadline, escrow.escrow_id));
+        escrows
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{crypto::SecretKey, types::PaymentPreimage};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn hold() -> EscrowType {
+        EscrowType::LightningHold {
+            payment_hash: PaymentPreimage::new([1; 32]).payment_hash(),
+            preimage:     None,
+        }
+    }
+
+    fn terms(bounty: &str, amount_sats: u64, claimant: &str, deadline: u64) -> EscrowTerms {
+        EscrowTerms {
+            bounty_id: bounty.to_string(),
+            amount_sats,
+            funder: "alice".to_string(),
+            claimant: claimant.to_string(),
+            deadline,
+            escrow_type: hold(),
+        }
+    }
+
+    fn create(manager: &mut EscrowManager, bounty: &str, deadline: u64) -> [u8; 32] {
+        manager.create_escrow(terms(bounty, 50_000, "bob", deadline), NOW).unwrap()
+    }
+
+    #[test]
+    fn test_create_validates_terms() {
+        let mut manager = EscrowManager::new();
+        let deadline = NOW + 3600;
+        assert_eq!(
+            manager.create_escrow(terms("b1", 0, "bob", deadline), NOW).unwrap_err(),
+            EscrowError::ZeroAmount
+        );
+        assert_eq!(
+            manager.create_escrow(terms("b1", 1, "alice", deadline), NOW).unwrap_err(),
+            EscrowError::SameParty
+        );
+        assert!(matches!(
+            manager.create_escrow(terms("b1", 1, "bob", NOW), NOW),
+            Err(EscrowError::DeadlineInPast { .. })
+        ));
+
+        let mut multisig = terms("b1", 1, "bob", deadline);
+        multisig.escrow_type = EscrowType::MultiSig {
+            funder_pubkey:   "02".repeat(33),
+            claimant_pubkey: "03".repeat(33),
+            arbiter_pubkey:  "02".repeat(33),
+            redeem_script:   String::new(),
+        };
+        assert!(matches!(
+            manager.create_escrow(multisig, NOW),
+            Err(EscrowError::InvalidMultiSig(_))
+        ));
+
+        let id = create(&mut manager, "b1", deadline);
+        let escrow = manager.escrow(&id).unwrap();
+        assert_eq!(escrow.status, EscrowStatus::Funded);
+        assert_eq!(escrow.history.len(), 1);
+    }
+
+    #[test]
+    fn test_release_and_refund_guards() {
+        let mut manager = EscrowManager::new();
+        let deadline = NOW + 3600;
+        let released = create(&mut manager, "b1", deadline);
+        let refunded = create(&mut manager, "b2", deadline);
+
+        assert_eq!(manager.release(&released, " ", None, NOW), Err(EscrowError::MissingReason));
+        manager.release(&released, "merged", Some("commit abc123"), NOW + 60).unwrap();
+        assert!(matches!(
+            manager.refund(&released, "changed mind", None, deadline),
+            Err(EscrowError::IllegalTransition { from: EscrowStatus::Released, .. })
+        ));
+
+        assert_eq!(
+            manager.refund(&refunded, "no work", None, NOW),
+            Err(EscrowError::DeadlineNotReached { deadline })
+        );
+        assert_eq!(
+            manager.release(&refunded, "late", None, deadline),
+            Err(EscrowError::DeadlinePassed { deadline })
+        );
+        manager.refund(&refunded, "no work delivered", None, deadline).unwrap();
+
+        let last = manager.escrow(&released).unwrap().history.last().unwrap().clone();
+        assert_eq!(last.from, Some(EscrowStatus::Funded));
+        assert_eq!(last.evidence.as_deref(), Some("commit abc123"));
+        assert_eq!(manager.escrows_with_status(EscrowStatus::Refunded).len(), 1);
+    }
+
+    #[test]
+    fn test_dispute_resolves_by_signed_ruling() {
+        let arbiter = SecretKey::from_slice(&[7; 32]).unwrap();
+        let mut manager = EscrowManager::new();
+        let id = create(&mut manager, "b1", NOW + 3600);
+        let rule = |ruling, key: &SecretKey| {
+            SignedRuling::sign(id, ruling, "work half done", NOW + 60, key)
+        };
+
+        assert_eq!(
+            manager.record_ruling(rule(Ruling::Release, &arbiter)),
+            Err(EscrowError::NotDisputed)
+        );
+        manager
+            .dispute(&id, DisputeParty::Funder, "incomplete work", Some("review #4"), NOW)
+            .unwrap();
+        assert!(manager.dispute(&id, DisputeParty::Claimant, "again", None, NOW).is_err());
+        assert_eq!(
+            manager.release(&id, "merged anyway", None, NOW),
+            Err(EscrowError::AwaitingRuling)
+        );
+
+        assert_eq!(
+            manager.record_ruling(rule(Ruling::Release, &arbiter)),
+            Err(EscrowError::NoArbiter)
+        );
+        manager.set_arbiter(arbiter.public_key().serialize());
+        let stranger = SecretKey::from_slice(&[8; 32]).unwrap();
+        assert_eq!(
+            manager.record_ruling(rule(Ruling::Release, &stranger)),
+            Err(EscrowError::WrongArbiter)
+        );
+        let mut tampered = rule(Ruling::Refund, &arbiter);
+        tampered.ruling = Ruling::Release;
+        assert_eq!(manager.record_ruling(tampered), Err(EscrowError::InvalidRulingSignature));
+        assert_eq!(
+            manager.record_ruling(rule(Ruling::Split { claimant_percent: 50 }, &arbiter)),
+            Err(EscrowError::SplitNeedsMultiSig)
+        );
+
+        let ruling = rule(Ruling::Release, &arbiter);
+        manager.record_ruling(ruling.clone()).unwrap();
+        assert_eq!(manager.record_ruling(ruling.clone()), Err(EscrowError::AlreadyRuled));
+
+        // Hold invoice escrows are executed by the plugin; the trail records it.
+        let payment_hash = PaymentPreimage::new([1; 32]).payment_hash();
+        manager
+            .complete_ruling(&id, RulingExecution::HoldSettled(payment_hash), NOW + 7200)
+            .unwrap();
+        assert_eq!(manager.pending_ruling(&id), Err(EscrowError::AlreadyExecuted));
+
+        let escrow = manager.escrow(&id).unwrap();
+        let statuses: Vec<_> = escrow.history.iter().map(|t| t.to).collect();
+        assert_eq!(
+            statuses,
+            vec![EscrowStatus::Funded, EscrowStatus::Disputed, EscrowStatus::Released]
+        );
+        let dispute = escrow.dispute.as_ref().unwrap();
+        assert_eq!(dispute.opened_by, DisputeParty::Funder);
+        assert_eq!(dispute.ruling.as_ref(), Some(&ruling));
+        assert_eq!(dispute.execution, Some(RulingExecution::HoldSettled(payment_hash)));
+        let last = escrow.history.last().unwrap();
+        assert!(last.reason.contains("work half done"));
+        assert_eq!(last.evidence, Some(hex::encode(&ruling.signature.serialize_compact())));
+    }
+
+    #[test]
+    fn test_multisig_split_ruling_builds_two_outputs() {
+        let [funder, claimant, arbiter] =
+            [1, 2, 3].map(|byte| SecretKey::from_slice(&[byte; 32]).unwrap());
+        let multisig = MultisigEscrow::new(
+            funder.public_key().serialize(),
+            claimant.public_key().serialize(),
+            arbiter.public_key().serialize(),
+        )
+        .unwrap();
+        let mut manager = EscrowManager::new();
+        let mut terms = terms("b1", 100_000, "bob", NOW + 3600);
+        terms.escrow_type = multisig.escrow_type();
+        let id = manager.create_escrow(terms, NOW).unwrap();
+        manager.dispute(&id, DisputeParty::Claimant, "payment withheld", None, NOW).unwrap();
+
+        let funding = OutPoint { txid: [5; 32], vout: 0 };
+        assert_eq!(
+            manager.execute_multisig_ruling(&id, funding, 1_000),
+            Err(EscrowError::NoRuling)
+        );
+        assert_eq!(
+            manager.record_ruling(SignedRuling::sign(
+                id,
+                Ruling::Split { claimant_percent: 100 },
+                "all",
+                NOW,
+                &arbiter
+            )),
+            Err(EscrowError::InvalidSplit(100))
+        );
+        let split = Ruling::Split { claimant_percent: 60 };
+        manager
+            .record_ruling(SignedRuling::sign(id, split, "partial delivery", NOW, &arbiter))
+            .unwrap();
+
+        let mut psbt = manager.execute_multisig_ruling(&id, funding, 1_000).unwrap();
+        let values: Vec<_> = psbt.unsigned_tx.output.iter().map(|output| output.value).collect();
+        assert_eq!(values, vec![59_400, 39_600]);
+        assert_eq!(psbt.fee(), Some(1_000));
+        // Nothing is paid out until the spend is signed.
+        assert_eq!(manager.escrow(&id).unwrap().status, EscrowStatus::Disputed);
+        assert_eq!(
+            manager.complete_multisig_ruling(&id, &psbt.unsigned_tx, NOW + 60),
+            Err(EscrowError::SpendMismatch)
+        );
+
+        // The arbiter and either party can complete the spend.
+        psbt.sign_input(0, &arbiter).unwrap();
+        psbt.sign_input(0, &claimant).unwrap();
+        psbt.finalize().unwrap();
+        let transaction = psbt.extract_tx().unwrap();
+        assert_eq!(transaction.input[0].witness.len(), 4);
+        manager.complete_multisig_ruling(&id, &transaction, NOW + 120).unwrap();
+        let escrow = manager.escrow(&id).unwrap();
+        assert_eq!(escrow.status, EscrowStatus::Released);
+        assert_eq!(
+            escrow.dispute.as_ref().unwrap().execution,
+            Some(RulingExecution::MultisigSpend(transaction.clone()))
+        );
+        assert_eq!(
+            manager.complete_multisig_ruling(&id, &transaction, NOW + 180),
+            Err(EscrowError::AlreadyExecuted)
+        );
+    }
+
+    #[test]
+    fn test_expiring_within() {
+        let mut manager = EscrowManager::new();
+        let soon = create(&mut manager, "b1", NOW + 2 * 3600);
+        let later = create(&mut manager, "b2", NOW + 48 * 3600);
+        let disputed = create(&mut manager, "b3", NOW + 3600);
+        manager.dispute(&disputed, DisputeParty::Claimant, "contested", None, NOW).unwrap();
+
+        let ids = |escrows: Vec<&Escrow>| escrows.iter().map(|e| e.escrow_id).collect::<Vec<_>>();
+        assert_eq!(ids(manager.expiring_within(NOW, 24)), vec![soon]);
+        assert_eq!(ids(manager.expiring_within(NOW, 48)), vec![soon, later]);
+        assert_eq!(ids(manager.expired(NOW + 3 * 3600)), vec![soon]);
+        assert_eq!(ids(manager.escrows_for_bounty("b2")), vec![later]);
+    }
+}
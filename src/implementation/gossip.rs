@@ -0,0 +1,704 @@
+//! BOLT7 gossip message parsing and verification.
+
+use core::fmt;
+
+use crate::{
+    crypto::{sha256d, PublicKey, Signature},
+    errors::{ErrorDetail, PaymentError},
+    implementation::graph::ChannelPolicy,
+};
+
+/// Wire type of `channel_announcement`.
+pub const CHANNEL_ANNOUNCEMENT_TYPE: u16 = 256;
+/// Wire type of `node_announcement`.
+pub const NODE_ANNOUNCEMENT_TYPE: u16 = 257;
+/// Wire type of `channel_update`.
+pub const CHANNEL_UPDATE_TYPE: u16 = 258;
+
+/// Age after which a channel without fresh updates is pruned (two weeks).
+pub const STALE_CHANNEL_AGE_SECS: u64 = 14 * 24 * 60 * 60;
+
+/// `channel_update` channel flag selecting the direction from `node_id_2`.
+const CHANNEL_FLAG_DIRECTION: u8 = 1;
+/// `channel_update` channel flag marking the direction disabled.
+const CHANNEL_FLAG_DISABLED: u8 = 1 << 1;
+
+/// Errors from parsing or verifying gossip messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipError {
+    /// Message ended before all fields were read.
+    Truncated,
+    /// Message type is not a gossip message this node handles.
+    UnknownMessageType(u16),
+    /// Message is for a different chain.
+    WrongChain,
+    /// Public key is not a valid curve point.
+    InvalidPublicKey,
+    /// Signature is malformed or does not verify.
+    InvalidSignature,
+    /// Message violates a protocol rule.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for GossipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "message is truncated"),
+            Self::UnknownMessageType(ty) => write!(f, "unknown gossip message type {ty}"),
+            Self::WrongChain => write!(f, "message is for a different chain"),
+            Self::InvalidPublicKey => write!(f, "invalid public key"),
+            Self::InvalidSignature => write!(f, "invalid signature"),
+            Self::Invalid(reason) => write!(f, "invalid message: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for GossipError {}
+
+impl From<GossipError> for PaymentError {
+    fn from(err: GossipError) -> Self {
+        PaymentError::Routing(ErrorDetail::caused_by(format!("Gossip {err}"), err).permanent())
+    }
+}
+
+/// Announcement of a public channel between two nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelAnnouncement {
+    /// Signature of `node_id_1`.
+    pub node_signature_1:    [u8; 64],
+    /// Signature of `node_id_2`.
+    pub node_signature_2:    [u8; 64],
+    /// Signature of `bitcoin_key_1`.
+    pub bitcoin_signature_1: [u8; 64],
+    /// Signature of `bitcoin_key_2`.
+    pub bitcoin_signature_2: [u8; 64],
+    /// Channel feature bits.
+    pub features:            Vec<u8>,
+    /// Chain the channel is funded on.
+    pub chain_hash:          [u8; 32],
+    /// Short channel ID of the funding output.
+    pub short_channel_id:    u64,
+    /// Lesser node public key.
+    pub node_id_1:           [u8; 33],
+    /// Greater node public key.
+    pub node_id_2:           [u8; 33],
+    /// Funding key of `node_id_1`.
+    pub bitcoin_key_1:       [u8; 33],
+    /// Funding key of `node_id_2`.
+    pub bitcoin_key_2:       [u8; 33],
+    /// Bytes after the known fields, from later protocol versions; signed too.
+    pub excess_data:         Vec<u8>,
+}
+
+impl ChannelAnnouncement {
+    /// Digest all four signatures commit to.
+    #[must_use]
+    pub fn signed_digest(&self) -> [u8; 32] {
+        let mut data = Vec::new();
+        self.encode_unsigned(&mut data);
+        sha256d(&data)
+    }
+
+    /// Verify key ordering and all four signatures.
+    pub fn verify(&self) -> Result<(), GossipError> {
+        if self.node_id_1 >= self.node_id_2 {
+            return Err(GossipError::Invalid("node ids are not in ascending order"));
+        }
+        let digest = self.signed_digest();
+        verify(&self.node_id_1, &digest, &self.node_signature_1)?;
+        verify(&self.node_id_2, &digest, &self.node_signature_2)?;
+        verify(&self.bitcoin_key_1, &digest, &self.bitcoin_signature_1)?;
+        verify(&self.bitcoin_key_2, &digest, &self.bitcoin_signature_2)
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        for signature in [
+            &self.node_signature_1,
+            &self.node_signature_2,
+            &self.bitcoin_signature_1,
+            &self.bitcoin_signature_2,
+        ] {
+            out.extend_from_slice(signature);
+        }
+        self.encode_unsigned(out);
+    }
+
+    fn encode_unsigned(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.features.len() as u16);
+        out.extend_from_slice(&self.features);
+        out.extend_from_slice(&self.chain_hash);
+        out.extend_from_slice(&self.short_channel_id.to_be_bytes());
+        for key in [&self.node_id_1, &self.node_id_2, &self.bitcoin_key_1, &self.bitcoin_key_2] {
+            out.extend_from_slice(key);
+        }
+        out.extend_from_slice(&self.excess_data);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, GossipError> {
+        Ok(Self {
+            node_signature_1:    reader.array()?,
+            node_signature_2:    reader.array()?,
+            bitcoin_signature_1: reader.array()?,
+            bitcoin_signature_2: reader.array()?,
+            features:            reader.var_bytes()?,
+            chain_hash:          reader.array()?,
+            short_channel_id:    reader.u64()?,
+            node_id_1:           reader.array()?,
+            node_id_2:           reader.array()?,
+            bitcoin_key_1:       reader.array()?,
+            bitcoin_key_2:       reader.array()?,
+            excess_data:         reader.rest(),
+        })
+    }
+}
+
+/// Announcement of a node's alias, color and addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeAnnouncement {
+    /// Signature of `node_id`.
+    pub signature:   [u8; 64],
+    /// Node feature bits.
+    pub features:    Vec<u8>,
+    /// Announcement time; later announcements replace earlier ones.
+    pub timestamp:   u32,
+    /// Announcing node.
+    pub node_id:     [u8; 33],
+    /// Display color.
+    pub rgb_color:   [u8; 3],
+    /// Alias, UTF-8 padded with zero bytes.
+    pub alias:       [u8; 32],
+    /// Encoded network addresses.
+    pub addresses:   Vec<u8>,
+    /// Bytes after the known fields, from later protocol versions; signed too.
+    pub excess_data: Vec<u8>,
+}
+
+impl NodeAnnouncement {
+    /// Digest the signature commits to.
+    #[must_use]
+    pub fn signed_digest(&self) -> [u8; 32] {
+        let mut data = Vec::new();
+        self.encode_unsigned(&mut data);
+        sha256d(&data)
+    }
+
+    /// Verify the node signature.
+    pub fn verify(&self) -> Result<(), GossipError> {
+        verify(&self.node_id, &self.signed_digest(), &self.signature)
+    }
+
+    /// Alias with zero padding removed.
+    #[must_use]
+    pub fn alias_string(&self) -> String {
+        let len = self.alias.iter().position(|&b| b == 0).unwrap_or(self.alias.len());
+        String::from_utf8_lossy(&self.alias[..len]).into_owned()
+    }
+
+    fn encode_unsigned(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.features.len() as u16);
+        out.extend_from_slice(&self.features);
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out.extend_from_slice(&self.node_id);
+        out.extend_from_slice(&self.rgb_color);
+        out.extend_from_slice(&self.alias);
+        write_u16(out, self.addresses.len() as u16);
+        out.extend_from_slice(&self.addresses);
+        out.extend_from_slice(&self.excess_data);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, GossipError> {
+        Ok(Self {
+            signature:   reader.array()?,
+            features:    reader.var_bytes()?,
+            timestamp:   reader.u32()?,
+            node_id:     reader.array()?,
+            rgb_color:   reader.array()?,
+            alias:       reader.array()?,
+            addresses:   reader.var_bytes()?,
+            excess_data: reader.rest(),
+        })
+    }
+}
+
+/// Forwarding policy update for one direction of a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelUpdate {
+    /// Signature of the originating node.
+    pub signature:                   [u8; 64],
+    /// Chain the channel is funded on.
+    pub chain_hash:                  [u8; 32],
+    /// Channel being updated.
+    pub short_channel_id:            u64,
+    /// Update time; later updates replace earlier ones.
+    pub timestamp:                   u32,
+    /// Message flags.
+    pub message_flags:               u8,
+    /// Direction and disable flags.
+    pub channel_flags:               u8,
+    /// CLTV delta the originating node requires.
+    pub cltv_expiry_delta:           u16,
+    /// Smallest HTLC accepted, in millisatoshis.
+    pub htlc_minimum_msat:           u64,
+    /// Base fee in millisatoshis.
+    pub fee_base_msat:               u32,
+    /// Proportional fee in millionths.
+    pub fee_proportional_millionths: u32,
+    /// Largest HTLC accepted, in millisatoshis.
+    pub htlc_maximum_msat:           u64,
+    /// Bytes after the known fields, from later protocol versions; signed too.
+    pub excess_data:                 Vec<u8>,
+}
+
+impl ChannelUpdate {
+    /// Check whether the update is for forwarding from `node_id_1`.
+    #[must_use]
+    pub fn from_node_one(&self) -> bool {
+        self.channel_flags & CHANNEL_FLAG_DIRECTION == 0
+    }
+
+    /// Check whether the originating node disabled the direction.
+    #[must_use]
+    pub fn is_disabled(&self) -> bool {
+        self.channel_flags & CHANNEL_FLAG_DISABLED != 0
+    }
+
+    /// Forwarding policy announced by the update.
+    #[must_use]
+    pub fn policy(&self) -> ChannelPolicy {
+        ChannelPolicy {
+            fee_base_msat:               self.fee_base_msat,
+            fee_proportional_millionths: self.fee_proportional_millionths,
+            cltv_expiry_delta:           self.cltv_expiry_delta,
+            htlc_minimum_msat:           self.htlc_minimum_msat,
+            htlc_maximum_msat:           self.htlc_maximum_msat,
+            enabled:                     !self.is_disabled(),
+            last_update:                 self.timestamp,
+        }
+    }
+
+    /// Digest the signature commits to.
+    #[must_use]
+    pub fn signed_digest(&self) -> [u8; 32] {
+        let mut data = Vec::new();
+        self.encode_unsigned(&mut data);
+        sha256d(&data)
+    }
+
+    /// Verify the signature against the originating node's key.
+    pub fn verify(&self, node_id: &[u8; 33]) -> Result<(), GossipError> {
+        verify(node_id, &self.signed_digest(), &self.signature)
+    }
+
+    fn encode_unsigned(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.chain_hash);
+        out.extend_from_slice(&self.short_channel_id.to_be_bytes());
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out.push(self.message_flags);
+        out.push(self.channel_flags);
+        write_u16(out, self.cltv_expiry_delta);
+        out.extend_from_slice(&self.htlc_minimum_msat.to_be_bytes());
+        out.extend_from_slice(&self.fee_base_msat.to_be_bytes());
+        out.extend_from_slice(&self.fee_proportional_millionths.to_be_bytes());
+        out.extend_from_slice(&self.htlc_maximum_msat.to_be_bytes());
+        out.extend_from_slice(&self.excess_data);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, GossipError> {
+        Ok(Self {
+            signature:                   reader.array()?,
+            chain_hash:                  reader.array()?,
+            short_channel_id:            reader.u64()?,
+            timestamp:                   reader.u32()?,
+            message_flags:               reader.u8()?,
+            channel_flags:               reader.u8()?,
+            cltv_expiry_delta:           reader.u16()?,
+            htlc_minimum_msat:           reader.u64()?,
+            fee_base_msat:               reader.u32()?,
+            fee_proportional_millionths: reader.u32()?,
+            htlc_maximum_msat:           reader.u64()?,
+            excess_data:                 reader.rest(),
+        })
+    }
+}
+
+/// Gossip message as received from a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipMessage {
+    /// `channel_announcement`.
+    ChannelAnnouncement(Box<ChannelAnnouncement>),
+    /// `node_announcement`.
+    NodeAnnouncement(NodeAnnouncement),
+    /// `channel_update`.
+    ChannelUpdate(ChannelUpdate),
+}
+
+impl GossipMessage {
+    /// Parse a wire message, starting with its two-byte type.
+    ///
+    /// Trailing bytes are kept as the message's `excess_data`, as later protocol
+    /// versions may append fields that the signatures also cover.
+    pub fn parse(bytes: &[u8]) -> Result<Self, GossipError> {
+        let mut reader = Reader::new(bytes);
+        match reader.u16()? {
+            CHANNEL_ANNOUNCEMENT_TYPE => {
+                Ok(Self::ChannelAnnouncement(Box::new(ChannelAnnouncement::read(&mut reader)?)))
+            },
+            NODE_ANNOUNCEMENT_TYPE => {
+                Ok(Self::NodeAnnouncement(NodeAnnouncement::read(&mut reader)?))
+            },
+            CHANNEL_UPDATE_TYPE => Ok(Self::ChannelUpdate(ChannelUpdate::read(&mut reader)?)),
+            ty => Err(GossipError::UnknownMessageType(ty)),
+        }
+    }
+
+    /// Serialize the message, including its type.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Self::ChannelAnnouncement(msg) => {
+                write_u16(&mut out, CHANNEL_ANNOUNCEMENT_TYPE);
+                msg.encode_into(&mut out);
+            },
+            Self::NodeAnnouncement(msg) => {
+                write_u16(&mut out, NODE_ANNOUNCEMENT_TYPE);
+                out.extend_from_slice(&msg.signature);
+                msg.encode_unsigned(&mut out);
+            },
+            Self::ChannelUpdate(msg) => {
+                write_u16(&mut out, CHANNEL_UPDATE_TYPE);
+                out.extend_from_slice(&msg.signature);
+                msg.encode_unsigned(&mut out);
+            },
+        }
+        out
+    }
+}
+
+/// Outcome of applying a gossip message to the graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GossipOutcome {
+    /// Message changed the graph.
+    Applied,
+    /// Message is not newer than what the graph already holds.
+    Stale,
+    /// Message refers to a channel or node the graph does not know.
+    Unknown,
+}
+
+/// Verify a compact signature over `digest` by the compressed key `pubkey`.
+fn verify(pubkey: &[u8; 33], digest: &[u8; 32], signature: &[u8; 64]) -> Result<(), GossipError> {
+    let pubkey = PublicKey::from_slice(pubkey).map_err(|_| GossipError::InvalidPublicKey)?;
+    let signature =
+        Signature::from_compact(signature).map_err(|_| GossipError::InvalidSignature)?;
+    if pubkey.verify_ecdsa(digest, &signature) {
+        Ok(())
+    } else {
+        Err(GossipError::InvalidSignature)
+    }
+}
+
+fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Big-endian cursor over a wire message.
+pub(super) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Number of unread bytes.
+    pub(super) fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub(super) fn array<const N: usize>(&mut self) -> Result<[u8; N], GossipError> {
+        let (head, rest) = self.bytes.split_first_chunk::<N>().ok_or(GossipError::Truncated)?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, GossipError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub(super) fn u16(&mut self) -> Result<u16, GossipError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, GossipError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, GossipError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    /// Read a BOLT1 `BigSize`, rejecting non-minimal encodings.
+    pub(super) fn big_size(&mut self) -> Result<u64, GossipError> {
+        let non_minimal = GossipError::Invalid("non-minimal big size");
+        match self.u8()? {
+            0xfd => match self.u16()? {
+                value if value < 0xfd => Err(non_minimal),
+                value => Ok(u64::from(value)),
+            },
+            0xfe => match self.u32()? {
+                value if value <= 0xffff => Err(non_minimal),
+                value => Ok(u64::from(value)),
+            },
+            0xff => match self.u64()? {
+                value if value <= 0xffff_ffff => Err(non_minimal),
+                value => Ok(value),
+            },
+            value => Ok(u64::from(value)),
+        }
+    }
+
+    /// Take every unread byte.
+    pub(super) fn rest(&mut self) -> Vec<u8> {
+        let rest = self.bytes.to_vec();
+        self.bytes = &[];
+        rest
+    }
+
+    pub(super) fn var_bytes(&mut self) -> Result<Vec<u8>, GossipError> {
+        let len = usize::from(self.u16()?);
+        if self.bytes.len() < len {
+            return Err(GossipError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head.to_vec())
+    }
+
+    /// Read a `BigSize` index into `node_ids`.
+    pub(super) fn node_id(&mut self, node_ids: &[[u8; 33]]) -> Result<[u8; 33], GossipError> {
+        let index = usize::try_from(self.big_size()?).unwrap_or(usize::MAX);
+        node_ids.get(index).copied().ok_or(GossipError::Invalid("node index out of range"))
+    }
+
+    /// Read a field present only when `flag` is set in `flags`.
+    pub(super) fn field<T>(
+        &mut self, flags: u8, flag: u8, read: fn(&mut Self) -> Result<T, GossipError>,
+    ) -> Result<Option<T>, GossipError> {
+        if flags & flag == 0 {
+            Ok(None)
+        } else {
+            read(self).map(Some)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{crypto::SecretKey, implementation::PaymentRouter, types::Network};
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn sign(key: &SecretKey, digest: &[u8; 32]) -> [u8; 64] {
+        key.sign_ecdsa(digest).serialize_compact()
+    }
+
+    /// Channel announcement between the nodes of `a` and `b`, signed by both.
+    fn announcement(a: &SecretKey, b: &SecretKey, short_channel_id: u64) -> ChannelAnnouncement {
+        let (one, two) =
+            if a.public_key().serialize() < b.public_key().serialize() { (a, b) } else { (b, a) };
+        let (funding_one, funding_two) = (key(0x51), key(0x52));
+        let mut msg = ChannelAnnouncement {
+            node_signature_1: [0; 64],
+            node_signature_2: [0; 64],
+            bitcoin_signature_1: [0; 64],
+            bitcoin_signature_2: [0; 64],
+            features: Vec::new(),
+            chain_hash: Network::Regtest.chain_hash(),
+            short_channel_id,
+            node_id_1: one.public_key().serialize(),
+            node_id_2: two.public_key().serialize(),
+            bitcoin_key_1: funding_one.public_key().serialize(),
+            bitcoin_key_2: funding_two.public_key().serialize(),
+            excess_data: Vec::new(),
+        };
+        let digest = msg.signed_digest();
+        msg.node_signature_1 = sign(one, &digest);
+        msg.node_signature_2 = sign(two, &digest);
+        msg.bitcoin_signature_1 = sign(&funding_one, &digest);
+        msg.bitcoin_signature_2 = sign(&funding_two, &digest);
+        msg
+    }
+
+    fn update(
+        origin: &SecretKey, from_node_one: bool, short_channel_id: u64, timestamp: u32,
+        fee_base_msat: u32,
+    ) -> ChannelUpdate {
+        let mut msg = ChannelUpdate {
+            signature: [0; 64],
+            chain_hash: Network::Regtest.chain_hash(),
+            short_channel_id,
+            timestamp,
+            message_flags: 1,
+            channel_flags: u8::from(!from_node_one),
+            cltv_expiry_delta: 40,
+            htlc_minimum_msat: 1,
+            fee_base_msat,
+            fee_proportional_millionths: 10,
+            htlc_maximum_msat: 1_000_000_000,
+            excess_data: Vec::new(),
+        };
+        msg.signature = sign(origin, &msg.signed_digest());
+        msg
+    }
+
+    fn node_announcement(node: &SecretKey, timestamp: u32, alias: &str) -> NodeAnnouncement {
+        let mut padded = [0u8; 32];
+        padded[..alias.len()].copy_from_slice(alias.as_bytes());
+        let mut msg = NodeAnnouncement {
+            signature: [0; 64],
+            features: vec![0x02],
+            timestamp,
+            node_id: node.public_key().serialize(),
+            rgb_color: [0xf7, 0x93, 0x1a],
+            alias: padded,
+            addresses: Vec::new(),
+            excess_data: Vec::new(),
+        };
+        msg.signature = sign(node, &msg.signed_digest());
+        msg
+    }
+
+    fn router() -> PaymentRouter {
+        let mut router = PaymentRouter::new();
+        router.set_network(Network::Regtest);
+        router
+    }
+
+    fn is_node_one(node: &SecretKey, other: &SecretKey) -> bool {
+        node.public_key().serialize() < other.public_key().serialize()
+    }
+
+    #[test]
+    fn test_messages_round_trip_and_verify() {
+        let (a, b) = (key(1), key(2));
+        let messages = [
+            GossipMessage::ChannelAnnouncement(Box::new(announcement(&a, &b, 42))),
+            GossipMessage::ChannelUpdate(update(&a, is_node_one(&a, &b), 42, 100, 1000)),
+            GossipMessage::NodeAnnouncement(node_announcement(&a, 100, "alice")),
+        ];
+        for message in messages {
+            let encoded = message.encode();
+            assert_eq!(GossipMessage::parse(&encoded).unwrap(), message);
+            assert_eq!(
+                GossipMessage::parse(&encoded[..encoded.len() - 1]),
+                Err(GossipError::Truncated)
+            );
+        }
+
+        let mut forged = announcement(&a, &b, 42);
+        forged.short_channel_id = 43;
+        assert_eq!(forged.verify(), Err(GossipError::InvalidSignature));
+        assert_eq!(GossipMessage::parse(&[0x01, 0x03]), Err(GossipError::UnknownMessageType(259)));
+
+        // Extension fields after the known ones are covered by the signature.
+        let mut extended = update(&a, true, 42, 100, 1000);
+        extended.excess_data = vec![0xfe, 0x01];
+        extended.signature = sign(&a, &extended.signed_digest());
+        let mut wire = GossipMessage::ChannelUpdate(update(&a, true, 42, 100, 1000)).encode();
+        wire[2..66].copy_from_slice(&extended.signature);
+        wire.extend_from_slice(&extended.excess_data);
+        let Ok(GossipMessage::ChannelUpdate(parsed)) = GossipMessage::parse(&wire) else {
+            panic!("channel_update with extension bytes did not parse");
+        };
+        assert_eq!(parsed, extended);
+        assert_eq!(parsed.verify(&a.public_key().serialize()), Ok(()));
+        assert_eq!(GossipMessage::ChannelUpdate(parsed).encode(), wire);
+    }
+
+    #[test]
+    fn test_router_ingests_gossip() {
+        let (a, b) = (key(1), key(2));
+        let mut router = router();
+
+        let channel = GossipMessage::ChannelAnnouncement(Box::new(announcement(&a, &b, 42)));
+        assert_eq!(
+            router.handle_gossip_message(&channel.encode()).unwrap(),
+            GossipOutcome::Applied
+        );
+        assert_eq!(router.handle_gossip_message(&channel.encode()).unwrap(), GossipOutcome::Stale);
+
+        let a_is_one = is_node_one(&a, &b);
+        let fresh = GossipMessage::ChannelUpdate(update(&a, a_is_one, 42, 200, 1000));
+        let stale = GossipMessage::ChannelUpdate(update(&a, a_is_one, 42, 100, 5));
+        assert_eq!(router.handle_gossip_message(&fresh.encode()).unwrap(), GossipOutcome::Applied);
+        assert_eq!(router.handle_gossip_message(&stale.encode()).unwrap(), GossipOutcome::Stale);
+
+        let policy = router.graph().channel(42).unwrap().policy_from(&a.public_key().serialize());
+        assert_eq!(policy.unwrap().fee_base_msat, 1000);
+        assert!(router
+            .find_route_from(&a.public_key().serialize(), &b.public_key().serialize(), 10_000)
+            .is_ok());
+
+        // An update for a's direction signed by b is rejected.
+        let forged = update(&b, a_is_one, 42, 300, 0);
+        assert!(router.handle_channel_update(&forged).is_err());
+
+        let unknown = update(&a, a_is_one, 99, 300, 0);
+        assert_eq!(router.handle_channel_update(&unknown).unwrap(), GossipOutcome::Unknown);
+    }
+
+    #[test]
+    fn test_node_announcements_expose_alias_and_color() {
+        let (a, b, c) = (key(1), key(2), key(3));
+        let mut router = router();
+        router.handle_channel_announcement(&announcement(&a, &b, 42)).unwrap();
+
+        let newer = node_announcement(&a, 200, "alice");
+        let older = node_announcement(&a, 100, "old alias");
+        assert_eq!(router.handle_node_announcement(&newer).unwrap(), GossipOutcome::Applied);
+        assert_eq!(router.handle_node_announcement(&older).unwrap(), GossipOutcome::Stale);
+
+        let info = router.node_info(&a.public_key().serialize()).unwrap();
+        assert_eq!(info.alias, "alice");
+        assert_eq!(info.color, [0xf7, 0x93, 0x1a]);
+        assert_eq!(router.announced_nodes().len(), 1);
+
+        // Nodes without channels are not tracked.
+        let stranger = node_announcement(&c, 100, "carol");
+        assert_eq!(router.handle_node_announcement(&stranger).unwrap(), GossipOutcome::Unknown);
+    }
+
+    #[test]
+    fn test_rejects_other_chains() {
+        let (a, b) = (key(1), key(2));
+        let mut router = PaymentRouter::new();
+        assert!(router.handle_channel_announcement(&announcement(&a, &b, 42)).is_err());
+    }
+
+    #[test]
+    fn test_prunes_channels_without_recent_updates() {
+        let (a, b, c) = (key(1), key(2), key(3));
+        let mut router = router();
+        router.handle_channel_announcement(&announcement(&a, &b, 1)).unwrap();
+        router.handle_channel_announcement(&announcement(&a, &c, 2)).unwrap();
+
+        let now = 1_700_000_000u64;
+        let old = (now - 15 * DAY) as u32;
+        let recent = (now - DAY) as u32;
+        router.handle_channel_update(&update(&a, is_node_one(&a, &b), 1, old, 0)).unwrap();
+        router.handle_channel_update(&update(&a, is_node_one(&a, &c), 2, old, 0)).unwrap();
+        router.handle_channel_update(&update(&c, is_node_one(&c, &a), 2, recent, 0)).unwrap();
+
+        assert_eq!(router.prune_stale_channels(now), 1);
+        assert!(router.graph().channel(1).is_none());
+        assert!(router.graph().channel(2).is_some());
+        assert!(router.graph().node(&b.public_key().serialize()).is_none());
+    }
+}
@@ -0,0 +1,307 @@
+//! In-memory Lightning Network channel graph.
+
+use std::collections::HashMap;
+
+use crate::errors::{PaymentError, PaymentResult};
+
+/// Forwarding policy for one direction of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelPolicy {
+    /// Base fee in millisatoshis.
+    pub fee_base_msat:               u32,
+    /// Proportional fee in millionths of the forwarded amount.
+    pub fee_proportional_millionths: u32,
+    /// CLTV delta the forwarding node requires.
+    pub cltv_expiry_delta:           u16,
+    /// Smallest HTLC accepted, in millisatoshis.
+    pub htlc_minimum_msat:           u64,
+    /// Largest HTLC accepted, in millisatoshis.
+    pub htlc_maximum_msat:           u64,
+    /// Whether this direction currently forwards payments.
+    pub enabled:                     bool,
+    /// Timestamp of the `channel_update` this policy came from, zero if set locally.
+    pub last_update:                 u32,
+}
+
+impl ChannelPolicy {
+    /// Fee charged for forwarding `amount_msat` through this direction.
+    #[must_use]
+    pub fn fee_msat(&self, amount_msat: u64) -> u64 {
+        let proportional =
+            u128::from(amount_msat) * u128::from(self.fee_proportional_millionths) / 1_000_000;
+        u64::from(self.fee_base_msat).saturating_add(proportional.min(u128::from(u64::MAX)) as u64)
+    }
+
+    /// Check whether an HTLC of `amount_msat` may be forwarded through this direction.
+    #[must_use]
+    pub fn allows(&self, amount_msat: u64) -> bool {
+        self.enabled
+            && amount_msat >= self.htlc_minimum_msat
+            && amount_msat <= self.htlc_maximum_msat
+    }
+}
+
+impl Default for ChannelPolicy {
+    fn default() -> Self {
+        Self {
+            fee_base_msat:               1000,
+            fee_proportional_millionths: 1,
+            cltv_expiry_delta:           40,
+            htlc_minimum_msat:           1,
+            htlc_maximum_msat:           u64::MAX,
+            enabled:                     true,
+            last_update:                 0,
+        }
+    }
+}
+
+/// Channel between two nodes in the graph.
+///
+/// Endpoints are ordered so that `node_one` is the lexicographically lesser key,
+/// matching the BOLT7 direction convention.
+#[derive(Debug, Clone)]
+pub struct GraphChannel {
+    /// Short channel ID.
+    pub short_channel_id: u64,
+    /// Lesser endpoint public key.
+    pub node_one:         [u8; 33],
+    /// Greater endpoint public key.
+    pub node_two:         [u8; 33],
+    /// Channel capacity in satoshis, when known.
+    pub capacity_sats:    Option<u64>,
+    /// Policy for forwarding from `node_one` to `node_two`.
+    pub one_to_two:       Option<ChannelPolicy>,
+    /// Policy for forwarding from `node_two` to `node_one`.
+    pub two_to_one:       Option<ChannelPolicy>,
+    /// Unix time the channel announcement was received; `None` if added locally.
+    pub announced_at:     Option<u64>,
+}
+
+impl GraphChannel {
+    /// Get the endpoint opposite `node`, if `node` is an endpoint.
+    #[must_use]
+    pub fn counterparty(&self, node: &[u8; 33]) -> Option<&[u8; 33]> {
+        if *node == self.node_one {
+            Some(&self.node_two)
+        } else if *node == self.node_two {
+            Some(&self.node_one)
+        } else {
+            None
+        }
+    }
+
+    /// Get the policy for forwarding out of `from`.
+    #[must_use]
+    pub fn policy_from(&self, from: &[u8; 33]) -> Option<&ChannelPolicy> {
+        if *from == self.node_one {
+            self.one_to_two.as_ref()
+        } else if *from == self.node_two {
+            self.two_to_one.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Unix time of the most recent gossip about this channel, if it was announced.
+    #[must_use]
+    pub fn last_refreshed(&self) -> Option<u64> {
+        let announced_at = self.announced_at?;
+        let last_update =
+            [self.one_to_two, self.two_to_one].iter().flatten().map(|p| p.last_update).max();
+        Some(last_update.map_or(announced_at, u64::from))
+    }
+}
+
+/// Announced details of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeAnnouncementInfo {
+    /// Timestamp of the `node_announcement` these details came from.
+    pub last_update: u32,
+    /// Node alias.
+    pub alias:       String,
+    /// Display color.
+    pub color:       [u8; 3],
+    /// Node feature bits.
+    pub features:    Vec<u8>,
+    /// Encoded network addresses.
+    pub addresses:   Vec<u8>,
+}
+
+/// Node in the graph.
+#[derive(Debug, Clone, Default)]
+pub struct GraphNode {
+    /// Short channel IDs of channels this node participates in.
+    pub channels:     Vec<u64>,
+    /// Latest announcement, if the node announced itself.
+    pub announcement: Option<NodeAnnouncementInfo>,
+}
+
+/// Directed channel graph keyed by node public key and short channel ID.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkGraph {
+    nodes:    HashMap<[u8; 33], GraphNode>,
+    channels: HashMap<u64, GraphChannel>,
+}
+
+impl NetworkGraph {
+    /// Create an empty graph.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of known nodes.
+    #[must_use]
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Number of known channels.
+    #[must_use]
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Look up a node.
+    #[must_use]
+    pub fn node(&self, pubkey: &[u8; 33]) -> Option<&GraphNode> {
+        self.nodes.get(pubkey)
+    }
+
+    /// Look up a channel.
+    #[must_use]
+    pub fn channel(&self, short_channel_id: u64) -> Option<&GraphChannel> {
+        self.channels.get(&short_channel_id)
+    }
+
+    /// Iterate over the channels of a node.
+    pub fn channels_of<'a>(
+        &'a self, pubkey: &[u8; 33],
+    ) -> impl Iterator<Item = &'a GraphChannel> + 'a {
+        self.nodes
+            .get(pubkey)
+            .into_iter()
+            .flat_map(|node| node.channels.iter())
+            .filter_map(|scid| self.channels.get(scid))
+    }
+
+    /// Add a node if it is not already known.
+    pub fn add_node(&mut self, pubkey: [u8; 33]) {
+        self.nodes.entry(pubkey).or_default();
+    }
+
+    /// Add a channel without policies; existing channels keep their policies.
+    pub fn add_channel(
+        &mut self, node_a: [u8; 33], node_b: [u8; 33], short_channel_id: u64,
+        capacity_sats: Option<u64>,
+    ) -> PaymentResult<()> {
+        if node_a == node_b {
+            return Err(PaymentError::Routing("Channel endpoints must differ".into()));
+        }
+        let (node_one, node_two) =
+            if node_a < node_b { (node_a, node_b) } else { (node_b, node_a) };
+
+        if let Some(existing) = self.channels.get_mut(&short_channel_id) {
+            if existing.node_one != node_one || existing.node_two != node_two {
+                return Err(PaymentError::Routing(
+                    format!("Channel {short_channel_id} already exists with different endpoints")
+                        .into(),
+                ));
+            }
+            if capacity_sats.is_some() {
+                existing.capacity_sats = capacity_sats;
+            }
+            return Ok(());
+        }
+
+        self.channels.insert(
+            short_channel_id,
+            GraphChannel {
+                short_channel_id,
+                node_one,
+                node_two,
+                capacity_sats,
+                one_to_two: None,
+                two_to_one: None,
+                announced_at: None,
+            },
+        );
+        for node in [node_one, node_two] {
+            self.nodes.entry(node).or_default().channels.push(short_channel_id);
+        }
+        Ok(())
+    }
+
+    /// Iterate over all nodes.
+    pub fn nodes(&self) -> impl Iterator<Item = (&[u8; 33], &GraphNode)> {
+        self.nodes.iter()
+    }
+
+    /// Record when a channel's announcement was received.
+    pub fn mark_announced(&mut self, short_channel_id: u64, received_at: u64) -> PaymentResult<()> {
+        let channel = self.channels.get_mut(&short_channel_id).ok_or_else(|| {
+            PaymentError::Routing(format!("Unknown channel {short_channel_id}").into())
+        })?;
+        channel.announced_at = Some(received_at);
+        Ok(())
+    }
+
+    /// Replace a node's announced details; returns `false` if the node is unknown.
+    pub fn set_node_announcement(&mut self, pubkey: &[u8; 33], info: NodeAnnouncementInfo) -> bool {
+        match self.nodes.get_mut(pubkey) {
+            Some(node) => {
+                node.announcement = Some(info);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Short channel IDs of announced channels not refreshed within `max_age_secs` of `now`.
+    #[must_use]
+    pub fn stale_channels(&self, now: u64, max_age_secs: u64) -> Vec<u64> {
+        self.channels
+            .values()
+            .filter(|channel| {
+                channel
+                    .last_refreshed()
+                    .is_some_and(|refreshed| now.saturating_sub(refreshed) > max_age_secs)
+            })
+            .map(|channel| channel.short_channel_id)
+            .collect()
+    }
+
+    /// Set the policy for forwarding over a channel out of `from`.
+    pub fn update_policy(
+        &mut self, short_channel_id: u64, from: &[u8; 33], policy: ChannelPolicy,
+    ) -> PaymentResult<()> {
+        let channel = self.channels.get_mut(&short_channel_id).ok_or_else(|| {
+            PaymentError::Routing(format!("Unknown channel {short_channel_id}").into())
+        })?;
+
+        if *from == channel.node_one {
+            channel.one_to_two = Some(policy);
+        } else if *from == channel.node_two {
+            channel.two_to_one = Some(policy);
+        } else {
+            return Err(PaymentError::Routing(
+                format!("Node is not an endpoint of channel {short_channel_id}").into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Remove a channel, dropping nodes left without channels.
+    pub fn remove_channel(&mut self, short_channel_id: u64) -> Option<GraphChannel> {
+        let channel = self.channels.remove(&short_channel_id)?;
+        for node in [channel.node_one, channel.node_two] {
+            if let Some(entry) = self.nodes.get_mut(&node) {
+                entry.channels.retain(|&scid| scid != short_channel_id);
+                if entry.channels.is_empty() {
+                    self.nodes.remove(&node);
+                }
+            }
+        }
+        Some(channel)
+    }
+}
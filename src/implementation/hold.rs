@@ -0,0 +1,229 @@
+//! Hold invoices.
+//!
+//! A hold invoice is created from a payment hash chosen by the caller, who
+//! keeps the preimage. Incoming HTLCs are accepted and held instead of being
+//! settled, so the funds stay locked until the caller settles with the
+//! preimage or cancels. This backs [`EscrowType::LightningHold`].
+
+use crate::{
+    errors::{PaymentError, PaymentResult},
+    types::{EscrowType, LightningInvoice, PaymentHash, PaymentPreimage, PaymentStatus},
+};
+
+/// Blocks before the earliest held HTLC expires at which the invoice is cancelled.
+///
+/// Leaves time to fail the HTLCs back before the peer has to go on-chain.
+pub const HOLD_INVOICE_CANCEL_DELTA: u32 = 10;
+
+/// Hold invoice state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldInvoiceState {
+    /// Waiting for HTLCs covering the amount.
+    Open,
+    /// HTLCs covering the amount arrived and are held.
+    Accepted,
+    /// Settled with the preimage; held HTLCs were fulfilled.
+    Settled,
+    /// Cancelled; held HTLCs were failed back.
+    Canceled,
+}
+
+/// Incoming HTLC held for a hold invoice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeldHtlc {
+    /// Channel the HTLC arrived on.
+    pub channel_id:  [u8; 32],
+    /// Received HTLC id within the channel.
+    pub htlc_id:     u64,
+    /// Amount in millisatoshis.
+    pub amount_msat: u64,
+    /// Block height at which the HTLC times out.
+    pub cltv_expiry: u32,
+}
+
+/// Invoice whose HTLCs are held until explicitly settled or cancelled.
+#[derive(Debug, Clone)]
+pub struct HoldInvoice {
+    invoice:  LightningInvoice,
+    state:    HoldInvoiceState,
+    htlcs:    Vec<HeldHtlc>,
+    preimage: Option<PaymentPreimage>,
+}
+
+impl HoldInvoice {
+    /// Track a freshly issued invoice.
+    #[must_use]
+    pub fn new(invoice: LightningInvoice) -> Self {
+        Self { invoice, state: HoldInvoiceState::Open, htlcs: Vec::new(), preimage: None }
+    }
+
+    /// Invoice as handed to the payer.
+    #[must_use]
+    pub fn invoice(&self) -> &LightningInvoice {
+        &self.invoice
+    }
+
+    /// Payment hash supplied by the creator.
+    #[must_use]
+    pub fn payment_hash(&self) -> &PaymentHash {
+        &self.invoice.payment_hash
+    }
+
+    /// Current state.
+    #[must_use]
+    pub fn state(&self) -> HoldInvoiceState {
+        self.state
+    }
+
+    /// HTLCs held so far.
+    #[must_use]
+    pub fn htlcs(&self) -> &[HeldHtlc] {
+        &self.htlcs
+    }
+
+    /// Preimage the invoice was settled with.
+    #[must_use]
+    pub fn preimage(&self) -> Option<&PaymentPreimage> {
+        self.preimage.as_ref()
+    }
+
+    /// Amount held across HTLCs, in millisatoshis.
+    #[must_use]
+    pub fn received_msat(&self) -> u64 {
+        self.htlcs.iter().map(|htlc| htlc.amount_msat).sum()
+    }
+
+    /// Earliest CLTV expiry among held HTLCs.
+    #[must_use]
+    pub fn cltv_deadline(&self) -> Option<u32> {
+        self.htlcs.iter().map(|htlc| htlc.cltv_expiry).min()
+    }
+
+    /// Whether the invoice must be cancelled at `height` to fail its HTLCs back in time.
+    #[must_use]
+    pub fn must_cancel_at(&self, height: u32) -> bool {
+        matches!(self.state, HoldInvoiceState::Open | HoldInvoiceState::Accepted)
+            && self.cltv_deadline().is_some_and(|deadline| {
+                height.saturating_add(HOLD_INVOICE_CANCEL_DELTA) >= deadline
+            })
+    }
+
+    /// Payment status as reported for the invoice.
+    #[must_use]
+    pub fn status(&self) -> PaymentStatus {
+        match self.state {
+            HoldInvoiceState::Open => PaymentStatus::Pending,
+            HoldInvoiceState::Accepted => PaymentStatus::InFlight,
+            HoldInvoiceState::Settled => PaymentStatus::Succeeded,
+            HoldInvoiceState::Canceled => PaymentStatus::Failed,
+        }
+    }
+
+    /// Escrow description of the invoice; carries the preimage once settled.
+    #[must_use]
+    pub fn escrow_type(&self) -> EscrowType {
+        EscrowType::LightningHold {
+            payment_hash: self.invoice.payment_hash,
+            preimage:     self.preimage.map(|preimage| *preimage.as_bytes()),
+        }
+    }
+
+    /// Hold an incoming HTLC, moving to `Accepted` once the amount is covered.
+    pub fn hold(&mut self, htlc: HeldHtlc) -> PaymentResult<HoldInvoiceState> {
+        if !matches!(self.state, HoldInvoiceState::Open | HoldInvoiceState::Accepted) {
+            return Err(PaymentError::Invoice(
+                format!("Hold invoice is {:?}, cannot accept HTLCs", self.state).into(),
+            ));
+        }
+
+        self.htlcs.push(htlc);
+        let amount_msat = self.invoice.amount_sats.unwrap_or(0).saturating_mul(1000);
+        if self.received_msat() >= amount_msat {
+            self.state = HoldInvoiceState::Accepted;
+        }
+        Ok(self.state)
+    }
+
+    /// Settle with the preimage, returning the HTLCs to fulfil.
+    pub fn settle(&mut self, preimage: PaymentPreimage) -> PaymentResult<Vec<HeldHtlc>> {
+        if !preimage.matches(&self.invoice.payment_hash) {
+            return Err(PaymentError::Invoice("Preimage does not match payment hash".into()));
+        }
+        if self.state != HoldInvoiceState::Accepted {
+            return Err(PaymentError::Invoice(
+                format!("Hold invoice is {:?}, only accepted invoices can settle", self.state)
+                    .into(),
+            ));
+        }
+
+        self.state = HoldInvoiceState::Settled;
+        self.preimage = Some(preimage);
+        Ok(self.htlcs.clone())
+    }
+
+    /// Cancel, returning the HTLCs to fail back.
+    pub fn cancel(&mut self) -> PaymentResult<Vec<HeldHtlc>> {
+        if !matches!(self.state, HoldInvoiceState::Open | HoldInvoiceState::Accepted) {
+            return Err(PaymentError::Invoice(
+                format!("Hold invoice is {:?}, cannot cancel", self.state).into(),
+            ));
+        }
+
+        self.state = HoldInvoiceState::Canceled;
+        Ok(self.htlcs.clone())
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+
+    fn invoice(preimage: &PaymentPreimage) -> HoldInvoice {
+        HoldInvoice::new(LightningInvoice {
+            payment_hash:   preimage.payment_hash(),
+            amount_sats:    Some(10_000),
+            description:    "bounty".into(),
+            expiry:         0,
+            bolt11:         String::new(),
+            payment_secret: Some([1; 32]),
+        })
+    }
+
+    fn htlc(htlc_id: u64, amount_msat: u64, cltv_expiry: u32) -> HeldHtlc {
+        HeldHtlc { channel_id: [3; 32], htlc_id, amount_msat, cltv_expiry }
+    }
+
+    #[test]
+    fn test_accepts_once_amount_is_held() {
+        let preimage = PaymentPreimage::new([9; 32]);
+        let mut hold = invoice(&preimage);
+        assert!(hold.settle(preimage).is_err());
+
+        assert_eq!(hold.hold(htlc(0, 4_000_000, 700)).unwrap(), HoldInvoiceState::Open);
+        assert_eq!(hold.hold(htlc(1, 6_000_000, 650)).unwrap(), HoldInvoiceState::Accepted);
+        assert_eq!(hold.cltv_deadline(), Some(650));
+        assert!(!hold.must_cancel_at(639));
+        assert!(hold.must_cancel_at(640));
+
+        assert!(hold.settle(PaymentPreimage::new([8; 32])).is_err());
+        assert_eq!(hold.settle(preimage).unwrap().len(), 2);
+        assert_eq!(hold.status(), PaymentStatus::Succeeded);
+        assert!(matches!(
+            hold.escrow_type(),
+            EscrowType::LightningHold { preimage: Some(bytes), .. } if bytes == [9; 32]
+        ));
+        assert!(hold.cancel().is_err());
+        assert!(!hold.must_cancel_at(700));
+    }
+
+    #[test]
+    fn test_cancel_rejects_later_htlcs() {
+        let preimage = PaymentPreimage::new([9; 32]);
+        let mut hold = invoice(&preimage);
+        hold.hold(htlc(0, 10_000_000, 700)).unwrap();
+        assert_eq!(hold.cancel().unwrap().len(), 1);
+        assert_eq!(hold.state(), HoldInvoiceState::Canceled);
+        assert!(hold.hold(htlc(1, 1_000, 700)).is_err());
+        assert!(hold.settle(preimage).is_err());
+    }
+}
@@ -1,30 +1,80 @@
 //! Invoice generation and management.
 
-use essentia_core::time;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
+    crypto::{random, SecretKey},
     errors::{PaymentError, PaymentResult},
-    implementation::config::PaymentConfig,
-    traits::InvoiceProvider,
-    types::PaymentInvoice,
+    implementation::{
+        bolt11::{Bolt11Invoice, InvoiceDescription},
+        clock::SystemClock,
+        config::PaymentConfig,
+    },
+    traits::{Clock, InvoiceProvider},
+    types::{PaymentHash, PaymentInvoice, PaymentPreimage},
 };
 
+/// Secrets retained for an issued invoice.
+#[derive(Debug, Clone, Copy)]
+struct IssuedSecrets {
+    preimage:       PaymentPreimage,
+    payment_secret: [u8; 32],
+}
+
 /// Invoice generator for creating payment invoices.
 #[derive(Debug)]
 pub struct InvoiceGenerator {
-    config: PaymentConfig,
+    config:   PaymentConfig,
+    /// Key invoices are signed with; its public key is the payee.
+    node_key: SecretKey,
+    /// Secrets of issued invoices, keyed by payment hash.
+    issued:   Mutex<HashMap<PaymentHash, IssuedSecrets>>,
+    /// Time source for invoice timestamps and expiry checks.
+    clock:    Arc<dyn Clock>,
 }
 
 impl InvoiceGenerator {
-    /// Create a new invoice generator.
+    /// Create a new invoice generator with a freshly generated node key.
+    ///
+    /// # Panics
+    ///
+    /// If the operating system CSPRNG is unavailable to generate the node key.
     #[must_use]
     pub fn new(config: PaymentConfig) -> Self {
-        Self { config }
+        Self::with_node_key(config, SecretKey::new_random())
+    }
+
+    /// Create a new invoice generator signing with an existing node key.
+    #[must_use]
+    pub fn with_node_key(config: PaymentConfig, node_key: SecretKey) -> Self {
+        Self::with_clock(config, node_key, Arc::new(SystemClock))
+    }
+
+    /// Create a new invoice generator signing with `node_key` and reading
+    /// time from `clock`.
+    #[must_use]
+    pub fn with_clock(config: PaymentConfig, node_key: SecretKey, clock: Arc<dyn Clock>) -> Self {
+        Self { config, node_key, issued: Mutex::new(HashMap::new()), clock }
+    }
+
+    /// Get the preimage of an invoice issued by this generator.
+    #[must_use]
+    pub fn preimage(&self, payment_hash: &PaymentHash) -> Option<PaymentPreimage> {
+        self.issued.lock().ok()?.get(payment_hash).map(|issued| issued.preimage)
+    }
+
+    /// Get the payment secret of an invoice issued by this generator.
+    #[must_use]
+    pub fn payment_secret(&self, payment_hash: &PaymentHash) -> Option<[u8; 32]> {
+        self.issued.lock().ok()?.get(payment_hash).map(|issued| issued.payment_secret)
     }
 
     /// Verify an invoice.
     pub fn verify(&self, invoice: &PaymentInvoice) -> PaymentResult<bool> {
-        let now = time::unix_seconds_sync();
+        let now = self.clock.now();
 
         if invoice.expiry < now {
             return Err(PaymentError::Invoice("Invoice has expired".into()));
@@ -42,18 +92,36 @@ impl InvoiceProvider for InvoiceGenerator {
             return Err(PaymentError::Invoice("Description cannot be empty".into()));
         }
 
-        // Generate random payment hash
-        let payment_hash = generate_random_bytes();
+        let preimage = PaymentPreimage::try_random()?;
+        let payment_hash = preimage.payment_hash();
+        let payment_secret = random::try_random_bytes()?;
 
         // Calculate expiry
-        let now = time::unix_seconds_sync();
+        let now = self.clock.now();
         let expiry = now + self.config.default_invoice_expiry;
 
-        // In production, this would generate a proper BOLT11 invoice
-        let encoded = format!("lnbc{}...placeholder", amount.unwrap_or(0));
+        let amount_msat = amount
+            .map(|sats| sats.checked_mul(1000))
+            .map(|msat| msat.ok_or_else(|| PaymentError::Invoice("Amount too large".into())))
+            .transpose()?;
+        let mut bolt11 = Bolt11Invoice::new(
+            self.config.network,
+            amount_msat,
+            now,
+            payment_hash.0,
+            InvoiceDescription::Direct(description.to_string()),
+        );
+        bolt11.set_payment_secret(payment_secret);
+        bolt11.expiry_secs = Some(self.config.default_invoice_expiry);
+        let encoded = bolt11.encode(&self.node_key)?;
+
+        self.issued
+            .lock()
+            .map_err(|_| PaymentError::Invoice("Invoice store poisoned".into()))?
+            .insert(payment_hash, IssuedSecrets { preimage, payment_secret });
 
         Ok(PaymentInvoice {
-            payment_hash,
+            payment_hash: payment_hash.0,
             amount,
             description: description.to_string(),
             expiry,
@@ -65,15 +133,3 @@ impl InvoiceProvider for InvoiceGenerator {
         self.verify(invoice)
     }
 }
-
-/// Generate random bytes for payment hash.
-fn generate_random_bytes() -> [u8; 32] {
-    // Simple PRNG for placeholder - use proper entropy in production
-    let mut bytes = [0u8; 32];
-    let seed = time::unix_nanos_sync();
-
-    for (i, byte) in bytes.iter_mut().enumerate() {
-        *byte = ((seed >> (i % 16)) & 0xFF) as u8;
-    }
-    bytes
-}
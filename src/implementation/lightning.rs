@@ -1,41 +1,85 @@
 //! Lightning Network integration for the payment plugin.
 
 use crate::{
+    crypto::{random, SecretKey},
     errors::{PaymentError, PaymentResult},
-    types::{LightningInvoice, LightningNode, PaymentHash, PaymentStatus},
+    implementation::{
+        bolt11::{Bolt11Invoice, InvoiceDescription, DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA},
+        channels::{ChannelLimits, ChannelManager},
+        hold::{HeldHtlc, HoldInvoice, HOLD_INVOICE_CANCEL_DELTA},
+        mpp::MultiPathPayment,
+    },
+    traits::ChannelProvider,
+    types::{
+        HtlcDirection, HtlcState, LightningInvoice, LightningNode, Network, PaymentChannel,
+        PaymentHash, PaymentPreimage, PaymentRoute, PaymentStatus,
+    },
 };
 
+/// Invoice issued by this node together with the preimage that settles it
+#[derive(Debug, Clone)]
+struct InvoiceRecord {
+    /// Invoice as handed to the payer
+    invoice:  LightningInvoice,
+    /// Preimage revealed on settlement
+    preimage: PaymentPreimage,
+    /// Whether an HTLC has paid the invoice
+    settled:  bool,
+}
+
 /// Lightning Network node implementation
 #[derive(Debug)]
 pub struct LightningNodeImpl {
+    /// Node secret key, used to sign invoices
+    secret_key:    SecretKey,
     /// Node public key
-    pubkey:   [u8; 33],
+    pubkey:        [u8; 33],
     /// Node alias
-    alias:    String,
-    /// Active channels
-    channels: std::collections::HashMap<[u8; 32], crate::types::PaymentChannel>,
+    alias:         String,
+    /// Network invoices are issued for
+    network:       Network,
+    /// Channel store, the single source of truth for balances
+    channels:      ChannelManager,
     /// Pending invoices
-    invoices: std::collections::HashMap<PaymentHash, LightningInvoice>,
+    invoices:      std::collections::HashMap<PaymentHash, InvoiceRecord>,
+    /// Outgoing payments, tracked per part
+    outbound:      std::collections::HashMap<PaymentHash, MultiPathPayment>,
+    /// Hold invoices, settled or cancelled by their creator
+    hold_invoices: std::collections::HashMap<PaymentHash, HoldInvoice>,
+    /// Offered HTLC (channel, HTLC id) carrying each outgoing payment part
+    part_htlcs:    std::collections::HashMap<(PaymentHash, u32), ([u8; 32], u64)>,
 }
 
 impl LightningNodeImpl {
-    /// Create new Lightning node
+    /// Create new Lightning node with a freshly generated key on mainnet
+    ///
+    /// # Panics
+    ///
+    /// If the operating system CSPRNG is unavailable to generate the node key.
     pub fn new(alias: String) -> Self {
-        // Generate a random pubkey for demo purposes
-        let mut pubkey = [0u8; 33];
-        pubkey[0] = 0x02; // Compressed pubkey prefix
-        for (i, item) in pubkey.iter_mut().enumerate().skip(1) {
-            *item = (i as u8).wrapping_mul(7);
-        }
+        Self::with_secret_key(alias, SecretKey::new_random(), Network::Bitcoin)
+    }
 
+    /// Create new Lightning node from an existing node key
+    pub fn with_secret_key(alias: String, secret_key: SecretKey, network: Network) -> Self {
         Self {
-            pubkey,
+            secret_key,
+            pubkey: secret_key.public_key().serialize(),
             alias,
-            channels: std::collections::HashMap::new(),
+            network,
+            channels: ChannelManager::new(),
             invoices: std::collections::HashMap::new(),
+            outbound: std::collections::HashMap::new(),
+            hold_invoices: std::collections::HashMap::new(),
+            part_htlcs: std::collections::HashMap::new(),
         }
     }
 
+    /// Get the node secret key
+    pub(crate) fn secret_key(&self) -> &SecretKey {
+        &self.secret_key
+    }
+
     /// Get node info
     pub fn get_node_info(&self) -> LightningNode {
         LightningNode {
@@ -49,111 +93,638 @@ impl LightningNodeImpl {
     pub async fn create_invoice(
         &mut self, amount_sats: u64, description: &str, expiry_secs: u64,
     ) -> PaymentResult<LightningInvoice> {
-        // Generate payment hash
-        let mut payment_hash_bytes = [0u8; 32];
-        for (i, item) in payment_hash_bytes.iter_mut().enumerate() {
-            *item = (i as u8).wrapping_add((amount_sats % 255) as u8);
-        }
-        let payment_hash = PaymentHash::new(payment_hash_bytes);
-
-        // Create BOLT11-like encoded string (simplified)
-        let bolt11 = format!(
-            "lnbc{}n1{}",
-            amount_sats,
-            bytes_to_hex(&payment_hash_bytes[..16])
+        let preimage = PaymentPreimage::try_random()?;
+        let invoice =
+            self.issue_invoice(preimage.payment_hash(), amount_sats, description, expiry_secs)?;
+        self.invoices.insert(
+            invoice.payment_hash,
+            InvoiceRecord { invoice: invoice.clone(), preimage, settled: false },
         );
+        Ok(invoice)
+    }
+
+    /// Create a hold invoice for a payment hash whose preimage the caller keeps
+    ///
+    /// Incoming HTLCs are held until [`Self::settle_hold_invoice`] or
+    /// [`Self::cancel_hold_invoice`] is called.
+    pub async fn create_hold_invoice(
+        &mut self, payment_hash: PaymentHash, amount_sats: u64, description: &str, expiry_secs: u64,
+    ) -> PaymentResult<LightningInvoice> {
+        if self.invoices.contains_key(&payment_hash)
+            || self.hold_invoices.contains_key(&payment_hash)
+        {
+            return Err(PaymentError::Invoice(
+                "An invoice for this payment hash already exists".to_string().into(),
+            ));
+        }
+
+        let invoice = self.issue_invoice(payment_hash, amount_sats, description, expiry_secs)?;
+        self.hold_invoices.insert(payment_hash, HoldInvoice::new(invoice.clone()));
+        Ok(invoice)
+    }
+
+    /// Encode and sign an invoice for a payment hash
+    fn issue_invoice(
+        &self, payment_hash: PaymentHash, amount_sats: u64, description: &str, expiry_secs: u64,
+    ) -> PaymentResult<LightningInvoice> {
+        let payment_secret = random::try_random_bytes()?;
+
+        let now = unix_now()?;
+        let amount_msat = amount_sats
+            .checked_mul(1000)
+            .ok_or_else(|| PaymentError::Invoice("Amount too large".into()))?;
 
-        let invoice = LightningInvoice {
+        let mut encoder = Bolt11Invoice::new(
+            self.network,
+            Some(amount_msat),
+            now,
+            payment_hash.0,
+            InvoiceDescription::Direct(description.to_string()),
+        );
+        encoder.set_payment_secret(payment_secret);
+        encoder.expiry_secs = Some(expiry_secs);
+        let bolt11 = encoder.encode(&self.secret_key)?;
+
+        Ok(LightningInvoice {
             payment_hash,
             amount_sats: Some(amount_sats),
             description: description.to_string(),
-            expiry: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|_| PaymentError::Invoice("Time error".to_string()))?
-                .as_secs()
-                + expiry_secs,
+            expiry: now + expiry_secs,
             bolt11,
-            payment_secret: None,
+            payment_secret: Some(payment_secret),
+        })
+    }
+
+    /// Get a hold invoice issued by this node
+    pub fn hold_invoice(&self, payment_hash: &PaymentHash) -> Option<&HoldInvoice> {
+        self.hold_invoices.get(payment_hash)
+    }
+
+    /// Handle an HTLC offered to us by a peer
+    ///
+    /// HTLCs for hold invoices are held; HTLCs paying a regular invoice in full
+    /// are fulfilled at once. Anything else, including HTLCs carrying the wrong
+    /// payment secret or arriving after the invoice expired, is failed back.
+    pub fn receive_htlc(
+        &mut self, channel_id: &[u8; 32], amount_msat: u64, payment_hash: PaymentHash,
+        payment_secret: &[u8; 32], cltv_expiry: u32,
+    ) -> PaymentResult<u64> {
+        let htlc_id = self.channels.add_htlc(
+            channel_id,
+            HtlcDirection::Received,
+            amount_msat,
+            payment_hash,
+            cltv_expiry,
+        )?;
+
+        let height = self.channels.best_block_height();
+        let now = unix_now()?;
+        let outcome = if let Some(hold) = self.hold_invoices.get_mut(&payment_hash) {
+            if let Err(err) = check_payable(hold.invoice(), payment_secret, now) {
+                Err(err)
+            } else if cltv_expiry <= height.saturating_add(HOLD_INVOICE_CANCEL_DELTA) {
+                Err(PaymentError::Invoice(
+                    format!(
+                        "HTLC expiry {cltv_expiry} is too close to block height {height} to hold"
+                    )
+                    .into(),
+                ))
+            } else {
+                let held = HeldHtlc { channel_id: *channel_id, htlc_id, amount_msat, cltv_expiry };
+                hold.hold(held).map(|_| None)
+            }
+        } else if let Some(record) = self.invoices.get_mut(&payment_hash) {
+            let expected_msat = record.invoice.amount_sats.unwrap_or(0).saturating_mul(1000);
+            if record.settled {
+                Err(PaymentError::Invoice("Invoice has already been paid".into()))
+            } else if let Err(err) = check_payable(&record.invoice, payment_secret, now) {
+                Err(err)
+            } else if amount_msat >= expected_msat {
+                record.settled = true;
+                Ok(Some(record.preimage))
+            } else {
+                Err(PaymentError::Invoice(
+                    format!("HTLC of {amount_msat} msat underpays invoice of {expected_msat} msat")
+                        .into(),
+                ))
+            }
+        } else {
+            Err(PaymentError::Invoice("No invoice for payment hash".into()))
         };
 
-        self.invoices.insert(payment_hash, invoice.clone());
-        Ok(invoice)
+        match outcome {
+            Ok(Some(preimage)) => self.channels.fulfill_htlc(
+                channel_id,
+                HtlcDirection::Received,
+                htlc_id,
+                &preimage,
+            )?,
+            Ok(None) => {},
+            Err(err) => {
+                self.channels.fail_htlc(channel_id, HtlcDirection::Received, htlc_id)?;
+                return Err(err);
+            },
+        }
+        Ok(htlc_id)
+    }
+
+    /// Settle an accepted hold invoice, fulfilling its held HTLCs
+    ///
+    /// Fails without settling if any held HTLC is no longer pending, e.g.
+    /// because it expired, since it can no longer be fulfilled.
+    pub fn settle_hold_invoice(&mut self, preimage: PaymentPreimage) -> PaymentResult<()> {
+        let hold = self
+            .hold_invoices
+            .get_mut(&preimage.payment_hash())
+            .ok_or_else(|| PaymentError::Invoice("Hold invoice not found".into()))?;
+        let resolved = hold.htlcs().iter().find(|htlc| {
+            self.channels
+                .htlc(&htlc.channel_id, HtlcDirection::Received, htlc.htlc_id)
+                .is_none_or(|held| held.state != HtlcState::Pending)
+        });
+        if let Some(htlc) = resolved {
+            return Err(PaymentError::Invoice(
+                format!("Held HTLC {} is no longer pending", htlc.htlc_id).into(),
+            ));
+        }
+        for htlc in hold.settle(preimage)? {
+            self.channels.fulfill_htlc(
+                &htlc.channel_id,
+                HtlcDirection::Received,
+                htlc.htlc_id,
+                &preimage,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Cancel a hold invoice, failing back any held HTLCs
+    pub fn cancel_hold_invoice(&mut self, payment_hash: &PaymentHash) -> PaymentResult<()> {
+        let hold = self
+            .hold_invoices
+            .get_mut(payment_hash)
+            .ok_or_else(|| PaymentError::Invoice("Hold invoice not found".into()))?;
+        for htlc in hold.cancel()? {
+            let pending = self
+                .channels
+                .htlc(&htlc.channel_id, HtlcDirection::Received, htlc.htlc_id)
+                .is_some_and(|held| held.state == HtlcState::Pending);
+            if pending {
+                self.channels.fail_htlc(&htlc.channel_id, HtlcDirection::Received, htlc.htlc_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Process a new block height
+    ///
+    /// Cancels hold invoices whose HTLCs would otherwise reach their CLTV
+    /// expiry, then expires any HTLCs past it. Returns the cancelled invoices.
+    pub fn block_connected(&mut self, height: u32) -> PaymentResult<Vec<PaymentHash>> {
+        let due: Vec<PaymentHash> = self
+            .hold_invoices
+            .values()
+            .filter(|hold| hold.must_cancel_at(height))
+            .map(|hold| *hold.payment_hash())
+            .collect();
+        for payment_hash in &due {
+            self.cancel_hold_invoice(payment_hash)?;
+        }
+        self.channels.expire_htlcs(height);
+        Ok(due)
+    }
+
+    /// Get an invoice issued by this node
+    pub fn get_invoice(&self, payment_hash: &PaymentHash) -> Option<&LightningInvoice> {
+        self.invoices.get(payment_hash).map(|record| &record.invoice)
+    }
+
+    /// Get the preimage of an invoice issued by this node, proving settlement
+    pub fn invoice_preimage(&self, payment_hash: &PaymentHash) -> Option<PaymentPreimage> {
+        self.invoices.get(payment_hash).map(|record| record.preimage)
     }
 
     /// Check invoice payment status
     pub async fn check_invoice(&self, payment_hash: &PaymentHash) -> PaymentResult<PaymentStatus> {
-        match self.invoices.get(payment_hash) {
-            Some(_) => {
-                // In a real implementation, this would check with the Lightning node
-                // For demo, assume payment is successful after some time
-                Ok(PaymentStatus::Succeeded)
-            },
-            None => Err(PaymentError::Invoice("Invoice not found".to_string())),
+        if let Some(hold) = self.hold_invoices.get(payment_hash) {
+            return Ok(hold.status());
+        }
+        let record = self
+            .invoices
+            .get(payment_hash)
+            .ok_or_else(|| PaymentError::Invoice("Invoice not found".into()))?;
+        if record.settled {
+            Ok(PaymentStatus::Succeeded)
+        } else if unix_now()? >= record.invoice.expiry {
+            Ok(PaymentStatus::Failed)
+        } else {
+            Ok(PaymentStatus::Pending)
+        }
+    }
+
+    /// Send a payment over the given routes, one part per route
+    ///
+    /// Each part locks an offered HTLC on an active channel to its first hop, so
+    /// concurrent payments cannot spend the same liquidity. All parts share the
+    /// invoice payment secret and total amount. The returned
+    /// status is the aggregate over parts; outcomes are reported back through
+    /// [`Self::settle_payment_part`] and [`Self::fail_payment_part`].
+    pub fn send_payment_parts(
+        &mut self, invoice: &LightningInvoice, total_msat: u64, routes: Vec<PaymentRoute>,
+    ) -> PaymentResult<PaymentStatus> {
+        if let Some(existing) = self.outbound.get(&invoice.payment_hash) {
+            if existing.status() != PaymentStatus::Failed {
+                return Err(PaymentError::Invoice("Invoice is already being paid".into()));
+            }
+        }
+
+        let committed_msat: u64 = routes.iter().map(PaymentRoute::total_amount_msat).sum();
+        let balance_msat = self.total_balance().saturating_mul(1000);
+        if committed_msat > balance_msat {
+            return Err(PaymentError::InsufficientFunds(
+                format!("Need {} msat, have {} msat in channels", committed_msat, balance_msat)
+                    .into(),
+            ));
+        }
+
+        let mut payment = MultiPathPayment::new(
+            invoice.payment_hash,
+            invoice.payment_secret,
+            total_msat,
+            routes,
+        )?;
+        if payment.unrouted_msat() > 0 {
+            return Err(PaymentError::Routing(
+                format!("Routes cover {} of {} msat", payment.routed_msat(), total_msat).into(),
+            ));
+        }
+
+        let mut locked = Vec::with_capacity(payment.parts().len());
+        for part in payment.parts() {
+            match self.lock_part_htlc(&invoice.payment_hash, &part.route) {
+                Ok(htlc) => locked.push((part.part_id, htlc)),
+                Err(err) => {
+                    for (_, (channel_id, htlc_id)) in locked {
+                        self.channels.fail_htlc(&channel_id, HtlcDirection::Offered, htlc_id)?;
+                    }
+                    return Err(err);
+                },
+            }
+        }
+        for (part_id, htlc) in locked {
+            payment.mark_in_flight(part_id)?;
+            self.part_htlcs.insert((invoice.payment_hash, part_id), htlc);
         }
+
+        let status = payment.status();
+        self.outbound.insert(invoice.payment_hash, payment);
+        Ok(status)
+    }
+
+    /// Get an outgoing payment
+    pub fn outbound_payment(&self, payment_hash: &PaymentHash) -> Option<&MultiPathPayment> {
+        self.outbound.get(payment_hash)
     }
 
-    /// Pay an invoice
-    pub async fn pay_invoice(&self, invoice: &LightningInvoice) -> PaymentResult<PaymentStatus> {
-        // In a real implementation, this would send the payment through the Lightning
-        // network For demo, check if we have sufficient balance
-        let total_balance: u64 = self.channels.values().map(|ch| ch.local_balance).sum();
-        let amount = invoice.amount_sats.unwrap_or(0);
+    /// Record that a part of an outgoing payment settled
+    pub fn settle_payment_part(
+        &mut self, payment_hash: &PaymentHash, part_id: u32, preimage: PaymentPreimage,
+    ) -> PaymentResult<PaymentStatus> {
+        let payment = self.outbound_mut(payment_hash)?;
+        payment.settle_part(part_id, preimage)?;
+        let status = payment.status();
+        if let Some((channel_id, htlc_id)) = self.part_htlcs.remove(&(*payment_hash, part_id)) {
+            self.channels.fulfill_htlc(&channel_id, HtlcDirection::Offered, htlc_id, &preimage)?;
+        }
+        Ok(status)
+    }
 
-        if total_balance < amount {
-            return Err(PaymentError::InsufficientFunds(format!(
-                "Need {} sats, have {} in channels",
-                amount, total_balance
-            )));
+    /// Record that a part of an outgoing payment failed
+    pub fn fail_payment_part(
+        &mut self, payment_hash: &PaymentHash, part_id: u32, permanent: bool,
+    ) -> PaymentResult<PaymentStatus> {
+        let payment = self.outbound_mut(payment_hash)?;
+        payment.fail_part(part_id, permanent)?;
+        let status = payment.status();
+        if let Some((channel_id, htlc_id)) = self.part_htlcs.remove(&(*payment_hash, part_id)) {
+            self.channels.fail_htlc(&channel_id, HtlcDirection::Offered, htlc_id)?;
         }
+        Ok(status)
+    }
+
+    /// Offer an HTLC for a route on an active channel to its first hop
+    fn lock_part_htlc(
+        &mut self, payment_hash: &PaymentHash, route: &PaymentRoute,
+    ) -> PaymentResult<([u8; 32], u64)> {
+        let first_hop =
+            route.hops.first().ok_or_else(|| PaymentError::Routing("Route has no hops".into()))?;
+        let amount_msat = route.total_amount_msat();
+        let can_carry = |channel: &&PaymentChannel| {
+            channel.peer_pubkey == first_hop.pubkey
+                && ChannelLimits::available_msat(channel, HtlcDirection::Offered) >= amount_msat
+        };
+        // Prefer the channel the route was found over, then any other to the same peer.
+        let active = self.channels.active_channels();
+        let carriers = || active.iter().copied().filter(can_carry);
+        let channel_id = carriers()
+            .find(|channel| channel.short_channel_id() == first_hop.short_channel_id)
+            .or_else(|| carriers().next())
+            .map(|channel| channel.channel_id)
+            .ok_or_else(|| {
+                PaymentError::InsufficientFunds(
+                    format!("No channel to the first hop can carry {} msat", amount_msat).into(),
+                )
+            })?;
 
-        Ok(PaymentStatus::Succeeded)
+        let cltv_expiry = self
+            .channels
+            .best_block_height()
+            .saturating_add(route.total_cltv_delta)
+            .saturating_add(DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA as u32);
+        let htlc_id = self.channels.add_htlc(
+            &channel_id,
+            HtlcDirection::Offered,
+            amount_msat,
+            *payment_hash,
+            cltv_expiry,
+        )?;
+        Ok((channel_id, htlc_id))
+    }
+
+    fn outbound_mut(&mut self, payment_hash: &PaymentHash) -> PaymentResult<&mut MultiPathPayment> {
+        self.outbound
+            .get_mut(payment_hash)
+            .ok_or_else(|| PaymentError::Invoice("Payment not found".into()))
+    }
+
+    /// Get the channel store
+    pub fn channel_manager(&self) -> &ChannelManager {
+        &self.channels
+    }
+
+    /// Get the mutable channel store
+    pub fn channel_manager_mut(&mut self) -> &mut ChannelManager {
+        &mut self.channels
+    }
+
+    /// Set the capacity limits applied to new channels
+    pub fn set_channel_limits(&mut self, limits: ChannelLimits) {
+        self.channels.set_limits(limits);
     }
 
     /// Open a channel with a peer
+    ///
+    /// The channel stays `Opening` until its funding transaction confirms.
     pub async fn open_channel(
         &mut self, peer_pubkey: [u8; 33], capacity_sats: u64, push_sats: u64,
     ) -> PaymentResult<[u8; 32]> {
-        let mut channel_id = [0u8; 32];
-        for i in 0..32 {
-            channel_id[i] = peer_pubkey[i % 33].wrapping_add(i as u8);
+        self.channels.open_channel_with_push(peer_pubkey, capacity_sats, push_sats)
+    }
+
+    /// Start closing a channel cooperatively
+    ///
+    /// The channel stays `Closing` until [`Self::confirm_channel_closed`] is called
+    /// for the confirmed closing transaction.
+    pub async fn close_channel(&mut self, channel_id: &[u8; 32]) -> PaymentResult<()> {
+        self.channels.close_channel(channel_id)
+    }
+
+    /// Mark a closing channel closed once its closing transaction confirms
+    pub fn confirm_channel_closed(&mut self, channel_id: &[u8; 32]) -> PaymentResult<()> {
+        self.channels.confirm_channel_closed(channel_id)
+    }
+
+    /// Get total local balance of active channels
+    pub fn total_balance(&self) -> u64 {
+        self.channels.total_local_balance()
+    }
+}
+
+/// Current Unix time in seconds
+fn unix_now() -> PaymentResult<u64> {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .map_err(|_| PaymentError::Invoice("Time error".into()))
+}
+
+/// Check that an HTLC carrying `payment_secret` may still pay `invoice` at `now`
+fn check_payable(
+    invoice: &LightningInvoice, payment_secret: &[u8; 32], now: u64,
+) -> PaymentResult<()> {
+    if invoice.payment_secret.as_ref() != Some(payment_secret) {
+        return Err(PaymentError::Invoice("Payment secret does not match invoice".into()));
+    }
+    if now >= invoice.expiry {
+        return Err(PaymentError::Invoice("Invoice has expired".into()));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::{
+        implementation::hold::HoldInvoiceState,
+        types::{ChannelState, RouteHop},
+    };
+
+    /// Drive a future that never waits on I/O to completion.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
         }
+    }
 
-        let channel = crate::types::PaymentChannel {
-            channel_id,
-            peer_pubkey,
-            capacity: capacity_sats,
-            local_balance: capacity_sats - push_sats,
-            remote_balance: push_sats,
-            state: crate::types::ChannelState::Active,
-        };
+    #[test]
+    fn test_open_channel_validates_push() {
+        let mut node = LightningNodeImpl::new("node".to_string());
+        assert!(block_on(node.open_channel([2; 33], 100_000, 100_001)).is_err());
+        assert!(block_on(node.open_channel([2; 33], 1_000, 0)).is_err());
+        assert_eq!(node.total_balance(), 0);
+
+        let first = block_on(node.open_channel([2; 33], 100_000, 25_000)).unwrap();
+        let second = block_on(node.open_channel([2; 33], 100_000, 0)).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(node.total_balance(), 0);
 
-        self.channels.insert(channel_id, channel);
-        Ok(channel_id)
+        for id in [first, second] {
+            node.channel_manager_mut().record_funding_confirmations(&id, 6).unwrap();
+        }
+        assert_eq!(node.total_balance(), 175_000);
     }
 
-    /// Close a channel
-    pub async fn close_channel(&mut self, channel_id: &[u8; 32]) -> PaymentResult<()> {
-        if let Some(channel) = self.channels.get_mut(channel_id) {
-            channel.state = crate::types::ChannelState::Closing;
-            // In real implementation, would negotiate closure
-            channel.state = crate::types::ChannelState::Closed;
+    #[test]
+    fn test_close_channel_waits_for_confirmation() {
+        let mut node = LightningNodeImpl::new("node".to_string());
+        let id = block_on(node.open_channel([2; 33], 100_000, 0)).unwrap();
+        node.channel_manager_mut().record_funding_confirmations(&id, 6).unwrap();
+
+        block_on(node.close_channel(&id)).unwrap();
+        let state = |node: &LightningNodeImpl| node.channel_manager().channel(&id).unwrap().state;
+        assert_eq!(state(&node), ChannelState::Closing);
+        assert!(block_on(node.close_channel(&id)).is_err());
+        node.confirm_channel_closed(&id).unwrap();
+        assert_eq!(state(&node), ChannelState::Closed);
+    }
+
+    fn invoice(preimage: PaymentPreimage) -> LightningInvoice {
+        LightningInvoice {
+            payment_hash:   preimage.payment_hash(),
+            amount_sats:    Some(60_000),
+            description:    String::new(),
+            expiry:         3600,
+            bolt11:         String::new(),
+            payment_secret: Some([1; 32]),
         }
-        Ok(())
     }
 
-    /// Get total channel balance
-    pub fn total_balance(&self) -> u64 {
-        self.channels.values().map(|ch| ch.local_balance).sum()
+    fn route(peer: [u8; 33], amount_msat: u64) -> PaymentRoute {
+        PaymentRoute {
+            hops: vec![RouteHop {
+                pubkey:            peer,
+                short_channel_id:  1,
+                fee_msat:          0,
+                cltv_expiry_delta: 40,
+            }],
+            total_fees_msat: 0,
+            total_cltv_delta: 40,
+            amount_msat,
+        }
     }
-}
 
-/// Convert bytes to hex string (SSOP compliant)
-fn bytes_to_hex(bytes: &[u8]) -> String {
-    const HEX_CHARS: &[u8] = b"0123456789abcdef";
-    let mut hex = String::with_capacity(bytes.len() * 2);
-    for &byte in bytes {
-        hex.push(HEX_CHARS[(byte >> 4) as usize] as char);
-        hex.push(HEX_CHARS[(byte & 0xF) as usize] as char);
+    #[test]
+    fn test_payment_parts_lock_channel_liquidity() {
+        let mut node = LightningNodeImpl::new("node".to_string());
+        let id = block_on(node.open_channel([2; 33], 100_000, 0)).unwrap();
+        node.channel_manager_mut().record_funding_confirmations(&id, 6).unwrap();
+
+        let first = PaymentPreimage::new([1; 32]);
+        node.send_payment_parts(&invoice(first), 60_000_000, vec![route([2; 33], 60_000_000)])
+            .unwrap();
+        assert_eq!(node.total_balance(), 40_000);
+        assert_eq!(node.channel_manager().total_in_flight_msat(HtlcDirection::Offered), 60_000_000);
+
+        let second = PaymentPreimage::new([2; 32]);
+        let routes = vec![route([2; 33], 30_000_000), route([2; 33], 30_000_000)];
+        assert!(matches!(
+            node.send_payment_parts(&invoice(second), 60_000_000, routes.clone()),
+            Err(PaymentError::InsufficientFunds(_))
+        ));
+        assert_eq!(node.total_balance(), 40_000);
+
+        node.fail_payment_part(&first.payment_hash(), 0, true).unwrap();
+        assert_eq!(node.total_balance(), 100_000);
+        node.send_payment_parts(&invoice(second), 60_000_000, routes).unwrap();
+        node.settle_payment_part(&second.payment_hash(), 0, second).unwrap();
+        assert_eq!(
+            node.settle_payment_part(&second.payment_hash(), 1, second).unwrap(),
+            PaymentStatus::Succeeded
+        );
+
+        let channel = node.channel_manager().channel(&id).unwrap();
+        assert_eq!(
+            (channel.local_balance_msat, channel.remote_balance_msat),
+            (40_000_000, 60_000_000)
+        );
+        assert!(channel.pending_htlcs().next().is_none());
+    }
+
+    #[test]
+    fn test_hold_invoice_settle_and_auto_cancel() {
+        let mut node = LightningNodeImpl::new("node".to_string());
+        let id = block_on(node.open_channel([2; 33], 100_000, 50_000)).unwrap();
+        node.channel_manager_mut().record_funding_confirmations(&id, 6).unwrap();
+
+        let preimage = PaymentPreimage::new([4; 32]);
+        let hash = preimage.payment_hash();
+        let invoice = block_on(node.create_hold_invoice(hash, 10_000, "bounty", 3600)).unwrap();
+        let secret = invoice.payment_secret.unwrap();
+        assert!(block_on(node.create_hold_invoice(hash, 10_000, "bounty", 3600)).is_err());
+        assert_eq!(block_on(node.check_invoice(&hash)).unwrap(), PaymentStatus::Pending);
+
+        assert!(node.receive_htlc(&id, 10_000_000, hash, &[0; 32], 700).is_err());
+        node.receive_htlc(&id, 10_000_000, hash, &secret, 700).unwrap();
+        assert_eq!(node.hold_invoice(&hash).unwrap().state(), HoldInvoiceState::Accepted);
+        assert_eq!(
+            node.channel_manager().total_in_flight_msat(HtlcDirection::Received),
+            10_000_000
+        );
+        assert_eq!(node.total_balance(), 50_000);
+
+        node.settle_hold_invoice(preimage).unwrap();
+        assert_eq!(block_on(node.check_invoice(&hash)).unwrap(), PaymentStatus::Succeeded);
+        assert_eq!(node.total_balance(), 60_000);
+
+        let refunded = PaymentPreimage::new([5; 32]).payment_hash();
+        let invoice = block_on(node.create_hold_invoice(refunded, 10_000, "bounty", 3600)).unwrap();
+        let secret = invoice.payment_secret.unwrap();
+        assert!(node.receive_htlc(&id, 10_000_000, refunded, &secret, 10).is_err());
+        node.receive_htlc(&id, 10_000_000, refunded, &secret, 700).unwrap();
+        assert!(node.block_connected(689).unwrap().is_empty());
+        assert_eq!(node.block_connected(690).unwrap(), vec![refunded]);
+        assert_eq!(node.hold_invoice(&refunded).unwrap().state(), HoldInvoiceState::Canceled);
+
+        let channel = node.channel_manager().channel(&id).unwrap();
+        assert_eq!(
+            (channel.local_balance_msat, channel.remote_balance_msat),
+            (60_000_000, 40_000_000)
+        );
+        assert!(channel.pending_htlcs().next().is_none());
+        assert!(node.receive_htlc(&id, 1_000, PaymentHash::new([7; 32]), &secret, 900).is_err());
+    }
+
+    #[test]
+    fn test_hold_invoice_settle_needs_pending_htlcs() {
+        let mut node = LightningNodeImpl::new("node".to_string());
+        let id = block_on(node.open_channel([2; 33], 100_000, 50_000)).unwrap();
+        node.channel_manager_mut().record_funding_confirmations(&id, 6).unwrap();
+
+        let preimage = PaymentPreimage::new([6; 32]);
+        let hash = preimage.payment_hash();
+        let invoice = block_on(node.create_hold_invoice(hash, 10_000, "bounty", 3600)).unwrap();
+        node.receive_htlc(&id, 10_000_000, hash, &invoice.payment_secret.unwrap(), 700).unwrap();
+        node.channel_manager_mut().expire_htlcs(700);
+
+        assert!(node.settle_hold_invoice(preimage).is_err());
+        assert_eq!(node.hold_invoice(&hash).unwrap().state(), HoldInvoiceState::Accepted);
+        assert_eq!(node.total_balance(), 50_000);
+    }
+
+    #[test]
+    fn test_invoice_status_follows_settlement() {
+        let mut node = LightningNodeImpl::new("node".to_string());
+        let id = block_on(node.open_channel([2; 33], 100_000, 50_000)).unwrap();
+        node.channel_manager_mut().record_funding_confirmations(&id, 6).unwrap();
+
+        let invoice = block_on(node.create_invoice(2_000, "coffee", 3600)).unwrap();
+        let (hash, secret) = (invoice.payment_hash, invoice.payment_secret.unwrap());
+        assert_eq!(block_on(node.check_invoice(&hash)).unwrap(), PaymentStatus::Pending);
+        assert!(node.receive_htlc(&id, 2_000_000, hash, &[0; 32], 700).is_err());
+        assert_eq!(block_on(node.check_invoice(&hash)).unwrap(), PaymentStatus::Pending);
+
+        node.receive_htlc(&id, 2_000_000, hash, &secret, 700).unwrap();
+        assert_eq!(block_on(node.check_invoice(&hash)).unwrap(), PaymentStatus::Succeeded);
+        assert!(node.receive_htlc(&id, 2_000_000, hash, &secret, 700).is_err());
+        assert_eq!(node.total_balance(), 52_000);
+
+        let expired = block_on(node.create_invoice(2_000, "stale", 0)).unwrap();
+        let secret = expired.payment_secret.unwrap();
+        assert_eq!(
+            block_on(node.check_invoice(&expired.payment_hash)).unwrap(),
+            PaymentStatus::Failed
+        );
+        assert!(node.receive_htlc(&id, 2_000_000, expired.payment_hash, &secret, 700).is_err());
+        assert_eq!(node.total_balance(), 52_000);
     }
-    hex
 }
@@ -0,0 +1,384 @@
+//! Usage metering against subscription tier limits.
+//!
+//! Services report usage per customer and the meter checks it against the
+//! [`TierFeatures`] of the customer's current tier. AI operations are counted
+//! per billing period; private repositories and repository sizes are checked
+//! as they change. With an overage rate set, AI operations beyond the quota
+//! are accepted and billed per operation instead of being refused.
+
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::{
+    errors::{ErrorDetail, PaymentError, PaymentResult},
+    implementation::subscription::{BillingEvent, SubscriptionManager},
+    traits::InvoiceProvider,
+    types::{SubscriptionTier, TierFeatures},
+};
+
+/// Bytes in a gigabyte as used for `max_repo_size_gb` (GiB).
+pub const BYTES_PER_GB: u64 = 1 << 30;
+
+/// Usage reported by a service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsageEvent {
+    /// AI operations performed.
+    AiOperations(u32),
+    /// Private repository created.
+    PrivateRepoCreated,
+    /// Private repository deleted.
+    PrivateRepoDeleted,
+    /// Repository grew or shrank to `size_bytes`.
+    RepoSize {
+        /// Repository identifier.
+        repo_id:    String,
+        /// Current size in bytes.
+        size_bytes: u64,
+    },
+}
+
+/// Tier limit a usage event is checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageLimit {
+    /// `ai_operations_per_month`.
+    AiOperations,
+    /// `private_repos`.
+    PrivateRepos,
+    /// `max_repo_size_gb`, in bytes.
+    RepoSize,
+}
+
+/// Usage refused because it would exceed the customer's tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// Limit that was hit.
+    pub limit:     UsageLimit,
+    /// Tier the limit belongs to.
+    pub tier:      SubscriptionTier,
+    /// Amount allowed.
+    pub allowed:   u64,
+    /// Amount already used.
+    pub used:      u64,
+    /// Amount requested by the refused event.
+    pub requested: u64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} quota of the {:?} tier exceeded: {} used, {} requested, {} allowed",
+            self.limit, self.tier, self.used, self.requested, self.allowed
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+impl From<QuotaExceeded> for PaymentError {
+    fn from(err: QuotaExceeded) -> Self {
+        PaymentError::Subscription(ErrorDetail::caused_by(err.to_string(), err))
+    }
+}
+
+/// Usage recorded by the meter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageRecorded {
+    /// Operations of the event billed as overage.
+    pub overage_operations: u64,
+}
+
+/// Remaining quota for the current billing period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaReport {
+    /// Tier the limits come from.
+    pub tier:                    SubscriptionTier,
+    /// Start of the billing period, unix seconds.
+    pub period_start:            u64,
+    /// End of the billing period, unix seconds.
+    pub period_end:              u64,
+    /// AI operations used this period, including overage.
+    pub ai_operations_used:      u64,
+    /// AI operations left within the quota.
+    pub ai_operations_remaining: u64,
+    /// Private repositories in use.
+    pub private_repos_used:      u64,
+    /// Private repositories that can still be created.
+    pub private_repos_remaining: u64,
+    /// Largest repository allowed, in bytes.
+    pub max_repo_size_bytes:     u64,
+    /// Overage operations not yet invoiced.
+    pub unbilled_overage:        u64,
+}
+
+/// Usage counters for one customer.
+#[derive(Debug, Clone, Default)]
+struct CustomerUsage {
+    period_start:     u64,
+    ai_operations:    u64,
+    private_repos:    u64,
+    repo_sizes:       HashMap<String, u64>,
+    unbilled_overage: u64,
+}
+
+/// Usage meter enforcing tier quotas.
+#[derive(Debug, Default)]
+pub struct UsageMeter {
+    usage:             HashMap<String, CustomerUsage>,
+    overage_rate_sats: Option<u64>,
+}
+
+impl UsageMeter {
+    /// Create a meter that refuses usage beyond the quota.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept AI operations beyond the quota, billed at `sats_per_operation`;
+    /// `None` refuses them instead.
+    pub fn set_overage_rate(&mut self, sats_per_operation: Option<u64>) {
+        self.overage_rate_sats = sats_per_operation.filter(|rate| *rate > 0);
+    }
+
+    /// Overage rate in satoshis per operation, if overage is allowed.
+    #[must_use]
+    pub fn overage_rate_sats(&self) -> Option<u64> {
+        self.overage_rate_sats
+    }
+
+    /// Record a usage event for a customer.
+    ///
+    /// Usage that would exceed the customer's tier is not recorded, unless it
+    /// is AI operations and an overage rate is set.
+    pub fn record(
+        &mut self, subscriptions: &SubscriptionManager, customer_id: &str, event: UsageEvent,
+    ) -> Result<UsageRecorded, QuotaExceeded> {
+        let tier = subscriptions.effective_tier(customer_id);
+        let features = tier.features();
+        let overage_allowed = self.overage_rate_sats.is_some();
+        let usage = self.current_usage(subscriptions, customer_id);
+        let exceeded = |limit, allowed, used, requested| QuotaExceeded {
+            limit,
+            tier,
+            allowed,
+            used,
+            requested,
+        };
+
+        match event {
+            UsageEvent::AiOperations(count) => {
+                let (count, allowed) =
+                    (u64::from(count), u64::from(features.ai_operations_per_month));
+                let within = allowed.saturating_sub(usage.ai_operations).min(count);
+                let overage = count - within;
+                if overage > 0 && !overage_allowed {
+                    return Err(exceeded(
+                        UsageLimit::AiOperations,
+                        allowed,
+                        usage.ai_operations,
+                        count,
+                    ));
+                }
+                usage.ai_operations = usage.ai_operations.saturating_add(count);
+                usage.unbilled_overage = usage.unbilled_overage.saturating_add(overage);
+                Ok(UsageRecorded { overage_operations: overage })
+            },
+            UsageEvent::PrivateRepoCreated => {
+                let allowed = u64::from(features.private_repos);
+                if usage.private_repos >= allowed {
+                    return Err(exceeded(
+                        UsageLimit::PrivateRepos,
+                        allowed,
+                        usage.private_repos,
+                        1,
+                    ));
+                }
+                usage.private_repos += 1;
+                Ok(UsageRecorded { overage_operations: 0 })
+            },
+            UsageEvent::PrivateRepoDeleted => {
+                usage.private_repos = usage.private_repos.saturating_sub(1);
+                Ok(UsageRecorded { overage_operations: 0 })
+            },
+            UsageEvent::RepoSize { repo_id, size_bytes } => {
+                let allowed = max_repo_size_bytes(&features);
+                if size_bytes > allowed {
+                    let used = usage.repo_sizes.get(&repo_id).copied().unwrap_or(0);
+                    return Err(exceeded(UsageLimit::RepoSize, allowed, used, size_bytes));
+                }
+                usage.repo_sizes.insert(repo_id, size_bytes);
+                Ok(UsageRecorded { overage_operations: 0 })
+            },
+        }
+    }
+
+    /// Remaining quota for the customer's current billing period.
+    #[must_use]
+    pub fn quota(&self, subscriptions: &SubscriptionManager, customer_id: &str) -> QuotaReport {
+        let tier = subscriptions.effective_tier(customer_id);
+        let features = tier.features();
+        let (period_start, period_end) = subscriptions.current_period(customer_id);
+        let usage = self.usage.get(customer_id);
+        let ai_operations_used = usage
+            .filter(|usage| usage.period_start == period_start)
+            .map_or(0, |usage| usage.ai_operations);
+        let private_repos_used = usage.map_or(0, |usage| usage.private_repos);
+
+        QuotaReport {
+            tier,
+            period_start,
+            period_end,
+            ai_operations_used,
+            ai_operations_remaining: u64::from(features.ai_operations_per_month)
+                .saturating_sub(ai_operations_used),
+            private_repos_used,
+            private_repos_remaining: u64::from(features.private_repos)
+                .saturating_sub(private_repos_used),
+            max_repo_size_bytes: max_repo_size_bytes(&features),
+            unbilled_overage: usage.map_or(0, |usage| usage.unbilled_overage),
+        }
+    }
+
+    /// Invoice every customer's unbilled overage at the overage rate.
+    pub fn bill_overage(
+        &mut self, invoices: &dyn InvoiceProvider,
+    ) -> PaymentResult<Vec<BillingEvent>> {
+        let Some(rate) = self.overage_rate_sats else {
+            return Ok(Vec::new());
+        };
+
+        let mut customers: Vec<String> = self.usage.keys().cloned().collect();
+        customers.sort();
+        let mut events = Vec::new();
+        for customer_id in customers {
+            let Some(usage) = self.usage.get_mut(&customer_id) else { continue };
+            if usage.unbilled_overage == 0 {
+                continue;
+            }
+            let operations = usage.unbilled_overage;
+            let description = format!("{operations} overage AI operations for {customer_id}");
+            let invoice =
+                invoices.generate_invoice(Some(operations.saturating_mul(rate)), &description)?;
+            usage.unbilled_overage = 0;
+            events.push(BillingEvent::OverageInvoiced { customer_id, invoice, operations });
+        }
+        Ok(events)
+    }
+
+    /// Counters for the customer, reset when a new billing period started.
+    fn current_usage(
+        &mut self, subscriptions: &SubscriptionManager, customer_id: &str,
+    ) -> &mut CustomerUsage {
+        let (period_start, _) = subscriptions.current_period(customer_id);
+        let usage = self.usage.entry(customer_id.to_string()).or_default();
+        if usage.period_start != period_start {
+            usage.period_start = period_start;
+            usage.ai_operations = 0;
+        }
+        usage
+    }
+}
+
+fn max_repo_size_bytes(features: &TierFeatures) -> u64 {
+    (features.max_repo_size_gb * BYTES_PER_GB as f64) as u64
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::implementation::{
+        BillingPolicy, InvoiceGenerator, ManualClock, PaymentConfig, DEFAULT_BILLING_PERIOD_SECS,
+    };
+
+    const T0: u64 = 1_700_000_000;
+
+    fn setup() -> (UsageMeter, SubscriptionManager, ManualClock, InvoiceGenerator) {
+        let clock = ManualClock::new(T0);
+        let subscriptions =
+            SubscriptionManager::new(Arc::new(clock.clone()), BillingPolicy::default());
+        (UsageMeter::new(), subscriptions, clock, InvoiceGenerator::new(PaymentConfig::default()))
+    }
+
+    #[test]
+    fn test_free_tier_quota_is_enforced() {
+        let (mut meter, subscriptions, _, _) = setup();
+        meter.record(&subscriptions, "gina", UsageEvent::AiOperations(8)).unwrap();
+        let err = meter.record(&subscriptions, "gina", UsageEvent::AiOperations(3)).unwrap_err();
+        assert_eq!(
+            err,
+            QuotaExceeded {
+                limit:     UsageLimit::AiOperations,
+                tier:      SubscriptionTier::Free,
+                allowed:   10,
+                used:      8,
+                requested: 3,
+            }
+        );
+        let quota = meter.quota(&subscriptions, "gina");
+        assert_eq!((quota.ai_operations_used, quota.ai_operations_remaining), (8, 2));
+
+        assert_eq!(
+            meter.record(&subscriptions, "gina", UsageEvent::PrivateRepoCreated).unwrap_err().limit,
+            UsageLimit::PrivateRepos
+        );
+        let repo = |size_bytes| UsageEvent::RepoSize { repo_id: "r1".into(), size_bytes };
+        meter.record(&subscriptions, "gina", repo(BYTES_PER_GB)).unwrap();
+        assert_eq!(
+            meter.record(&subscriptions, "gina", repo(BYTES_PER_GB + 1)).unwrap_err().limit,
+            UsageLimit::RepoSize
+        );
+    }
+
+    #[test]
+    fn test_quota_follows_tier_and_resets_each_period() {
+        let (mut meter, mut subscriptions, clock, invoices) = setup();
+        let invoice =
+            subscriptions.subscribe("hank", SubscriptionTier::Pro, &invoices).unwrap().unwrap();
+        subscriptions.record_payment(&invoice.payment_hash).unwrap();
+
+        for _ in 0..10 {
+            meter.record(&subscriptions, "hank", UsageEvent::PrivateRepoCreated).unwrap();
+        }
+        assert!(meter.record(&subscriptions, "hank", UsageEvent::PrivateRepoCreated).is_err());
+        meter.record(&subscriptions, "hank", UsageEvent::PrivateRepoDeleted).unwrap();
+        meter.record(&subscriptions, "hank", UsageEvent::AiOperations(1000)).unwrap();
+        let quota = meter.quota(&subscriptions, "hank");
+        assert_eq!(quota.tier, SubscriptionTier::Pro);
+        assert_eq!((quota.ai_operations_remaining, quota.private_repos_remaining), (0, 1));
+        assert_eq!(quota.max_repo_size_bytes, 10 * BYTES_PER_GB);
+        assert!(meter.record(&subscriptions, "hank", UsageEvent::AiOperations(1)).is_err());
+
+        // Renewing starts a fresh period; repositories carry over.
+        clock.set(T0 + DEFAULT_BILLING_PERIOD_SECS - 86_400);
+        let renewal = subscriptions.run_billing(&invoices).unwrap();
+        let BillingEvent::InvoiceIssued { invoice, .. } = &renewal[0] else { unreachable!() };
+        subscriptions.record_payment(&invoice.payment_hash).unwrap();
+        meter.record(&subscriptions, "hank", UsageEvent::AiOperations(1)).unwrap();
+        let quota = meter.quota(&subscriptions, "hank");
+        assert_eq!((quota.ai_operations_used, quota.private_repos_used), (1, 9));
+    }
+
+    #[test]
+    fn test_overage_is_accepted_and_invoiced() {
+        let (mut meter, subscriptions, _, invoices) = setup();
+        meter.set_overage_rate(Some(50));
+        let recorded = meter.record(&subscriptions, "ivy", UsageEvent::AiOperations(14)).unwrap();
+        assert_eq!(recorded.overage_operations, 4);
+        meter.record(&subscriptions, "ivy", UsageEvent::AiOperations(2)).unwrap();
+        assert_eq!(meter.quota(&subscriptions, "ivy").unbilled_overage, 6);
+
+        let events = meter.bill_overage(&invoices).unwrap();
+        let [BillingEvent::OverageInvoiced { invoice, operations: 6, .. }] = events.as_slice()
+        else {
+            panic!("expected one overage invoice")
+        };
+        assert_eq!(invoice.amount, Some(300));
+        assert!(meter.bill_overage(&invoices).unwrap().is_empty());
+
+        meter.set_overage_rate(None);
+        assert!(meter.record(&subscriptions, "ivy", UsageEvent::AiOperations(1)).is_err());
+    }
+}
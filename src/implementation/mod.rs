@@ -4,19 +4,106 @@
 //! - `PaymentConfig` - Configuration
 //! - `ChannelManager` - Lightning channel management
 //! - `InvoiceGenerator` - Invoice creation and verification
+//! - `HoldInvoice` - Hold invoices for Lightning escrow
+//! - `EscrowManager` - Bounty escrow lifecycle
+//! - `SignedRuling` - Arbiter rulings on disputed escrows
+//! - `MultisigEscrow` - 2-of-3 multisig escrow scripts and addresses
+//! - `Transaction` - Bitcoin transaction encoding and segwit signature hashes
+//! - `Psbt` - Partially signed transactions for escrow release and refund
+//! - `Bolt11Invoice` - BOLT11 invoice encoding and decoding
+//! - `NetworkGraph` - Directed channel graph
+//! - `GossipMessage` - BOLT7 gossip parsing and verification
+//! - `RapidGossipSnapshot` - Rapid Gossip Sync snapshot parsing
 //! - `PaymentRouter` - Payment routing
+//! - `PaymentOrchestrator` - Payment retries and timeouts
+//! - `LiquidityScorer` - Probabilistic liquidity scoring and mission control
+//! - `MultiPathPayment` - Multi-part payment tracking
+//! - `OnionFailure` - BOLT4 onion keys and failure messages
+//! - `OnionPacket` - BOLT4 Sphinx onion construction and peeling
+//! - `PaymentStore` - Payment lifecycle records
+//! - `SubscriptionManager` - Subscription billing
+//! - `Proration` - Mid-period tier change proration
+//! - `UsageMeter` - Usage metering and quota enforcement
 //! - `PaymentPlugin` - Main plugin interface
 
+mod arbitration;
+mod bolt11;
 mod channels;
+mod clock;
 mod config;
+mod escrow;
+mod gossip;
+mod graph;
+mod hold;
 mod invoices;
 mod lightning;
+mod metering;
+mod mpp;
+mod multisig;
+mod onion;
+mod payments;
 mod plugin;
+mod proration;
+mod psbt;
+mod rapid_sync;
+mod retry;
 mod router;
+mod scorer;
+mod sphinx;
+mod subscription;
+#[cfg(all(test, feature = "full-tests"))]
+mod test_utils;
+mod transaction;
 
-pub use channels::ChannelManager;
-pub use config::PaymentConfig;
+pub use arbitration::{Dispute, DisputeParty, Ruling, RulingExecution, SignedRuling};
+pub use bolt11::{
+    Bolt11Invoice, Bolt11ParseError, InvoiceDescription, InvoiceFeatures, RouteHintHop,
+    SignedBolt11Invoice,
+};
+pub use channels::{
+    ChannelLimits, ChannelManager, ChannelOpenError, ChannelTransition,
+    DEFAULT_FUNDING_CONFIRMATIONS, MIN_CHANNEL_RESERVE_SATS,
+};
+pub use clock::{ManualClock, SystemClock};
+pub use config::{PaymentConfig, DEFAULT_INVOICE_EXPIRY};
+pub use escrow::{Escrow, EscrowError, EscrowManager, EscrowTerms, EscrowTransition};
+pub use gossip::{
+    ChannelAnnouncement, ChannelUpdate, GossipError, GossipMessage, GossipOutcome, NodeAnnouncement,
+};
+pub use graph::{ChannelPolicy, GraphChannel, GraphNode, NetworkGraph, NodeAnnouncementInfo};
+pub use hold::{HeldHtlc, HoldInvoice, HoldInvoiceState, HOLD_INVOICE_CANCEL_DELTA};
 pub use invoices::InvoiceGenerator;
 pub use lightning::LightningNodeImpl;
+pub use metering::{
+    QuotaExceeded, QuotaReport, UsageEvent, UsageLimit, UsageMeter, UsageRecorded, BYTES_PER_GB,
+};
+pub use mpp::{MultiPathPayment, PaymentPart};
+pub use multisig::{
+    p2wpkh_script_pubkey, p2wsh_address, p2wsh_script_pubkey, MultisigError, MultisigEscrow,
+    MULTISIG_THRESHOLD, P2WPKH_DUST_SATS,
+};
+pub use onion::{
+    decrypt_failure, encode_failure, generate_key, hop_keys, wrap_failure, FailureCode, OnionError,
+    OnionFailure, OnionHopKeys, FAILURE_MESSAGE_PAD_LEN,
+};
+pub use payments::{PaymentDirection, PaymentQuery, PaymentRecord, PaymentStore};
 pub use plugin::PaymentPlugin;
-pub use router::PaymentRouter;
+pub use proration::{Proration, ProrationOutcome};
+pub use psbt::{Psbt, PsbtError, PsbtInput, PsbtOutput};
+pub use rapid_sync::{RapidGossipSnapshot, SnapshotChannel, SnapshotUpdate};
+pub use retry::{AttemptEvent, AttemptFailure, PaymentAttempt, PaymentOrchestrator, RetryPolicy};
+pub use router::{PaymentRouter, RouteExclusions, DEFAULT_MAX_PAYMENT_PARTS};
+pub use scorer::{LiquidityBounds, LiquidityScorer, ScoringParameters};
+pub use sphinx::{
+    HopPayload, OnionPacket, PaymentData, PeeledOnion, HOP_PAYLOADS_LEN, ONION_PACKET_LEN,
+};
+pub use subscription::{
+    BillingEvent, BillingPolicy, InvoicePurpose, Subscription, SubscriptionError,
+    SubscriptionInvoice, SubscriptionInvoiceStatus, SubscriptionManager, SubscriptionStatus,
+    TierChange, TierChangeTiming, DEFAULT_BILLING_PERIOD_SECS, DEFAULT_GRACE_PERIOD_SECS,
+    DEFAULT_RENEWAL_LEAD_SECS,
+};
+pub use transaction::{
+    OutPoint, Transaction, TransactionError, TxIn, TxOut, SEQUENCE_FINAL, SIGHASH_ALL,
+    SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE,
+};
@@ -0,0 +1,292 @@
+//! Multi-part payment tracking.
+
+use crate::{
+    errors::{PaymentError, PaymentResult},
+    types::{PaymentHash, PaymentPreimage, PaymentRoute, PaymentStatus},
+};
+
+/// One part of a multi-part payment.
+#[derive(Debug, Clone)]
+pub struct PaymentPart {
+    /// Identifier of the part within its payment.
+    pub part_id:   u32,
+    /// Route the part is sent over.
+    pub route:     PaymentRoute,
+    /// Current state of the part.
+    pub status:    PaymentStatus,
+    /// Whether the failure of this part ends the whole payment.
+    pub permanent: bool,
+}
+
+/// Outgoing payment split across one or more routes.
+///
+/// Every part carries the same payment hash, payment secret and total amount so
+/// the recipient can hold them until the full amount has arrived.
+#[derive(Debug, Clone)]
+pub struct MultiPathPayment {
+    payment_hash:   PaymentHash,
+    payment_secret: Option<[u8; 32]>,
+    total_msat:     u64,
+    parts:          Vec<PaymentPart>,
+    next_part_id:   u32,
+    preimage:       Option<PaymentPreimage>,
+}
+
+impl MultiPathPayment {
+    /// Create a payment from the routes chosen for its parts.
+    pub fn new(
+        payment_hash: PaymentHash, payment_secret: Option<[u8; 32]>, total_msat: u64,
+        routes: Vec<PaymentRoute>,
+    ) -> PaymentResult<Self> {
+        if routes.len() > 1 && payment_secret.is_none() {
+            return Err(PaymentError::Invoice(
+                "Multi-part payments require a payment secret".into(),
+            ));
+        }
+
+        let mut payment = Self {
+            payment_hash,
+            payment_secret,
+            total_msat,
+            parts: Vec::new(),
+            next_part_id: 0,
+            preimage: None,
+        };
+        for route in routes {
+            payment.add_part(route)?;
+        }
+        Ok(payment)
+    }
+
+    /// Payment hash shared by all parts.
+    #[must_use]
+    pub fn payment_hash(&self) -> &PaymentHash {
+        &self.payment_hash
+    }
+
+    /// Payment secret shared by all parts.
+    #[must_use]
+    pub fn payment_secret(&self) -> Option<&[u8; 32]> {
+        self.payment_secret.as_ref()
+    }
+
+    /// Total amount the recipient expects across all parts.
+    #[must_use]
+    pub fn total_msat(&self) -> u64 {
+        self.total_msat
+    }
+
+    /// Parts sent so far, including failed ones.
+    #[must_use]
+    pub fn parts(&self) -> &[PaymentPart] {
+        &self.parts
+    }
+
+    /// Look up a part.
+    #[must_use]
+    pub fn part(&self, part_id: u32) -> Option<&PaymentPart> {
+        self.parts.iter().find(|part| part.part_id == part_id)
+    }
+
+    /// Preimage revealed by the recipient, once any part has settled.
+    #[must_use]
+    pub fn preimage(&self) -> Option<&PaymentPreimage> {
+        self.preimage.as_ref()
+    }
+
+    /// Amount carried by parts that have not failed.
+    #[must_use]
+    pub fn routed_msat(&self) -> u64 {
+        self.parts
+            .iter()
+            .filter(|part| part.status != PaymentStatus::Failed)
+            .map(|part| part.route.amount_msat)
+            .sum()
+    }
+
+    /// Amount still needing a route, e.g. after a part failed temporarily.
+    #[must_use]
+    pub fn unrouted_msat(&self) -> u64 {
+        self.total_msat.saturating_sub(self.routed_msat())
+    }
+
+    /// Add a part, e.g. to replace one that failed temporarily.
+    pub fn add_part(&mut self, route: PaymentRoute) -> PaymentResult<u32> {
+        if route.amount_msat == 0 {
+            return Err(PaymentError::Routing("Payment part carries no amount".into()));
+        }
+        if route.amount_msat > self.unrouted_msat() {
+            return Err(PaymentError::Routing(
+                format!(
+                    "Part of {} msat exceeds the {} msat left to route",
+                    route.amount_msat,
+                    self.unrouted_msat()
+                )
+                .into(),
+            ));
+        }
+        if !self.parts.is_empty() && self.payment_secret.is_none() {
+            return Err(PaymentError::Invoice(
+                "Multi-part payments require a payment secret".into(),
+            ));
+        }
+
+        let part_id = self.next_part_id;
+        self.next_part_id += 1;
+        self.parts.push(PaymentPart {
+            part_id,
+            route,
+            status: PaymentStatus::Pending,
+            permanent: false,
+        });
+        Ok(part_id)
+    }
+
+    /// Mark a part as sent.
+    pub fn mark_in_flight(&mut self, part_id: u32) -> PaymentResult<()> {
+        let part = self.part_mut(part_id)?;
+        if part.status != PaymentStatus::Pending {
+            return Err(PaymentError::Routing(format!("Part {part_id} is not pending").into()));
+        }
+        part.status = PaymentStatus::InFlight;
+        Ok(())
+    }
+
+    /// Record that a part settled with the recipient's preimage.
+    pub fn settle_part(&mut self, part_id: u32, preimage: PaymentPreimage) -> PaymentResult<()> {
+        if !preimage.matches(&self.payment_hash) {
+            return Err(PaymentError::Invoice("Preimage does not match payment hash".into()));
+        }
+        let part = self.part_mut(part_id)?;
+        if part.status == PaymentStatus::Failed {
+            return Err(PaymentError::Routing(format!("Part {part_id} already failed").into()));
+        }
+        part.status = PaymentStatus::Succeeded;
+        self.preimage = Some(preimage);
+        Ok(())
+    }
+
+    /// Record that a part failed.
+    ///
+    /// A temporary failure leaves its amount to be routed again; a permanent
+    /// failure fails the whole payment.
+    pub fn fail_part(&mut self, part_id: u32, permanent: bool) -> PaymentResult<()> {
+        let part = self.part_mut(part_id)?;
+        if part.status == PaymentStatus::Succeeded {
+            return Err(PaymentError::Routing(format!("Part {part_id} already settled").into()));
+        }
+        part.status = PaymentStatus::Failed;
+        part.permanent = permanent;
+        Ok(())
+    }
+
+    /// Aggregate status of the payment.
+    ///
+    /// Succeeds once parts covering the total amount have all settled and fails
+    /// as soon as any part fails permanently.
+    #[must_use]
+    pub fn status(&self) -> PaymentStatus {
+        let live = || self.parts.iter().filter(|part| part.status != PaymentStatus::Failed);
+
+        if self.parts.iter().any(|part| part.permanent) {
+            PaymentStatus::Failed
+        } else if self.unrouted_msat() == 0
+            && live().all(|part| part.status == PaymentStatus::Succeeded)
+        {
+            PaymentStatus::Succeeded
+        } else if live().any(|part| part.status != PaymentStatus::Pending) {
+            PaymentStatus::InFlight
+        } else {
+            PaymentStatus::Pending
+        }
+    }
+
+    fn part_mut(&mut self, part_id: u32) -> PaymentResult<&mut PaymentPart> {
+        self.parts
+            .iter_mut()
+            .find(|part| part.part_id == part_id)
+            .ok_or_else(|| PaymentError::Routing(format!("Unknown payment part {part_id}").into()))
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::types::RouteHop;
+
+    fn route(amount_msat: u64) -> PaymentRoute {
+        PaymentRoute {
+            hops: vec![RouteHop {
+                pubkey:            [2; 33],
+                short_channel_id:  1,
+                fee_msat:          0,
+                cltv_expiry_delta: 0,
+            }],
+            total_fees_msat: 0,
+            total_cltv_delta: 18,
+            amount_msat,
+        }
+    }
+
+    fn payment(parts: &[u64]) -> (MultiPathPayment, PaymentPreimage) {
+        let preimage = PaymentPreimage::new([7; 32]);
+        let total = parts.iter().sum();
+        let routes = parts.iter().map(|&amount| route(amount)).collect();
+        let payment =
+            MultiPathPayment::new(preimage.payment_hash(), Some([1; 32]), total, routes).unwrap();
+        (payment, preimage)
+    }
+
+    #[test]
+    fn test_succeeds_once_all_parts_settle() {
+        let (mut payment, preimage) = payment(&[60_000, 40_000]);
+        payment.mark_in_flight(0).unwrap();
+        payment.mark_in_flight(1).unwrap();
+
+        payment.settle_part(0, preimage).unwrap();
+        assert_eq!(payment.status(), PaymentStatus::InFlight);
+
+        payment.settle_part(1, preimage).unwrap();
+        assert_eq!(payment.status(), PaymentStatus::Succeeded);
+        assert_eq!(payment.preimage(), Some(&preimage));
+    }
+
+    #[test]
+    fn test_temporary_failure_needs_new_part() {
+        let (mut payment, preimage) = payment(&[60_000, 40_000]);
+        payment.mark_in_flight(0).unwrap();
+        payment.mark_in_flight(1).unwrap();
+
+        payment.fail_part(1, false).unwrap();
+        assert_eq!(payment.status(), PaymentStatus::InFlight);
+        assert_eq!(payment.unrouted_msat(), 40_000);
+        assert!(payment.add_part(route(50_000)).is_err());
+
+        let replacement = payment.add_part(route(40_000)).unwrap();
+        payment.mark_in_flight(replacement).unwrap();
+        payment.settle_part(0, preimage).unwrap();
+        payment.settle_part(replacement, preimage).unwrap();
+        assert_eq!(payment.status(), PaymentStatus::Succeeded);
+    }
+
+    #[test]
+    fn test_permanent_failure_fails_payment() {
+        let (mut payment, _) = payment(&[60_000, 40_000]);
+        payment.mark_in_flight(0).unwrap();
+        payment.fail_part(0, true).unwrap();
+        assert_eq!(payment.status(), PaymentStatus::Failed);
+    }
+
+    #[test]
+    fn test_multiple_parts_require_secret() {
+        let hash = PaymentPreimage::new([7; 32]).payment_hash();
+        let routes = vec![route(1_000), route(1_000)];
+        assert!(MultiPathPayment::new(hash, None, 2_000, routes).is_err());
+    }
+
+    #[test]
+    fn test_rejects_wrong_preimage() {
+        let (mut payment, _) = payment(&[10_000]);
+        assert!(payment.settle_part(0, PaymentPreimage::new([8; 32])).is_err());
+    }
+}
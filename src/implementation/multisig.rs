@@ -0,0 +1,410 @@
+//! 2-of-3 multisig escrow scripts.
+//!
+//! Bounty funds locked on-chain pay to a P2WSH output whose witness script is a
+//! 2-of-3 `OP_CHECKMULTISIG` over the funder, claimant and arbiter keys. Keys
+//! are sorted (BIP 67) so every party derives the same script and address
+//! regardless of the order they were supplied in.
+
+use core::fmt;
+
+use crate::{
+    crypto::{hash160, sha256, PublicKey},
+    encoding::{bech32, hex},
+    errors::{ErrorDetail, PaymentError},
+    implementation::{
+        psbt::{Psbt, PsbtError},
+        transaction::{OutPoint, Transaction, TxIn, TxOut, SEQUENCE_FINAL},
+    },
+    types::{EscrowType, Network},
+};
+
+/// Script opcodes used by the escrow script.
+mod opcode {
+    pub const OP_0: u8 = 0x00;
+    pub const OP_1: u8 = 0x51;
+    pub const OP_2: u8 = 0x52;
+    pub const OP_3: u8 = 0x53;
+    pub const OP_16: u8 = 0x60;
+    pub const OP_CHECKMULTISIG: u8 = 0xae;
+    /// Push of the next 20 bytes.
+    pub const PUSH_20: u8 = 0x14;
+    /// Push of the next 32 bytes.
+    pub const PUSH_32: u8 = 0x20;
+    /// Push of the next 33 bytes.
+    pub const PUSH_33: u8 = 0x21;
+}
+
+/// Signatures required to spend the escrow.
+pub const MULTISIG_THRESHOLD: usize = 2;
+
+/// Smallest P2WPKH output relayed by default policy.
+pub const P2WPKH_DUST_SATS: u64 = 294;
+
+/// Reasons a multisig escrow is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultisigError {
+    /// A key is not a valid compressed secp256k1 public key.
+    InvalidPublicKey(&'static str),
+    /// Two parties share a key.
+    DuplicateKey,
+    /// Escrow is not a multisig escrow.
+    NotMultiSig,
+    /// Stored redeem script is not hex.
+    InvalidRedeemScript,
+    /// Stored redeem script differs from the one the keys produce.
+    RedeemScriptMismatch,
+}
+
+impl fmt::Display for MultisigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPublicKey(party) => write!(f, "invalid {party} public key"),
+            Self::DuplicateKey => write!(f, "escrow parties must use distinct keys"),
+            Self::NotMultiSig => write!(f, "escrow is not a multisig escrow"),
+            Self::InvalidRedeemScript => write!(f, "redeem script is not valid hex"),
+            Self::RedeemScriptMismatch => {
+                write!(f, "redeem script does not match the escrow keys")
+            },
+        }
+    }
+}
+
+impl std::error::Error for MultisigError {}
+
+impl From<MultisigError> for PaymentError {
+    fn from(err: MultisigError) -> Self {
+        PaymentError::Escrow(ErrorDetail::caused_by(err.to_string(), err))
+    }
+}
+
+/// 2-of-3 multisig escrow over the funder, claimant and arbiter keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultisigEscrow {
+    funder:   [u8; 33],
+    claimant: [u8; 33],
+    arbiter:  [u8; 33],
+}
+
+impl MultisigEscrow {
+    /// Build the escrow from compressed public keys.
+    pub fn new(
+        funder: [u8; 33], claimant: [u8; 33], arbiter: [u8; 33],
+    ) -> Result<Self, MultisigError> {
+        for (key, party) in [(&funder, "funder"), (&claimant, "claimant"), (&arbiter, "arbiter")] {
+            PublicKey::from_slice(key).map_err(|_| MultisigError::InvalidPublicKey(party))?;
+        }
+        if funder == claimant || funder == arbiter || claimant == arbiter {
+            return Err(MultisigError::DuplicateKey);
+        }
+        Ok(Self { funder, claimant, arbiter })
+    }
+
+    /// Rebuild the escrow from an [`EscrowType::MultiSig`], checking that its
+    /// redeem script is the one its keys produce.
+    pub fn from_escrow_type(escrow_type: &EscrowType) -> Result<Self, MultisigError> {
+        let EscrowType::MultiSig { funder_pubkey, claimant_pubkey, arbiter_pubkey, redeem_script } =
+            escrow_type
+        else {
+            return Err(MultisigError::NotMultiSig);
+        };
+
+        let escrow = Self::new(
+            parse_key(funder_pubkey, "funder")?,
+            parse_key(claimant_pubkey, "claimant")?,
+            parse_key(arbiter_pubkey, "arbiter")?,
+        )?;
+        let script = hex::decode(redeem_script).ok_or(MultisigError::InvalidRedeemScript)?;
+        if script != escrow.witness_script() {
+            return Err(MultisigError::RedeemScriptMismatch);
+        }
+        Ok(escrow)
+    }
+
+    /// Check an [`EscrowType::MultiSig`] for internal consistency.
+    pub fn validate(escrow_type: &EscrowType) -> Result<(), MultisigError> {
+        Self::from_escrow_type(escrow_type).map(|_| ())
+    }
+
+    /// Funder key.
+    #[must_use]
+    pub fn funder(&self) -> &[u8; 33] {
+        &self.funder
+    }
+
+    /// Claimant key.
+    #[must_use]
+    pub fn claimant(&self) -> &[u8; 33] {
+        &self.claimant
+    }
+
+    /// Arbiter key.
+    #[must_use]
+    pub fn arbiter(&self) -> &[u8; 33] {
+        &self.arbiter
+    }
+
+    /// Keys in script order, sorted lexicographically.
+    #[must_use]
+    pub fn sorted_keys(&self) -> [[u8; 33]; 3] {
+        let mut keys = [self.funder, self.claimant, self.arbiter];
+        keys.sort_unstable();
+        keys
+    }
+
+    /// `OP_2 <key> <key> <key> OP_3 OP_CHECKMULTISIG` over the sorted keys.
+    #[must_use]
+    pub fn witness_script(&self) -> Vec<u8> {
+        let mut script = Vec::with_capacity(3 + 3 * 34);
+        script.push(opcode::OP_2);
+        for key in self.sorted_keys() {
+            script.push(opcode::PUSH_33);
+            script.extend_from_slice(&key);
+        }
+        script.push(opcode::OP_3);
+        script.push(opcode::OP_CHECKMULTISIG);
+        script
+    }
+
+    /// P2WSH output script paying to the escrow.
+    #[must_use]
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        p2wsh_script_pubkey(&self.witness_script())
+    }
+
+    /// P2WSH address of the escrow on `network`.
+    #[must_use]
+    pub fn address(&self, network: Network) -> String {
+        p2wsh_address(&self.witness_script(), network)
+    }
+
+    /// Unsigned PSBT spending the escrow output `funding`, worth `funding_sats`,
+    /// to `outputs`. Whatever the outputs leave over is the fee.
+    pub fn spend_psbt(
+        &self, funding: OutPoint, funding_sats: u64, outputs: Vec<TxOut>,
+    ) -> Result<Psbt, PsbtError> {
+        let total = outputs.iter().try_fold(0u64, |sum, output| sum.checked_add(output.value));
+        if total.is_none_or(|total| total > funding_sats) {
+            return Err(PsbtError::OutputsExceedInputs);
+        }
+
+        let tx = Transaction {
+            version:   2,
+            input:     vec![TxIn {
+                previous_output: funding,
+                script_sig:      Vec::new(),
+                sequence:        SEQUENCE_FINAL,
+                witness:         Vec::new(),
+            }],
+            output:    outputs,
+            lock_time: 0,
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx)?;
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: funding_sats, script_pubkey: self.script_pubkey() });
+        psbt.inputs[0].witness_script = Some(self.witness_script());
+        Ok(psbt)
+    }
+
+    /// PSBT paying the escrow, less `fee_sats`, to the claimant's P2WPKH.
+    pub fn release_psbt(
+        &self, funding: OutPoint, funding_sats: u64, fee_sats: u64,
+    ) -> Result<Psbt, PsbtError> {
+        self.pay_single(&self.claimant, funding, funding_sats, fee_sats)
+    }
+
+    /// PSBT returning the escrow, less `fee_sats`, to the funder's P2WPKH.
+    pub fn refund_psbt(
+        &self, funding: OutPoint, funding_sats: u64, fee_sats: u64,
+    ) -> Result<Psbt, PsbtError> {
+        self.pay_single(&self.funder, funding, funding_sats, fee_sats)
+    }
+
+    /// PSBT paying the claimant `claimant_percent` of the escrow less
+    /// `fee_sats`, and the rest back to the funder.
+    pub fn split_psbt(
+        &self, funding: OutPoint, funding_sats: u64, fee_sats: u64, claimant_percent: u8,
+    ) -> Result<Psbt, PsbtError> {
+        let spendable = funding_sats.checked_sub(fee_sats).ok_or(PsbtError::OutputsExceedInputs)?;
+        let claimant_sats =
+            (u128::from(spendable) * u128::from(claimant_percent.min(100)) / 100) as u64;
+        let outputs = vec![
+            p2wpkh_output(&self.claimant, claimant_sats)?,
+            p2wpkh_output(&self.funder, spendable - claimant_sats)?,
+        ];
+        self.spend_psbt(funding, funding_sats, outputs)
+    }
+
+    fn pay_single(
+        &self, pubkey: &[u8; 33], funding: OutPoint, funding_sats: u64, fee_sats: u64,
+    ) -> Result<Psbt, PsbtError> {
+        let value = funding_sats.checked_sub(fee_sats).ok_or(PsbtError::OutputsExceedInputs)?;
+        self.spend_psbt(funding, funding_sats, vec![p2wpkh_output(pubkey, value)?])
+    }
+
+    /// Escrow description with hex keys and redeem script.
+    #[must_use]
+    pub fn escrow_type(&self) -> EscrowType {
+        EscrowType::MultiSig {
+            funder_pubkey:   hex::encode(&self.funder),
+            claimant_pubkey: hex::encode(&self.claimant),
+            arbiter_pubkey:  hex::encode(&self.arbiter),
+            redeem_script:   hex::encode(&self.witness_script()),
+        }
+    }
+}
+
+/// `OP_0 <sha256(script)>` output script for a witness script.
+#[must_use]
+pub fn p2wsh_script_pubkey(witness_script: &[u8]) -> Vec<u8> {
+    let mut script_pubkey = Vec::with_capacity(34);
+    script_pubkey.push(opcode::OP_0);
+    script_pubkey.push(opcode::PUSH_32);
+    script_pubkey.extend_from_slice(&sha256(witness_script));
+    script_pubkey
+}
+
+/// `OP_0 <hash160(pubkey)>` output script paying a compressed public key.
+#[must_use]
+pub fn p2wpkh_script_pubkey(pubkey: &[u8; 33]) -> Vec<u8> {
+    let mut script_pubkey = Vec::with_capacity(22);
+    script_pubkey.push(opcode::OP_0);
+    script_pubkey.push(opcode::PUSH_20);
+    script_pubkey.extend_from_slice(&hash160(pubkey));
+    script_pubkey
+}
+
+fn p2wpkh_output(pubkey: &[u8; 33], value: u64) -> Result<TxOut, PsbtError> {
+    if value < P2WPKH_DUST_SATS {
+        return Err(PsbtError::DustOutput);
+    }
+    Ok(TxOut { value, script_pubkey: p2wpkh_script_pubkey(pubkey) })
+}
+
+/// Threshold and keys of an `OP_m <key>... OP_n OP_CHECKMULTISIG` script over
+/// compressed keys.
+pub(crate) fn multisig_keys(script: &[u8]) -> Option<(usize, Vec<[u8; 33]>)> {
+    let (&op_m, rest) = script.split_first()?;
+    let (&op_checkmultisig, rest) = rest.split_last()?;
+    let (&op_n, rest) = rest.split_last()?;
+    let small_int =
+        |op: u8| (opcode::OP_1..=opcode::OP_16).contains(&op).then(|| (op - 0x50) as usize);
+    let (threshold, count) = (small_int(op_m)?, small_int(op_n)?);
+    if op_checkmultisig != opcode::OP_CHECKMULTISIG || threshold > count || rest.len() != count * 34
+    {
+        return None;
+    }
+
+    rest.chunks_exact(34)
+        .map(|push| match push.split_first() {
+            Some((&opcode::PUSH_33, key)) => key.try_into().ok(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|keys| (threshold, keys))
+}
+
+/// Segwit v0 address for a witness script.
+#[must_use]
+pub fn p2wsh_address(witness_script: &[u8], network: Network) -> String {
+    let program = sha256(witness_script);
+    // Witness version 0, then the program; padding bytes into 5-bit groups cannot fail.
+    let mut data = vec![0u8];
+    data.extend(bech32::convert_bits(&program, 8, 5, true).unwrap_or_default());
+    bech32::encode(network.segwit_hrp(), &data)
+}
+
+fn parse_key(encoded: &str, party: &'static str) -> Result<[u8; 33], MultisigError> {
+    hex::decode(encoded)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(MultisigError::InvalidPublicKey(party))
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::crypto::SecretKey;
+
+    fn key(byte: u8) -> [u8; 33] {
+        SecretKey::from_slice(&[byte; 32]).unwrap().public_key().serialize()
+    }
+
+    #[test]
+    fn test_p2wsh_address_vectors() {
+        // BIP 173 P2WSH vectors for a pay-to-pubkey witness script.
+        let script =
+            hex::decode("210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac")
+                .unwrap();
+        assert_eq!(
+            p2wsh_address(&script, Network::Bitcoin),
+            "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3"
+        );
+        assert_eq!(
+            p2wsh_address(&script, Network::Testnet),
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        );
+        assert!(p2wsh_address(&script, Network::Regtest).starts_with("bcrt1q"));
+    }
+
+    #[test]
+    fn test_script_is_independent_of_key_order() {
+        let escrow = MultisigEscrow::new(key(1), key(2), key(3)).unwrap();
+        let shuffled = MultisigEscrow::new(key(3), key(1), key(2)).unwrap();
+        assert_eq!(escrow.witness_script(), shuffled.witness_script());
+        assert_eq!(escrow.address(Network::Regtest), shuffled.address(Network::Regtest));
+
+        let script = escrow.witness_script();
+        assert_eq!(script.len(), 105);
+        assert_eq!((script[0], script[103], script[104]), (0x52, 0x53, 0xae));
+        let keys = escrow.sorted_keys();
+        assert!(keys[0] < keys[1] && keys[1] < keys[2]);
+        assert_eq!(&script[2..35], &keys[0]);
+        assert_eq!(&escrow.script_pubkey()[2..], &sha256(&script));
+    }
+
+    #[test]
+    fn test_rejects_bad_keys() {
+        assert_eq!(MultisigEscrow::new(key(1), key(1), key(3)), Err(MultisigError::DuplicateKey));
+        assert_eq!(
+            MultisigEscrow::new(key(1), [4; 33], key(3)),
+            Err(MultisigError::InvalidPublicKey("claimant"))
+        );
+    }
+
+    #[test]
+    fn test_validates_escrow_type() {
+        let escrow = MultisigEscrow::new(key(1), key(2), key(3)).unwrap();
+        let escrow_type = escrow.escrow_type();
+        assert_eq!(MultisigEscrow::from_escrow_type(&escrow_type), Ok(escrow));
+
+        let EscrowType::MultiSig { funder_pubkey, claimant_pubkey, arbiter_pubkey, .. } =
+            escrow_type
+        else {
+            unreachable!()
+        };
+        let other = MultisigEscrow::new(key(1), key(2), key(4)).unwrap();
+        let tampered = EscrowType::MultiSig {
+            funder_pubkey:   funder_pubkey.clone(),
+            claimant_pubkey: claimant_pubkey.clone(),
+            arbiter_pubkey:  arbiter_pubkey.clone(),
+            redeem_script:   hex::encode(&other.witness_script()),
+        };
+        assert_eq!(MultisigEscrow::validate(&tampered), Err(MultisigError::RedeemScriptMismatch));
+
+        let garbled = EscrowType::MultiSig {
+            funder_pubkey,
+            claimant_pubkey,
+            arbiter_pubkey: "zz".into(),
+            redeem_script: String::new(),
+        };
+        assert_eq!(
+            MultisigEscrow::validate(&garbled),
+            Err(MultisigError::InvalidPublicKey("arbiter"))
+        );
+
+        let hold = EscrowType::LightningHold {
+            payment_hash: crate::types::PaymentHash::new([0; 32]),
+            preimage:     None,
+        };
+        assert_eq!(MultisigEscrow::validate(&hold), Err(MultisigError::NotMultiSig));
+    }
+}
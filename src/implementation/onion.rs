@@ -0,0 +1,578 @@
+//! BOLT4 onion keys and failure messages.
+//!
+//! The sender derives a shared secret with every hop of a route from a fresh
+//! session key. A hop that fails an HTLC returns a failure message
+//! authenticated and encrypted under its own secret, and every earlier hop adds
+//! a layer of encryption on the way back. The sender peels the layers in route
+//! order until a message authenticates, which identifies the erring hop.
+
+use core::fmt;
+
+use crate::{
+    crypto::{chacha20, hmac_sha256, sha256, PublicKey, SecretKey},
+    errors::{ErrorDetail, PaymentError},
+    implementation::{
+        gossip::{ChannelUpdate, GossipMessage, Reader, CHANNEL_UPDATE_TYPE},
+        retry::AttemptFailure,
+        sphinx::{HOP_PAYLOADS_LEN, ONION_PACKET_LEN},
+    },
+    types::PaymentRoute,
+};
+
+/// Length failure messages are padded to, hiding which failure occurred.
+pub const FAILURE_MESSAGE_PAD_LEN: usize = 256;
+
+/// Shortest returned failure packet: HMAC, two lengths and the padded message.
+const MIN_FAILURE_PACKET_LEN: usize = 32 + 2 + 2 + FAILURE_MESSAGE_PAD_LEN;
+
+/// Onion construction and failure decoding errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OnionError {
+    /// A hop public key is not a valid curve point.
+    InvalidPublicKey,
+    /// The route has no hops.
+    EmptyRoute,
+    /// Failure packet is shorter than the padded minimum.
+    FailureTooShort(usize),
+    /// No hop's key authenticates the failure packet.
+    UnknownErringHop,
+    /// Failure message authenticated but its contents are malformed.
+    MalformedFailure(&'static str),
+    /// Number of hop payloads differs from the number of hops.
+    PayloadCountMismatch,
+    /// Hop payloads and their HMACs exceed the packet's payload space.
+    PayloadsTooLarge(usize),
+    /// Onion packet is not the fixed packet length.
+    InvalidPacketLength(usize),
+    /// Onion packet version is not 0.
+    UnknownVersion(u8),
+    /// Onion packet HMAC does not authenticate its contents.
+    InvalidHmac,
+    /// Hop payload is not a valid TLV stream.
+    MalformedPayload(&'static str),
+}
+
+impl fmt::Display for OnionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPublicKey => write!(f, "invalid hop public key"),
+            Self::EmptyRoute => write!(f, "route has no hops"),
+            Self::FailureTooShort(len) => {
+                write!(f, "failure packet of {len} bytes is shorter than {MIN_FAILURE_PACKET_LEN}")
+            },
+            Self::UnknownErringHop => write!(f, "failure packet matches no hop on the route"),
+            Self::MalformedFailure(what) => write!(f, "malformed failure message: {what}"),
+            Self::PayloadCountMismatch => write!(f, "hop payloads do not match the hops"),
+            Self::PayloadsTooLarge(len) => {
+                write!(f, "hop payloads of {len} bytes exceed {HOP_PAYLOADS_LEN}")
+            },
+            Self::InvalidPacketLength(len) => {
+                write!(f, "onion packet of {len} bytes is not {ONION_PACKET_LEN}")
+            },
+            Self::UnknownVersion(version) => write!(f, "unknown onion version {version}"),
+            Self::InvalidHmac => write!(f, "onion HMAC does not match"),
+            Self::MalformedPayload(what) => write!(f, "malformed hop payload: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for OnionError {}
+
+impl From<OnionError> for PaymentError {
+    fn from(err: OnionError) -> Self {
+        PaymentError::Routing(ErrorDetail::caused_by(format!("Onion: {err}"), err).permanent())
+    }
+}
+
+/// Keys shared between the sender and one hop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnionHopKeys {
+    /// Ephemeral public key the hop receives.
+    pub ephemeral_pubkey: [u8; 33],
+    /// ECDH secret between the ephemeral key and the hop's node key.
+    pub shared_secret:    [u8; 32],
+}
+
+/// Derive the keys shared with each hop from the session key.
+///
+/// Each hop's ephemeral key is the previous one blinded by the hash of that
+/// key and its shared secret, so hops cannot link their keys.
+pub fn hop_keys(
+    session_key: &SecretKey, hops: &[[u8; 33]],
+) -> Result<Vec<OnionHopKeys>, OnionError> {
+    let mut ephemeral = *session_key;
+    let mut keys = Vec::with_capacity(hops.len());
+    for hop in hops {
+        let node = PublicKey::from_slice(hop).map_err(|_| OnionError::InvalidPublicKey)?;
+        let ephemeral_pubkey = ephemeral.public_key().serialize();
+        let shared_secret = ephemeral.ecdh(&node);
+
+        let mut preimage = [0u8; 65];
+        preimage[..33].copy_from_slice(&ephemeral_pubkey);
+        preimage[33..].copy_from_slice(&shared_secret);
+        ephemeral =
+            ephemeral.mul_tweak(&sha256(&preimage)).map_err(|_| OnionError::InvalidPublicKey)?;
+        keys.push(OnionHopKeys { ephemeral_pubkey, shared_secret });
+    }
+    Ok(keys)
+}
+
+/// Derive a key of the named type (`rho`, `mu`, `um`, `ammag`, `pad`) from a shared secret.
+#[must_use]
+pub fn generate_key(key_type: &[u8], shared_secret: &[u8; 32]) -> [u8; 32] {
+    hmac_sha256(key_type, shared_secret)
+}
+
+/// BOLT4 failure code: a type in the low byte and flags in the high byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FailureCode(pub u16);
+
+impl FailureCode {
+    /// The onion could not be parsed by the hop before the reporting one.
+    pub const BADONION: u16 = 0x8000;
+    /// Retrying the same hop will not help.
+    pub const PERM: u16 = 0x4000;
+    /// The failure concerns the node rather than a channel.
+    pub const NODE: u16 = 0x2000;
+    /// The message carries a `channel_update`.
+    pub const UPDATE: u16 = 0x1000;
+
+    /// `invalid_realm`.
+    pub const INVALID_REALM: Self = Self(Self::PERM | 1);
+    /// `temporary_node_failure`.
+    pub const TEMPORARY_NODE_FAILURE: Self = Self(Self::NODE | 2);
+    /// `permanent_node_failure`.
+    pub const PERMANENT_NODE_FAILURE: Self = Self(Self::PERM | Self::NODE | 2);
+    /// `required_node_feature_missing`.
+    pub const REQUIRED_NODE_FEATURE_MISSING: Self = Self(Self::PERM | Self::NODE | 3);
+    /// `invalid_onion_version`.
+    pub const INVALID_ONION_VERSION: Self = Self(Self::BADONION | Self::PERM | 4);
+    /// `invalid_onion_hmac`.
+    pub const INVALID_ONION_HMAC: Self = Self(Self::BADONION | Self::PERM | 5);
+    /// `invalid_onion_key`.
+    pub const INVALID_ONION_KEY: Self = Self(Self::BADONION | Self::PERM | 6);
+    /// `temporary_channel_failure`.
+    pub const TEMPORARY_CHANNEL_FAILURE: Self = Self(Self::UPDATE | 7);
+    /// `permanent_channel_failure`.
+    pub const PERMANENT_CHANNEL_FAILURE: Self = Self(Self::PERM | 8);
+    /// `required_channel_feature_missing`.
+    pub const REQUIRED_CHANNEL_FEATURE_MISSING: Self = Self(Self::PERM | 9);
+    /// `unknown_next_peer`.
+    pub const UNKNOWN_NEXT_PEER: Self = Self(Self::PERM | 10);
+    /// `amount_below_minimum`.
+    pub const AMOUNT_BELOW_MINIMUM: Self = Self(Self::UPDATE | 11);
+    /// `fee_insufficient`.
+    pub const FEE_INSUFFICIENT: Self = Self(Self::UPDATE | 12);
+    /// `incorrect_cltv_expiry`.
+    pub const INCORRECT_CLTV_EXPIRY: Self = Self(Self::UPDATE | 13);
+    /// `expiry_too_soon`.
+    pub const EXPIRY_TOO_SOON: Self = Self(Self::UPDATE | 14);
+    /// `incorrect_or_unknown_payment_details`.
+    pub const INCORRECT_OR_UNKNOWN_PAYMENT_DETAILS: Self = Self(Self::PERM | 15);
+    /// `final_incorrect_cltv_expiry`.
+    pub const FINAL_INCORRECT_CLTV_EXPIRY: Self = Self(18);
+    /// `final_incorrect_htlc_amount`.
+    pub const FINAL_INCORRECT_HTLC_AMOUNT: Self = Self(19);
+    /// `channel_disabled`.
+    pub const CHANNEL_DISABLED: Self = Self(Self::UPDATE | 20);
+    /// `expiry_too_far`.
+    pub const EXPIRY_TOO_FAR: Self = Self(21);
+    /// `invalid_onion_payload`.
+    pub const INVALID_ONION_PAYLOAD: Self = Self(Self::PERM | 22);
+    /// `mpp_timeout`.
+    pub const MPP_TIMEOUT: Self = Self(23);
+    /// `invalid_onion_blinding`.
+    pub const INVALID_ONION_BLINDING: Self = Self(Self::BADONION | Self::PERM | 24);
+
+    /// Whether the hop before the reporting one could not parse the onion.
+    #[must_use]
+    pub fn is_bad_onion(self) -> bool {
+        self.0 & Self::BADONION != 0
+    }
+
+    /// Whether retrying through the same hop will not help.
+    #[must_use]
+    pub fn is_permanent(self) -> bool {
+        self.0 & Self::PERM != 0
+    }
+
+    /// Whether the failure concerns the node rather than a channel.
+    #[must_use]
+    pub fn is_node(self) -> bool {
+        self.0 & Self::NODE != 0
+    }
+
+    /// Whether the message carries a `channel_update`.
+    #[must_use]
+    pub fn has_update(self) -> bool {
+        self.0 & Self::UPDATE != 0
+    }
+
+    /// BOLT4 name of the code, if it is a known one.
+    #[must_use]
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self {
+            Self::INVALID_REALM => "invalid_realm",
+            Self::TEMPORARY_NODE_FAILURE => "temporary_node_failure",
+            Self::PERMANENT_NODE_FAILURE => "permanent_node_failure",
+            Self::REQUIRED_NODE_FEATURE_MISSING => "required_node_feature_missing",
+            Self::INVALID_ONION_VERSION => "invalid_onion_version",
+            Self::INVALID_ONION_HMAC => "invalid_onion_hmac",
+            Self::INVALID_ONION_KEY => "invalid_onion_key",
+            Self::TEMPORARY_CHANNEL_FAILURE => "temporary_channel_failure",
+            Self::PERMANENT_CHANNEL_FAILURE => "permanent_channel_failure",
+            Self::REQUIRED_CHANNEL_FEATURE_MISSING => "required_channel_feature_missing",
+            Self::UNKNOWN_NEXT_PEER => "unknown_next_peer",
+            Self::AMOUNT_BELOW_MINIMUM => "amount_below_minimum",
+            Self::FEE_INSUFFICIENT => "fee_insufficient",
+            Self::INCORRECT_CLTV_EXPIRY => "incorrect_cltv_expiry",
+            Self::EXPIRY_TOO_SOON => "expiry_too_soon",
+            Self::INCORRECT_OR_UNKNOWN_PAYMENT_DETAILS => "incorrect_or_unknown_payment_details",
+            Self::FINAL_INCORRECT_CLTV_EXPIRY => "final_incorrect_cltv_expiry",
+            Self::FINAL_INCORRECT_HTLC_AMOUNT => "final_incorrect_htlc_amount",
+            Self::CHANNEL_DISABLED => "channel_disabled",
+            Self::EXPIRY_TOO_FAR => "expiry_too_far",
+            Self::INVALID_ONION_PAYLOAD => "invalid_onion_payload",
+            Self::MPP_TIMEOUT => "mpp_timeout",
+            Self::INVALID_ONION_BLINDING => "invalid_onion_blinding",
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for FailureCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name} (0x{:04x})", self.0),
+            None => write!(f, "unknown failure 0x{:04x}", self.0),
+        }
+    }
+}
+
+/// Build the failure packet an erring hop returns, encrypted under its shared secret.
+///
+/// `data` is the code-specific part of the message following the code.
+#[must_use]
+pub fn encode_failure(shared_secret: &[u8; 32], code: FailureCode, data: &[u8]) -> Vec<u8> {
+    let message_len = 2 + data.len();
+    let pad_len = FAILURE_MESSAGE_PAD_LEN.saturating_sub(message_len);
+
+    let mut payload = Vec::with_capacity(2 + message_len + 2 + pad_len);
+    payload.extend_from_slice(&(message_len as u16).to_be_bytes());
+    payload.extend_from_slice(&code.0.to_be_bytes());
+    payload.extend_from_slice(data);
+    payload.extend_from_slice(&(pad_len as u16).to_be_bytes());
+    payload.resize(payload.len() + pad_len, 0);
+
+    let mut packet = hmac_sha256(&generate_key(b"um", shared_secret), &payload).to_vec();
+    packet.extend_from_slice(&payload);
+    wrap_failure(shared_secret, &mut packet);
+    packet
+}
+
+/// Add a hop's layer of encryption to a failure packet travelling back.
+pub fn wrap_failure(shared_secret: &[u8; 32], packet: &mut [u8]) {
+    chacha20::chacha20(&generate_key(b"ammag", shared_secret), &[0u8; 12], 0, packet);
+}
+
+/// Peel a failure packet with each hop's shared secret in route order.
+///
+/// Returns the index of the hop whose key authenticates the message, with the
+/// failure message (code and data) it sent.
+pub fn decrypt_failure(
+    shared_secrets: &[[u8; 32]], packet: &[u8],
+) -> Result<(usize, Vec<u8>), OnionError> {
+    if packet.len() < MIN_FAILURE_PACKET_LEN {
+        return Err(OnionError::FailureTooShort(packet.len()));
+    }
+    let mut packet = packet.to_vec();
+    for (index, shared_secret) in shared_secrets.iter().enumerate() {
+        wrap_failure(shared_secret, &mut packet);
+        let (mac, payload) = packet.split_at(32);
+        if hmac_sha256(&generate_key(b"um", shared_secret), payload)[..] != *mac {
+            continue;
+        }
+        let len = usize::from(u16::from_be_bytes([payload[0], payload[1]]));
+        let message = payload
+            .get(2..2 + len)
+            .filter(|message| message.len() >= 2)
+            .ok_or(OnionError::MalformedFailure("failure message length"))?;
+        return Ok((index, message.to_vec()));
+    }
+    Err(OnionError::UnknownErringHop)
+}
+
+/// Failure reported by a hop of a payment route, decoded from its failure onion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnionFailure {
+    /// Index into the route's hops of the node that reported the failure.
+    pub hop_index:        usize,
+    /// Node that reported the failure.
+    pub erring_node:      [u8; 33],
+    /// Channel the erring node was asked to forward over; `None` at the payee.
+    pub short_channel_id: Option<u64>,
+    /// BOLT4 failure code.
+    pub code:             FailureCode,
+    /// Whether the payee itself reported the failure.
+    pub from_payee:       bool,
+    /// HTLC amount the erring node saw, in millisatoshis.
+    pub htlc_msat:        Option<u64>,
+    /// CLTV expiry the erring node saw.
+    pub cltv_expiry:      Option<u32>,
+    /// Block height at the payee when it rejected the payment details.
+    pub height:           Option<u32>,
+    /// Latest policy of the failing channel, as sent by the erring node.
+    pub channel_update:   Option<ChannelUpdate>,
+}
+
+impl OnionFailure {
+    /// Decode the failure packet returned for an HTLC sent over `route` with `session_key`.
+    pub fn decode(
+        route: &PaymentRoute, session_key: &SecretKey, packet: &[u8],
+    ) -> Result<Self, OnionError> {
+        if route.hops.is_empty() {
+            return Err(OnionError::EmptyRoute);
+        }
+        let pubkeys: Vec<[u8; 33]> = route.hops.iter().map(|hop| hop.pubkey).collect();
+        let secrets: Vec<[u8; 32]> =
+            hop_keys(session_key, &pubkeys)?.into_iter().map(|keys| keys.shared_secret).collect();
+        let (hop_index, message) = decrypt_failure(&secrets, packet)?;
+        Self::parse(route, hop_index, &message)
+    }
+
+    /// Parse a decrypted failure message reported by hop `hop_index` of `route`.
+    pub fn parse(
+        route: &PaymentRoute, hop_index: usize, message: &[u8],
+    ) -> Result<Self, OnionError> {
+        let hop = route.hops.get(hop_index).ok_or(OnionError::UnknownErringHop)?;
+        let mut reader = Reader::new(message);
+        let code = FailureCode(
+            reader.u16().map_err(|_| OnionError::MalformedFailure("missing failure code"))?,
+        );
+
+        let mut failure = Self {
+            hop_index,
+            erring_node: hop.pubkey,
+            short_channel_id: route.hops.get(hop_index + 1).map(|next| next.short_channel_id),
+            code,
+            from_payee: hop_index + 1 == route.hops.len(),
+            htlc_msat: None,
+            cltv_expiry: None,
+            height: None,
+            channel_update: None,
+        };
+        // Implementations differ in how much data they send; keep whatever parses.
+        match code {
+            FailureCode::AMOUNT_BELOW_MINIMUM | FailureCode::FEE_INSUFFICIENT => {
+                failure.htlc_msat = reader.u64().ok();
+            },
+            FailureCode::INCORRECT_CLTV_EXPIRY | FailureCode::FINAL_INCORRECT_CLTV_EXPIRY => {
+                failure.cltv_expiry = reader.u32().ok();
+            },
+            FailureCode::CHANNEL_DISABLED => {
+                let _disabled_flags = reader.u16();
+            },
+            FailureCode::INCORRECT_OR_UNKNOWN_PAYMENT_DETAILS => {
+                failure.htlc_msat = reader.u64().ok();
+                failure.height = reader.u32().ok();
+            },
+            FailureCode::FINAL_INCORRECT_HTLC_AMOUNT => failure.htlc_msat = reader.u64().ok(),
+            _ => {},
+        }
+        if code.has_update() {
+            failure.channel_update = reader.var_bytes().ok().and_then(|bytes| parse_update(&bytes));
+        }
+        Ok(failure)
+    }
+
+    /// Classify the failure for retrying the payment over another route.
+    ///
+    /// Failures at the payee end the payment. A node failure, or a bad onion
+    /// that the previous hop could not forward, avoids the node; anything else
+    /// avoids the channel the erring node would have forwarded over.
+    #[must_use]
+    pub fn attempt_failure(&self) -> AttemptFailure {
+        let reason = self.code.to_string();
+        match self.short_channel_id {
+            _ if self.from_payee => AttemptFailure::Permanent { reason },
+            Some(short_channel_id) if !self.code.is_node() && !self.code.is_bad_onion() => {
+                AttemptFailure::Channel { short_channel_id, reason }
+            },
+            _ => AttemptFailure::Node { pubkey: self.erring_node, reason },
+        }
+    }
+}
+
+impl fmt::Display for OnionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} from hop {}", self.code, self.hop_index)?;
+        if let Some(short_channel_id) = self.short_channel_id {
+            write!(f, " on channel {short_channel_id}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for OnionFailure {}
+
+impl From<OnionFailure> for PaymentError {
+    fn from(failure: OnionFailure) -> Self {
+        PaymentError::HopFailure(Box::new(failure))
+    }
+}
+
+/// Parse an embedded `channel_update`, with or without its message type.
+fn parse_update(bytes: &[u8]) -> Option<ChannelUpdate> {
+    let typed = if bytes.starts_with(&CHANNEL_UPDATE_TYPE.to_be_bytes()) {
+        bytes.to_vec()
+    } else {
+        [&CHANNEL_UPDATE_TYPE.to_be_bytes()[..], bytes].concat()
+    };
+    match GossipMessage::parse(&typed) {
+        Ok(GossipMessage::ChannelUpdate(update)) => Some(update),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{
+        encoding::hex,
+        implementation::test_utils::{bolt4_route, secret},
+    };
+
+    #[test]
+    fn test_hop_keys_match_bolt4_vectors() {
+        let pubkeys: Vec<[u8; 33]> =
+            bolt4_route(0, 1_000).hops.iter().map(|hop| hop.pubkey).collect();
+        let keys = hop_keys(&secret(0x41), &pubkeys).unwrap();
+        let secrets: Vec<String> = keys.iter().map(|k| hex::encode(&k.shared_secret)).collect();
+        assert_eq!(
+            secrets,
+            [
+                "53eb63ea8a3fec3b3cd433b85cd62a4b145e1dda09391b348c4e1cd36a03ea66",
+                "a6519e98832a0b179f62123b3567c106db99ee37bef036e783263602f3488fae",
+                "3a6b412548762f0dbccce5c7ae7bb8147d1caf9b5471c34120b30bc9c04891cc",
+                "21e13c2d7cfe7e18836df50872466117a295783ab8aab0e7ecc8c725503ad02d",
+                "b5756b9b542727dbafc6765a49488b023a725d631af688fc031217e90770c328",
+            ]
+        );
+        assert_eq!(
+            hex::encode(&keys[1].ephemeral_pubkey),
+            "028f9438bfbf7feac2e108d677e3a82da596be706cc1cf342b75c7b7e22bf4e6e2"
+        );
+        assert_eq!(
+            hex::encode(&generate_key(b"mu", &keys[0].shared_secret)),
+            "b57061dc6d0a2b9f261ac410c8b26d64ac5506cbba30267a649c28c179400eba"
+        );
+    }
+
+    #[test]
+    fn test_decodes_failure_from_intermediate_hop_and_payee() {
+        let route = bolt4_route(0, 1_000);
+        let session_key = secret(0x41);
+        let pubkeys: Vec<[u8; 33]> = route.hops.iter().map(|hop| hop.pubkey).collect();
+        let secrets: Vec<[u8; 32]> =
+            hop_keys(&session_key, &pubkeys).unwrap().iter().map(|k| k.shared_secret).collect();
+        let returned = |index: usize, code: FailureCode, data: &[u8]| {
+            let mut packet = encode_failure(&secrets[index], code, data);
+            for secret in secrets[..index].iter().rev() {
+                wrap_failure(secret, &mut packet);
+            }
+            packet
+        };
+
+        let update = ChannelUpdate {
+            signature:                   [7; 64],
+            chain_hash:                  [0; 32],
+            short_channel_id:            103,
+            timestamp:                   1_700_000_000,
+            message_flags:               1,
+            channel_flags:               0,
+            cltv_expiry_delta:           144,
+            htlc_minimum_msat:           1,
+            fee_base_msat:               2_000,
+            fee_proportional_millionths: 10,
+            htlc_maximum_msat:           1_000_000,
+            excess_data:                 Vec::new(),
+        };
+        let encoded = GossipMessage::ChannelUpdate(update.clone()).encode();
+        let mut data = 1_000u64.to_be_bytes().to_vec();
+        data.extend_from_slice(&(encoded.len() as u16).to_be_bytes());
+        data.extend_from_slice(&encoded);
+        let packet = returned(2, FailureCode::FEE_INSUFFICIENT, &data);
+        assert_eq!(packet.len(), MIN_FAILURE_PACKET_LEN);
+
+        let failure = OnionFailure::decode(&route, &session_key, &packet).unwrap();
+        assert_eq!(failure.hop_index, 2);
+        assert_eq!(failure.erring_node, route.hops[2].pubkey);
+        assert_eq!(failure.short_channel_id, Some(103));
+        assert_eq!((failure.code, failure.htlc_msat), (FailureCode::FEE_INSUFFICIENT, Some(1_000)));
+        assert_eq!(failure.channel_update, Some(update));
+        assert!(matches!(
+            failure.attempt_failure(),
+            AttemptFailure::Channel { short_channel_id: 103, .. }
+        ));
+        assert_eq!(failure.to_string(), "fee_insufficient (0x100c) from hop 2 on channel 103");
+
+        let mut data = 1_000u64.to_be_bytes().to_vec();
+        data.extend_from_slice(&800_000u32.to_be_bytes());
+        let packet = returned(4, FailureCode::INCORRECT_OR_UNKNOWN_PAYMENT_DETAILS, &data);
+        let failure = OnionFailure::decode(&route, &session_key, &packet).unwrap();
+        assert!(failure.from_payee && failure.code.is_permanent());
+        assert_eq!((failure.short_channel_id, failure.height), (None, Some(800_000)));
+        assert!(failure.attempt_failure().is_permanent());
+        let err = PaymentError::from(failure);
+        assert!(matches!(err, PaymentError::HopFailure(_)) && err.is_permanent());
+
+        let packet = returned(1, FailureCode::TEMPORARY_NODE_FAILURE, &[]);
+        let failure = OnionFailure::decode(&route, &session_key, &packet).unwrap();
+        assert!(
+            matches!(failure.attempt_failure(), AttemptFailure::Node { pubkey, .. } if pubkey == route.hops[1].pubkey)
+        );
+    }
+
+    #[test]
+    fn test_failure_matches_bolt4_returning_errors_vector() {
+        let route = bolt4_route(0, 1_000);
+        let session_key = secret(0x41);
+        let pubkeys: Vec<[u8; 33]> = route.hops.iter().map(|hop| hop.pubkey).collect();
+        let secrets: Vec<[u8; 32]> =
+            hop_keys(&session_key, &pubkeys).unwrap().iter().map(|k| k.shared_secret).collect();
+
+        // The payee returns temporary_node_failure and every hop adds its layer.
+        let mut packet = encode_failure(&secrets[4], FailureCode::TEMPORARY_NODE_FAILURE, &[]);
+        for secret in secrets[..4].iter().rev() {
+            wrap_failure(secret, &mut packet);
+        }
+        assert_eq!(
+            hex::encode(&packet),
+            "9c5add3963fc7f6ed7f148623c84134b5647e1306419dbe2174e523fa9e2fbed3a06a19f899145610741\
+             c83ad40b7712aefaddec8c6baf7325d92ea4ca4d1df8bce517f7e54554608bf2bd8071a4f52a7a2f7ffb\
+             b1413edad81eeea5785aa9d990f2865dc23b4bc3c301a94eec4eabebca66be5cf638f693ec256aec5146\
+             20cc28ee4a94bd9565bc4d4962b9d3641d4278fb319ed2b84de5b665f307a2db0f7fbb757366067d88c5\
+             0f7e829138fde4f78d39b5b5802f1b92a8a820865af5cc79f9f30bc3f461c66af95d13e5e1f0381c1845\
+             72a91dee1c849048a647a1158cf884064deddbf1b0b88dfe2f791428d0ba0f6fb2f04e14081f69165ae6\
+             6d9297c118f0907705c9c4954a199bae0bb96fad763d690e7daa6cfda59ba7f2c8d11448b604d12d"
+        );
+        assert_eq!(decrypt_failure(&secrets, &packet), Ok((4, vec![0x20, 0x02])));
+    }
+
+    #[test]
+    fn test_rejects_unauthenticated_failure() {
+        let route = bolt4_route(0, 1_000);
+        let session_key = secret(0x41);
+        let mut packet = encode_failure(&[9; 32], FailureCode::TEMPORARY_NODE_FAILURE, &[]);
+        assert_eq!(
+            OnionFailure::decode(&route, &session_key, &packet),
+            Err(OnionError::UnknownErringHop)
+        );
+        packet.truncate(100);
+        assert_eq!(
+            OnionFailure::decode(&route, &session_key, &packet),
+            Err(OnionError::FailureTooShort(100))
+        );
+        assert_eq!(FailureCode(0x0099).to_string(), "unknown failure 0x0099");
+    }
+}
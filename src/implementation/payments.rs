@@ -0,0 +1,358 @@
+//! Payment lifecycle store.
+//!
+//! Every payment the plugin sends or expects to receive is recorded here by
+//! payment hash, from the first attempt until it succeeds or fails for good.
+
+use std::collections::HashMap;
+
+use crate::{
+    errors::{PaymentError, PaymentResult},
+    types::{PaymentHash, PaymentPreimage, PaymentRoute, PaymentStatus},
+};
+
+/// Which way a payment flows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentDirection {
+    /// Sent by this node.
+    Outgoing,
+    /// Paid to an invoice issued by this node.
+    Incoming,
+}
+
+/// Lifecycle of a single payment.
+#[derive(Debug, Clone)]
+pub struct PaymentRecord {
+    /// Payment hash identifying the payment.
+    pub payment_hash:   PaymentHash,
+    /// Which way the payment flows.
+    pub direction:      PaymentDirection,
+    /// Current status.
+    pub status:         PaymentStatus,
+    /// Amount delivered to the payee, in millisatoshis.
+    pub amount_msat:    u64,
+    /// Routing fees paid on top of the amount, in millisatoshis.
+    pub fees_msat:      u64,
+    /// Routes of the latest attempt, one per part.
+    pub routes:         Vec<PaymentRoute>,
+    /// Attempts made so far; HTLCs received for incoming payments.
+    pub attempts:       u32,
+    /// Why the latest attempt failed.
+    pub failure_reason: Option<String>,
+    /// Preimage proving settlement.
+    pub preimage:       Option<PaymentPreimage>,
+    /// Unix time the payment was first recorded.
+    pub created_at:     u64,
+    /// Unix time of the latest change.
+    pub updated_at:     u64,
+}
+
+impl PaymentRecord {
+    fn new(
+        payment_hash: PaymentHash, direction: PaymentDirection, amount_msat: u64, now: u64,
+    ) -> Self {
+        Self {
+            payment_hash,
+            direction,
+            status: PaymentStatus::Pending,
+            amount_msat,
+            fees_msat: 0,
+            routes: Vec::new(),
+            attempts: 0,
+            failure_reason: None,
+            preimage: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Whether the payment has succeeded or failed.
+    #[must_use]
+    pub fn is_final(&self) -> bool {
+        matches!(self.status, PaymentStatus::Succeeded | PaymentStatus::Failed)
+    }
+}
+
+/// Filter for listing payments. Unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct PaymentQuery {
+    /// Only payments flowing this way.
+    pub direction: Option<PaymentDirection>,
+    /// Only payments with this status.
+    pub status:    Option<PaymentStatus>,
+    /// Only payments created at or after this unix time.
+    pub since:     Option<u64>,
+    /// Only payments created before this unix time.
+    pub until:     Option<u64>,
+    /// Skip this many matching payments.
+    pub offset:    usize,
+    /// Return at most this many payments.
+    pub limit:     Option<usize>,
+}
+
+impl PaymentQuery {
+    /// Check whether a payment passes the filter, ignoring offset and limit.
+    #[must_use]
+    pub fn matches(&self, record: &PaymentRecord) -> bool {
+        self.direction.is_none_or(|direction| record.direction == direction)
+            && self.status.is_none_or(|status| record.status == status)
+            && self.since.is_none_or(|since| record.created_at >= since)
+            && self.until.is_none_or(|until| record.created_at < until)
+    }
+}
+
+/// Payments keyed by payment hash.
+#[derive(Debug, Clone, Default)]
+pub struct PaymentStore {
+    payments: HashMap<PaymentHash, PaymentRecord>,
+}
+
+impl PaymentStore {
+    /// Create an empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up a payment.
+    #[must_use]
+    pub fn get(&self, payment_hash: &PaymentHash) -> Option<&PaymentRecord> {
+        self.payments.get(payment_hash)
+    }
+
+    /// Status of a payment.
+    #[must_use]
+    pub fn status(&self, payment_hash: &PaymentHash) -> Option<PaymentStatus> {
+        self.get(payment_hash).map(|record| record.status)
+    }
+
+    /// Number of payments recorded.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.payments.len()
+    }
+
+    /// Whether no payments are recorded.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.payments.is_empty()
+    }
+
+    /// Payments passing `query`, oldest first.
+    #[must_use]
+    pub fn list(&self, query: &PaymentQuery) -> Vec<&PaymentRecord> {
+        let mut records: Vec<&PaymentRecord> =
+            self.payments.values().filter(|record| query.matches(record)).collect();
+        records.sort_by_key(|record| (record.created_at, record.payment_hash.0));
+        records.into_iter().skip(query.offset).take(query.limit.unwrap_or(usize::MAX)).collect()
+    }
+
+    /// Start an attempt at sending a payment.
+    ///
+    /// A failed or still pending payment may be attempted again; one in flight
+    /// or already paid may not.
+    pub fn record_outgoing(
+        &mut self, payment_hash: PaymentHash, amount_msat: u64, now: u64,
+    ) -> PaymentResult<()> {
+        let record = self.payments.entry(payment_hash).or_insert_with(|| {
+            PaymentRecord::new(payment_hash, PaymentDirection::Outgoing, amount_msat, now)
+        });
+        if record.direction == PaymentDirection::Incoming {
+            return Err(PaymentError::Invoice("Cannot pay an invoice issued by this node".into()));
+        }
+        match record.status {
+            PaymentStatus::InFlight => {
+                return Err(PaymentError::Invoice("Invoice is already being paid".into()))
+            },
+            PaymentStatus::Succeeded => {
+                return Err(PaymentError::Invoice("Invoice has already been paid".into()))
+            },
+            PaymentStatus::Pending | PaymentStatus::Failed => {},
+        }
+        record.status = PaymentStatus::Pending;
+        record.amount_msat = amount_msat;
+        record.fees_msat = 0;
+        record.routes.clear();
+        record.attempts += 1;
+        record.failure_reason = None;
+        record.updated_at = now;
+        Ok(())
+    }
+
+    /// Record an invoice issued by this node, awaiting payment.
+    pub fn record_incoming(
+        &mut self, payment_hash: PaymentHash, amount_msat: u64, now: u64,
+    ) -> PaymentResult<()> {
+        if self.payments.contains_key(&payment_hash) {
+            return Err(PaymentError::Invoice(
+                "A payment with this payment hash already exists".into(),
+            ));
+        }
+        self.payments.insert(
+            payment_hash,
+            PaymentRecord::new(payment_hash, PaymentDirection::Incoming, amount_msat, now),
+        );
+        Ok(())
+    }
+
+    /// Count an HTLC received for an incoming payment.
+    pub fn record_htlc(&mut self, payment_hash: &PaymentHash, now: u64) -> PaymentResult<()> {
+        let record = self.open_mut(payment_hash)?;
+        record.attempts += 1;
+        record.updated_at = now;
+        Ok(())
+    }
+
+    /// Record why an attempt failed without ending the payment, e.g. an HTLC
+    /// rejected while the invoice stays open.
+    pub fn record_failure_reason(
+        &mut self, payment_hash: &PaymentHash, reason: impl Into<String>, now: u64,
+    ) -> PaymentResult<()> {
+        let record = self.open_mut(payment_hash)?;
+        record.failure_reason = Some(reason.into());
+        record.updated_at = now;
+        Ok(())
+    }
+
+    /// Mark a payment as in flight over `routes`, one per part.
+    pub fn mark_in_flight(
+        &mut self, payment_hash: &PaymentHash, routes: Vec<PaymentRoute>, now: u64,
+    ) -> PaymentResult<()> {
+        let record = self.open_mut(payment_hash)?;
+        record.status = PaymentStatus::InFlight;
+        record.fees_msat = routes.iter().map(|route| route.total_fees_msat).sum();
+        record.routes = routes;
+        record.updated_at = now;
+        Ok(())
+    }
+
+    /// Mark a payment as settled, keeping the preimage when it is known.
+    ///
+    /// An outgoing payment is only settled once the payee has revealed the
+    /// preimage, so one is required for it.
+    pub fn mark_succeeded(
+        &mut self, payment_hash: &PaymentHash, preimage: Option<PaymentPreimage>, now: u64,
+    ) -> PaymentResult<()> {
+        if preimage.is_some_and(|preimage| !preimage.matches(payment_hash)) {
+            return Err(PaymentError::Invoice("Preimage does not match payment hash".into()));
+        }
+        let record = self.open_mut(payment_hash)?;
+        if record.direction == PaymentDirection::Outgoing && preimage.is_none() {
+            return Err(PaymentError::Invoice(
+                "An outgoing payment needs the preimage to succeed".into(),
+            ));
+        }
+        record.status = PaymentStatus::Succeeded;
+        record.preimage = preimage;
+        record.failure_reason = None;
+        record.updated_at = now;
+        Ok(())
+    }
+
+    /// Mark the latest attempt as failed.
+    pub fn mark_failed(
+        &mut self, payment_hash: &PaymentHash, reason: impl Into<String>, now: u64,
+    ) -> PaymentResult<()> {
+        let record = self.open_mut(payment_hash)?;
+        record.status = PaymentStatus::Failed;
+        record.failure_reason = Some(reason.into());
+        record.updated_at = now;
+        Ok(())
+    }
+
+    fn open_mut(&mut self, payment_hash: &PaymentHash) -> PaymentResult<&mut PaymentRecord> {
+        let record = self
+            .payments
+            .get_mut(payment_hash)
+            .ok_or_else(|| PaymentError::Invoice("Payment not found".into()))?;
+        if record.status == PaymentStatus::Succeeded {
+            return Err(PaymentError::Invoice("Payment has already succeeded".into()));
+        }
+        Ok(record)
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::types::RouteHop;
+
+    fn route(fees_msat: u64) -> PaymentRoute {
+        PaymentRoute {
+            hops:             vec![RouteHop {
+                pubkey:            [2; 33],
+                short_channel_id:  7,
+                fee_msat:          fees_msat,
+                cltv_expiry_delta: 40,
+            }],
+            total_fees_msat:  fees_msat,
+            total_cltv_delta: 40,
+            amount_msat:      50_000,
+        }
+    }
+
+    #[test]
+    fn test_outgoing_lifecycle() {
+        let mut store = PaymentStore::new();
+        let preimage = PaymentPreimage::new([1; 32]);
+        let hash = preimage.payment_hash();
+
+        store.record_outgoing(hash, 100_000, 10).unwrap();
+        store.mark_in_flight(&hash, vec![route(3), route(4)], 11).unwrap();
+        assert!(store.record_outgoing(hash, 100_000, 12).is_err());
+        store.mark_failed(&hash, "temporary channel failure", 12).unwrap();
+
+        store.record_outgoing(hash, 100_000, 20).unwrap();
+        let record = store.get(&hash).unwrap();
+        assert_eq!(
+            (record.attempts, record.fees_msat, record.failure_reason.as_deref()),
+            (2, 0, None)
+        );
+        store.mark_in_flight(&hash, vec![route(5)], 21).unwrap();
+        assert!(store.mark_succeeded(&hash, Some(PaymentPreimage::new([2; 32])), 22).is_err());
+        assert!(store.mark_succeeded(&hash, None, 22).is_err());
+        store.mark_succeeded(&hash, Some(preimage), 22).unwrap();
+
+        let record = store.get(&hash).unwrap();
+        assert_eq!(record.status, PaymentStatus::Succeeded);
+        assert_eq!((record.fees_msat, record.routes.len()), (5, 1));
+        assert_eq!((record.created_at, record.updated_at), (10, 22));
+        assert_eq!(record.preimage, Some(preimage));
+        assert!(store.mark_failed(&hash, "late", 23).is_err());
+        assert!(store.record_outgoing(hash, 100_000, 23).is_err());
+    }
+
+    #[test]
+    fn test_list_filters_and_pages() {
+        let mut store = PaymentStore::new();
+        for seed in 0..6u8 {
+            let hash = PaymentPreimage::new([seed; 32]).payment_hash();
+            if seed % 2 == 0 {
+                store.record_outgoing(hash, 1_000, u64::from(seed)).unwrap();
+            } else {
+                store.record_incoming(hash, 1_000, u64::from(seed)).unwrap();
+                store.record_htlc(&hash, u64::from(seed)).unwrap();
+            }
+        }
+        let paid = PaymentPreimage::new([3; 32]);
+        store.mark_succeeded(&paid.payment_hash(), Some(paid), 9).unwrap();
+        assert!(store.record_outgoing(paid.payment_hash(), 1_000, 9).is_err());
+
+        let created = |records: Vec<&PaymentRecord>| {
+            records.iter().map(|record| record.created_at).collect::<Vec<_>>()
+        };
+        assert_eq!(created(store.list(&PaymentQuery::default())), vec![0, 1, 2, 3, 4, 5]);
+        let incoming =
+            PaymentQuery { direction: Some(PaymentDirection::Incoming), ..Default::default() };
+        assert_eq!(created(store.list(&incoming)), vec![1, 3, 5]);
+        let succeeded = PaymentQuery { status: Some(PaymentStatus::Succeeded), ..incoming };
+        assert_eq!(created(store.list(&succeeded)), vec![3]);
+        let window = PaymentQuery {
+            since: Some(1),
+            until: Some(5),
+            offset: 1,
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(created(store.list(&window)), vec![2, 3]);
+    }
+}
@@ -1,19 +1,34 @@
 //! Payment plugin implementation.
 
+use std::sync::Arc;
+
 use crate::{
+    crypto::SecretKey,
     errors::{PaymentError, PaymentResult},
     implementation::{
-        ChannelManager, InvoiceGenerator, LightningNodeImpl, PaymentConfig, PaymentRouter,
+        AttemptEvent, AttemptFailure, BillingEvent, BillingPolicy, Bolt11Invoice, ChannelLimits,
+        ChannelManager, EscrowError, EscrowManager, InvoiceGenerator, LightningNodeImpl,
+        PaymentAttempt, PaymentConfig, PaymentOrchestrator, PaymentQuery, PaymentRecord,
+        PaymentRouter, PaymentStore, QuotaExceeded, QuotaReport, RetryPolicy, Ruling,
+        RulingExecution, SignedBolt11Invoice, SubscriptionManager, SystemClock, TierChange,
+        TierChangeTiming, UsageEvent, UsageMeter, UsageRecorded, DEFAULT_MAX_PAYMENT_PARTS,
+    },
+    traits::{ChannelProvider, Clock, InvoiceProvider},
+    types::{
+        ChannelState, EscrowType, HtlcDirection, LightningInvoice, PaymentAmount, PaymentHash,
+        PaymentInvoice, PaymentPreimage, PaymentRoute, PaymentStatus, SubscriptionTier,
     },
-    traits::{ChannelProvider, InvoiceProvider},
-    types::{LightningInvoice, PaymentAmount, PaymentInvoice, PaymentStatus},
 };
 
 /// Main payment plugin interface.
 #[derive(Debug)]
 pub struct PaymentPlugin {
     config:            PaymentConfig,
-    channel_manager:   ChannelManager,
+    clock:             Arc<dyn Clock>,
+    escrows:           EscrowManager,
+    subscriptions:     SubscriptionManager,
+    usage:             UsageMeter,
+    payments:          PaymentStore,
     invoice_generator: InvoiceGenerator,
     router:            PaymentRouter,
     lightning_node:    LightningNodeImpl,
@@ -21,16 +36,50 @@ pub struct PaymentPlugin {
 
 impl PaymentPlugin {
     /// Create a new payment plugin.
+    ///
+    /// # Panics
+    ///
+    /// If the operating system CSPRNG is unavailable to generate the node key.
     #[must_use]
     pub fn new(config: PaymentConfig) -> Self {
-        let invoice_generator = InvoiceGenerator::new(config.clone());
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create a new payment plugin whose invoices, billing and payment records
+    /// read time from `clock`.
+    ///
+    /// # Panics
+    ///
+    /// If the operating system CSPRNG is unavailable to generate the node key.
+    #[must_use]
+    pub fn with_clock(config: PaymentConfig, clock: Arc<dyn Clock>) -> Self {
+        let mut lightning_node = LightningNodeImpl::with_secret_key(
+            "EssentiaNode".to_string(),
+            SecretKey::new_random(),
+            config.network,
+        );
+        // The node owns the only channel store; the plugin exposes it as its channel manager.
+        *lightning_node.channel_manager_mut() = ChannelManager::with_config(&config);
+        // Sign plain invoices with the node key so both invoice paths share a payee.
+        let invoice_generator = InvoiceGenerator::with_clock(
+            config.clone(),
+            *lightning_node.secret_key(),
+            Arc::clone(&clock),
+        );
+
+        let mut router = PaymentRouter::with_local_node(lightning_node.get_node_info().pubkey);
+        router.set_network(config.network);
 
         Self {
             config,
-            channel_manager: ChannelManager::new(),
+            clock: Arc::clone(&clock),
+            escrows: EscrowManager::new(),
+            subscriptions: SubscriptionManager::new(clock, BillingPolicy::default()),
+            usage: UsageMeter::new(),
+            payments: PaymentStore::new(),
             invoice_generator,
-            router: PaymentRouter::new(),
-            lightning_node: LightningNodeImpl::new("EssentiaNode".to_string()),
+            router,
+            lightning_node,
         }
     }
 
@@ -43,12 +92,177 @@ impl PaymentPlugin {
     /// Get channel manager.
     #[must_use]
     pub fn channels(&self) -> &ChannelManager {
-        &self.channel_manager
+        self.lightning_node.channel_manager()
     }
 
     /// Get mutable channel manager.
     pub fn channels_mut(&mut self) -> &mut ChannelManager {
-        &mut self.channel_manager
+        self.lightning_node.channel_manager_mut()
+    }
+
+    /// Get the bounty escrow manager.
+    #[must_use]
+    pub fn escrows(&self) -> &EscrowManager {
+        &self.escrows
+    }
+
+    /// Get the mutable bounty escrow manager.
+    pub fn escrows_mut(&mut self) -> &mut EscrowManager {
+        &mut self.escrows
+    }
+
+    /// Carry out the arbiter's ruling on a hold invoice escrow.
+    ///
+    /// A release settles the hold invoice with `preimage`; a refund cancels it
+    /// and fails the held HTLCs back to the funder.
+    pub fn execute_hold_ruling(
+        &mut self, escrow_id: &[u8; 32], preimage: Option<PaymentPreimage>, now: u64,
+    ) -> PaymentResult<()> {
+        let ruling = self.escrows.pending_ruling(escrow_id)?.ruling;
+        let escrow = self.escrows.escrow(escrow_id).ok_or(EscrowError::NotFound)?;
+        let EscrowType::LightningHold { payment_hash, .. } = escrow.escrow_type else {
+            return Err(EscrowError::WrongBacking("hold invoice").into());
+        };
+
+        let execution = match ruling {
+            Ruling::Release => {
+                let preimage = preimage
+                    .filter(|preimage| preimage.matches(&payment_hash))
+                    .ok_or(EscrowError::MissingPreimage)?;
+                self.lightning_node.settle_hold_invoice(preimage)?;
+                RulingExecution::HoldSettled(payment_hash)
+            },
+            Ruling::Refund => {
+                self.lightning_node.cancel_hold_invoice(&payment_hash)?;
+                RulingExecution::HoldCanceled(payment_hash)
+            },
+            Ruling::Split { .. } => return Err(EscrowError::SplitNeedsMultiSig.into()),
+        };
+        self.escrows.complete_ruling(escrow_id, execution, now)?;
+        Ok(())
+    }
+
+    /// Get the subscription billing engine.
+    #[must_use]
+    pub fn subscriptions(&self) -> &SubscriptionManager {
+        &self.subscriptions
+    }
+
+    /// Subscribe a customer to a tier, returning the first invoice for paid tiers.
+    pub fn subscribe(
+        &mut self, customer_id: &str, tier: SubscriptionTier,
+    ) -> PaymentResult<Option<PaymentInvoice>> {
+        let invoice = self.subscriptions.subscribe(customer_id, tier, &self.invoice_generator)?;
+        if let Some(invoice) = &invoice {
+            self.track_incoming(invoice)?;
+        }
+        Ok(invoice)
+    }
+
+    /// Change a customer's tier.
+    ///
+    /// Upgrades apply immediately against a prorated invoice; downgrades take
+    /// effect at the end of the paid period.
+    pub fn change_subscription_tier(
+        &mut self, customer_id: &str, tier: SubscriptionTier,
+    ) -> PaymentResult<TierChange> {
+        let current = self.subscriptions.effective_tier(customer_id);
+        let timing = if tier.monthly_price_sats() > current.monthly_price_sats() {
+            TierChangeTiming::Immediate
+        } else {
+            TierChangeTiming::PeriodEnd
+        };
+        let change =
+            self.subscriptions.change_tier(customer_id, tier, timing, &self.invoice_generator)?;
+        if let TierChange::Invoiced { invoice, .. } = &change {
+            self.track_incoming(invoice)?;
+        }
+        Ok(change)
+    }
+
+    /// Record payment of a subscription invoice.
+    ///
+    /// `preimage` is the payer's proof of payment: the invoice it settles is
+    /// marked paid and the subscription activated only if it hashes to an
+    /// invoice this plugin issued.
+    pub fn record_subscription_payment(
+        &mut self, preimage: PaymentPreimage,
+    ) -> PaymentResult<BillingEvent> {
+        let payment_hash = preimage.payment_hash();
+        if self.invoice_generator.preimage(&payment_hash) != Some(preimage) {
+            return Err(PaymentError::Invoice("Preimage settles no issued invoice".into()));
+        }
+        let event = self.subscriptions.record_payment(&payment_hash.0)?;
+        if self.payments.get(&payment_hash).is_some_and(|record| !record.is_final()) {
+            self.payments.mark_succeeded(&payment_hash, Some(preimage), self.clock.now())?;
+        }
+        Ok(event)
+    }
+
+    /// Get the usage meter.
+    #[must_use]
+    pub fn usage(&self) -> &UsageMeter {
+        &self.usage
+    }
+
+    /// Get the mutable usage meter.
+    pub fn usage_mut(&mut self) -> &mut UsageMeter {
+        &mut self.usage
+    }
+
+    /// Record usage against the customer's tier quota.
+    pub fn record_usage(
+        &mut self, customer_id: &str, event: UsageEvent,
+    ) -> Result<UsageRecorded, QuotaExceeded> {
+        self.usage.record(&self.subscriptions, customer_id, event)
+    }
+
+    /// Remaining quota for the customer's current billing period.
+    #[must_use]
+    pub fn quota(&self, customer_id: &str) -> QuotaReport {
+        self.usage.quota(&self.subscriptions, customer_id)
+    }
+
+    /// Issue due renewal invoices, apply grace periods and downgrades, and
+    /// invoice unbilled usage overage.
+    pub fn run_billing(&mut self) -> PaymentResult<Vec<BillingEvent>> {
+        let mut events = self.subscriptions.run_billing(&self.invoice_generator)?;
+        events.extend(self.usage.bill_overage(&self.invoice_generator)?);
+        for event in &events {
+            if let BillingEvent::InvoiceIssued { invoice, .. }
+            | BillingEvent::OverageInvoiced { invoice, .. } = event
+            {
+                self.track_incoming(invoice)?;
+            }
+        }
+        Ok(events)
+    }
+
+    /// Get the payment lifecycle store.
+    #[must_use]
+    pub fn payments(&self) -> &PaymentStore {
+        &self.payments
+    }
+
+    /// Look up a payment by hash.
+    #[must_use]
+    pub fn payment(&self, payment_hash: &[u8; 32]) -> Option<&PaymentRecord> {
+        self.payments.get(&PaymentHash::new(*payment_hash))
+    }
+
+    /// List payments passing `query`, oldest first.
+    #[must_use]
+    pub fn list_payments(&self, query: &PaymentQuery) -> Vec<&PaymentRecord> {
+        self.payments.list(query)
+    }
+
+    /// Record an invoice issued through the plugin as an incoming payment.
+    fn track_incoming(&mut self, invoice: &PaymentInvoice) -> PaymentResult<()> {
+        self.payments.record_incoming(
+            PaymentHash::new(invoice.payment_hash),
+            invoice.amount.unwrap_or(0).saturating_mul(1000),
+            self.clock.now(),
+        )
     }
 
     /// Get the payment router.
@@ -57,6 +271,37 @@ impl PaymentPlugin {
         &self.router
     }
 
+    /// Get the mutable payment router.
+    pub fn router_mut(&mut self) -> &mut PaymentRouter {
+        &mut self.router
+    }
+
+    /// Mirror the node's channels into the router: active channels become
+    /// routable out of the local node up to what they can spend, and channels
+    /// that stopped being active are removed. Route searches through the
+    /// plugin do this first.
+    pub fn sync_router_channels(&mut self) -> PaymentResult<()> {
+        for channel in self.lightning_node.channel_manager().channels() {
+            let short_channel_id = channel.short_channel_id();
+            let routed = self.router.graph().channel(short_channel_id).is_some();
+            match channel.state {
+                ChannelState::Active => self.router.set_local_channel(
+                    channel.peer_pubkey,
+                    short_channel_id,
+                    channel.capacity,
+                    ChannelLimits::available_msat(channel, HtlcDirection::Offered),
+                )?,
+                ChannelState::Opening => {},
+                ChannelState::Closing | ChannelState::ForceClosed | ChannelState::Closed => {
+                    if routed {
+                        self.router.remove_channel(short_channel_id);
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
     /// Get the Lightning node.
     #[must_use]
     pub fn lightning_node(&self) -> &LightningNodeImpl {
@@ -70,56 +315,248 @@ impl PaymentPlugin {
 
     /// Create an invoice.
     pub fn create_invoice(
-        &self, amount: Option<u64>, description: impl Into<String>,
+        &mut self, amount: Option<u64>, description: impl Into<String>,
     ) -> PaymentResult<PaymentInvoice> {
-        self.invoice_generator.generate_invoice(amount, &description.into())
+        let invoice = self.invoice_generator.generate_invoice(amount, &description.into())?;
+        self.track_incoming(&invoice)?;
+        Ok(invoice)
     }
 
     /// Create a Lightning invoice.
     pub async fn create_lightning_invoice(
         &mut self, amount_sats: u64, description: &str, expiry_secs: u64,
     ) -> PaymentResult<LightningInvoice> {
-        self.lightning_node.create_invoice(amount_sats, description, expiry_secs).await
+        let invoice =
+            self.lightning_node.create_invoice(amount_sats, description, expiry_secs).await?;
+        self.payments.record_incoming(
+            invoice.payment_hash,
+            amount_sats.saturating_mul(1000),
+            self.clock.now(),
+        )?;
+        Ok(invoice)
     }
 
-    /// Send a payment.
-    pub fn send_payment(&self, invoice: &PaymentInvoice) -> PaymentResult<PaymentStatus> {
-        // Verify invoice first
-        self.invoice_generator.verify(invoice)?;
+    /// Handle an HTLC offered by a peer, recording it against the incoming payment.
+    ///
+    /// See [`LightningNodeImpl::receive_htlc`].
+    pub fn receive_htlc(
+        &mut self, channel_id: &[u8; 32], amount_msat: u64, payment_hash: PaymentHash,
+        payment_secret: &[u8; 32], cltv_expiry: u32,
+    ) -> PaymentResult<u64> {
+        let tracked = self.payments.get(&payment_hash).is_some_and(|record| !record.is_final());
+        let now = self.clock.now();
+        if tracked {
+            self.payments.record_htlc(&payment_hash, now)?;
+        }
+        let received = self.lightning_node.receive_htlc(
+            channel_id,
+            amount_msat,
+            payment_hash,
+            payment_secret,
+            cltv_expiry,
+        );
+        if tracked {
+            match &received {
+                Ok(_) => {
+                    if let Some(preimage) = self.lightning_node.invoice_preimage(&payment_hash) {
+                        self.payments.mark_succeeded(&payment_hash, Some(preimage), now)?;
+                    }
+                },
+                Err(err) => {
+                    self.payments.record_failure_reason(&payment_hash, err.to_string(), now)?
+                },
+            }
+        }
+        received
+    }
 
-        // Check we have sufficient balance
-        let amount = invoice.amount.unwrap_or(0);
-        let balance = self.channel_manager.total_local_balance();
+    /// Decode a BOLT11 string into a Lightning invoice for the configured network.
+    pub fn decode_lightning_invoice(&self, encoded: &str) -> PaymentResult<LightningInvoice> {
+        Ok(Bolt11Invoice::decode_for_network(encoded, self.config.network)?.to_lightning_invoice())
+    }
 
-        if amount > balance {
-            return Err(PaymentError::InsufficientFunds(format!(
-                "Need {} sats, have {}",
-                amount, balance
-            )));
-        }
+    /// Decode a BOLT11 string into a payment invoice for the configured network.
+    pub fn decode_invoice(&self, encoded: &str) -> PaymentResult<PaymentInvoice> {
+        Ok(Bolt11Invoice::decode_for_network(encoded, self.config.network)?.to_payment_invoice())
+    }
 
-        // In production, would route and send payment
-        Ok(PaymentStatus::Pending)
+    /// Send a payment for a BOLT11-encoded payment invoice.
+    ///
+    /// The encoded invoice is paid as by [`Self::send_lightning_payment`], with
+    /// `invoice.amount` paying an invoice that does not state one. The encoded
+    /// invoice must carry `invoice.payment_hash`, which tracks the payment.
+    pub async fn send_payment(&mut self, invoice: &PaymentInvoice) -> PaymentResult<PaymentStatus> {
+        let decoded = Bolt11Invoice::decode_for_network(&invoice.encoded, self.config.network)?;
+        let lightning = LightningInvoice {
+            payment_hash: PaymentHash::new(invoice.payment_hash),
+            ..decoded.to_lightning_invoice()
+        };
+        let amount_msat = invoice.amount.map(|sats| sats.saturating_mul(1000));
+        self.send_lightning_payment(&lightning, amount_msat).await
     }
 
     /// Send a Lightning payment.
+    ///
+    /// `amount_msat` is the amount to pay an invoice that does not state one;
+    /// it is ignored when the invoice does. The amount is split across as many
+    /// routes through the graph as channel liquidity requires. The attempt and
+    /// its outcome are recorded in the payment store. An invoice that does not
+    /// decode or has expired by the plugin clock is a permanent invoice error.
     pub async fn send_lightning_payment(
-        &self, invoice: &LightningInvoice,
+        &mut self, invoice: &LightningInvoice, amount_msat: Option<u64>,
+    ) -> PaymentResult<PaymentStatus> {
+        let decoded = Bolt11Invoice::decode_for_network(&invoice.bolt11, self.config.network)?;
+        if decoded.is_expired(self.clock.now()) {
+            return Err(PaymentError::Invoice("Invoice has expired".into()));
+        }
+        let total_msat = decoded.invoice.amount_msat.or(amount_msat).ok_or_else(|| {
+            PaymentError::Invoice("Invoice has no amount and none was given".into())
+        })?;
+        check_matches_bolt11(invoice, &decoded)?;
+        let payment_hash = invoice.payment_hash;
+        self.payments.record_outgoing(payment_hash, total_msat, self.clock.now())?;
+
+        let sent = self.dispatch_lightning_payment(invoice, &decoded, total_msat);
+        let now = self.clock.now();
+        let outbound = self.lightning_node.outbound_payment(&payment_hash);
+        match &sent {
+            Ok(PaymentStatus::Succeeded) => {
+                let preimage = outbound.and_then(|payment| payment.preimage().copied());
+                self.payments.mark_succeeded(&payment_hash, preimage, now)?
+            },
+            Ok(PaymentStatus::Failed) => {
+                self.payments.mark_failed(&payment_hash, "All payment parts failed", now)?
+            },
+            Ok(_) => {
+                let routes = outbound
+                    .map(|payment| payment.parts().iter().map(|part| part.route.clone()).collect())
+                    .unwrap_or_default();
+                self.payments.mark_in_flight(&payment_hash, routes, now)?;
+            },
+            Err(err) => self.payments.mark_failed(&payment_hash, err.to_string(), now)?,
+        }
+        sent
+    }
+
+    /// Pay over routes through the graph to the invoice's payee.
+    fn dispatch_lightning_payment(
+        &mut self, invoice: &LightningInvoice, decoded: &SignedBolt11Invoice, total_msat: u64,
+    ) -> PaymentResult<PaymentStatus> {
+        self.sync_router_channels()?;
+        if self.router.graph().node(&decoded.payee_pubkey).is_none() {
+            return Err(PaymentError::Routing("Payee is not in the routing graph".into()));
+        }
+        let routes = self.router.find_multipath_routes(
+            &decoded.payee_pubkey,
+            total_msat,
+            DEFAULT_MAX_PAYMENT_PARTS,
+        )?;
+        self.lightning_node.send_payment_parts(invoice, total_msat, routes)
+    }
+
+    /// Pay a BOLT11 invoice one route at a time, retrying around failures.
+    ///
+    /// `send` delivers the payment over a route and reports the outcome. Retries
+    /// are bounded by `max_payment_retries` and `payment_timeout`; the timeout
+    /// is checked between attempts and cannot interrupt `send`. The payment
+    /// store records each attempt as in flight while `send` runs.
+    pub fn send_payment_with_retries(
+        &mut self, invoice: &LightningInvoice,
+        send: impl FnMut(&PaymentRoute) -> Result<PaymentPreimage, AttemptFailure>,
+    ) -> PaymentResult<PaymentPreimage> {
+        let decoded = Bolt11Invoice::decode_for_network(&invoice.bolt11, self.config.network)?;
+        let amount_msat = decoded
+            .invoice
+            .amount_msat
+            .or_else(|| invoice.amount_sats.map(|sats| sats.saturating_mul(1000)))
+            .ok_or_else(|| PaymentError::Invoice("Invoice has no amount".into()))?;
+        check_matches_bolt11(invoice, &decoded)?;
+        let payment_hash = invoice.payment_hash;
+        self.payments.record_outgoing(payment_hash, amount_msat, self.clock.now())?;
+
+        if let Err(err) = self.sync_router_channels() {
+            self.payments.mark_failed(&payment_hash, err.to_string(), self.clock.now())?;
+            return Err(err);
+        }
+        let policy = RetryPolicy::from_config(&self.config);
+        let mut orchestrator = PaymentOrchestrator::new(&mut self.router, &*self.clock, policy);
+        let payments = &mut self.payments;
+        let paid =
+            orchestrator.pay_with_progress(&decoded.payee_pubkey, amount_msat, send, |event| {
+                match event {
+                    AttemptEvent::Started { number, route, at } => {
+                        if number > 1 {
+                            payments.record_outgoing(payment_hash, amount_msat, at)?;
+                        }
+                        payments.mark_in_flight(&payment_hash, vec![route.clone()], at)
+                    },
+                    AttemptEvent::Finished(PaymentAttempt {
+                        failure: Some(failure),
+                        ended_at,
+                        ..
+                    }) => payments.mark_failed(&payment_hash, failure.to_string(), *ended_at),
+                    AttemptEvent::Finished(_) => Ok(()),
+                }
+            });
+        let now = self.clock.now();
+        match &paid {
+            Ok(preimage) => self.payments.mark_succeeded(&payment_hash, Some(*preimage), now)?,
+            Err(err) => self.payments.mark_failed(&payment_hash, err.to_string(), now)?,
+        }
+        paid
+    }
+
+    /// Decode a BOLT11 string and send a Lightning payment for it.
+    ///
+    /// See [`Self::send_lightning_payment`] for `amount_msat`.
+    pub async fn send_bolt11_payment(
+        &mut self, encoded: &str, amount_msat: Option<u64>,
     ) -> PaymentResult<PaymentStatus> {
-        self.lightning_node.pay_invoice(invoice).await
+        let decoded = Bolt11Invoice::decode_for_network(encoded, self.config.network)?;
+        self.send_lightning_payment(&decoded.to_lightning_invoice(), amount_msat).await
     }
 
     /// Get total spendable balance.
     #[must_use]
     pub fn spendable_balance(&self) -> PaymentAmount {
-        PaymentAmount::from_satoshis(self.channel_manager.total_local_balance())
+        PaymentAmount::from_satoshis(self.lightning_node.total_balance())
+    }
+
+    /// Record that a part of an outgoing payment settled.
+    ///
+    /// See [`LightningNodeImpl::settle_payment_part`].
+    pub fn settle_payment_part(
+        &mut self, payment_hash: &PaymentHash, part_id: u32, preimage: PaymentPreimage,
+    ) -> PaymentResult<PaymentStatus> {
+        let status = self.lightning_node.settle_payment_part(payment_hash, part_id, preimage)?;
+        if status == PaymentStatus::Succeeded
+            && self.payments.get(payment_hash).is_some_and(|record| !record.is_final())
+        {
+            self.payments.mark_succeeded(payment_hash, Some(preimage), self.clock.now())?;
+        }
+        Ok(status)
+    }
+
+    /// Record that a part of an outgoing payment failed for `reason`.
+    ///
+    /// See [`LightningNodeImpl::fail_payment_part`].
+    pub fn fail_payment_part(
+        &mut self, payment_hash: &PaymentHash, part_id: u32, permanent: bool, reason: &str,
+    ) -> PaymentResult<PaymentStatus> {
+        let status = self.lightning_node.fail_payment_part(payment_hash, part_id, permanent)?;
+        if status == PaymentStatus::Failed
+            && self.payments.get(payment_hash).is_some_and(|record| !record.is_final())
+        {
+            self.payments.mark_failed(payment_hash, reason, self.clock.now())?;
+        }
+        Ok(status)
     }
 
     /// Get payment status by hash.
-    pub fn get_payment_status(&self, _payment_hash: &[u8; 32]) -> PaymentResult<PaymentStatus> {
-        // In a real implementation, this would check the payment status
-        // For now, return pending
-        Ok(PaymentStatus::Pending)
+    pub fn get_payment_status(&self, payment_hash: &[u8; 32]) -> PaymentResult<PaymentStatus> {
+        self.payments
+            .status(&PaymentHash::new(*payment_hash))
+            .ok_or_else(|| PaymentError::Invoice("Payment not found".into()))
     }
 
     /// Check Lightning invoice status.
@@ -136,9 +573,47 @@ impl Default for PaymentPlugin {
     }
 }
 
+/// Reject an invoice whose payment hash or secret differs from its BOLT11
+/// string, so HTLCs are only ever locked to what the payee signed.
+fn check_matches_bolt11(
+    invoice: &LightningInvoice, decoded: &SignedBolt11Invoice,
+) -> PaymentResult<()> {
+    if invoice.payment_hash.0 != decoded.invoice.payment_hash
+        || invoice.payment_secret != decoded.invoice.payment_secret
+    {
+        return Err(PaymentError::Invoice(
+            "Payment hash or secret does not match the BOLT11 invoice".into(),
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(all(test, feature = "full-tests"))]
 mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use essentia_core::time;
+
     use super::*;
+    use crate::{
+        implementation::{DisputeParty, EscrowTerms, HoldInvoiceState, ManualClock, SignedRuling},
+        types::{EscrowStatus, PaymentHash},
+    };
+
+    /// Drive a future that never waits on I/O to completion.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
 
     #[test]
     fn test_plugin_creation() {
@@ -148,14 +623,338 @@ mod tests {
 
     #[test]
     fn test_create_invoice() {
-        let plugin = PaymentPlugin::default();
+        let mut plugin = PaymentPlugin::default();
         let invoice = plugin.create_invoice(Some(1000), "Test payment");
         assert!(invoice.is_ok());
     }
 
+    #[test]
+    fn test_decode_created_invoice() {
+        let mut plugin = PaymentPlugin::default();
+        let invoice = plugin.create_invoice(Some(1000), "Test payment").unwrap();
+        let decoded = plugin.decode_invoice(&invoice.encoded).unwrap();
+        assert_eq!(decoded.payment_hash, invoice.payment_hash);
+        assert_eq!(decoded.amount, Some(1000));
+        assert_eq!(decoded.description, "Test payment");
+    }
+
+    #[test]
+    fn test_invoices_use_distinct_preimages() {
+        let mut plugin = PaymentPlugin::default();
+        let first = plugin.create_invoice(Some(1000), "Same amount").unwrap();
+        let second = plugin.create_invoice(Some(1000), "Same amount").unwrap();
+        assert_ne!(first.payment_hash, second.payment_hash);
+
+        let hash = PaymentHash::new(first.payment_hash);
+        let preimage = plugin.invoice_generator.preimage(&hash).unwrap();
+        assert!(preimage.matches(&hash));
+
+        let decoded = Bolt11Invoice::decode(&first.encoded).unwrap();
+        assert_eq!(decoded.invoice.payment_secret, plugin.invoice_generator.payment_secret(&hash));
+    }
+
     #[test]
     fn test_initial_balance() {
         let plugin = PaymentPlugin::default();
         assert_eq!(plugin.spendable_balance().satoshis, 0);
     }
+
+    #[test]
+    fn test_node_and_manager_share_channels() {
+        let mut plugin = PaymentPlugin::default();
+        let id =
+            block_on(plugin.lightning_node_mut().open_channel([2; 33], 200_000, 50_000)).unwrap();
+        assert_eq!(plugin.channels().channels().len(), 1);
+        assert_eq!(plugin.spendable_balance().satoshis, 0);
+
+        plugin.channels_mut().record_funding_confirmations(&id, 6).unwrap();
+        assert_eq!(plugin.spendable_balance().satoshis, 150_000);
+        assert_eq!(plugin.lightning_node().total_balance(), 150_000);
+        assert_eq!(plugin.channels().active_channels().len(), 1);
+
+        // Active channels are routable from the local node until they close.
+        let short_channel_id = plugin.channels().channel(&id).unwrap().short_channel_id();
+        plugin.sync_router_channels().unwrap();
+        let local = plugin.lightning_node().get_node_info().pubkey;
+        let routed = plugin.router().graph().channel(short_channel_id).unwrap();
+        assert_eq!(routed.capacity_sats, Some(200_000));
+        assert!(routed.policy_from(&local).is_some());
+        assert!(plugin.router().find_route(&[2; 33], 10_000).is_ok());
+        block_on(plugin.lightning_node_mut().close_channel(&id)).unwrap();
+        plugin.sync_router_channels().unwrap();
+        assert!(plugin.router().graph().channel(short_channel_id).is_none());
+    }
+
+    #[test]
+    fn test_hold_ruling_settles_or_cancels_invoice() {
+        let mut plugin = PaymentPlugin::default();
+        let id =
+            block_on(plugin.lightning_node_mut().open_channel([2; 33], 200_000, 100_000)).unwrap();
+        plugin.channels_mut().record_funding_confirmations(&id, 6).unwrap();
+        let arbiter = SecretKey::from_slice(&[7; 32]).unwrap();
+        plugin.escrows_mut().set_arbiter(arbiter.public_key().serialize());
+
+        let now = time::unix_seconds_sync();
+        let disputed_escrow = |plugin: &mut PaymentPlugin, seed: u8, ruling: Ruling| {
+            let payment_hash = PaymentPreimage::new([seed; 32]).payment_hash();
+            let node = plugin.lightning_node_mut();
+            let invoice =
+                block_on(node.create_hold_invoice(payment_hash, 10_000, "bounty", 3600)).unwrap();
+            let secret = invoice.payment_secret.unwrap();
+            node.receive_htlc(&id, 10_000_000, payment_hash, &secret, 700).unwrap();
+
+            let escrows = plugin.escrows_mut();
+            let terms = EscrowTerms {
+                bounty_id:   format!("bounty-{seed}"),
+                amount_sats: 10_000,
+                funder:      "alice".into(),
+                claimant:    "bob".into(),
+                deadline:    now + 3600,
+                escrow_type: EscrowType::LightningHold { payment_hash, preimage: None },
+            };
+            let escrow_id = escrows.create_escrow(terms, now).unwrap();
+            escrows.dispute(&escrow_id, DisputeParty::Claimant, "unpaid", None, now).unwrap();
+            escrows
+                .record_ruling(SignedRuling::sign(escrow_id, ruling, "reviewed", now, &arbiter))
+                .unwrap();
+            (escrow_id, payment_hash)
+        };
+
+        let (released, released_hash) = disputed_escrow(&mut plugin, 4, Ruling::Release);
+        assert!(plugin.execute_hold_ruling(&released, None, now).is_err());
+        let wrong = PaymentPreimage::new([9; 32]);
+        assert!(plugin.execute_hold_ruling(&released, Some(wrong), now).is_err());
+        plugin.execute_hold_ruling(&released, Some(PaymentPreimage::new([4; 32])), now).unwrap();
+        let hold = plugin.lightning_node().hold_invoice(&released_hash).unwrap();
+        assert_eq!(hold.state(), HoldInvoiceState::Settled);
+        assert_eq!(plugin.escrows().escrow(&released).unwrap().status, EscrowStatus::Released);
+
+        let (refunded, refunded_hash) = disputed_escrow(&mut plugin, 5, Ruling::Refund);
+        plugin.execute_hold_ruling(&refunded, None, now).unwrap();
+        let hold = plugin.lightning_node().hold_invoice(&refunded_hash).unwrap();
+        assert_eq!(hold.state(), HoldInvoiceState::Canceled);
+        let escrow = plugin.escrows().escrow(&refunded).unwrap();
+        assert_eq!(escrow.status, EscrowStatus::Refunded);
+        assert_eq!(
+            escrow.dispute.as_ref().unwrap().execution,
+            Some(RulingExecution::HoldCanceled(refunded_hash))
+        );
+        assert!(plugin.execute_hold_ruling(&refunded, None, now).is_err());
+        assert_eq!(plugin.lightning_node().total_balance(), 110_000);
+    }
+
+    #[test]
+    fn test_subscription_invoices_use_plugin_invoice_path() {
+        let clock = ManualClock::new(1_700_000_000);
+        let mut plugin =
+            PaymentPlugin::with_clock(PaymentConfig::default(), Arc::new(clock.clone()));
+        let invoice = plugin.subscribe("carol", SubscriptionTier::Pro).unwrap().unwrap();
+        assert_eq!(plugin.decode_invoice(&invoice.encoded).unwrap().amount, Some(10_000));
+
+        assert!(plugin.record_subscription_payment(PaymentPreimage::new([1; 32])).is_err());
+        let hash = PaymentHash::new(invoice.payment_hash);
+        let preimage = plugin.invoice_generator.preimage(&hash).unwrap();
+        plugin.record_subscription_payment(preimage).unwrap();
+        assert_eq!(plugin.payment(&invoice.payment_hash).unwrap().preimage, Some(preimage));
+        assert_eq!(plugin.subscriptions().effective_tier("carol"), SubscriptionTier::Pro);
+        clock.advance(plugin.subscriptions().policy().period_secs);
+        let events = plugin.run_billing().unwrap();
+        assert!(matches!(events.first(), Some(BillingEvent::InvoiceIssued { .. })));
+    }
+
+    #[test]
+    fn test_payment_store_tracks_outgoing_and_incoming() {
+        let clock = ManualClock::new(1_700_000_000);
+        let mut plugin =
+            PaymentPlugin::with_clock(PaymentConfig::default(), Arc::new(clock.clone()));
+        let unknown = [9; 32];
+        assert!(plugin.get_payment_status(&unknown).is_err());
+
+        let mut payee = PaymentPlugin::default();
+        let outgoing = block_on(payee.create_lightning_invoice(5_000, "tip", 3600)).unwrap();
+        let remote = payee.lightning_node().get_node_info().pubkey;
+        let err = block_on(plugin.send_lightning_payment(&outgoing, None)).unwrap_err();
+        assert!(matches!(err, PaymentError::Routing(_)));
+        let record = plugin.payment(&outgoing.payment_hash.0).unwrap();
+        assert_eq!(record.status, PaymentStatus::Failed);
+        assert_eq!((record.amount_msat, record.attempts), (5_000_000, 1));
+        assert!(record.failure_reason.as_deref().unwrap().contains("routing graph"));
+
+        let id =
+            block_on(plugin.lightning_node_mut().open_channel(remote, 200_000, 100_000)).unwrap();
+        plugin.channels_mut().record_funding_confirmations(&id, 6).unwrap();
+        clock.advance(60);
+        let status = block_on(plugin.send_lightning_payment(&outgoing, None)).unwrap();
+        assert_eq!(status, PaymentStatus::InFlight);
+        assert_eq!(plugin.get_payment_status(&outgoing.payment_hash.0).unwrap(), status);
+        assert_eq!(plugin.payment(&outgoing.payment_hash.0).unwrap().attempts, 2);
+        assert!(block_on(plugin.send_lightning_payment(&outgoing, None)).is_err());
+
+        let incoming = block_on(plugin.create_lightning_invoice(2_000, "coffee", 3600)).unwrap();
+        let hash = incoming.payment_hash;
+        assert_eq!(plugin.get_payment_status(&hash.0).unwrap(), PaymentStatus::Pending);
+        let secret = incoming.payment_secret.unwrap();
+        assert!(plugin.receive_htlc(&id, 1_000_000, hash, &secret, 700).is_err());
+        let record = plugin.payment(&hash.0).unwrap();
+        assert_eq!(record.status, PaymentStatus::Pending);
+        assert!(record.failure_reason.as_deref().unwrap().contains("underpays"));
+        clock.advance(60);
+        plugin.receive_htlc(&id, 2_000_000, hash, &secret, 700).unwrap();
+        let record = plugin.payment(&hash.0).unwrap();
+        assert_eq!(record.status, PaymentStatus::Succeeded);
+        assert_eq!((record.attempts, record.updated_at), (2, 1_700_000_120));
+        assert!(record.preimage.unwrap().matches(&hash));
+
+        let query = PaymentQuery {
+            direction: Some(crate::implementation::PaymentDirection::Incoming),
+            ..Default::default()
+        };
+        let listed = plugin.list_payments(&query);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].payment_hash, hash);
+        assert_eq!(plugin.payments().len(), 2);
+    }
+
+    #[test]
+    fn test_send_payment_reaches_final_status() {
+        let mut payer = PaymentPlugin::default();
+        let mut payee = PaymentPlugin::default();
+        let invoice = payee.create_invoice(Some(5_000), "tip").unwrap();
+        let mut swapped = invoice.clone();
+        swapped.payment_hash = [7; 32];
+        let err = block_on(payer.send_payment(&swapped)).unwrap_err();
+        assert!(matches!(err, PaymentError::Invoice(_)));
+
+        let err = block_on(payer.send_payment(&invoice)).unwrap_err();
+        assert!(matches!(err, PaymentError::Routing(_)));
+        assert_eq!(payer.get_payment_status(&invoice.payment_hash).unwrap(), PaymentStatus::Failed);
+
+        let remote = payee.lightning_node().get_node_info().pubkey;
+        let id =
+            block_on(payer.lightning_node_mut().open_channel(remote, 200_000, 100_000)).unwrap();
+        payer.channels_mut().record_funding_confirmations(&id, 6).unwrap();
+        let status = block_on(payer.send_payment(&invoice)).unwrap();
+        assert_eq!(status, PaymentStatus::InFlight);
+        assert!(block_on(payer.send_payment(&invoice)).is_err());
+
+        let hash = PaymentHash::new(invoice.payment_hash);
+        let preimage = payee.invoice_generator.preimage(&hash).unwrap();
+        payer.settle_payment_part(&hash, 0, preimage).unwrap();
+        let status = payer.get_payment_status(&invoice.payment_hash).unwrap();
+        assert_eq!(status, PaymentStatus::Succeeded);
+        assert!(block_on(payer.send_payment(&invoice)).is_err());
+    }
+
+    #[test]
+    fn test_amountless_invoice_needs_caller_amount() {
+        let mut payer = PaymentPlugin::default();
+        let mut payee = PaymentPlugin::default();
+        let encoded = payee.create_invoice(None, "donation").unwrap().encoded;
+        let invoice = payer.decode_lightning_invoice(&encoded).unwrap();
+        assert_eq!(invoice.amount_sats, None);
+
+        let err = block_on(payer.send_lightning_payment(&invoice, None)).unwrap_err();
+        assert!(matches!(err, PaymentError::Invoice(_)));
+        assert!(payer.payment(&invoice.payment_hash.0).is_none());
+
+        let err = block_on(payer.send_bolt11_payment(&encoded, Some(1_500_000))).unwrap_err();
+        assert!(matches!(err, PaymentError::Routing(_)));
+        assert_eq!(payer.payment(&invoice.payment_hash.0).unwrap().amount_msat, 1_500_000);
+    }
+
+    #[test]
+    fn test_payment_keeps_bolt11_hash_and_secret() {
+        let mut payer = PaymentPlugin::default();
+        let mut payee = PaymentPlugin::default();
+        let encoded = payee.create_invoice(Some(1_000), "coffee").unwrap().encoded;
+        let signed = payer.decode_lightning_invoice(&encoded).unwrap();
+
+        let mut swapped_hash = signed.clone();
+        swapped_hash.payment_hash = PaymentHash::new([7; 32]);
+        let mut swapped_secret = signed;
+        swapped_secret.payment_secret = Some([7; 32]);
+        for invoice in [swapped_hash, swapped_secret] {
+            let err = block_on(payer.send_lightning_payment(&invoice, None)).unwrap_err();
+            assert!(
+                matches!(err, PaymentError::Invoice(ref msg) if msg.contains("does not match"))
+            );
+            assert!(payer.payment(&invoice.payment_hash.0).is_none());
+        }
+    }
+
+    #[test]
+    fn test_bolt11_expiry_follows_plugin_clock() {
+        let mut payee = PaymentPlugin::default();
+        let encoded = payee.create_invoice(Some(1_000), "late").unwrap().encoded;
+        let expires_at = Bolt11Invoice::decode(&encoded).unwrap().invoice.expires_at();
+        let clock = ManualClock::new(expires_at - 1);
+        let mut payer =
+            PaymentPlugin::with_clock(PaymentConfig::default(), Arc::new(clock.clone()));
+
+        let err = block_on(payer.send_bolt11_payment(&encoded, None)).unwrap_err();
+        assert!(matches!(err, PaymentError::Routing(_)));
+        clock.advance(1);
+        let err = block_on(payer.send_bolt11_payment(&encoded, None)).unwrap_err();
+        assert!(matches!(err, PaymentError::Invoice(ref msg) if msg.contains("expired")));
+
+        let mut invoice = payer.decode_lightning_invoice(&encoded).unwrap();
+        let err = block_on(payer.send_lightning_payment(&invoice, None)).unwrap_err();
+        assert!(matches!(err, PaymentError::Invoice(ref msg) if msg.contains("expired")));
+        invoice.bolt11.push('x');
+        let err = block_on(payer.send_lightning_payment(&invoice, None)).unwrap_err();
+        assert!(matches!(err, PaymentError::Invoice(_)) && !err.is_retryable());
+    }
+
+    #[test]
+    fn test_retried_payment_is_recorded_per_attempt() {
+        let clock = ManualClock::new(1_700_000_000);
+        let mut payer =
+            PaymentPlugin::with_clock(PaymentConfig::default(), Arc::new(clock.clone()));
+        let mut payee = PaymentPlugin::default();
+        let invoice = block_on(payee.create_lightning_invoice(1_000, "retry", 3600)).unwrap();
+        let preimage = payee.lightning_node().invoice_preimage(&invoice.payment_hash).unwrap();
+
+        let remote = payee.lightning_node().get_node_info().pubkey;
+        let open = |payer: &mut PaymentPlugin| {
+            let id = block_on(payer.lightning_node_mut().open_channel(remote, 100_000, 0)).unwrap();
+            payer.channels_mut().record_funding_confirmations(&id, 6).unwrap();
+            payer.channels().channel(&id).unwrap().short_channel_id()
+        };
+        let channels = [open(&mut payer), open(&mut payer)];
+
+        let mut failed = None;
+        let paid = payer.send_payment_with_retries(&invoice, |route| {
+            clock.advance(5);
+            let short_channel_id = route.hops[0].short_channel_id;
+            if failed.is_some() {
+                return Ok(preimage);
+            }
+            failed = Some(short_channel_id);
+            Err(AttemptFailure::Channel {
+                short_channel_id,
+                reason: "temporary channel failure".into(),
+            })
+        });
+        assert_eq!(paid.unwrap(), preimage);
+        let record = payer.payment(&invoice.payment_hash.0).unwrap();
+        assert_eq!(record.status, PaymentStatus::Succeeded);
+        assert_eq!((record.attempts, record.routes.len()), (2, 1));
+        let retried = record.routes[0].hops[0].short_channel_id;
+        assert!(channels.contains(&retried) && Some(retried) != failed);
+        assert_eq!(record.updated_at, 1_700_000_010);
+
+        let config = PaymentConfig { max_payment_retries: 0, ..PaymentConfig::default() };
+        let mut payer = PaymentPlugin::with_clock(config, Arc::new(clock.clone()));
+        let short_channel_id = open(&mut payer);
+        let err = payer
+            .send_payment_with_retries(&invoice, |_| {
+                Err(AttemptFailure::Node { pubkey: remote, reason: "offline".into() })
+            })
+            .unwrap_err();
+        assert!(matches!(err, PaymentError::Timeout(_)));
+        let record = payer.payment(&invoice.payment_hash.0).unwrap();
+        assert_eq!((record.status, record.attempts), (PaymentStatus::Failed, 1));
+        let via = format!("attempt 1 via [{short_channel_id}]");
+        assert!(record.failure_reason.as_deref().unwrap().contains(&via));
+    }
 }
@@ -0,0 +1,161 @@
+//! Proration of mid-period subscription tier changes.
+//!
+//! Switching tier part way through a billing period credits the unused share
+//! of the old tier's price and charges the same share of the new tier's
+//! price. Shares are taken by time, in whole seconds, and rounded down.
+
+use core::cmp::Ordering;
+
+use crate::types::SubscriptionTier;
+
+/// Net result of a proration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProrationOutcome {
+    /// Customer owes this many satoshis.
+    Charge(u64),
+    /// Customer is owed this many satoshis.
+    Credit(u64),
+    /// Nothing is owed either way.
+    Even,
+}
+
+/// Credit and charge for switching tier at some point in a period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Proration {
+    /// Tier being left.
+    pub from:           SubscriptionTier,
+    /// Tier being moved to.
+    pub to:             SubscriptionTier,
+    /// Length of the period, in seconds.
+    pub period_secs:    u64,
+    /// Part of the period still to run, in seconds.
+    pub remaining_secs: u64,
+    /// Unused share of the old tier's price, in satoshis.
+    pub credit_sats:    u64,
+    /// Share of the new tier's price for the rest of the period, in satoshis.
+    pub charge_sats:    u64,
+}
+
+impl Proration {
+    /// Prorate a switch from `from` to `to` at `now` within the period
+    /// `[period_start, period_end)`.
+    ///
+    /// A switch outside the period has nothing left to prorate.
+    #[must_use]
+    pub fn compute(
+        period_start: u64, period_end: u64, now: u64, from: SubscriptionTier, to: SubscriptionTier,
+    ) -> Self {
+        let period_secs = period_end.saturating_sub(period_start);
+        let remaining_secs = period_end.saturating_sub(now.max(period_start)).min(period_secs);
+        let share = |price: u64| {
+            if period_secs == 0 {
+                return 0;
+            }
+            (u128::from(price) * u128::from(remaining_secs) / u128::from(period_secs)) as u64
+        };
+        Self {
+            from,
+            to,
+            period_secs,
+            remaining_secs,
+            credit_sats: share(from.monthly_price_sats()),
+            charge_sats: share(to.monthly_price_sats()),
+        }
+    }
+
+    /// Net amount owed after offsetting the credit against the charge.
+    #[must_use]
+    pub fn outcome(&self) -> ProrationOutcome {
+        Self::net(self.charge_sats, self.credit_sats)
+    }
+
+    /// Net amount owed after also spending `account_credit_sats`.
+    #[must_use]
+    pub fn outcome_with_credit(&self, account_credit_sats: u64) -> ProrationOutcome {
+        Self::net(self.charge_sats, self.credit_sats.saturating_add(account_credit_sats))
+    }
+
+    fn net(charge: u64, credit: u64) -> ProrationOutcome {
+        match charge.cmp(&credit) {
+            Ordering::Greater => ProrationOutcome::Charge(charge - credit),
+            Ordering::Less => ProrationOutcome::Credit(credit - charge),
+            Ordering::Equal => ProrationOutcome::Even,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+
+    const START: u64 = 1_700_000_000;
+    const PERIOD: u64 = 30 * 86_400;
+
+    #[test]
+    fn test_mid_period_upgrade_and_downgrade() {
+        let half = START + PERIOD / 2;
+        let upgrade = Proration::compute(
+            START,
+            START + PERIOD,
+            half,
+            SubscriptionTier::Pro,
+            SubscriptionTier::Enterprise,
+        );
+        assert_eq!((upgrade.credit_sats, upgrade.charge_sats), (5_000, 50_000));
+        assert_eq!(upgrade.outcome(), ProrationOutcome::Charge(45_000));
+        assert_eq!(upgrade.outcome_with_credit(50_000), ProrationOutcome::Credit(5_000));
+
+        let downgrade = Proration::compute(
+            START,
+            START + PERIOD,
+            half,
+            SubscriptionTier::Enterprise,
+            SubscriptionTier::Free,
+        );
+        assert_eq!(downgrade.outcome(), ProrationOutcome::Credit(50_000));
+        assert_eq!(
+            Proration::compute(
+                START,
+                START + PERIOD,
+                half,
+                SubscriptionTier::Pro,
+                SubscriptionTier::Pro
+            )
+            .outcome(),
+            ProrationOutcome::Even
+        );
+    }
+
+    #[test]
+    fn test_rounds_down_and_clamps_to_period() {
+        // One third of the period left: 10_000 / 3 rounds down.
+        let third = Proration::compute(
+            START,
+            START + PERIOD,
+            START + 2 * PERIOD / 3,
+            SubscriptionTier::Free,
+            SubscriptionTier::Pro,
+        );
+        assert_eq!(third.charge_sats, 3_333);
+
+        let before = Proration::compute(
+            START,
+            START + PERIOD,
+            START - 1,
+            SubscriptionTier::Free,
+            SubscriptionTier::Pro,
+        );
+        assert_eq!((before.remaining_secs, before.charge_sats), (PERIOD, 10_000));
+        let after = Proration::compute(
+            START,
+            START + PERIOD,
+            START + PERIOD,
+            SubscriptionTier::Free,
+            SubscriptionTier::Pro,
+        );
+        assert_eq!(after.outcome(), ProrationOutcome::Even);
+        let empty =
+            Proration::compute(START, START, START, SubscriptionTier::Free, SubscriptionTier::Pro);
+        assert_eq!(empty.charge_sats, 0);
+    }
+}
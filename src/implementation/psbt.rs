@@ -0,0 +1,709 @@
+//! Partially signed Bitcoin transactions (BIP 174, version 0).
+//!
+//! Covers the roles needed to spend a multisig escrow offline: the creator
+//! builds the PSBT, each signer adds a partial signature, the combiner merges
+//! copies signed by different parties, and the finalizer assembles the
+//! witness so the transaction can be extracted and broadcast.
+
+use core::fmt;
+use std::collections::BTreeMap;
+
+use crate::{
+    crypto::{PublicKey, SecretKey, Signature},
+    encoding::base64,
+    errors::{ErrorDetail, PaymentError},
+    implementation::{
+        multisig::{multisig_keys, p2wsh_script_pubkey},
+        transaction::{
+            write_compact_size, write_var_bytes, Cursor, Transaction, TransactionError, TxOut,
+            SIGHASH_ALL,
+        },
+    },
+};
+
+/// Magic bytes opening every PSBT.
+const PSBT_MAGIC: [u8; 5] = *b"psbt\xff";
+
+/// Global key types.
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+/// Input key types.
+const IN_WITNESS_UTXO: u8 = 0x01;
+const IN_PARTIAL_SIG: u8 = 0x02;
+const IN_SIGHASH_TYPE: u8 = 0x03;
+const IN_WITNESS_SCRIPT: u8 = 0x05;
+const IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+/// Output key types.
+const OUT_WITNESS_SCRIPT: u8 = 0x01;
+
+/// Reasons a PSBT operation fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PsbtError {
+    /// Missing `psbt\xff` prefix.
+    InvalidMagic,
+    /// Not valid base64.
+    InvalidBase64,
+    /// Malformed consensus encoding.
+    Transaction(TransactionError),
+    /// Key appears twice in one map.
+    DuplicateKey,
+    /// Field has an invalid key or value.
+    InvalidField(&'static str),
+    /// Global map lacks the unsigned transaction.
+    MissingUnsignedTx,
+    /// Unsigned transaction carries scripts or witnesses.
+    UnsignedTxHasScripts,
+    /// Map count does not match the transaction.
+    MapCountMismatch,
+    /// Input index is out of range.
+    NoSuchInput(usize),
+    /// Input lacks the data needed to sign or finalize it.
+    MissingInputData(usize, &'static str),
+    /// Witness script does not hash to the spent output.
+    WitnessScriptMismatch(usize),
+    /// Witness script is not a multisig script.
+    NotMultisig(usize),
+    /// Signing key is not part of the witness script.
+    KeyNotInScript(usize),
+    /// Too few valid signatures to finalize an input.
+    NotEnoughSignatures {
+        /// Input index.
+        index: usize,
+        /// Valid signatures present.
+        have:  usize,
+        /// Signatures required.
+        need:  usize,
+    },
+    /// PSBTs to combine spend different transactions.
+    DifferentTransaction,
+    /// Input is not finalized.
+    NotFinalized(usize),
+    /// Outputs spend more than the inputs provide.
+    OutputsExceedInputs,
+    /// Output would be below the dust limit.
+    DustOutput,
+}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "missing PSBT magic bytes"),
+            Self::InvalidBase64 => write!(f, "PSBT is not valid base64"),
+            Self::Transaction(err) => write!(f, "{err}"),
+            Self::DuplicateKey => write!(f, "duplicate key in PSBT map"),
+            Self::InvalidField(field) => write!(f, "invalid {field} field"),
+            Self::MissingUnsignedTx => write!(f, "PSBT has no unsigned transaction"),
+            Self::UnsignedTxHasScripts => {
+                write!(f, "unsigned transaction must not carry scripts or witnesses")
+            },
+            Self::MapCountMismatch => write!(f, "PSBT maps do not match the transaction"),
+            Self::NoSuchInput(index) => write!(f, "no input {index}"),
+            Self::MissingInputData(index, field) => write!(f, "input {index} has no {field}"),
+            Self::WitnessScriptMismatch(index) => {
+                write!(f, "input {index} witness script does not match the spent output")
+            },
+            Self::NotMultisig(index) => write!(f, "input {index} is not a multisig spend"),
+            Self::KeyNotInScript(index) => {
+                write!(f, "signing key is not part of input {index} witness script")
+            },
+            Self::NotEnoughSignatures { index, have, need } => {
+                write!(f, "input {index} has {have} of {need} required signatures")
+            },
+            Self::DifferentTransaction => write!(f, "PSBTs spend different transactions"),
+            Self::NotFinalized(index) => write!(f, "input {index} is not finalized"),
+            Self::OutputsExceedInputs => write!(f, "outputs spend more than the inputs provide"),
+            Self::DustOutput => write!(f, "output would be below the dust limit"),
+        }
+    }
+}
+
+impl From<TransactionError> for PsbtError {
+    fn from(err: TransactionError) -> Self {
+        Self::Transaction(err)
+    }
+}
+
+impl std::error::Error for PsbtError {}
+
+impl From<PsbtError> for PaymentError {
+    fn from(err: PsbtError) -> Self {
+        PaymentError::Escrow(ErrorDetail::caused_by(format!("PSBT: {err}"), err))
+    }
+}
+
+/// Per-input PSBT data.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PsbtInput {
+    /// Output being spent.
+    pub witness_utxo:         Option<TxOut>,
+    /// Signatures by public key, DER encoded with a trailing sighash byte.
+    pub partial_sigs:         BTreeMap<[u8; 33], Vec<u8>>,
+    /// Sighash type signers must use.
+    pub sighash_type:         Option<u32>,
+    /// Script the spent P2WSH output commits to.
+    pub witness_script:       Option<Vec<u8>>,
+    /// Complete witness, once finalized.
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    /// Fields this implementation does not interpret, kept for round trips.
+    pub unknown:              BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Per-output PSBT data.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PsbtOutput {
+    /// Script a P2WSH output commits to.
+    pub witness_script: Option<Vec<u8>>,
+    /// Fields this implementation does not interpret, kept for round trips.
+    pub unknown:        BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Partially signed Bitcoin transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Psbt {
+    /// Transaction being signed, without scripts or witnesses.
+    pub unsigned_tx:    Transaction,
+    /// Data for each input.
+    pub inputs:         Vec<PsbtInput>,
+    /// Data for each output.
+    pub outputs:        Vec<PsbtOutput>,
+    /// Global fields this implementation does not interpret.
+    pub unknown_global: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Psbt {
+    /// Wrap an unsigned transaction.
+    pub fn from_unsigned_tx(unsigned_tx: Transaction) -> Result<Self, PsbtError> {
+        if unsigned_tx
+            .input
+            .iter()
+            .any(|input| !input.script_sig.is_empty() || !input.witness.is_empty())
+        {
+            return Err(PsbtError::UnsignedTxHasScripts);
+        }
+        Ok(Self {
+            inputs: vec![PsbtInput::default(); unsigned_tx.input.len()],
+            outputs: vec![PsbtOutput::default(); unsigned_tx.output.len()],
+            unsigned_tx,
+            unknown_global: BTreeMap::new(),
+        })
+    }
+
+    /// Fee paid, when every input's spent output is known.
+    #[must_use]
+    pub fn fee(&self) -> Option<u64> {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.as_ref().map(|utxo| utxo.value))
+            .sum::<Option<u64>>()?;
+        let outputs: u64 = self.unsigned_tx.output.iter().map(|output| output.value).sum();
+        inputs.checked_sub(outputs)
+    }
+
+    /// Sign a P2WSH input with `key`, adding a partial signature.
+    pub fn sign_input(&mut self, index: usize, key: &SecretKey) -> Result<(), PsbtError> {
+        let pubkey = key.public_key().serialize();
+        let (sighash, sighash_type) = self.sighash(index)?;
+        let input = &mut self.inputs[index];
+        let script = input.witness_script.as_deref().unwrap_or_default();
+        if !multisig_keys(script).is_some_and(|(_, keys)| keys.contains(&pubkey)) {
+            return Err(PsbtError::KeyNotInScript(index));
+        }
+
+        let mut signature = key.sign_ecdsa(&sighash).serialize_der();
+        signature.push(sighash_type as u8);
+        input.partial_sigs.insert(pubkey, signature);
+        Ok(())
+    }
+
+    /// Merge signatures and fields from another copy of the same PSBT.
+    pub fn combine(&mut self, other: Psbt) -> Result<(), PsbtError> {
+        if self.unsigned_tx.txid() != other.unsigned_tx.txid() {
+            return Err(PsbtError::DifferentTransaction);
+        }
+
+        for (ours, theirs) in self.inputs.iter_mut().zip(other.inputs) {
+            ours.witness_utxo = ours.witness_utxo.take().or(theirs.witness_utxo);
+            ours.sighash_type = ours.sighash_type.or(theirs.sighash_type);
+            ours.witness_script = ours.witness_script.take().or(theirs.witness_script);
+            ours.final_script_witness =
+                ours.final_script_witness.take().or(theirs.final_script_witness);
+            ours.partial_sigs.extend(theirs.partial_sigs);
+            ours.unknown.extend(theirs.unknown);
+        }
+        for (ours, theirs) in self.outputs.iter_mut().zip(other.outputs) {
+            ours.witness_script = ours.witness_script.take().or(theirs.witness_script);
+            ours.unknown.extend(theirs.unknown);
+        }
+        self.unknown_global.extend(other.unknown_global);
+        Ok(())
+    }
+
+    /// Finalize every input that is not final yet.
+    pub fn finalize(&mut self) -> Result<(), PsbtError> {
+        for index in 0..self.inputs.len() {
+            if self.inputs[index].final_script_witness.is_none() {
+                self.finalize_input(index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Assemble the witness of a multisig input from valid partial signatures.
+    ///
+    /// Signatures are placed in the order of the keys in the witness script, as
+    /// `OP_CHECKMULTISIG` requires. Signing data is dropped afterwards.
+    pub fn finalize_input(&mut self, index: usize) -> Result<(), PsbtError> {
+        let (sighash, sighash_type) = self.sighash(index)?;
+        let input = &mut self.inputs[index];
+        let script = input.witness_script.clone().unwrap_or_default();
+        let (threshold, keys) = multisig_keys(&script).ok_or(PsbtError::NotMultisig(index))?;
+
+        let mut witness = vec![Vec::new()]; // OP_CHECKMULTISIG pops one extra item
+        for key in &keys {
+            if witness.len() > threshold {
+                break;
+            }
+            let Some(signature) = input.partial_sigs.get(key) else { continue };
+            if valid_signature(key, signature, &sighash, sighash_type) {
+                witness.push(signature.clone());
+            }
+        }
+        if witness.len() <= threshold {
+            return Err(PsbtError::NotEnoughSignatures {
+                index,
+                have: witness.len() - 1,
+                need: threshold,
+            });
+        }
+        witness.push(script);
+
+        input.final_script_witness = Some(witness);
+        input.partial_sigs.clear();
+        input.sighash_type = None;
+        input.witness_script = None;
+        Ok(())
+    }
+
+    /// Extract the signed transaction once every input is finalized.
+    pub fn extract_tx(&self) -> Result<Transaction, PsbtError> {
+        let mut tx = self.unsigned_tx.clone();
+        for (index, (txin, input)) in tx.input.iter_mut().zip(&self.inputs).enumerate() {
+            txin.witness =
+                input.final_script_witness.clone().ok_or(PsbtError::NotFinalized(index))?;
+        }
+        Ok(tx)
+    }
+
+    /// Serialize to the binary PSBT format.
+    #[must_use]
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = PSBT_MAGIC.to_vec();
+
+        write_pair(&mut out, &[GLOBAL_UNSIGNED_TX], &self.unsigned_tx.serialize_without_witness());
+        write_unknown(&mut out, &self.unknown_global);
+        out.push(0);
+
+        for input in &self.inputs {
+            if let Some(utxo) = &input.witness_utxo {
+                let mut value = utxo.value.to_le_bytes().to_vec();
+                write_var_bytes(&mut value, &utxo.script_pubkey);
+                write_pair(&mut out, &[IN_WITNESS_UTXO], &value);
+            }
+            for (pubkey, signature) in &input.partial_sigs {
+                let mut key = vec![IN_PARTIAL_SIG];
+                key.extend_from_slice(pubkey);
+                write_pair(&mut out, &key, signature);
+            }
+            if let Some(sighash_type) = input.sighash_type {
+                write_pair(&mut out, &[IN_SIGHASH_TYPE], &sighash_type.to_le_bytes());
+            }
+            if let Some(script) = &input.witness_script {
+                write_pair(&mut out, &[IN_WITNESS_SCRIPT], script);
+            }
+            if let Some(witness) = &input.final_script_witness {
+                let mut value = Vec::new();
+                write_compact_size(&mut value, witness.len() as u64);
+                for item in witness {
+                    write_var_bytes(&mut value, item);
+                }
+                write_pair(&mut out, &[IN_FINAL_SCRIPTWITNESS], &value);
+            }
+            write_unknown(&mut out, &input.unknown);
+            out.push(0);
+        }
+
+        for output in &self.outputs {
+            if let Some(script) = &output.witness_script {
+                write_pair(&mut out, &[OUT_WITNESS_SCRIPT], script);
+            }
+            write_unknown(&mut out, &output.unknown);
+            out.push(0);
+        }
+        out
+    }
+
+    /// Parse the binary PSBT format.
+    pub fn parse(bytes: &[u8]) -> Result<Self, PsbtError> {
+        let mut reader = Cursor { bytes };
+        if reader.array::<5>()? != PSBT_MAGIC {
+            return Err(PsbtError::InvalidMagic);
+        }
+
+        let mut unsigned_tx = None;
+        let mut unknown_global = BTreeMap::new();
+        for (key, value) in read_map(&mut reader)? {
+            if key == [GLOBAL_UNSIGNED_TX] {
+                unsigned_tx = Some(Transaction::parse(&value)?);
+            } else {
+                unknown_global.insert(key, value);
+            }
+        }
+        let mut psbt = Self::from_unsigned_tx(unsigned_tx.ok_or(PsbtError::MissingUnsignedTx)?)?;
+        psbt.unknown_global = unknown_global;
+
+        for input in &mut psbt.inputs {
+            for (key, value) in read_map(&mut reader)? {
+                match key[0] {
+                    IN_WITNESS_UTXO if key.len() == 1 => {
+                        let mut cursor = Cursor { bytes: &value };
+                        let utxo = TxOut {
+                            value:         u64::from_le_bytes(cursor.array()?),
+                            script_pubkey: cursor.var_bytes()?,
+                        };
+                        expect_consumed(&cursor, "witness UTXO")?;
+                        input.witness_utxo = Some(utxo);
+                    },
+                    IN_PARTIAL_SIG => {
+                        let pubkey: [u8; 33] = key[1..]
+                            .try_into()
+                            .map_err(|_| PsbtError::InvalidField("partial signature"))?;
+                        input.partial_sigs.insert(pubkey, value);
+                    },
+                    IN_SIGHASH_TYPE if key.len() == 1 => {
+                        let bytes: [u8; 4] = value
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| PsbtError::InvalidField("sighash type"))?;
+                        input.sighash_type = Some(u32::from_le_bytes(bytes));
+                    },
+                    IN_WITNESS_SCRIPT if key.len() == 1 => input.witness_script = Some(value),
+                    IN_FINAL_SCRIPTWITNESS if key.len() == 1 => {
+                        let mut cursor = Cursor { bytes: &value };
+                        let count = cursor.compact_size()?;
+                        let mut witness = Vec::new();
+                        for _ in 0..count {
+                            witness.push(cursor.var_bytes()?);
+                        }
+                        expect_consumed(&cursor, "final script witness")?;
+                        input.final_script_witness = Some(witness);
+                    },
+                    _ => {
+                        input.unknown.insert(key, value);
+                    },
+                }
+            }
+        }
+
+        for output in &mut psbt.outputs {
+            for (key, value) in read_map(&mut reader)? {
+                if key == [OUT_WITNESS_SCRIPT] {
+                    output.witness_script = Some(value);
+                } else {
+                    output.unknown.insert(key, value);
+                }
+            }
+        }
+
+        if !reader.bytes.is_empty() {
+            return Err(PsbtError::MapCountMismatch);
+        }
+        Ok(psbt)
+    }
+
+    /// Encode as base64, the usual exchange format.
+    #[must_use]
+    pub fn to_base64(&self) -> String {
+        base64::encode(&self.serialize())
+    }
+
+    /// Decode from base64.
+    pub fn from_base64(encoded: &str) -> Result<Self, PsbtError> {
+        Self::parse(&base64::decode(encoded.trim()).ok_or(PsbtError::InvalidBase64)?)
+    }
+
+    /// BIP 143 signature hash of a P2WSH input, with its sighash type.
+    fn sighash(&self, index: usize) -> Result<([u8; 32], u32), PsbtError> {
+        let input = self.inputs.get(index).ok_or(PsbtError::NoSuchInput(index))?;
+        let utxo = input
+            .witness_utxo
+            .as_ref()
+            .ok_or(PsbtError::MissingInputData(index, "witness UTXO"))?;
+        let script = input
+            .witness_script
+            .as_ref()
+            .ok_or(PsbtError::MissingInputData(index, "witness script"))?;
+        if p2wsh_script_pubkey(script) != utxo.script_pubkey {
+            return Err(PsbtError::WitnessScriptMismatch(index));
+        }
+
+        let sighash_type = input.sighash_type.unwrap_or(SIGHASH_ALL);
+        let sighash = self.unsigned_tx.segwit_v0_sighash(index, script, utxo.value, sighash_type);
+        Ok((sighash, sighash_type))
+    }
+}
+
+/// Check a DER signature with trailing sighash byte against `pubkey`.
+fn valid_signature(
+    pubkey: &[u8; 33], signature: &[u8], sighash: &[u8; 32], sighash_type: u32,
+) -> bool {
+    let Some((&hash_byte, der)) = signature.split_last() else {
+        return false;
+    };
+    let (Ok(pubkey), Ok(signature)) = (PublicKey::from_slice(pubkey), Signature::from_der(der))
+    else {
+        return false;
+    };
+    u32::from(hash_byte) == sighash_type && pubkey.verify_ecdsa(sighash, &signature)
+}
+
+fn write_pair(out: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    write_var_bytes(out, key);
+    write_var_bytes(out, value);
+}
+
+fn write_unknown(out: &mut Vec<u8>, unknown: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    for (key, value) in unknown {
+        write_pair(out, key, value);
+    }
+}
+
+/// Raw key and value of a map entry.
+type Pair = (Vec<u8>, Vec<u8>);
+
+/// Read key-value pairs up to the map separator.
+fn read_map(reader: &mut Cursor<'_>) -> Result<Vec<Pair>, PsbtError> {
+    let mut pairs: Vec<Pair> = Vec::new();
+    loop {
+        let key = reader.var_bytes()?;
+        if key.is_empty() {
+            return Ok(pairs);
+        }
+        if pairs.iter().any(|(existing, _)| *existing == key) {
+            return Err(PsbtError::DuplicateKey);
+        }
+        let value = reader.var_bytes()?;
+        pairs.push((key, value));
+    }
+}
+
+fn expect_consumed(cursor: &Cursor<'_>, field: &'static str) -> Result<(), PsbtError> {
+    if cursor.bytes.is_empty() {
+        Ok(())
+    } else {
+        Err(PsbtError::InvalidField(field))
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::hash160,
+        encoding::hex,
+        implementation::{
+            multisig::{p2wpkh_script_pubkey, MultisigEscrow},
+            test_utils::secret,
+            transaction::OutPoint,
+        },
+    };
+
+    fn escrow() -> MultisigEscrow {
+        let [funder, claimant, arbiter] =
+            [1, 2, 3].map(|byte| secret(byte).public_key().serialize());
+        MultisigEscrow::new(funder, claimant, arbiter).unwrap()
+    }
+
+    fn funding() -> OutPoint {
+        OutPoint::from_display_txid(
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+            1,
+        )
+        .unwrap()
+    }
+
+    /// Unsigned release of `escrow()` from `funding()`: 100_000 sats less 500 fee.
+    const RELEASE_PSBT: &str = "\
+        cHNidP8BAFICAAAAARaeHoPpMIUzkbxvNfYFxnVM/q1Xz4OHY507QJbFTxj0AQAAAAD/////AayEAQAAAAAA\
+        FgAU68DuCyq56Cd6YAwlFHXiKjJBocEAAAAAAAEBK6CGAQAAAAAAIgAgozeYhMmRnorjelaOdrSvnXKwkov1\
+        L16o5fUwMmkdF74BBWlSIQJNS2zRNhAyypvSrrnZAKpNRdnq2ArJQjN0xFGnJU0HZiECUx/mBoE0UD0nIxMy\
+        J8hnrI+myDxTfppEw8W9vcsf4zchAxuExVZ7EmRAmV0+1aq6BWXXHhg0YEgZ/5wX9enV3QePU64AAA==";
+
+    /// `RELEASE_PSBT` signed by the claimant and arbiter, then finalized.
+    const RELEASE_TX_HEX: &str = "\
+        02000000000101169e1e83e930853391bc6f35f605c6754cfead57cf8387639d3b4096c54f18f4010000\
+        0000ffffffff01ac84010000000000160014ebc0ee0b2ab9e8277a600c251475e22a3241a1c104004830\
+        4502210093713acd30ef01d70d4d9cedb4b1db546db15ff080230ccbb11cddfdb0cf3e32022028f9221e\
+        c273f688b5cb0eabfaf16d6f8658391edfa385021948f1dd98b4627601483045022100dc6e1b0e275194\
+        7662983cf612b0ab9cdcf8a5afc7001c4e050079deed77023b02204d0ba73e18f14484c57b765c34104a\
+        2138a6caf29b8bbf55fa70c5dc9e8ff3c101695221024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead8\
+        0ac9423374c451a7254d07662102531fe6068134503d2723133227c867ac8fa6c83c537e9a44c3c5bdbd\
+        cb1fe33721031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f53ae0000\
+        0000";
+
+    /// BIP 174 valid vector: one P2PKH input with its full previous transaction.
+    const BIP174_P2PKH_PSBT: &str = "\
+        cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAA\
+        GXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAA\
+        AQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89\
+        p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkC\
+        xDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cl\
+        uiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc\
+        0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUC\
+        SDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8\
+        hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+
+    #[test]
+    fn test_p2wpkh_script_vector() {
+        // BIP 173: the generator point's P2WPKH program.
+        let pubkey: [u8; 33] =
+            hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap()
+                .try_into()
+                .unwrap();
+        assert_eq!(hex::encode(&hash160(&pubkey)), "751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(
+            hex::encode(&p2wpkh_script_pubkey(&pubkey)),
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6"
+        );
+    }
+
+    #[test]
+    fn test_release_with_two_of_three_signatures() {
+        let escrow = escrow();
+        let psbt = escrow.release_psbt(funding(), 100_000, 500).unwrap();
+        assert_eq!(psbt.to_base64(), RELEASE_PSBT);
+        assert_eq!(psbt.fee(), Some(500));
+        assert_eq!(
+            psbt.unsigned_tx.output[0].script_pubkey,
+            p2wpkh_script_pubkey(escrow.claimant())
+        );
+
+        // Claimant and arbiter sign separate copies exchanged as base64.
+        let encoded = psbt.to_base64();
+        let mut claimant_copy = Psbt::from_base64(&encoded).unwrap();
+        claimant_copy.sign_input(0, &secret(2)).unwrap();
+        let mut arbiter_copy = Psbt::from_base64(&encoded).unwrap();
+        arbiter_copy.sign_input(0, &secret(3)).unwrap();
+
+        let mut combined = Psbt::from_base64(&claimant_copy.to_base64()).unwrap();
+        combined.combine(Psbt::parse(&arbiter_copy.serialize()).unwrap()).unwrap();
+        assert_eq!(combined.inputs[0].partial_sigs.len(), 2);
+        combined.finalize().unwrap();
+        assert_eq!(Psbt::from_base64(&combined.to_base64()).unwrap(), combined);
+
+        let tx = combined.extract_tx().unwrap();
+        let witness = &tx.input[0].witness;
+        assert_eq!(witness.len(), 4);
+        assert!(witness[0].is_empty());
+        assert_eq!(witness[3], escrow.witness_script());
+        assert_eq!(tx.txid(), psbt.unsigned_tx.txid());
+
+        // Signatures sit in script key order and commit to the BIP 143 digest.
+        let sighash =
+            psbt.unsigned_tx.segwit_v0_sighash(0, &escrow.witness_script(), 100_000, SIGHASH_ALL);
+        let mut signers = [secret(2).public_key(), secret(3).public_key()];
+        signers.sort_by_key(PublicKey::serialize);
+        for (signer, signature) in signers.iter().zip(&witness[1..3]) {
+            assert_eq!(signature.last(), Some(&(SIGHASH_ALL as u8)));
+            let der = Signature::from_der(&signature[..signature.len() - 1]).unwrap();
+            assert!(signer.verify_ecdsa(&sighash, &der));
+        }
+
+        // Signing is deterministic (RFC 6979), so the release transaction is
+        // fixed; checked against an independent BIP 143 and RFC 6979 signer.
+        assert_eq!(tx.to_hex(), RELEASE_TX_HEX);
+        assert_eq!(Transaction::parse(&hex::decode(RELEASE_TX_HEX).unwrap()).unwrap(), tx);
+    }
+
+    #[test]
+    fn test_bip174_p2pkh_vector() {
+        // BIP 174: "PSBT with one P2PKH input. Outputs are empty".
+        let psbt = Psbt::from_base64(BIP174_P2PKH_PSBT).unwrap();
+        let tx = &psbt.unsigned_tx;
+        assert_eq!(
+            tx.display_txid(),
+            "af2cac1e0e33d896d9d0751d66fcb2fa54b737c7a13199281fb57e4f497bb652"
+        );
+        assert_eq!(
+            tx.input[0].previous_output,
+            OutPoint::from_display_txid(
+                "f61b1742ca13176464adb3cb66050c00787bb3a4eead37e985f2df1e37718126",
+                0,
+            )
+            .unwrap()
+        );
+        let values: Vec<u64> = tx.output.iter().map(|output| output.value).collect();
+        assert_eq!(values, [99_999_699, 100_000_000]);
+        assert_eq!(tx.lock_time, 1_257_139);
+
+        // The full previous transaction is kept as an unknown key and written back.
+        let previous = Transaction::parse(&psbt.inputs[0].unknown[&vec![0x00]]).unwrap();
+        assert_eq!(previous.txid(), tx.input[0].previous_output.txid);
+        assert_eq!(psbt.fee(), None);
+        assert_eq!(psbt.to_base64(), BIP174_P2PKH_PSBT);
+    }
+
+    #[test]
+    fn test_refund_needs_threshold_and_escrow_keys() {
+        let escrow = escrow();
+        let mut psbt = escrow.refund_psbt(funding(), 100_000, 500).unwrap();
+        assert_eq!(psbt.unsigned_tx.output[0].script_pubkey, p2wpkh_script_pubkey(escrow.funder()));
+
+        assert_eq!(psbt.sign_input(0, &secret(9)), Err(PsbtError::KeyNotInScript(0)));
+        assert_eq!(psbt.sign_input(1, &secret(1)), Err(PsbtError::NoSuchInput(1)));
+        psbt.sign_input(0, &secret(1)).unwrap();
+        assert_eq!(psbt.extract_tx(), Err(PsbtError::NotFinalized(0)));
+        assert_eq!(
+            psbt.finalize(),
+            Err(PsbtError::NotEnoughSignatures { index: 0, have: 1, need: 2 })
+        );
+
+        // A signature over a different transaction does not count.
+        let mut other = escrow.refund_psbt(funding(), 100_000, 900).unwrap();
+        other.sign_input(0, &secret(3)).unwrap();
+        assert_eq!(psbt.combine(other.clone()), Err(PsbtError::DifferentTransaction));
+        let forged = other.inputs[0].partial_sigs.values().next().unwrap().clone();
+        psbt.inputs[0].partial_sigs.insert(secret(3).public_key().serialize(), forged);
+        assert!(matches!(psbt.finalize(), Err(PsbtError::NotEnoughSignatures { have: 1, .. })));
+
+        assert_eq!(
+            escrow.refund_psbt(funding(), 100_000, 99_800).unwrap_err(),
+            PsbtError::DustOutput
+        );
+        assert_eq!(
+            escrow.refund_psbt(funding(), 100_000, 100_001).unwrap_err(),
+            PsbtError::OutputsExceedInputs
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_psbts() {
+        let psbt = escrow().release_psbt(funding(), 100_000, 500).unwrap();
+        let bytes = psbt.serialize();
+        assert_eq!(&bytes[..5], b"psbt\xff");
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[4] = 0;
+        assert_eq!(Psbt::parse(&bad_magic), Err(PsbtError::InvalidMagic));
+        assert!(matches!(
+            Psbt::parse(&bytes[..bytes.len() - 1]),
+            Err(PsbtError::Transaction(TransactionError::Truncated))
+        ));
+        assert_eq!(Psbt::from_base64("not base64!"), Err(PsbtError::InvalidBase64));
+
+        // Unknown fields survive a round trip.
+        let mut extended = psbt.clone();
+        extended.inputs[0].unknown.insert(vec![0xfc, 1], vec![7]);
+        assert_eq!(Psbt::parse(&extended.serialize()).unwrap(), extended);
+    }
+}
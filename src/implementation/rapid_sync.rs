@@ -0,0 +1,258 @@
+//! Rapid Gossip Sync snapshot parsing.
+//!
+//! Snapshots are the compact, unsigned graph dumps served by Rapid Gossip Sync
+//! servers (format version 1). A full snapshot lists every channel with
+//! complete policies; a delta only lists what changed since an earlier
+//! snapshot and may carry incremental updates that modify existing policies.
+
+use crate::implementation::gossip::{GossipError, Reader};
+
+/// Magic prefix of a version 1 snapshot.
+const SNAPSHOT_PREFIX: [u8; 4] = [b'L', b'D', b'K', 1];
+/// Update carries only changed fields on top of the existing policy.
+const FLAG_INCREMENTAL: u8 = 1 << 7;
+/// Update flags marking which policy fields follow.
+const FLAG_CLTV_EXPIRY_DELTA: u8 = 1 << 6;
+const FLAG_HTLC_MINIMUM_MSAT: u8 = 1 << 5;
+const FLAG_FEE_BASE_MSAT: u8 = 1 << 4;
+const FLAG_FEE_PROPORTIONAL_MILLIONTHS: u8 = 1 << 3;
+const FLAG_HTLC_MAXIMUM_MSAT: u8 = 1 << 2;
+/// Update flags shared with `channel_update`.
+const FLAG_DISABLED: u8 = 1 << 1;
+const FLAG_DIRECTION: u8 = 1;
+
+/// Channel listed in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotChannel {
+    /// Short channel ID.
+    pub short_channel_id: u64,
+    /// Lesser endpoint.
+    pub node_one:         [u8; 33],
+    /// Greater endpoint.
+    pub node_two:         [u8; 33],
+    /// Channel feature bits.
+    pub features:         Vec<u8>,
+}
+
+/// Policy update listed in a snapshot.
+///
+/// Fields are `None` when an incremental update leaves them unchanged; full
+/// updates always carry every field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotUpdate {
+    /// Channel being updated.
+    pub short_channel_id:            u64,
+    /// Whether the update is for forwarding from `node_one`.
+    pub from_node_one:               bool,
+    /// Whether the direction is disabled.
+    pub disabled:                    bool,
+    /// Whether the update modifies an existing policy.
+    pub incremental:                 bool,
+    /// CLTV delta.
+    pub cltv_expiry_delta:           Option<u16>,
+    /// Smallest HTLC accepted, in millisatoshis.
+    pub htlc_minimum_msat:           Option<u64>,
+    /// Base fee in millisatoshis.
+    pub fee_base_msat:               Option<u32>,
+    /// Proportional fee in millionths.
+    pub fee_proportional_millionths: Option<u32>,
+    /// Largest HTLC accepted, in millisatoshis.
+    pub htlc_maximum_msat:           Option<u64>,
+}
+
+/// Parsed Rapid Gossip Sync snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RapidGossipSnapshot {
+    /// Chain the snapshot describes.
+    pub chain_hash:            [u8; 32],
+    /// Server time the snapshot is current as of; request the next delta from here.
+    pub latest_seen_timestamp: u32,
+    /// Announced channels.
+    pub channels:              Vec<SnapshotChannel>,
+    /// Channel policy updates.
+    pub updates:               Vec<SnapshotUpdate>,
+}
+
+impl RapidGossipSnapshot {
+    /// Parse a snapshot or delta.
+    pub fn parse(bytes: &[u8]) -> Result<Self, GossipError> {
+        let mut reader = Reader::new(bytes);
+        if reader.array::<4>()? != SNAPSHOT_PREFIX {
+            return Err(GossipError::Invalid("not a version 1 rapid gossip sync snapshot"));
+        }
+        let chain_hash = reader.array()?;
+        let latest_seen_timestamp = reader.u32()?;
+
+        let node_count = reader.u32()? as usize;
+        let mut node_ids = Vec::with_capacity(node_count.min(reader.remaining() / 33));
+        for _ in 0..node_count {
+            node_ids.push(reader.array::<33>()?);
+        }
+
+        let channel_count = reader.u32()? as usize;
+        let mut channels = Vec::with_capacity(channel_count.min(reader.remaining()));
+        let mut short_channel_id = 0u64;
+        for _ in 0..channel_count {
+            let features = reader.var_bytes()?;
+            short_channel_id = short_channel_id
+                .checked_add(reader.big_size()?)
+                .ok_or(GossipError::Invalid("short channel id overflows"))?;
+            let node_one = reader.node_id(&node_ids)?;
+            let node_two = reader.node_id(&node_ids)?;
+            channels.push(SnapshotChannel { short_channel_id, node_one, node_two, features });
+        }
+
+        let update_count = reader.u32()? as usize;
+        let mut updates = Vec::with_capacity(update_count.min(reader.remaining()));
+        if update_count > 0 {
+            let default_cltv_expiry_delta = reader.u16()?;
+            let default_htlc_minimum_msat = reader.u64()?;
+            let default_fee_base_msat = reader.u32()?;
+            let default_fee_proportional_millionths = reader.u32()?;
+            let default_htlc_maximum_msat = reader.u64()?;
+
+            let mut short_channel_id = 0u64;
+            for _ in 0..update_count {
+                short_channel_id = short_channel_id
+                    .checked_add(reader.big_size()?)
+                    .ok_or(GossipError::Invalid("short channel id overflows"))?;
+                let flags = reader.u8()?;
+                let incremental = flags & FLAG_INCREMENTAL != 0;
+
+                let mut update = SnapshotUpdate {
+                    short_channel_id,
+                    from_node_one: flags & FLAG_DIRECTION == 0,
+                    disabled: flags & FLAG_DISABLED != 0,
+                    incremental,
+                    cltv_expiry_delta: reader.field(flags, FLAG_CLTV_EXPIRY_DELTA, Reader::u16)?,
+                    htlc_minimum_msat: reader.field(flags, FLAG_HTLC_MINIMUM_MSAT, Reader::u64)?,
+                    fee_base_msat: reader.field(flags, FLAG_FEE_BASE_MSAT, Reader::u32)?,
+                    fee_proportional_millionths: reader.field(
+                        flags,
+                        FLAG_FEE_PROPORTIONAL_MILLIONTHS,
+                        Reader::u32,
+                    )?,
+                    htlc_maximum_msat: reader.field(flags, FLAG_HTLC_MAXIMUM_MSAT, Reader::u64)?,
+                };
+                if !incremental {
+                    update.cltv_expiry_delta.get_or_insert(default_cltv_expiry_delta);
+                    update.htlc_minimum_msat.get_or_insert(default_htlc_minimum_msat);
+                    update.fee_base_msat.get_or_insert(default_fee_base_msat);
+                    update
+                        .fee_proportional_millionths
+                        .get_or_insert(default_fee_proportional_millionths);
+                    update.htlc_maximum_msat.get_or_insert(default_htlc_maximum_msat);
+                }
+                updates.push(update);
+            }
+        }
+
+        Ok(Self { chain_hash, latest_seen_timestamp, channels, updates })
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{
+        implementation::{test_utils::node, PaymentRouter},
+        types::Network,
+    };
+
+    /// Snapshot with channels 1-2 (scid 100) and 2-3 (scid 250), updates for both
+    /// directions of 100 and one direction of 250.
+    fn full_snapshot() -> Vec<u8> {
+        let mut out = SNAPSHOT_PREFIX.to_vec();
+        out.extend_from_slice(&Network::Regtest.chain_hash());
+        out.extend_from_slice(&1_700_000_000u32.to_be_bytes());
+
+        out.extend_from_slice(&3u32.to_be_bytes());
+        for id in 1..=3 {
+            out.extend_from_slice(&node(id));
+        }
+
+        out.extend_from_slice(&2u32.to_be_bytes());
+        out.extend_from_slice(&[0, 0, 100, 0, 1]); // no features, scid 100, nodes 0 and 1
+        out.extend_from_slice(&[0, 0, 150, 1, 2]); // scid 100 + 150, nodes 1 and 2
+
+        out.extend_from_slice(&3u32.to_be_bytes());
+        out.extend_from_slice(&40u16.to_be_bytes());
+        out.extend_from_slice(&1u64.to_be_bytes());
+        out.extend_from_slice(&1000u32.to_be_bytes());
+        out.extend_from_slice(&100u32.to_be_bytes());
+        out.extend_from_slice(&5_000_000_000u64.to_be_bytes());
+
+        out.extend_from_slice(&[100, 0]); // scid 100, from node one, all defaults
+        out.extend_from_slice(&[0, FLAG_DIRECTION | FLAG_FEE_BASE_MSAT]); // from node two
+        out.extend_from_slice(&2500u32.to_be_bytes());
+        out.extend_from_slice(&[150, FLAG_DISABLED]); // scid 250, disabled
+        out
+    }
+
+    #[test]
+    fn test_parses_full_snapshot() {
+        let snapshot = RapidGossipSnapshot::parse(&full_snapshot()).unwrap();
+        assert_eq!(snapshot.latest_seen_timestamp, 1_700_000_000);
+        assert_eq!(snapshot.channels.len(), 2);
+        assert_eq!(snapshot.channels[1].short_channel_id, 250);
+        assert_eq!(snapshot.channels[1].node_one, node(2));
+
+        let update = &snapshot.updates[1];
+        assert!(!update.from_node_one);
+        assert_eq!(update.fee_base_msat, Some(2500));
+        assert_eq!(update.cltv_expiry_delta, Some(40));
+        assert!(snapshot.updates[2].disabled);
+
+        let bytes = full_snapshot();
+        assert_eq!(
+            RapidGossipSnapshot::parse(&bytes[..bytes.len() - 1]),
+            Err(GossipError::Truncated)
+        );
+        assert!(RapidGossipSnapshot::parse(b"LDK\x02").is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_minimal_big_size() {
+        let mut reader = Reader::new(&[0xfd, 0x00, 0x10]);
+        assert!(reader.big_size().is_err());
+        let mut reader = Reader::new(&[0xfd, 0x01, 0x00]);
+        assert_eq!(reader.big_size(), Ok(256));
+    }
+
+    #[test]
+    fn test_router_applies_snapshot_and_delta() {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        router.set_network(Network::Regtest);
+        assert_eq!(router.apply_rapid_gossip_sync(&full_snapshot()).unwrap(), 1_700_000_000);
+        assert_eq!(router.graph().channel_count(), 2);
+        assert_eq!(router.rapid_gossip_sync_timestamp(), Some(1_700_000_000));
+
+        let channel = router.graph().channel(100).unwrap();
+        assert_eq!(channel.two_to_one.unwrap().fee_base_msat, 2500);
+        assert_eq!(channel.one_to_two.unwrap().fee_base_msat, 1000);
+        assert!(!router.graph().channel(250).unwrap().one_to_two.unwrap().enabled);
+        assert!(router.find_route(&node(2), 10_000).is_ok());
+        assert!(router.find_route(&node(3), 10_000).is_err());
+
+        // Delta: enable 250 with a new fee, leaving other fields untouched.
+        let mut delta = SNAPSHOT_PREFIX.to_vec();
+        delta.extend_from_slice(&Network::Regtest.chain_hash());
+        delta.extend_from_slice(&1_700_003_600u32.to_be_bytes());
+        delta.extend_from_slice(&0u32.to_be_bytes());
+        delta.extend_from_slice(&0u32.to_be_bytes());
+        delta.extend_from_slice(&1u32.to_be_bytes());
+        delta.extend_from_slice(&[0; 26]);
+        delta.extend_from_slice(&[250, FLAG_INCREMENTAL | FLAG_FEE_BASE_MSAT]);
+        delta.extend_from_slice(&7u32.to_be_bytes());
+
+        router.apply_rapid_gossip_sync(&delta).unwrap();
+        let policy = router.graph().channel(250).unwrap().one_to_two.unwrap();
+        assert!(policy.enabled);
+        assert_eq!(policy.fee_base_msat, 7);
+        assert_eq!(policy.fee_proportional_millionths, 100);
+        assert!(router.find_route(&node(3), 10_000).is_ok());
+
+        let mut other_chain = PaymentRouter::new();
+        assert!(other_chain.apply_rapid_gossip_sync(&full_snapshot()).is_err());
+    }
+}
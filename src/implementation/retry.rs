@@ -0,0 +1,409 @@
+//! Payment retries and timeouts.
+//!
+//! A payment is sent over one route at a time. When an attempt fails, the
+//! failure is classified: a failing channel or node is excluded and the router
+//! is asked for a new route, while a failure at the payee ends the payment.
+//! Retries stop at the configured retry budget or wall-clock timeout.
+
+use core::fmt;
+
+use crate::{
+    encoding::hex,
+    errors::{PaymentError, PaymentResult},
+    implementation::{
+        config::PaymentConfig,
+        router::{PaymentRouter, RouteExclusions},
+    },
+    traits::Clock,
+    types::{PaymentPreimage, PaymentRoute},
+};
+
+/// Limits on retrying a payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub max_retries:  u32,
+    /// Wall-clock time allowed for the whole payment, in seconds.
+    pub timeout_secs: u64,
+}
+
+impl RetryPolicy {
+    /// Policy from `max_payment_retries` and `payment_timeout`.
+    #[must_use]
+    pub fn from_config(config: &PaymentConfig) -> Self {
+        Self { max_retries: config.max_payment_retries, timeout_secs: config.payment_timeout }
+    }
+
+    /// Attempts allowed, counting the first.
+    #[must_use]
+    pub fn max_attempts(&self) -> u32 {
+        self.max_retries.saturating_add(1)
+    }
+}
+
+/// Why an attempt failed, as reported back along its route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttemptFailure {
+    /// A channel on the route could not forward the payment.
+    Channel {
+        /// Channel that failed.
+        short_channel_id: u64,
+        /// Failure reported.
+        reason:           String,
+    },
+    /// A node on the route failed regardless of the channel used.
+    Node {
+        /// Node that failed.
+        pubkey: [u8; 33],
+        /// Failure reported.
+        reason: String,
+    },
+    /// The payee rejected the payment; no other route can help.
+    Permanent {
+        /// Failure reported.
+        reason: String,
+    },
+}
+
+impl AttemptFailure {
+    /// Whether retrying over another route cannot succeed.
+    #[must_use]
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Self::Permanent { .. })
+    }
+}
+
+impl fmt::Display for AttemptFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Channel { short_channel_id, reason } => {
+                write!(f, "channel {short_channel_id} failed: {reason}")
+            },
+            Self::Node { pubkey, reason } => {
+                write!(f, "node {} failed: {reason}", hex::encode(pubkey))
+            },
+            Self::Permanent { reason } => write!(f, "payee rejected payment: {reason}"),
+        }
+    }
+}
+
+/// One attempt at a payment.
+#[derive(Debug, Clone)]
+pub struct PaymentAttempt {
+    /// Attempt number, starting at 1.
+    pub number:     u32,
+    /// Route the attempt was sent over.
+    pub route:      PaymentRoute,
+    /// Unix time the attempt started.
+    pub started_at: u64,
+    /// Unix time the outcome was known.
+    pub ended_at:   u64,
+    /// Why the attempt failed, if it did.
+    pub failure:    Option<AttemptFailure>,
+}
+
+impl fmt::Display for PaymentAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channels: Vec<String> =
+            self.route.hops.iter().map(|hop| hop.short_channel_id.to_string()).collect();
+        write!(
+            f,
+            "attempt {} via [{}] took {}s: ",
+            self.number,
+            channels.join(", "),
+            self.ended_at.saturating_sub(self.started_at)
+        )?;
+        match &self.failure {
+            Some(failure) => write!(f, "{failure}"),
+            None => write!(f, "succeeded"),
+        }
+    }
+}
+
+/// Progress of a payment, reported while its attempts run.
+#[derive(Debug, Clone, Copy)]
+pub enum AttemptEvent<'a> {
+    /// An attempt is about to be sent.
+    Started {
+        /// Attempt number, starting at 1.
+        number: u32,
+        /// Route the attempt is sent over.
+        route:  &'a PaymentRoute,
+        /// Unix time the attempt started.
+        at:     u64,
+    },
+    /// An attempt's outcome is known.
+    Finished(&'a PaymentAttempt),
+}
+
+/// Sends a payment over successive routes until it settles or gives up.
+#[derive(Debug)]
+pub struct PaymentOrchestrator<'a> {
+    router:   &'a mut PaymentRouter,
+    clock:    &'a dyn Clock,
+    policy:   RetryPolicy,
+    attempts: Vec<PaymentAttempt>,
+}
+
+impl<'a> PaymentOrchestrator<'a> {
+    /// Create an orchestrator routing through `router` and timing out by `clock`.
+    #[must_use]
+    pub fn new(router: &'a mut PaymentRouter, clock: &'a dyn Clock, policy: RetryPolicy) -> Self {
+        Self { router, clock, policy, attempts: Vec::new() }
+    }
+
+    /// Attempts made by the last payment, successful or not.
+    #[must_use]
+    pub fn attempts(&self) -> &[PaymentAttempt] {
+        &self.attempts
+    }
+
+    /// Pay `amount_msat` to `destination`, sending each attempt with `send`.
+    ///
+    /// `send` delivers the payment over a route and returns the preimage or the
+    /// failure reported back. Failed channels feed the router's liquidity
+    /// scorer. A permanent failure returns [`PaymentError::Rejected`]; running
+    /// out of retries, time or routes returns [`PaymentError::Timeout`]. Both
+    /// list every attempt, as does [`Self::attempts`].
+    ///
+    /// The timeout is checked before each attempt; it cannot interrupt a
+    /// `send` that is still waiting, so `send` must bound its own wait.
+    pub fn pay(
+        &mut self, destination: &[u8; 33], amount_msat: u64,
+        send: impl FnMut(&PaymentRoute) -> Result<PaymentPreimage, AttemptFailure>,
+    ) -> PaymentResult<PaymentPreimage> {
+        self.pay_with_progress(destination, amount_msat, send, |_| Ok(()))
+    }
+
+    /// Pay as [`Self::pay`], reporting each attempt to `progress` as it starts
+    /// and as it ends.
+    ///
+    /// An error from `progress` stops the payment; no further attempt is sent.
+    pub fn pay_with_progress(
+        &mut self, destination: &[u8; 33], amount_msat: u64,
+        mut send: impl FnMut(&PaymentRoute) -> Result<PaymentPreimage, AttemptFailure>,
+        mut progress: impl FnMut(AttemptEvent<'_>) -> PaymentResult<()>,
+    ) -> PaymentResult<PaymentPreimage> {
+        let started_at = self.clock.now();
+        let deadline = started_at.saturating_add(self.policy.timeout_secs);
+        let mut exclusions = RouteExclusions::new();
+        let attempts = &mut self.attempts;
+        attempts.clear();
+
+        loop {
+            let now = self.clock.now();
+            if now >= deadline {
+                return Err(give_up("timed out", started_at, now, attempts));
+            }
+            let route =
+                match self.router.find_route_excluding(destination, amount_msat, &exclusions) {
+                    Ok(route) => route,
+                    Err(err) if attempts.is_empty() => return Err(err),
+                    Err(_) => return Err(give_up("ran out of routes", started_at, now, attempts)),
+                };
+
+            let number = attempts.len() as u32 + 1;
+            progress(AttemptEvent::Started { number, route: &route, at: now })?;
+            let outcome = send(&route);
+            let ended_at = self.clock.now();
+            let failure = match outcome {
+                Ok(preimage) => {
+                    self.router.payment_path_succeeded(&route);
+                    let attempt =
+                        PaymentAttempt { number, route, started_at: now, ended_at, failure: None };
+                    progress(AttemptEvent::Finished(&attempt))?;
+                    attempts.push(attempt);
+                    return Ok(preimage);
+                },
+                Err(failure) => failure,
+            };
+
+            match &failure {
+                AttemptFailure::Channel { short_channel_id, .. } => {
+                    self.router.payment_path_failed(&route, *short_channel_id);
+                    exclusions.exclude_channel(*short_channel_id);
+                },
+                AttemptFailure::Node { pubkey, .. } => exclusions.exclude_node(*pubkey),
+                AttemptFailure::Permanent { .. } => {},
+            }
+            let permanent = failure.is_permanent();
+            let attempt =
+                PaymentAttempt { number, route, started_at: now, ended_at, failure: Some(failure) };
+            progress(AttemptEvent::Finished(&attempt))?;
+            attempts.push(attempt);
+
+            if permanent {
+                return Err(PaymentError::Rejected(breakdown(attempts).into()));
+            }
+            if attempts.len() as u32 >= self.policy.max_attempts() {
+                return Err(give_up("exhausted its retries", started_at, ended_at, attempts));
+            }
+        }
+    }
+}
+
+/// Timeout error listing every attempt.
+fn give_up(why: &str, started_at: u64, now: u64, attempts: &[PaymentAttempt]) -> PaymentError {
+    PaymentError::Timeout(
+        format!(
+            "Payment {why} after {} attempts in {}s: {}",
+            attempts.len(),
+            now.saturating_sub(started_at),
+            breakdown(attempts)
+        )
+        .into(),
+    )
+}
+
+fn breakdown(attempts: &[PaymentAttempt]) -> String {
+    if attempts.is_empty() {
+        return "no attempts made".into();
+    }
+    attempts.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::implementation::{test_utils::node, ChannelPolicy, ManualClock};
+
+    /// Local node 1 reaches node 5 through node 2, 3 or 4; the cheapest first.
+    fn router() -> PaymentRouter {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        for (peer, fee_base_msat) in [(2u8, 0u32), (3, 100), (4, 200)] {
+            let first = u64::from(peer) * 10;
+            let second = first + 5;
+            router.add_channel_with_capacity(node(1), node(peer), first, 1_000).unwrap();
+            router.add_channel_with_capacity(node(peer), node(5), second, 1_000).unwrap();
+            for (scid, from, fee_base_msat) in [(first, 1, 0), (second, peer, fee_base_msat)] {
+                let policy = ChannelPolicy {
+                    fee_base_msat,
+                    fee_proportional_millionths: 0,
+                    ..ChannelPolicy::default()
+                };
+                router.update_channel_policy(scid, &node(from), policy).unwrap();
+            }
+        }
+        router
+    }
+
+    fn temporary(short_channel_id: u64) -> AttemptFailure {
+        AttemptFailure::Channel { short_channel_id, reason: "temporary channel failure".into() }
+    }
+
+    #[test]
+    fn test_retries_around_failed_channel_and_node() {
+        let mut router = router();
+        let clock = ManualClock::new(1_000);
+        let preimage = PaymentPreimage::new([1; 32]);
+        let policy = RetryPolicy { max_retries: 3, timeout_secs: 60 };
+        let mut orchestrator = PaymentOrchestrator::new(&mut router, &clock, policy);
+
+        let settled =
+            orchestrator.pay(&node(5), 100_000, |route| match route.hops[0].short_channel_id {
+                20 => Err(temporary(25)),
+                30 => Err(AttemptFailure::Node { pubkey: node(3), reason: "offline".into() }),
+                _ => Ok(preimage),
+            });
+        assert_eq!(settled.unwrap(), preimage);
+        let attempts = orchestrator.attempts();
+        assert_eq!(attempts.len(), 3);
+        assert_eq!(attempts[0].failure, Some(temporary(25)));
+        assert_eq!(attempts[2].route.hops[0].short_channel_id, 40);
+        assert!(attempts[2].failure.is_none());
+    }
+
+    #[test]
+    fn test_progress_is_reported_while_attempts_run() {
+        let mut router = router();
+        let clock = ManualClock::new(1_000);
+        let policy = RetryPolicy { max_retries: 3, timeout_secs: 60 };
+        let log = std::cell::RefCell::new(Vec::new());
+        let settled = PaymentOrchestrator::new(&mut router, &clock, policy).pay_with_progress(
+            &node(5),
+            100_000,
+            |route| {
+                log.borrow_mut().push(format!("send {}", route.hops[0].short_channel_id));
+                match route.hops[0].short_channel_id {
+                    20 => Err(temporary(25)),
+                    _ => Ok(PaymentPreimage::new([1; 32])),
+                }
+            },
+            |event| {
+                log.borrow_mut().push(match event {
+                    AttemptEvent::Started { number, .. } => format!("start {number}"),
+                    AttemptEvent::Finished(attempt) => format!("end {attempt}"),
+                });
+                Ok(())
+            },
+        );
+        assert!(settled.is_ok());
+        assert_eq!(
+            log.into_inner(),
+            [
+                "start 1",
+                "send 20",
+                "end attempt 1 via [20, 25] took 0s: channel 25 failed: temporary channel failure",
+                "start 2",
+                "send 30",
+                "end attempt 2 via [30, 35] took 0s: succeeded",
+            ]
+        );
+
+        let mut router = self::router();
+        let err = PaymentOrchestrator::new(&mut router, &clock, policy)
+            .pay_with_progress(
+                &node(5),
+                100_000,
+                |_| panic!("no attempt is sent once progress fails"),
+                |_| Err(PaymentError::Invoice("store rejected the attempt".into())),
+            )
+            .unwrap_err();
+        assert!(matches!(err, PaymentError::Invoice(_)));
+    }
+
+    #[test]
+    fn test_gives_up_with_attempt_breakdown() {
+        let clock = ManualClock::new(1_000);
+        let mut router = router();
+        let budget = RetryPolicy { max_retries: 1, timeout_secs: 60 };
+        let err = PaymentOrchestrator::new(&mut router, &clock, budget)
+            .pay(&node(5), 100_000, |route| Err(temporary(route.hops[1].short_channel_id)))
+            .unwrap_err();
+        let PaymentError::Timeout(msg) = err else { panic!("expected timeout, got {err}") };
+        assert!(msg.contains("exhausted its retries after 2 attempts"), "{msg}");
+        assert!(msg.contains("attempt 1 via [20, 25] took 0s: channel 25 failed"), "{msg}");
+
+        let mut router = self::router();
+        let err = PaymentOrchestrator::new(&mut router, &clock, budget)
+            .pay(&node(5), 100_000, |_| {
+                clock.advance(61);
+                Err(temporary(25))
+            })
+            .unwrap_err();
+        let PaymentError::Timeout(msg) = err else { panic!("expected timeout, got {err}") };
+        assert!(msg.contains("timed out after 1 attempts in 61s"), "{msg}");
+
+        let mut router = self::router();
+        let generous = RetryPolicy { max_retries: 10, timeout_secs: 60 };
+        let err = PaymentOrchestrator::new(&mut router, &clock, generous)
+            .pay(&node(5), 100_000, |route| Err(temporary(route.hops[0].short_channel_id)))
+            .unwrap_err();
+        let PaymentError::Timeout(msg) = err else { panic!("expected timeout, got {err}") };
+        assert!(msg.contains("ran out of routes after 3 attempts"), "{msg}");
+
+        let mut router = self::router();
+        let err = PaymentOrchestrator::new(&mut router, &clock, generous)
+            .pay(&node(5), 100_000, |_| {
+                Err(AttemptFailure::Permanent { reason: "unknown payment hash".into() })
+            })
+            .unwrap_err();
+        assert!(err.is_permanent());
+        assert!(matches!(err, PaymentError::Rejected(msg) if msg.contains("unknown payment hash")));
+        assert!(matches!(
+            PaymentOrchestrator::new(&mut router, &clock, generous)
+                .pay(&node(9), 100_000, |_| Ok(PaymentPreimage::new([0; 32]))),
+            Err(PaymentError::Routing(_))
+        ));
+    }
+}
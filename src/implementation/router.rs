@@ -1,49 +1,935 @@
 //! Payment routing.
 
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    path::Path,
+};
+
+use essentia_core::time;
+
 use crate::{
     errors::{PaymentError, PaymentResult},
-    types::PaymentRoute,
+    implementation::{
+        gossip::{
+            ChannelAnnouncement, ChannelUpdate, GossipError, GossipMessage, GossipOutcome,
+            NodeAnnouncement, STALE_CHANNEL_AGE_SECS,
+        },
+        graph::{ChannelPolicy, GraphChannel, NetworkGraph, NodeAnnouncementInfo},
+        onion::OnionFailure,
+        rapid_sync::RapidGossipSnapshot,
+        scorer::LiquidityScorer,
+    },
+    types::{LightningNode, Network, PaymentRoute, RouteHop},
 };
 
+/// Maximum number of hops in a route (onion payload limit).
+const MAX_PATH_LENGTH: usize = 20;
+/// Maximum total CLTV delta a route may lock funds for (two weeks of blocks).
+const MAX_TOTAL_CLTV_EXPIRY_DELTA: u32 = 2016;
+/// Cost per millisatoshi per block of time lock, in parts per billion.
+const RISK_FACTOR_PPB: u128 = 15;
+/// Default upper bound on the number of parts a payment is split into.
+pub const DEFAULT_MAX_PAYMENT_PARTS: usize = 16;
+/// Smallest part the splitter will try, in millisatoshis.
+const MIN_PART_MSAT: u64 = 10_000;
+
+/// How far Rapid Gossip Sync policies are backdated, so signed gossip seen
+/// directly from peers takes precedence (one week).
+const RAPID_SYNC_BACKDATE_SECS: u32 = 7 * 24 * 60 * 60;
+
+/// Liquidity already committed per channel direction, keyed by
+/// `(short_channel_id, forwards_from_node_one)`.
+type Reservations = HashMap<(u64, bool), u64>;
+
+/// Channels and nodes a route search must avoid, e.g. after they failed a payment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteExclusions {
+    channels: HashSet<u64>,
+    nodes:    HashSet<[u8; 33]>,
+}
+
+impl RouteExclusions {
+    /// Create an empty set of exclusions.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Avoid a channel in both directions.
+    pub fn exclude_channel(&mut self, short_channel_id: u64) {
+        self.channels.insert(short_channel_id);
+    }
+
+    /// Avoid forwarding through a node.
+    pub fn exclude_node(&mut self, pubkey: [u8; 33]) {
+        self.nodes.insert(pubkey);
+    }
+
+    /// Whether a channel is excluded.
+    #[must_use]
+    pub fn excludes_channel(&self, short_channel_id: u64) -> bool {
+        self.channels.contains(&short_channel_id)
+    }
+
+    /// Whether a node is excluded.
+    #[must_use]
+    pub fn excludes_node(&self, pubkey: &[u8; 33]) -> bool {
+        self.nodes.contains(pubkey)
+    }
+
+    /// Whether nothing is excluded.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.nodes.is_empty()
+    }
+}
+
+/// Best known way to reach the destination from a node, found while searching backwards.
+#[derive(Debug, Clone, Copy)]
+struct PathLabel {
+    /// Path cost from this node to the destination.
+    cost:        u64,
+    /// Amount this node must receive, including its own forwarding fee.
+    amount_msat: u64,
+    /// CLTV delta accumulated from this node to the destination.
+    total_cltv:  u32,
+    /// Fee this node charges for forwarding.
+    fee_msat:    u64,
+    /// CLTV delta this node requires for forwarding.
+    cltv_delta:  u16,
+    /// Number of hops from this node to the destination.
+    hops:        usize,
+    /// Channel and node this node forwards to.
+    next:        Option<(u64, [u8; 33])>,
+}
+
 /// Payment router for finding routes through the Lightning Network.
 #[derive(Debug)]
 pub struct PaymentRouter {
-    /// Known nodes in the network graph.
-    node_count: usize,
+    /// Known channel graph.
+    graph:      NetworkGraph,
+    /// Our own node, the source of every route.
+    local_node: Option<[u8; 33]>,
+    /// Liquidity learned from earlier payment attempts.
+    scorer:     LiquidityScorer,
+    /// Chain gossip must be for.
+    network:    Network,
+    /// Server timestamp of the last applied Rapid Gossip Sync snapshot.
+    rapid_sync: Option<u32>,
+    /// Millisatoshis our own channels can still offer in new HTLCs, by short channel id.
+    spendable:  HashMap<u64, u64>,
 }
 
 impl PaymentRouter {
     /// Create a new payment router.
     #[must_use]
     pub fn new() -> Self {
-        Self { node_count: 0 }
+        Self {
+            graph:      NetworkGraph::new(),
+            local_node: None,
+            scorer:     LiquidityScorer::new(),
+            network:    Network::Bitcoin,
+            rapid_sync: None,
+            spendable:  HashMap::new(),
+        }
+    }
+
+    /// Create a new payment router that routes from `local_node`.
+    #[must_use]
+    pub fn with_local_node(local_node: [u8; 33]) -> Self {
+        let mut router = Self::new();
+        router.set_local_node(local_node);
+        router
+    }
+
+    /// Set the node routes start from.
+    pub fn set_local_node(&mut self, local_node: [u8; 33]) {
+        self.graph.add_node(local_node);
+        self.local_node = Some(local_node);
+    }
+
+    /// Set the chain gossip is accepted for.
+    pub fn set_network(&mut self, network: Network) {
+        self.network = network;
+    }
+
+    /// Get the routing graph.
+    #[must_use]
+    pub fn graph(&self) -> &NetworkGraph {
+        &self.graph
+    }
+
+    /// Get the liquidity scorer.
+    #[must_use]
+    pub fn scorer(&self) -> &LiquidityScorer {
+        &self.scorer
+    }
+
+    /// Replace the liquidity scorer, e.g. with restored history.
+    pub fn set_scorer(&mut self, scorer: LiquidityScorer) {
+        self.scorer = scorer;
     }
 
-    /// Find a route to the destination.
+    /// Find a route from the local node to the destination.
     pub fn find_route(
-        &self, _destination: &[u8; 33], _amount_msat: u64,
+        &self, destination: &[u8; 33], amount_msat: u64,
+    ) -> PaymentResult<PaymentRoute> {
+        self.find_route_excluding(destination, amount_msat, &RouteExclusions::new())
+    }
+
+    /// Find a route from the local node to the destination that avoids `exclusions`.
+    pub fn find_route_excluding(
+        &self, destination: &[u8; 33], amount_msat: u64, exclusions: &RouteExclusions,
     ) -> PaymentResult<PaymentRoute> {
-        if self.node_count == 0 {
+        if self.graph.node_count() == 0 {
             return Err(PaymentError::Routing("No nodes in graph".into()));
         }
+        let source =
+            self.local_node.ok_or_else(|| PaymentError::Routing("Local node is not set".into()))?;
 
-        // Placeholder - would implement pathfinding
-        Ok(PaymentRoute { hops: Vec::new(), total_fees_msat: 0, total_cltv_delta: 0 })
+        self.search(&source, destination, amount_msat, &Reservations::new(), exclusions)
+    }
+
+    /// Find the cheapest route between two nodes, weighing fees and time lock.
+    ///
+    /// The search runs backwards from the destination so that each hop's fee is
+    /// computed on the exact amount it must forward.
+    pub fn find_route_from(
+        &self, source: &[u8; 33], destination: &[u8; 33], amount_msat: u64,
+    ) -> PaymentResult<PaymentRoute> {
+        self.search(source, destination, amount_msat, &Reservations::new(), &RouteExclusions::new())
+    }
+
+    /// Split a payment across routes bounded by channel liquidity.
+    ///
+    /// Each part is routed over liquidity not yet committed to earlier parts,
+    /// and our own channels only carry what they can still spend; when no
+    /// route can carry the remaining amount, the part size is halved.
+    pub fn find_multipath_routes(
+        &self, destination: &[u8; 33], amount_msat: u64, max_parts: usize,
+    ) -> PaymentResult<Vec<PaymentRoute>> {
+        let source =
+            self.local_node.ok_or_else(|| PaymentError::Routing("Local node is not set".into()))?;
+
+        let exclusions = RouteExclusions::new();
+        let mut reservations = Reservations::new();
+        let mut routes = Vec::new();
+        let mut remaining = amount_msat;
+        let mut part_size = amount_msat;
+
+        while remaining > 0 {
+            if routes.len() >= max_parts {
+                return Err(PaymentError::Routing(
+                    format!("Cannot route {amount_msat} msat in {max_parts} parts").into(),
+                ));
+            }
+
+            let attempt = part_size.min(remaining);
+            match self.search(&source, destination, attempt, &reservations, &exclusions) {
+                Ok(route) => {
+                    self.reserve(&mut reservations, &route);
+                    remaining -= attempt;
+                    routes.push(route);
+                },
+                Err(err) if attempt / 2 < MIN_PART_MSAT => return Err(err),
+                Err(_) => part_size = attempt / 2,
+            }
+        }
+
+        Ok(routes)
+    }
+
+    fn search(
+        &self, source: &[u8; 33], destination: &[u8; 33], amount_msat: u64,
+        reservations: &Reservations, exclusions: &RouteExclusions,
+    ) -> PaymentResult<PaymentRoute> {
+        if source == destination {
+            return Err(PaymentError::Routing("Cannot route to self".into()));
+        }
+        if self.graph.node(destination).is_none() {
+            return Err(PaymentError::Routing("Destination not in graph".into()));
+        }
+
+        let now = time::unix_seconds_sync();
+        let mut labels: HashMap<[u8; 33], PathLabel> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        labels.insert(
+            *destination,
+            PathLabel {
+                cost: 0,
+                amount_msat,
+                total_cltv: 0,
+                fee_msat: 0,
+                cltv_delta: 0,
+                hops: 0,
+                next: None,
+            },
+        );
+        queue.push(Reverse((0u64, *destination)));
+
+        while let Some(Reverse((cost, node))) = queue.pop() {
+            let label = labels[&node];
+            if cost > label.cost {
+                continue;
+            }
+            if node == *source {
+                break;
+            }
+            if label.hops >= MAX_PATH_LENGTH {
+                continue;
+            }
+
+            for channel in self.graph.channels_of(&node) {
+                if exclusions.excludes_channel(channel.short_channel_id) {
+                    continue;
+                }
+                let Some(&prev) = channel.counterparty(&node) else {
+                    continue;
+                };
+                if prev != *source && exclusions.excludes_node(&prev) {
+                    continue;
+                }
+                let Some(policy) = channel.policy_from(&prev) else {
+                    continue;
+                };
+                if !policy.allows(label.amount_msat) {
+                    continue;
+                }
+                let from_node_one = prev == channel.node_one;
+                let reserved = reservations
+                    .get(&(channel.short_channel_id, from_node_one))
+                    .copied()
+                    .unwrap_or(0);
+                let in_use_msat = label.amount_msat.saturating_add(reserved);
+                let mut limit_msat = channel_limit_msat(channel, policy);
+                if prev == *source {
+                    if let Some(&spendable) = self.spendable.get(&channel.short_channel_id) {
+                        limit_msat = limit_msat.min(spendable);
+                    }
+                }
+                if in_use_msat > limit_msat {
+                    continue;
+                }
+                let Some(penalty_msat) = self.scorer.penalty_msat(
+                    channel.short_channel_id,
+                    from_node_one,
+                    in_use_msat,
+                    limit_msat,
+                    now,
+                ) else {
+                    continue;
+                };
+
+                let candidate =
+                    self.extend(&label, &prev, *source, channel.short_channel_id, node, policy);
+                let Some(mut candidate) = candidate else {
+                    continue;
+                };
+                candidate.cost = candidate.cost.saturating_add(penalty_msat);
+                if labels.get(&prev).is_some_and(|existing| existing.cost <= candidate.cost) {
+                    continue;
+                }
+                labels.insert(prev, candidate);
+                queue.push(Reverse((candidate.cost, prev)));
+            }
+        }
+
+        let source_label = labels
+            .get(source)
+            .ok_or_else(|| PaymentError::Routing("No route to destination".into()))?;
+        Ok(Self::build_route(&labels, source_label, amount_msat))
     }
 
     /// Add a node to the routing graph.
-    pub fn add_node(&mut self, _pubkey: [u8; 33]) {
-        self.node_count += 1;
+    pub fn add_node(&mut self, pubkey: [u8; 33]) {
+        self.graph.add_node(pubkey);
     }
 
     /// Add a channel to the routing graph.
-    pub fn add_channel(&mut self, _node_a: [u8; 33], _node_b: [u8; 33], _short_channel_id: u64) {
-        // Placeholder - would add to graph
+    ///
+    /// The channel is not used for routing until a policy is set for a direction
+    /// with [`Self::update_channel_policy`].
+    pub fn add_channel(&mut self, node_a: [u8; 33], node_b: [u8; 33], short_channel_id: u64) {
+        let _ = self.graph.add_channel(node_a, node_b, short_channel_id, None);
+    }
+
+    /// Add a channel with a known capacity to the routing graph.
+    pub fn add_channel_with_capacity(
+        &mut self, node_a: [u8; 33], node_b: [u8; 33], short_channel_id: u64, capacity_sats: u64,
+    ) -> PaymentResult<()> {
+        self.graph.add_channel(node_a, node_b, short_channel_id, Some(capacity_sats))
+    }
+
+    /// Add or refresh one of our own channels, routable out of the local node.
+    ///
+    /// Routes over it are bounded by `spendable_msat`, what the channel can
+    /// still offer in new HTLCs, rather than by its capacity. The sender pays
+    /// no fee on its own channel, so the direction out of the local node gets
+    /// the default policy without waiting for gossip.
+    pub fn set_local_channel(
+        &mut self, peer: [u8; 33], short_channel_id: u64, capacity_sats: u64, spendable_msat: u64,
+    ) -> PaymentResult<()> {
+        let local =
+            self.local_node.ok_or_else(|| PaymentError::Routing("Local node is not set".into()))?;
+        self.graph.add_channel(local, peer, short_channel_id, Some(capacity_sats))?;
+        self.graph.update_policy(short_channel_id, &local, ChannelPolicy::default())?;
+        self.spendable.insert(short_channel_id, spendable_msat);
+        Ok(())
+    }
+
+    /// Set the forwarding policy for a channel direction out of `from`.
+    pub fn update_channel_policy(
+        &mut self, short_channel_id: u64, from: &[u8; 33], policy: ChannelPolicy,
+    ) -> PaymentResult<()> {
+        self.graph.update_policy(short_channel_id, from, policy)
+    }
+
+    /// Remove a channel from the routing graph.
+    pub fn remove_channel(&mut self, short_channel_id: u64) {
+        self.graph.remove_channel(short_channel_id);
+        self.scorer.remove_channel(short_channel_id);
+        self.spendable.remove(&short_channel_id);
+    }
+
+    /// Apply a raw BOLT7 gossip message, starting with its two-byte type.
+    pub fn handle_gossip_message(&mut self, bytes: &[u8]) -> PaymentResult<GossipOutcome> {
+        match GossipMessage::parse(bytes)? {
+            GossipMessage::ChannelAnnouncement(msg) => self.handle_channel_announcement(&msg),
+            GossipMessage::NodeAnnouncement(msg) => self.handle_node_announcement(&msg),
+            GossipMessage::ChannelUpdate(msg) => self.handle_channel_update(&msg),
+        }
+    }
+
+    /// Add an announced channel after verifying its signatures.
+    pub fn handle_channel_announcement(
+        &mut self, msg: &ChannelAnnouncement,
+    ) -> PaymentResult<GossipOutcome> {
+        if msg.chain_hash != self.network.chain_hash() {
+            return Err(GossipError::WrongChain.into());
+        }
+        if self.graph.channel(msg.short_channel_id).is_some_and(|c| c.announced_at.is_some()) {
+            return Ok(GossipOutcome::Stale);
+        }
+        msg.verify()?;
+
+        self.graph.add_channel(msg.node_id_1, msg.node_id_2, msg.short_channel_id, None)?;
+        self.graph.mark_announced(msg.short_channel_id, time::unix_seconds_sync())?;
+        Ok(GossipOutcome::Applied)
+    }
+
+    /// Apply a channel update signed by the node it originates from.
+    ///
+    /// Updates not newer than the current policy for the direction are ignored.
+    pub fn handle_channel_update(&mut self, msg: &ChannelUpdate) -> PaymentResult<GossipOutcome> {
+        if msg.chain_hash != self.network.chain_hash() {
+            return Err(GossipError::WrongChain.into());
+        }
+        let Some(channel) = self.graph.channel(msg.short_channel_id) else {
+            return Ok(GossipOutcome::Unknown);
+        };
+        let (origin, current) = if msg.from_node_one() {
+            (channel.node_one, channel.one_to_two)
+        } else {
+            (channel.node_two, channel.two_to_one)
+        };
+        if current.is_some_and(|policy| policy.last_update >= msg.timestamp) {
+            return Ok(GossipOutcome::Stale);
+        }
+        msg.verify(&origin)?;
+
+        self.graph.update_policy(msg.short_channel_id, &origin, msg.policy())?;
+        Ok(GossipOutcome::Applied)
+    }
+
+    /// Record a node's alias and color.
+    ///
+    /// Announcements are only kept for nodes with channels in the graph.
+    pub fn handle_node_announcement(
+        &mut self, msg: &NodeAnnouncement,
+    ) -> PaymentResult<GossipOutcome> {
+        let Some(node) = self.graph.node(&msg.node_id) else {
+            return Ok(GossipOutcome::Unknown);
+        };
+        if node.announcement.as_ref().is_some_and(|info| info.last_update >= msg.timestamp) {
+            return Ok(GossipOutcome::Stale);
+        }
+        msg.verify()?;
+
+        let info = NodeAnnouncementInfo {
+            last_update: msg.timestamp,
+            alias:       msg.alias_string(),
+            color:       msg.rgb_color,
+            features:    msg.features.clone(),
+            addresses:   msg.addresses.clone(),
+        };
+        self.graph.set_node_announcement(&msg.node_id, info);
+        Ok(GossipOutcome::Applied)
+    }
+
+    /// Apply a Rapid Gossip Sync snapshot or delta; returns its server timestamp.
+    ///
+    /// Channels and full policies are added in bulk. Incremental updates only
+    /// modify policies the graph already has, and no update replaces a policy
+    /// from newer signed gossip.
+    pub fn apply_rapid_gossip_sync(&mut self, bytes: &[u8]) -> PaymentResult<u32> {
+        let snapshot = RapidGossipSnapshot::parse(bytes)?;
+        if snapshot.chain_hash != self.network.chain_hash() {
+            return Err(GossipError::WrongChain.into());
+        }
+
+        let now = time::unix_seconds_sync();
+        for channel in &snapshot.channels {
+            let added = self.graph.add_channel(
+                channel.node_one,
+                channel.node_two,
+                channel.short_channel_id,
+                None,
+            );
+            if added.is_ok()
+                && self
+                    .graph
+                    .channel(channel.short_channel_id)
+                    .is_some_and(|c| c.announced_at.is_none())
+            {
+                self.graph.mark_announced(channel.short_channel_id, now)?;
+            }
+        }
+
+        let timestamp = snapshot.latest_seen_timestamp.saturating_sub(RAPID_SYNC_BACKDATE_SECS);
+        for update in &snapshot.updates {
+            let Some(channel) = self.graph.channel(update.short_channel_id) else {
+                continue;
+            };
+            let (origin, current) = if update.from_node_one {
+                (channel.node_one, channel.one_to_two)
+            } else {
+                (channel.node_two, channel.two_to_one)
+            };
+            if current.is_some_and(|policy| policy.last_update >= timestamp) {
+                continue;
+            }
+            let base = match current {
+                Some(policy) => policy,
+                None if update.incremental => continue,
+                None => ChannelPolicy::default(),
+            };
+
+            let policy = ChannelPolicy {
+                fee_base_msat:               update.fee_base_msat.unwrap_or(base.fee_base_msat),
+                fee_proportional_millionths: update
+                    .fee_proportional_millionths
+                    .unwrap_or(base.fee_proportional_millionths),
+                cltv_expiry_delta:           update
+                    .cltv_expiry_delta
+                    .unwrap_or(base.cltv_expiry_delta),
+                htlc_minimum_msat:           update
+                    .htlc_minimum_msat
+                    .unwrap_or(base.htlc_minimum_msat),
+                htlc_maximum_msat:           update
+                    .htlc_maximum_msat
+                    .unwrap_or(base.htlc_maximum_msat),
+                enabled:                     !update.disabled,
+                last_update:                 timestamp,
+            };
+            self.graph.update_policy(update.short_channel_id, &origin, policy)?;
+        }
+
+        self.rapid_sync = Some(snapshot.latest_seen_timestamp);
+        Ok(snapshot.latest_seen_timestamp)
+    }
+
+    /// Apply a Rapid Gossip Sync snapshot or delta read from a file.
+    pub fn load_rapid_gossip_sync(&mut self, path: impl AsRef<Path>) -> PaymentResult<u32> {
+        let bytes = std::fs::read(path).map_err(|e| {
+            PaymentError::Configuration(format!("Failed to read gossip snapshot: {e}").into())
+        })?;
+        self.apply_rapid_gossip_sync(&bytes)
+    }
+
+    /// Server timestamp of the last applied Rapid Gossip Sync snapshot, to request deltas from.
+    #[must_use]
+    pub fn rapid_gossip_sync_timestamp(&self) -> Option<u32> {
+        self.rapid_sync
+    }
+
+    /// Remove announced channels not refreshed in two weeks; returns how many were removed.
+    pub fn prune_stale_channels(&mut self, now: u64) -> usize {
+        let stale = self.graph.stale_channels(now, STALE_CHANNEL_AGE_SECS);
+        for &short_channel_id in &stale {
+            self.remove_channel(short_channel_id);
+        }
+        stale.len()
+    }
+
+    /// Get the announced details of a node.
+    #[must_use]
+    pub fn node_info(&self, pubkey: &[u8; 33]) -> Option<LightningNode> {
+        let info = self.graph.node(pubkey)?.announcement.as_ref()?;
+        Some(LightningNode { pubkey: *pubkey, alias: info.alias.clone(), color: info.color })
+    }
+
+    /// Get all nodes that announced themselves.
+    #[must_use]
+    pub fn announced_nodes(&self) -> Vec<LightningNode> {
+        self.graph.nodes().filter_map(|(pubkey, _)| self.node_info(pubkey)).collect()
+    }
+
+    /// Learn from a route whose payment settled.
+    pub fn payment_path_succeeded(&mut self, route: &PaymentRoute) {
+        let now = time::unix_seconds_sync();
+        for (short_channel_id, from_node_one, amount_msat, limit_msat) in self.route_channels(route)
+        {
+            self.scorer.channel_succeeded(
+                short_channel_id,
+                from_node_one,
+                amount_msat,
+                limit_msat,
+                now,
+            );
+        }
+    }
+
+    /// Learn from a route that failed at `failed_short_channel_id`.
+    ///
+    /// Channels before the failing one forwarded the HTLC; the failing one could not.
+    pub fn payment_path_failed(&mut self, route: &PaymentRoute, failed_short_channel_id: u64) {
+        let now = time::unix_seconds_sync();
+        for (short_channel_id, from_node_one, amount_msat, limit_msat) in self.route_channels(route)
+        {
+            if short_channel_id == failed_short_channel_id {
+                self.scorer.channel_failed(
+                    short_channel_id,
+                    from_node_one,
+                    amount_msat,
+                    limit_msat,
+                    now,
+                );
+                break;
+            }
+            self.scorer.channel_succeeded(
+                short_channel_id,
+                from_node_one,
+                amount_msat,
+                limit_msat,
+                now,
+            );
+        }
+    }
+
+    /// Learn from a failure a hop reported for `route`.
+    ///
+    /// A `channel_update` sent with the failure is applied first, as it may
+    /// carry the fee or CLTV delta the route fell short of. Channel failures
+    /// feed the liquidity scorer.
+    pub fn handle_onion_failure(&mut self, route: &PaymentRoute, failure: &OnionFailure) {
+        if let Some(update) = &failure.channel_update {
+            // A forged or stale update is simply not applied.
+            let _ = self.handle_channel_update(update);
+        }
+        if let Some(short_channel_id) = failure.short_channel_id {
+            if !failure.code.is_node() && !failure.code.is_bad_onion() {
+                self.payment_path_failed(route, short_channel_id);
+            }
+        }
+    }
+
+    /// Persist the mission control history to a file.
+    pub fn save_mission_control(&self, path: impl AsRef<Path>) -> PaymentResult<()> {
+        std::fs::write(path, self.scorer.encode()).map_err(|e| {
+            PaymentError::Configuration(format!("Failed to write mission control: {e}").into())
+        })
+    }
+
+    /// Restore mission control history from a file written by [`Self::save_mission_control`].
+    pub fn load_mission_control(&mut self, path: impl AsRef<Path>) -> PaymentResult<()> {
+        let bytes = std::fs::read(path).map_err(|e| {
+            PaymentError::Configuration(format!("Failed to read mission control: {e}").into())
+        })?;
+        self.scorer = LiquidityScorer::decode(*self.scorer.parameters(), &bytes)?;
+        Ok(())
+    }
+
+    /// Compute the label for `prev`, which forwards over `short_channel_id` to `next`.
+    fn extend(
+        &self, label: &PathLabel, prev: &[u8; 33], source: [u8; 33], short_channel_id: u64,
+        next: [u8; 33], policy: &ChannelPolicy,
+    ) -> Option<PathLabel> {
+        // The sender pays no fee and needs no delta for its own outgoing channel.
+        let (fee_msat, cltv_delta) = if *prev == source {
+            (0, 0)
+        } else {
+            (policy.fee_msat(label.amount_msat), policy.cltv_expiry_delta)
+        };
+
+        let amount_msat = label.amount_msat.checked_add(fee_msat)?;
+        let total_cltv = label.total_cltv + u32::from(cltv_delta);
+        if total_cltv > MAX_TOTAL_CLTV_EXPIRY_DELTA {
+            return None;
+        }
+
+        let risk =
+            u128::from(amount_msat) * u128::from(cltv_delta) * RISK_FACTOR_PPB / 1_000_000_000;
+        let cost = label.cost.saturating_add(fee_msat).saturating_add(risk as u64);
+
+        Some(PathLabel {
+            cost,
+            amount_msat,
+            total_cltv,
+            fee_msat,
+            cltv_delta,
+            hops: label.hops + 1,
+            next: Some((short_channel_id, next)),
+        })
+    }
+
+    /// Commit the liquidity a route uses on every channel it crosses.
+    fn reserve(&self, reservations: &mut Reservations, route: &PaymentRoute) {
+        for (short_channel_id, from_node_one, amount_msat, _) in self.route_channels(route) {
+            *reservations.entry((short_channel_id, from_node_one)).or_insert(0) += amount_msat;
+        }
+    }
+
+    /// Channel directions a route crosses, with the amount and limit of each.
+    ///
+    /// Yields `(short_channel_id, from_node_one, amount_msat, limit_msat)` for
+    /// channels still in the graph.
+    fn route_channels(&self, route: &PaymentRoute) -> Vec<(u64, bool, u64, u64)> {
+        let Some(mut from) = self.local_node else {
+            return Vec::new();
+        };
+        let mut amount_msat = route.total_amount_msat();
+        let mut channels = Vec::with_capacity(route.hops.len());
+
+        for hop in &route.hops {
+            if let Some(channel) = self.graph.channel(hop.short_channel_id) {
+                let limit_msat =
+                    channel.policy_from(&from).map_or(u64::MAX, |p| channel_limit_msat(channel, p));
+                channels.push((
+                    hop.short_channel_id,
+                    from == channel.node_one,
+                    amount_msat,
+                    limit_msat,
+                ));
+            }
+            // The amount leaving this hop excludes the fee it keeps.
+            amount_msat -= hop.fee_msat;
+            from = hop.pubkey;
+        }
+        channels
+    }
+
+    fn build_route(
+        labels: &HashMap<[u8; 33], PathLabel>, source_label: &PathLabel, amount_msat: u64,
+    ) -> PaymentRoute {
+        let mut hops = Vec::with_capacity(source_label.hops);
+        let mut next = source_label.next;
+
+        while let Some((short_channel_id, pubkey)) = next {
+            let label = &labels[&pubkey];
+            hops.push(RouteHop {
+                pubkey,
+                short_channel_id,
+                fee_msat: label.fee_msat,
+                cltv_expiry_delta: label.cltv_delta,
+            });
+            next = label.next;
+        }
+
+        PaymentRoute {
+            total_fees_msat: hops.iter().map(|hop| hop.fee_msat).sum(),
+            total_cltv_delta: hops.iter().map(|hop| u32::from(hop.cltv_expiry_delta)).sum(),
+            hops,
+            amount_msat,
+        }
     }
 }
 
+/// Largest amount a channel direction can carry, from capacity and HTLC maximum.
+fn channel_limit_msat(channel: &GraphChannel, policy: &ChannelPolicy) -> u64 {
+    let capacity_msat = channel.capacity_sats.map_or(u64::MAX, |sats| sats.saturating_mul(1000));
+    capacity_msat.min(policy.htlc_maximum_msat)
+}
+
 impl Default for PaymentRouter {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::implementation::test_utils::node;
+
+    fn policy(fee_base_msat: u32, fee_ppm: u32, cltv_expiry_delta: u16) -> ChannelPolicy {
+        ChannelPolicy {
+            fee_base_msat,
+            fee_proportional_millionths: fee_ppm,
+            cltv_expiry_delta,
+            ..ChannelPolicy::default()
+        }
+    }
+
+    fn connect(router: &mut PaymentRouter, a: u8, b: u8, scid: u64, policy: ChannelPolicy) {
+        router.add_channel(node(a), node(b), scid);
+        router.update_channel_policy(scid, &node(a), policy).unwrap();
+        router.update_channel_policy(scid, &node(b), policy).unwrap();
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let router = PaymentRouter::new();
+        assert!(router.find_route(&node(2), 1000).is_err());
+    }
+
+    #[test]
+    fn test_multi_hop_fees_and_cltv() {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        connect(&mut router, 1, 2, 12, policy(1000, 100, 40));
+        connect(&mut router, 2, 3, 23, policy(2000, 1000, 30));
+        connect(&mut router, 3, 4, 34, policy(500, 0, 20));
+
+        let route = router.find_route(&node(4), 1_000_000).unwrap();
+        let scids: Vec<u64> = route.hops.iter().map(|hop| hop.short_channel_id).collect();
+        assert_eq!(scids, vec![12, 23, 34]);
+
+        // Node 3 forwards 1_000_000 over 3->4: 500 base.
+        assert_eq!(route.hops[1].fee_msat, 500);
+        assert_eq!(route.hops[1].cltv_expiry_delta, 20);
+        // Node 2 forwards 1_000_500 over 2->3: 2000 + 1000 ppm.
+        assert_eq!(route.hops[0].fee_msat, 2000 + 1000);
+        assert_eq!(route.hops[0].cltv_expiry_delta, 30);
+        assert_eq!(route.hops[2].fee_msat, 0);
+
+        assert_eq!(route.total_fees_msat, 3500);
+        assert_eq!(route.total_cltv_delta, 50);
+        assert_eq!(route.total_amount_msat(), 1_003_500);
+    }
+
+    #[test]
+    fn test_prefers_cheaper_path_and_skips_disabled() {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        connect(&mut router, 1, 2, 12, policy(0, 0, 40));
+        connect(&mut router, 2, 4, 24, policy(5000, 0, 40));
+        connect(&mut router, 1, 3, 13, policy(0, 0, 40));
+        connect(&mut router, 3, 4, 34, policy(100, 0, 40));
+
+        let route = router.find_route(&node(4), 10_000).unwrap();
+        assert_eq!(route.hops[0].short_channel_id, 13);
+
+        let disabled = ChannelPolicy { enabled: false, ..policy(100, 0, 40) };
+        router.update_channel_policy(13, &node(3), disabled).unwrap();
+        router.update_channel_policy(34, &node(3), disabled).unwrap();
+        let route = router.find_route(&node(4), 10_000).unwrap();
+        assert_eq!(route.hops[0].short_channel_id, 12);
+        assert_eq!(route.total_fees_msat, 5000);
+    }
+
+    #[test]
+    fn test_respects_htlc_maximum_and_capacity() {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        router.add_channel_with_capacity(node(1), node(2), 12, 10).unwrap();
+        router.update_channel_policy(12, &node(1), policy(0, 0, 40)).unwrap();
+
+        assert!(router.find_route(&node(2), 10_000).is_ok());
+        assert!(router.find_route(&node(2), 10_001).is_err());
+
+        let limited = ChannelPolicy { htlc_maximum_msat: 5_000, ..policy(0, 0, 40) };
+        router.update_channel_policy(12, &node(1), limited).unwrap();
+        assert!(router.find_route(&node(2), 6_000).is_err());
+    }
+
+    #[test]
+    fn test_splits_payment_across_channel_liquidity() {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        for (peer, scid, capacity_sats) in [(2, 12, 600), (3, 13, 500)] {
+            router.add_channel_with_capacity(node(1), node(peer), scid, capacity_sats).unwrap();
+            router.update_channel_policy(scid, &node(1), policy(0, 0, 40)).unwrap();
+            router.add_channel_with_capacity(node(peer), node(4), scid * 10, 1_000).unwrap();
+            router.update_channel_policy(scid * 10, &node(peer), policy(0, 0, 40)).unwrap();
+        }
+
+        assert!(router.find_route(&node(4), 1_000_000).is_err());
+
+        let routes = router.find_multipath_routes(&node(4), 1_000_000, 16).unwrap();
+        assert!(routes.len() > 1);
+        assert_eq!(routes.iter().map(|route| route.amount_msat).sum::<u64>(), 1_000_000);
+
+        for scid in [12, 13] {
+            let limit = router.graph().channel(scid).unwrap().capacity_sats.unwrap() * 1000;
+            let used: u64 = routes
+                .iter()
+                .filter(|route| route.hops[0].short_channel_id == scid)
+                .map(PaymentRoute::total_amount_msat)
+                .sum();
+            assert!(used <= limit);
+        }
+
+        assert!(router.find_multipath_routes(&node(4), 1_200_000, 16).is_err());
+        assert!(router.find_multipath_routes(&node(4), 1_000_000, 1).is_err());
+    }
+
+    #[test]
+    fn test_local_balance_forces_split() {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        for (peer, scid) in [(2, 12), (3, 13)] {
+            router.set_local_channel(node(peer), scid, 100_000, 60_000_000).unwrap();
+            router.add_channel_with_capacity(node(peer), node(4), scid * 10, 1_000_000).unwrap();
+            router.update_channel_policy(scid * 10, &node(peer), policy(0, 0, 40)).unwrap();
+        }
+
+        // Either channel's capacity fits the payment; neither balance does.
+        assert!(router.find_route(&node(4), 80_000_000).is_err());
+        let routes = router.find_multipath_routes(&node(4), 80_000_000, 16).unwrap();
+        assert!(routes.len() > 1);
+        for scid in [12, 13] {
+            let used: u64 = routes
+                .iter()
+                .filter(|route| route.hops[0].short_channel_id == scid)
+                .map(PaymentRoute::total_amount_msat)
+                .sum();
+            assert!(used <= 60_000_000);
+        }
+
+        router.set_local_channel(node(2), 12, 100_000, 90_000_000).unwrap();
+        assert_eq!(router.find_multipath_routes(&node(4), 80_000_000, 16).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_channel_steers_next_route() {
+        let mut router = PaymentRouter::with_local_node(node(1));
+        for (peer, scid, fee_base_msat) in [(2, 12, 0), (3, 13, 100)] {
+            router.add_channel_with_capacity(node(1), node(peer), scid, 1_000).unwrap();
+            router.update_channel_policy(scid, &node(1), policy(0, 0, 40)).unwrap();
+            router.add_channel_with_capacity(node(peer), node(4), scid * 10, 1_000).unwrap();
+            router
+                .update_channel_policy(scid * 10, &node(peer), policy(fee_base_msat, 0, 40))
+                .unwrap();
+        }
+
+        let route = router.find_route(&node(4), 100_000).unwrap();
+        assert_eq!(route.hops[1].short_channel_id, 120);
+        let mut exclusions = RouteExclusions::new();
+        exclusions.exclude_node(node(2));
+        let avoiding = router.find_route_excluding(&node(4), 100_000, &exclusions).unwrap();
+        assert_eq!(avoiding.hops[1].short_channel_id, 130);
+        exclusions.exclude_channel(130);
+        assert!(router.find_route_excluding(&node(4), 100_000, &exclusions).is_err());
+
+        router.payment_path_failed(&route, 120);
+        let first_hop = router.scorer().bounds(12, node(1) < node(2)).unwrap();
+        assert!(first_hop.min_msat >= 100_000);
+
+        let retry = router.find_route(&node(4), 100_000).unwrap();
+        assert_eq!(retry.hops[1].short_channel_id, 130);
+        router.payment_path_succeeded(&retry);
+
+        let path = std::env::temp_dir().join(format!("mission-control-{}", std::process::id()));
+        router.save_mission_control(&path).unwrap();
+        let mut restored = PaymentRouter::with_local_node(node(1));
+        restored.load_mission_control(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.scorer().len(), router.scorer().len());
+    }
+}
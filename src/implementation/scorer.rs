@@ -0,0 +1,303 @@
+//! Probabilistic liquidity scoring and mission control.
+//!
+//! Every payment attempt narrows down how much liquidity a channel direction
+//! has: a forwarded HTLC proves at least that amount was available, a failure
+//! proves less was. The scorer keeps these bounds, lets them decay back towards
+//! the channel limit over time, and turns them into a success probability that
+//! the router adds to path cost.
+
+use std::collections::HashMap;
+
+use crate::errors::{PaymentError, PaymentResult};
+
+/// Magic prefix and version of serialized mission control data.
+const SERIALIZATION_MAGIC: &[u8; 4] = b"MC\x00\x01";
+/// Size of one serialized bounds entry.
+const ENTRY_LEN: usize = 8 + 1 + 8 + 8 + 8;
+/// Largest `-log10` of a success probability that is still penalised linearly.
+const MAX_NEGATIVE_LOG10: f64 = 2.0;
+
+/// Tuning parameters for [`LiquidityScorer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoringParameters {
+    /// Penalty in millisatoshis per order of magnitude of failure probability.
+    pub liquidity_penalty_multiplier_msat: u64,
+    /// Time after which learned bounds have moved halfway back to the channel limit.
+    pub liquidity_half_life_secs:          u64,
+}
+
+impl Default for ScoringParameters {
+    fn default() -> Self {
+        Self {
+            liquidity_penalty_multiplier_msat: 30_000,
+            liquidity_half_life_secs:          6 * 3600,
+        }
+    }
+}
+
+/// Liquidity learned for one channel direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidityBounds {
+    /// Amount the direction has been seen to forward, in millisatoshis.
+    pub min_msat:     u64,
+    /// Largest amount the direction may still be able to forward, in millisatoshis.
+    pub max_msat:     u64,
+    /// Unix time of the last update, in seconds.
+    pub last_updated: u64,
+}
+
+impl LiquidityBounds {
+    /// Bounds at `now`, with each elapsed half-life halving the distance to `[0, limit]`.
+    #[must_use]
+    pub fn decayed(&self, limit_msat: u64, half_life_secs: u64, now: u64) -> (u64, u64) {
+        let halvings = now.saturating_sub(self.last_updated) / half_life_secs.max(1);
+        let shift = u32::try_from(halvings).unwrap_or(u32::MAX).min(63);
+
+        let max_msat = self.max_msat.min(limit_msat);
+        let min_msat = (self.min_msat >> shift).min(max_msat);
+        let max_msat = limit_msat - ((limit_msat - max_msat) >> shift);
+        (min_msat, max_msat)
+    }
+}
+
+/// Per-channel liquidity estimates learned from payment attempts.
+#[derive(Debug, Clone, Default)]
+pub struct LiquidityScorer {
+    params: ScoringParameters,
+    /// Bounds keyed by `(short_channel_id, forwards_from_node_one)`.
+    bounds: HashMap<(u64, bool), LiquidityBounds>,
+}
+
+impl LiquidityScorer {
+    /// Create a scorer with default parameters.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a scorer with custom parameters.
+    #[must_use]
+    pub fn with_parameters(params: ScoringParameters) -> Self {
+        Self { params, bounds: HashMap::new() }
+    }
+
+    /// Get the scoring parameters.
+    #[must_use]
+    pub fn parameters(&self) -> &ScoringParameters {
+        &self.params
+    }
+
+    /// Number of channel directions with learned bounds.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    /// Check whether nothing has been learned yet.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    /// Get the raw bounds learned for a channel direction.
+    #[must_use]
+    pub fn bounds(&self, short_channel_id: u64, from_node_one: bool) -> Option<&LiquidityBounds> {
+        self.bounds.get(&(short_channel_id, from_node_one))
+    }
+
+    /// Estimate the probability that a channel direction can forward `amount_msat`.
+    ///
+    /// Liquidity is assumed uniformly distributed between the learned bounds.
+    #[must_use]
+    pub fn success_probability(
+        &self, short_channel_id: u64, from_node_one: bool, amount_msat: u64, limit_msat: u64,
+        now: u64,
+    ) -> f64 {
+        let (min_msat, max_msat) =
+            self.current_bounds(short_channel_id, from_node_one, limit_msat, now);
+        if amount_msat <= min_msat {
+            1.0
+        } else if amount_msat > max_msat {
+            0.0
+        } else {
+            ((max_msat - amount_msat) as f64 + 1.0) / ((max_msat - min_msat) as f64 + 1.0)
+        }
+    }
+
+    /// Penalty to add to path cost, or `None` when the amount is known not to fit.
+    #[must_use]
+    pub fn penalty_msat(
+        &self, short_channel_id: u64, from_node_one: bool, amount_msat: u64, limit_msat: u64,
+        now: u64,
+    ) -> Option<u64> {
+        let probability =
+            self.success_probability(short_channel_id, from_node_one, amount_msat, limit_msat, now);
+        if probability <= 0.0 {
+            return None;
+        }
+        let negative_log10 = (-probability.log10()).clamp(0.0, MAX_NEGATIVE_LOG10);
+        Some((negative_log10 * self.params.liquidity_penalty_multiplier_msat as f64) as u64)
+    }
+
+    /// Record that a channel direction failed to forward `amount_msat`.
+    pub fn channel_failed(
+        &mut self, short_channel_id: u64, from_node_one: bool, amount_msat: u64, limit_msat: u64,
+        now: u64,
+    ) {
+        let (mut min_msat, max_msat) =
+            self.current_bounds(short_channel_id, from_node_one, limit_msat, now);
+        let max_msat = max_msat.min(amount_msat.saturating_sub(1));
+        // A failure below a previous success means liquidity moved; trust the failure.
+        if min_msat > max_msat {
+            min_msat = 0;
+        }
+        self.bounds.insert(
+            (short_channel_id, from_node_one),
+            LiquidityBounds { min_msat, max_msat, last_updated: now },
+        );
+    }
+
+    /// Record that a channel direction forwarded `amount_msat`.
+    pub fn channel_succeeded(
+        &mut self, short_channel_id: u64, from_node_one: bool, amount_msat: u64, limit_msat: u64,
+        now: u64,
+    ) {
+        let (min_msat, mut max_msat) =
+            self.current_bounds(short_channel_id, from_node_one, limit_msat, now);
+        let min_msat = min_msat.max(amount_msat.min(limit_msat));
+        if max_msat < min_msat {
+            max_msat = limit_msat;
+        }
+        self.bounds.insert(
+            (short_channel_id, from_node_one),
+            LiquidityBounds { min_msat, max_msat, last_updated: now },
+        );
+    }
+
+    /// Forget everything learned about a channel.
+    pub fn remove_channel(&mut self, short_channel_id: u64) {
+        self.bounds.retain(|&(scid, _), _| scid != short_channel_id);
+    }
+
+    /// Serialize the learned bounds for persistence.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut entries: Vec<_> = self.bounds.iter().collect();
+        entries.sort_by_key(|(key, _)| **key);
+
+        let mut out = Vec::with_capacity(SERIALIZATION_MAGIC.len() + 4 + entries.len() * ENTRY_LEN);
+        out.extend_from_slice(SERIALIZATION_MAGIC);
+        out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for (&(short_channel_id, from_node_one), bounds) in entries {
+            out.extend_from_slice(&short_channel_id.to_be_bytes());
+            out.push(u8::from(from_node_one));
+            out.extend_from_slice(&bounds.min_msat.to_be_bytes());
+            out.extend_from_slice(&bounds.max_msat.to_be_bytes());
+            out.extend_from_slice(&bounds.last_updated.to_be_bytes());
+        }
+        out
+    }
+
+    /// Restore bounds serialized with [`Self::encode`].
+    pub fn decode(params: ScoringParameters, bytes: &[u8]) -> PaymentResult<Self> {
+        let malformed = || PaymentError::Routing("Malformed mission control data".into());
+
+        let body = bytes.strip_prefix(SERIALIZATION_MAGIC.as_slice()).ok_or_else(malformed)?;
+        let (count, entries) = body.split_first_chunk::<4>().ok_or_else(malformed)?;
+        let count = u32::from_be_bytes(*count) as usize;
+        if entries.len() != count.checked_mul(ENTRY_LEN).ok_or_else(malformed)? {
+            return Err(malformed());
+        }
+
+        let u64_at = |entry: &[u8], at: usize| {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(&entry[at..at + 8]);
+            u64::from_be_bytes(buf)
+        };
+        let mut bounds = HashMap::with_capacity(count);
+        for entry in entries.chunks_exact(ENTRY_LEN) {
+            let from_node_one = match entry[8] {
+                0 => false,
+                1 => true,
+                _ => return Err(malformed()),
+            };
+            bounds.insert(
+                (u64_at(entry, 0), from_node_one),
+                LiquidityBounds {
+                    min_msat:     u64_at(entry, 9),
+                    max_msat:     u64_at(entry, 17),
+                    last_updated: u64_at(entry, 25),
+                },
+            );
+        }
+        Ok(Self { params, bounds })
+    }
+
+    fn current_bounds(
+        &self, short_channel_id: u64, from_node_one: bool, limit_msat: u64, now: u64,
+    ) -> (u64, u64) {
+        self.bounds.get(&(short_channel_id, from_node_one)).map_or((0, limit_msat), |bounds| {
+            bounds.decayed(limit_msat, self.params.liquidity_half_life_secs, now)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+
+    const LIMIT: u64 = 1_000_000;
+    const HALF_LIFE: u64 = 6 * 3600;
+
+    #[test]
+    fn test_unknown_channel_is_uniform() {
+        let scorer = LiquidityScorer::new();
+        assert_eq!(scorer.success_probability(1, true, 0, LIMIT, 0), 1.0);
+        let half = scorer.success_probability(1, true, LIMIT / 2, LIMIT, 0);
+        assert!((half - 0.5).abs() < 0.001);
+        assert_eq!(scorer.success_probability(1, true, LIMIT + 1, LIMIT, 0), 0.0);
+    }
+
+    #[test]
+    fn test_failure_and_success_narrow_bounds() {
+        let mut scorer = LiquidityScorer::new();
+        scorer.channel_failed(1, true, 600_000, LIMIT, 100);
+        assert_eq!(scorer.penalty_msat(1, true, 600_000, LIMIT, 100), None);
+        assert!(scorer.penalty_msat(1, true, 300_000, LIMIT, 100).is_some());
+        // The other direction is unaffected.
+        assert!(scorer.penalty_msat(1, false, 600_000, LIMIT, 100).is_some());
+
+        scorer.channel_succeeded(1, true, 200_000, LIMIT, 100);
+        assert_eq!(scorer.success_probability(1, true, 200_000, LIMIT, 100), 1.0);
+        assert_eq!(scorer.penalty_msat(1, true, 100_000, LIMIT, 100), Some(0));
+    }
+
+    #[test]
+    fn test_bounds_decay_towards_limit() {
+        let mut scorer = LiquidityScorer::new();
+        scorer.channel_failed(1, true, 200_001, LIMIT, 0);
+        scorer.channel_succeeded(1, true, 100_000, LIMIT, 0);
+
+        let bounds = scorer.bounds(1, true).unwrap();
+        assert_eq!(bounds.decayed(LIMIT, HALF_LIFE, 0), (100_000, 200_000));
+        assert_eq!(bounds.decayed(LIMIT, HALF_LIFE, HALF_LIFE), (50_000, 600_000));
+        assert_eq!(bounds.decayed(LIMIT, HALF_LIFE, 64 * HALF_LIFE), (0, LIMIT));
+        assert!(scorer.penalty_msat(1, true, 500_000, LIMIT, HALF_LIFE).is_some());
+    }
+
+    #[test]
+    fn test_round_trips_serialized_history() {
+        let mut scorer = LiquidityScorer::new();
+        scorer.channel_failed(7, false, 5_000, LIMIT, 42);
+        scorer.channel_succeeded(9, true, 1_000, LIMIT, 43);
+
+        let encoded = scorer.encode();
+        let restored = LiquidityScorer::decode(ScoringParameters::default(), &encoded).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored.bounds(7, false), scorer.bounds(7, false));
+        assert_eq!(restored.bounds(9, true), scorer.bounds(9, true));
+
+        assert!(LiquidityScorer::decode(ScoringParameters::default(), &encoded[..10]).is_err());
+        assert!(LiquidityScorer::decode(ScoringParameters::default(), b"nope").is_err());
+    }
+}
@@ -0,0 +1,576 @@
+//! BOLT4 Sphinx onion packets.
+//!
+//! The sender wraps one payload per hop in layers of ChaCha20 encryption,
+//! innermost for the payee. Each hop authenticates the packet with the HMAC
+//! the previous layer carried, decrypts its own payload and shifts the rest
+//! forward. Filler bytes keep the packet a fixed length at every hop so a node
+//! cannot tell how far it is from either end of the route.
+
+use crate::{
+    crypto::{chacha20, hmac_sha256, sha256, PublicKey, SecretKey},
+    implementation::{
+        gossip::Reader,
+        onion::{generate_key, hop_keys, OnionError, OnionHopKeys},
+    },
+    types::PaymentRoute,
+};
+
+/// Bytes of hop payloads carried by every onion packet.
+pub const HOP_PAYLOADS_LEN: usize = 1300;
+
+/// Serialized onion packet length: version, ephemeral key, payloads and HMAC.
+pub const ONION_PACKET_LEN: usize = 1 + 33 + HOP_PAYLOADS_LEN + 32;
+
+/// Only onion packet version defined.
+const VERSION: u8 = 0;
+
+const AMT_TO_FORWARD_TYPE: u64 = 2;
+const OUTGOING_CLTV_VALUE_TYPE: u64 = 4;
+const SHORT_CHANNEL_ID_TYPE: u64 = 6;
+const PAYMENT_DATA_TYPE: u64 = 8;
+
+/// `payment_data` for the final hop: the invoice's secret and the total paid across parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaymentData {
+    /// Payment secret from the invoice.
+    pub payment_secret: [u8; 32],
+    /// Total amount of the payment across all parts, in millisatoshis.
+    pub total_msat:     u64,
+}
+
+/// Instructions for one hop, carried as a TLV stream in the onion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopPayload {
+    /// Amount to forward, or to receive at the final hop, in millisatoshis.
+    pub amt_to_forward:      u64,
+    /// CLTV expiry of the outgoing HTLC, or the expected expiry at the final hop.
+    pub outgoing_cltv_value: u32,
+    /// Channel to forward over; `None` at the final hop.
+    pub short_channel_id:    Option<u64>,
+    /// Payment secret and total; final hop only.
+    pub payment_data:        Option<PaymentData>,
+}
+
+impl HopPayload {
+    /// Payloads for every hop of `route`, paying `route.amount_msat` to the payee.
+    ///
+    /// `final_cltv_expiry` is the absolute expiry the payee expects. Each
+    /// earlier hop forwards the amount and expiry the next hop must receive,
+    /// which include that hop's fee and CLTV delta.
+    #[must_use]
+    pub fn for_route(
+        route: &PaymentRoute, final_cltv_expiry: u32, payment_data: Option<PaymentData>,
+    ) -> Vec<Self> {
+        let mut amount_msat = route.amount_msat;
+        let mut cltv_expiry = final_cltv_expiry;
+        let mut payloads = Vec::with_capacity(route.hops.len());
+        for (index, hop) in route.hops.iter().enumerate().rev() {
+            let short_channel_id = route.hops.get(index + 1).map(|next| next.short_channel_id);
+            payloads.push(Self {
+                amt_to_forward: amount_msat,
+                outgoing_cltv_value: cltv_expiry,
+                short_channel_id,
+                payment_data: if short_channel_id.is_none() { payment_data } else { None },
+            });
+            amount_msat = amount_msat.saturating_add(hop.fee_msat);
+            cltv_expiry = cltv_expiry.saturating_add(u32::from(hop.cltv_expiry_delta));
+        }
+        payloads.reverse();
+        payloads
+    }
+
+    /// Encode as a `BigSize` length followed by the TLV stream.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut stream = Vec::new();
+        write_record(&mut stream, AMT_TO_FORWARD_TYPE, &truncated(self.amt_to_forward));
+        write_record(
+            &mut stream,
+            OUTGOING_CLTV_VALUE_TYPE,
+            &truncated(u64::from(self.outgoing_cltv_value)),
+        );
+        if let Some(short_channel_id) = self.short_channel_id {
+            write_record(&mut stream, SHORT_CHANNEL_ID_TYPE, &short_channel_id.to_be_bytes());
+        }
+        if let Some(data) = &self.payment_data {
+            let mut value = data.payment_secret.to_vec();
+            value.extend_from_slice(&truncated(data.total_msat));
+            write_record(&mut stream, PAYMENT_DATA_TYPE, &value);
+        }
+
+        let mut out = Vec::with_capacity(stream.len() + 3);
+        write_big_size(&mut out, stream.len() as u64);
+        out.extend_from_slice(&stream);
+        out
+    }
+
+    /// Decode a TLV stream, without its length prefix.
+    ///
+    /// Records must be in ascending type order. Unknown odd types are skipped
+    /// and unknown even types rejected, as BOLT1 requires.
+    pub fn decode(stream: &[u8]) -> Result<Self, OnionError> {
+        let malformed = |_| OnionError::MalformedPayload("truncated TLV record");
+        let mut reader = Reader::new(stream);
+        let mut last_type = None;
+        let (mut amount, mut cltv, mut short_channel_id, mut payment_data) =
+            (None, None, None, None);
+
+        while reader.remaining() > 0 {
+            let record_type = reader.big_size().map_err(malformed)?;
+            if last_type.is_some_and(|last| record_type <= last) {
+                return Err(OnionError::MalformedPayload("TLV records out of order"));
+            }
+            last_type = Some(record_type);
+            let len = usize::try_from(reader.big_size().map_err(malformed)?).unwrap_or(usize::MAX);
+            if len > reader.remaining() {
+                return Err(OnionError::MalformedPayload("truncated TLV record"));
+            }
+            let mut value = vec![0u8; len];
+            for byte in &mut value {
+                *byte = reader.u8().map_err(malformed)?;
+            }
+
+            match record_type {
+                AMT_TO_FORWARD_TYPE => amount = Some(read_truncated(&value, 8)?),
+                OUTGOING_CLTV_VALUE_TYPE => cltv = Some(read_truncated(&value, 4)? as u32),
+                SHORT_CHANNEL_ID_TYPE => {
+                    let bytes: [u8; 8] = value
+                        .try_into()
+                        .map_err(|_| OnionError::MalformedPayload("short_channel_id length"))?;
+                    short_channel_id = Some(u64::from_be_bytes(bytes));
+                },
+                PAYMENT_DATA_TYPE => {
+                    if value.len() < 32 {
+                        return Err(OnionError::MalformedPayload("payment_data length"));
+                    }
+                    let (secret, total) = value.split_at(32);
+                    let mut payment_secret = [0u8; 32];
+                    payment_secret.copy_from_slice(secret);
+                    payment_data =
+                        Some(PaymentData { payment_secret, total_msat: read_truncated(total, 8)? });
+                },
+                unknown if unknown % 2 == 0 => {
+                    return Err(OnionError::MalformedPayload("unknown even TLV type"));
+                },
+                _ => {},
+            }
+        }
+
+        Ok(Self {
+            amt_to_forward: amount.ok_or(OnionError::MalformedPayload("missing amt_to_forward"))?,
+            outgoing_cltv_value: cltv
+                .ok_or(OnionError::MalformedPayload("missing outgoing_cltv_value"))?,
+            short_channel_id,
+            payment_data,
+        })
+    }
+}
+
+/// A BOLT4 onion packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnionPacket {
+    /// Packet version; always 0.
+    pub version:      u8,
+    /// Ephemeral public key for the receiving hop.
+    pub public_key:   [u8; 33],
+    /// Encrypted hop payloads, [`HOP_PAYLOADS_LEN`] bytes.
+    pub hop_payloads: Vec<u8>,
+    /// HMAC over the hop payloads and associated data.
+    pub hmac:         [u8; 32],
+}
+
+/// What a hop learns from peeling its layer of an onion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeeledOnion {
+    /// Instructions for this hop.
+    pub payload:       HopPayload,
+    /// Secret shared with the sender, used to wrap any failure returned.
+    pub shared_secret: [u8; 32],
+    /// Packet to forward to the next hop; `None` at the final hop.
+    pub next:          Option<OnionPacket>,
+}
+
+impl OnionPacket {
+    /// Build the onion for paying along `route`.
+    ///
+    /// The payment hash is the associated data every hop authenticates.
+    pub fn from_route(
+        route: &PaymentRoute, session_key: &SecretKey, final_cltv_expiry: u32,
+        payment_data: Option<PaymentData>, payment_hash: &[u8; 32],
+    ) -> Result<Self, OnionError> {
+        let hops: Vec<[u8; 33]> = route.hops.iter().map(|hop| hop.pubkey).collect();
+        let payloads = HopPayload::for_route(route, final_cltv_expiry, payment_data);
+        Self::construct(session_key, &hops, &payloads, payment_hash)
+    }
+
+    /// Build an onion carrying `payloads[i]` to `hops[i]`.
+    pub fn construct(
+        session_key: &SecretKey, hops: &[[u8; 33]], payloads: &[HopPayload], associated_data: &[u8],
+    ) -> Result<Self, OnionError> {
+        let payloads: Vec<Vec<u8>> = payloads.iter().map(HopPayload::encode).collect();
+        Self::construct_raw(session_key, hops, &payloads, associated_data)
+    }
+
+    /// Build an onion from already encoded, length-prefixed hop payloads.
+    pub fn construct_raw(
+        session_key: &SecretKey, hops: &[[u8; 33]], payloads: &[Vec<u8>], associated_data: &[u8],
+    ) -> Result<Self, OnionError> {
+        if hops.is_empty() {
+            return Err(OnionError::EmptyRoute);
+        }
+        if payloads.len() != hops.len() {
+            return Err(OnionError::PayloadCountMismatch);
+        }
+        let total: usize = payloads.iter().map(|payload| payload.len() + 32).sum();
+        if total > HOP_PAYLOADS_LEN {
+            return Err(OnionError::PayloadsTooLarge(total));
+        }
+        let keys = hop_keys(session_key, hops)?;
+        let filler = filler(&keys, payloads);
+
+        let mut packet = chacha20::keystream(
+            &generate_key(b"pad", &session_key.secret_bytes()),
+            HOP_PAYLOADS_LEN,
+        );
+        let mut hmac = [0u8; 32];
+        for (index, (keys, payload)) in keys.iter().zip(payloads).enumerate().rev() {
+            let shift = payload.len() + 32;
+            packet.copy_within(..HOP_PAYLOADS_LEN - shift, shift);
+            packet[..payload.len()].copy_from_slice(payload);
+            packet[payload.len()..shift].copy_from_slice(&hmac);
+            xor(
+                &mut packet,
+                &chacha20::keystream(&generate_key(b"rho", &keys.shared_secret), HOP_PAYLOADS_LEN),
+            );
+            if index + 1 == payloads.len() {
+                packet[HOP_PAYLOADS_LEN - filler.len()..].copy_from_slice(&filler);
+            }
+            hmac = packet_hmac(&keys.shared_secret, &packet, associated_data);
+        }
+
+        Ok(Self {
+            version: VERSION,
+            public_key: keys[0].ephemeral_pubkey,
+            hop_payloads: packet,
+            hmac,
+        })
+    }
+
+    /// Parse a serialized packet.
+    pub fn parse(bytes: &[u8]) -> Result<Self, OnionError> {
+        if bytes.len() != ONION_PACKET_LEN {
+            return Err(OnionError::InvalidPacketLength(bytes.len()));
+        }
+        let mut public_key = [0u8; 33];
+        public_key.copy_from_slice(&bytes[1..34]);
+        let mut hmac = [0u8; 32];
+        hmac.copy_from_slice(&bytes[ONION_PACKET_LEN - 32..]);
+        Ok(Self {
+            version: bytes[0],
+            public_key,
+            hop_payloads: bytes[34..ONION_PACKET_LEN - 32].to_vec(),
+            hmac,
+        })
+    }
+
+    /// Serialize as sent in `update_add_htlc`.
+    #[must_use]
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(ONION_PACKET_LEN);
+        out.push(self.version);
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.hop_payloads);
+        out.extend_from_slice(&self.hmac);
+        out
+    }
+
+    /// Peel this hop's layer with its node key.
+    ///
+    /// Checks the HMAC over the payloads and `associated_data`, decodes this
+    /// hop's payload and, unless this is the final hop, builds the packet to
+    /// forward under the next blinded ephemeral key.
+    pub fn peel(
+        &self, node_key: &SecretKey, associated_data: &[u8],
+    ) -> Result<PeeledOnion, OnionError> {
+        if self.version != VERSION {
+            return Err(OnionError::UnknownVersion(self.version));
+        }
+        if self.hop_payloads.len() != HOP_PAYLOADS_LEN {
+            return Err(OnionError::InvalidPacketLength(self.hop_payloads.len() + 66));
+        }
+        let ephemeral =
+            PublicKey::from_slice(&self.public_key).map_err(|_| OnionError::InvalidPublicKey)?;
+        let shared_secret = node_key.ecdh(&ephemeral);
+        if packet_hmac(&shared_secret, &self.hop_payloads, associated_data) != self.hmac {
+            return Err(OnionError::InvalidHmac);
+        }
+
+        // Decrypt past the end so the shifted-in tail is the next hop's filler.
+        let mut bytes = self.hop_payloads.clone();
+        bytes.resize(2 * HOP_PAYLOADS_LEN, 0);
+        xor(
+            &mut bytes,
+            &chacha20::keystream(&generate_key(b"rho", &shared_secret), 2 * HOP_PAYLOADS_LEN),
+        );
+
+        let mut reader = Reader::new(&bytes);
+        let len = reader.big_size().map_err(|_| OnionError::MalformedPayload("payload length"))?;
+        let start = bytes.len() - reader.remaining();
+        let end = usize::try_from(len)
+            .ok()
+            .filter(|&len| len > 0)
+            .map(|len| start + len)
+            .filter(|&end| end + 32 <= HOP_PAYLOADS_LEN)
+            .ok_or(OnionError::MalformedPayload("payload length"))?;
+        let payload = HopPayload::decode(&bytes[start..end])?;
+        let mut hmac = [0u8; 32];
+        hmac.copy_from_slice(&bytes[end..end + 32]);
+
+        let next = if hmac == [0u8; 32] {
+            None
+        } else {
+            let mut preimage = [0u8; 65];
+            preimage[..33].copy_from_slice(&self.public_key);
+            preimage[33..].copy_from_slice(&shared_secret);
+            let next_key = ephemeral
+                .mul_tweak(&sha256(&preimage))
+                .map_err(|_| OnionError::InvalidPublicKey)?;
+            Some(Self {
+                version: VERSION,
+                public_key: next_key.serialize(),
+                hop_payloads: bytes[end + 32..end + 32 + HOP_PAYLOADS_LEN].to_vec(),
+                hmac,
+            })
+        };
+        Ok(PeeledOnion { payload, shared_secret, next })
+    }
+}
+
+/// Filler the last hop sees shifted in, as left by every earlier hop's decryption.
+fn filler(keys: &[OnionHopKeys], payloads: &[Vec<u8>]) -> Vec<u8> {
+    let mut filler = Vec::new();
+    for (keys, payload) in keys.iter().zip(payloads).take(payloads.len() - 1) {
+        let shift = payload.len() + 32;
+        let stream = chacha20::keystream(
+            &generate_key(b"rho", &keys.shared_secret),
+            HOP_PAYLOADS_LEN + shift,
+        );
+        let start = HOP_PAYLOADS_LEN - filler.len();
+        filler.resize(filler.len() + shift, 0);
+        xor(&mut filler, &stream[start..]);
+    }
+    filler
+}
+
+fn packet_hmac(shared_secret: &[u8; 32], hop_payloads: &[u8], associated_data: &[u8]) -> [u8; 32] {
+    let mut data = Vec::with_capacity(hop_payloads.len() + associated_data.len());
+    data.extend_from_slice(hop_payloads);
+    data.extend_from_slice(associated_data);
+    hmac_sha256(&generate_key(b"mu", shared_secret), &data)
+}
+
+fn xor(data: &mut [u8], stream: &[u8]) {
+    for (byte, key_byte) in data.iter_mut().zip(stream) {
+        *byte ^= key_byte;
+    }
+}
+
+/// Big-endian value without leading zero bytes, as BOLT1 `tu64`.
+fn truncated(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let zeros = (value.leading_zeros() / 8) as usize;
+    bytes[zeros..].to_vec()
+}
+
+/// Read a truncated integer of at most `max_len` bytes, rejecting leading zeros.
+fn read_truncated(bytes: &[u8], max_len: usize) -> Result<u64, OnionError> {
+    if bytes.len() > max_len {
+        return Err(OnionError::MalformedPayload("truncated integer too long"));
+    }
+    if bytes.first() == Some(&0) {
+        return Err(OnionError::MalformedPayload("non-minimal truncated integer"));
+    }
+    Ok(bytes.iter().fold(0u64, |value, byte| (value << 8) | u64::from(*byte)))
+}
+
+fn write_record(out: &mut Vec<u8>, record_type: u64, value: &[u8]) {
+    write_big_size(out, record_type);
+    write_big_size(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+/// Write a BOLT1 `BigSize`.
+fn write_big_size(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => out.push(value as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        },
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        },
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&value.to_be_bytes());
+        },
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{
+        encoding::hex,
+        implementation::test_utils::{bolt4_route, secret},
+    };
+
+    /// BOLT4 legacy packet vector: session key 0x41.., associated data 0x42..
+    /// and fixed realm-0 hop payloads with every field set to the hop's index.
+    const BOLT4_PACKET: &str = "\
+        0002eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619e5f14350c2a76fc2\
+        32b5e46d421e9615471ab9e0bc887beff8c95fdb878f7b3a716590438074b10b7f1e35cb07523c426252\
+        1d677fe9f518f3781ad501d739f23bd10f7d61ab590531cf09010079a232a247f9b5062e216400406bdf\
+        3bf038659793e05a2fc562f7b66f04b36ded95d7e1e80beb68ed662cfad7732c9850af1fc6edce721caa\
+        d69320c3a46ba000f1e66ac47ead787eda226d0fd32f7b48084dca885d70cb244fd8243bd076348436a3\
+        2fa4e8d31c09146ad5090b250c911040fcb4439a1ec866abe044a9ad60547bb962ff0476df802839451b\
+        d5d35072d2269cf9b0406da711198d0906f7066de102e2c1e76f3938cd8a024055adc76fdc52f629826f\
+        9ed4c7f0218ff8c6c7d97625d58dc25f3b9eae75a01484b122846c7c7b57e02e679ea8469b70e14fe4f7\
+        0fee4d87b910cf144be6fe48eef24da475c0b0bcc6565a9f99728426ce2380a9580e2a9442481ceae767\
+        9906c30b1a0e21a10f26150e0645ab6edfdab1ce8f8bea7b1dee511c5fd38ac0e702c1c15bb86b52bca1\
+        b71e15b96982d262a442024c33ceb7dd8f949063c2e5e613e873250e2f8708bd4e1924abd45f65c2fa56\
+        17bfb10ee9e4a42d6b5811acc8029c16274f937dac9e8817c7e579fdb767ffe277f26d413ced06b620ed\
+        e8362081da21cf67c2ca9d6f15fe5bc05f82f5bb93f8916bad3d63338ca824f3bbc11b57ce94a5fa1bc2\
+        39533679903d6fec92a8c792fd86e2960188c14f21e399cfd72a50c620e10aefc6249360b463df9a89bf\
+        6836f4f26359207b765578e5ed76ae9f31b1cc48324be576e3d8e44d217445dba466f9b6293fdf054485\
+        84eb64f61e02903f834518622b7d4732471c6e0e22e22d1f45e31f0509eab39cdea5980a492a1da2aaac\
+        55a98a01216cd4bfe7abaa682af0fbff2dfed030ba28f1285df750e4d3477190dd193f8643b61d8ac1c4\
+        27d590badb1f61a05d480908fbdc7c6f0502dd0c4abb51d725e92f95da2a8facb79881a844e2026911ad\
+        cc659d1fb20a2fce63787c8bb0d9f6789c4b231c76da81c3f0718eb7156565a081d2be6b4170c0e0bceb\
+        ddd459f53db2590c974bca0d705c055dee8c629bf854a5d58edc85228499ec6dde80cce4c8910b81b1e9\
+        e8b0f43bd39c8d69c3a80672729b7dc952dd9448688b6bd06afc2d2819cda80b66c57b52ccf7ac1a8660\
+        1410d18d0c732f69de792e0894a9541684ef174de766fd4ce55efea8f53812867be6a391ac865802dbc2\
+        6d93959df327ec2667c7256aa5a1d3c45a69a6158f285d6c97c3b8eedb09527848500517995a9eae4cd9\
+        11df531544c77f5a9a2f22313e3eb72ca7a07dba243476bc926992e0d1e58b4a2fc8c7b01e0cad726237\
+        933ea319bad7537d39f3ed635d1e6c1d29e97b3d2160a09e30ee2b65ac5bce00996a73c008bcf351cecb\
+        97b6833b6d121dcf4644260b2946ea204732ac9954b228f0beaa15071930fd9583dfc466d12b5f0eeeba\
+        6dcf23d5ce8ae62ee5796359d97a4a15955c778d868d0ef9991d9f2833b5bb66119c5f8b396fd108baed\
+        7906cbb3cc376d13551caed97fece6f42a4c908ee279f1127fda1dd3ee77d8de0a6f3c135fa3f1cffe38\
+        591b6738dc97b55f0acc52be9753ce53e64d7e497bb00ca6123758df3b68fad99e35c04389f7514a8e36\
+        039f541598a417275e77869989782325a15b5342ac5011ff07af698584b476b35d941a4981eac590a07a\
+        092bb50342da5d3341f901aa07964a8d02b623c7b106dd0ae50bfa007a22d46c8772fa55558176602946\
+        cb1d11ea5460db7586fb89c6d3bcd3ab6dd20df4a4db63d2e7d52380800ad81203d2297ae381f431d7ea\
+        daf580212a1e68dced19a7219a821c84a2e08775d87f";
+
+    #[test]
+    fn test_packet_matches_bolt4_vector() {
+        let hops: Vec<[u8; 33]> = bolt4_route(0, 1_000).hops.iter().map(|hop| hop.pubkey).collect();
+        let payloads: Vec<Vec<u8>> = (0..5u8)
+            .map(|i| {
+                let mut payload = vec![0u8; 33];
+                payload[1..21].fill(i);
+                payload
+            })
+            .collect();
+        let onion =
+            OnionPacket::construct_raw(&secret(0x41), &hops, &payloads, &[0x42; 32]).unwrap();
+        assert_eq!(hex::encode(&onion.serialize()), BOLT4_PACKET);
+        assert_eq!(OnionPacket::parse(&hex::decode(BOLT4_PACKET).unwrap()), Ok(onion));
+    }
+
+    #[test]
+    fn test_onion_peels_hop_by_hop_to_the_payee() {
+        let route = bolt4_route(1_000, 50_000);
+        let payment_hash = [0x42; 32];
+        let data = PaymentData { payment_secret: [7; 32], total_msat: 50_000 };
+        let onion =
+            OnionPacket::from_route(&route, &secret(0x41), 800_000, Some(data), &payment_hash)
+                .unwrap();
+        let mut packet = OnionPacket::parse(&onion.serialize()).unwrap();
+        assert_eq!(packet, onion);
+
+        let payloads = HopPayload::for_route(&route, 800_000, Some(data));
+        assert_eq!(
+            (payloads[0].amt_to_forward, payloads[0].outgoing_cltv_value),
+            (53_000, 800_120)
+        );
+        for (index, expected) in payloads.iter().enumerate() {
+            let peeled = packet.peel(&secret(0x41 + index as u8), &payment_hash).unwrap();
+            assert_eq!(peeled.payload, *expected);
+            assert_eq!(
+                peeled.payload.short_channel_id,
+                route.hops.get(index + 1).map(|h| h.short_channel_id)
+            );
+            match peeled.next {
+                Some(next) => packet = next,
+                None => assert_eq!(index, 4),
+            }
+        }
+        assert_eq!(payloads[4].payment_data, Some(data));
+        assert_eq!(
+            (payloads[4].amt_to_forward, payloads[4].outgoing_cltv_value),
+            (50_000, 800_000)
+        );
+    }
+
+    #[test]
+    fn test_rejects_tampered_or_misaddressed_onion() {
+        let route = bolt4_route(1_000, 50_000);
+        let onion =
+            OnionPacket::from_route(&route, &secret(0x41), 800_000, None, &[1; 32]).unwrap();
+        assert_eq!(onion.peel(&secret(0x41), &[2; 32]), Err(OnionError::InvalidHmac));
+        assert_eq!(onion.peel(&secret(0x42), &[1; 32]), Err(OnionError::InvalidHmac));
+
+        let mut tampered = onion.clone();
+        tampered.hop_payloads[700] ^= 1;
+        assert_eq!(tampered.peel(&secret(0x41), &[1; 32]), Err(OnionError::InvalidHmac));
+        let next = onion.peel(&secret(0x41), &[1; 32]).unwrap().next.unwrap();
+        let mut tampered = next.clone();
+        tampered.hmac[0] ^= 1;
+        assert_eq!(tampered.peel(&secret(0x42), &[1; 32]), Err(OnionError::InvalidHmac));
+
+        assert_eq!(
+            OnionPacket { version: 1, ..onion.clone() }.peel(&secret(0x41), &[1; 32]),
+            Err(OnionError::UnknownVersion(1))
+        );
+        assert_eq!(OnionPacket::parse(&[0; 100]), Err(OnionError::InvalidPacketLength(100)));
+        let hops: Vec<[u8; 33]> = route.hops.iter().map(|hop| hop.pubkey).collect();
+        let oversized = vec![vec![0u8; 300]; 5];
+        assert_eq!(
+            OnionPacket::construct_raw(&secret(0x41), &hops, &oversized, &[]),
+            Err(OnionError::PayloadsTooLarge(5 * 332))
+        );
+    }
+
+    #[test]
+    fn test_hop_payload_tlv_encoding() {
+        let payload = HopPayload {
+            amt_to_forward:      0x0100,
+            outgoing_cltv_value: 0x12,
+            short_channel_id:    Some(0x0102_0304_0506_0708),
+            payment_data:        None,
+        };
+        assert_eq!(hex::encode(&payload.encode()), "110202010004011206080102030405060708");
+        assert_eq!(HopPayload::decode(&payload.encode()[1..]), Ok(payload));
+
+        let malformed = |stream: &str| HopPayload::decode(&hex::decode(stream).unwrap());
+        assert_eq!(
+            malformed("0202000104010a"),
+            Err(OnionError::MalformedPayload("non-minimal truncated integer"))
+        );
+        assert_eq!(
+            malformed("040112020101"),
+            Err(OnionError::MalformedPayload("TLV records out of order"))
+        );
+        assert_eq!(
+            malformed("02010104010a0a00"),
+            Err(OnionError::MalformedPayload("unknown even TLV type"))
+        );
+        assert!(malformed("02010104010a0b0100").is_ok());
+        assert_eq!(
+            malformed("020101"),
+            Err(OnionError::MalformedPayload("missing outgoing_cltv_value"))
+        );
+    }
+}
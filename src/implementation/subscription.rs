@@ -0,0 +1,924 @@
+//! Subscription billing.
+//!
+//! Each customer has a [`SubscriptionTier`] billed per period. A paid tier
+//! becomes active once its first invoice is paid. Ahead of every period end a
+//! renewal invoice is issued; if it is still unpaid when the period ends the
+//! subscription enters a grace period, and when the grace period runs out it
+//! is downgraded to [`SubscriptionTier::Free`]. Tier changes within a period
+//! are prorated, see [`Proration`]. All timing comes from an injected
+//! [`Clock`].
+
+use core::fmt;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    errors::{ErrorDetail, PaymentError, PaymentResult},
+    implementation::proration::{Proration, ProrationOutcome},
+    traits::{Clock, InvoiceProvider},
+    types::{PaymentInvoice, SubscriptionTier},
+};
+
+/// Seconds in a day.
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Default billing period, 30 days; each period is charged the tier's monthly price.
+pub const DEFAULT_BILLING_PERIOD_SECS: u64 = 30 * SECS_PER_DAY;
+/// Default time a lapsed subscription keeps its tier, 7 days.
+pub const DEFAULT_GRACE_PERIOD_SECS: u64 = 7 * SECS_PER_DAY;
+/// Default lead time for renewal invoices, 3 days before the period ends.
+pub const DEFAULT_RENEWAL_LEAD_SECS: u64 = 3 * SECS_PER_DAY;
+
+/// Billing timing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BillingPolicy {
+    /// Length of a billing period, in seconds.
+    pub period_secs:       u64,
+    /// Time after the period end before an unpaid subscription is downgraded.
+    pub grace_period_secs: u64,
+    /// Time before the period end at which the renewal invoice is issued.
+    pub renewal_lead_secs: u64,
+}
+
+impl Default for BillingPolicy {
+    fn default() -> Self {
+        Self {
+            period_secs:       DEFAULT_BILLING_PERIOD_SECS,
+            grace_period_secs: DEFAULT_GRACE_PERIOD_SECS,
+            renewal_lead_secs: DEFAULT_RENEWAL_LEAD_SECS,
+        }
+    }
+}
+
+/// Reasons a subscription operation is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionError {
+    /// Customer has no subscription.
+    UnknownCustomer,
+    /// Customer already has a paid subscription.
+    AlreadySubscribed,
+    /// No open subscription invoice has the payment hash.
+    UnknownInvoice,
+    /// Customer id is empty.
+    EmptyCustomerId,
+    /// Tier changes need a paid, active subscription.
+    NotActive,
+    /// Customer is already on the requested tier.
+    SameTier,
+    /// An earlier tier change is still awaiting payment.
+    ChangePending,
+    /// Subscription invoice expired before it was paid.
+    InvoiceExpired,
+}
+
+impl fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadySubscribed => write!(f, "customer already has a paid subscription"),
+            Self::UnknownCustomer => write!(f, "customer has no subscription"),
+            Self::UnknownInvoice => write!(f, "no open subscription invoice for payment hash"),
+            Self::EmptyCustomerId => write!(f, "customer id cannot be empty"),
+            Self::NotActive => write!(f, "tier changes need a paid, active subscription"),
+            Self::SameTier => write!(f, "customer is already on that tier"),
+            Self::ChangePending => write!(f, "an earlier tier change awaits payment"),
+            Self::InvoiceExpired => write!(f, "subscription invoice expired unpaid"),
+        }
+    }
+}
+
+impl std::error::Error for SubscriptionError {}
+
+impl From<SubscriptionError> for PaymentError {
+    fn from(err: SubscriptionError) -> Self {
+        PaymentError::Subscription(ErrorDetail::caused_by(err.to_string(), err))
+    }
+}
+
+/// Where a subscription stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    /// First invoice issued, not yet paid; the customer gets free features.
+    AwaitingPayment,
+    /// Paid through the current period.
+    Active,
+    /// Period ended without the renewal being paid; the tier is kept until
+    /// the grace period runs out.
+    GracePeriod,
+}
+
+/// What a subscription invoice pays for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvoicePurpose {
+    /// First period, starting when paid.
+    Initial,
+    /// Period following the current one.
+    Renewal,
+    /// Net charge for switching tier within the current period.
+    Proration,
+}
+
+/// When a tier change takes effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TierChangeTiming {
+    /// Now, prorated against the current period.
+    Immediate,
+    /// When the current period ends, billed by the next renewal.
+    PeriodEnd,
+}
+
+/// Result of a tier change request.
+#[derive(Debug, Clone)]
+pub enum TierChange {
+    /// Change applies once the net prorated charge is paid.
+    Invoiced {
+        /// Invoice for the net charge.
+        invoice:   PaymentInvoice,
+        /// How the charge was computed.
+        proration: Proration,
+    },
+    /// Change applied at once; the account holds `credit_sats` afterwards.
+    Applied {
+        /// How the credit was computed.
+        proration:   Proration,
+        /// Account credit after the change, in satoshis.
+        credit_sats: u64,
+    },
+    /// Change takes effect at the end of the current period.
+    Scheduled {
+        /// Unix time the new tier starts.
+        effective_at: u64,
+    },
+}
+
+/// Payment state of a subscription invoice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionInvoiceStatus {
+    /// Waiting for payment.
+    Open,
+    /// Paid at the given unix time.
+    Paid(u64),
+    /// Withdrawn; payment is no longer expected.
+    Void,
+}
+
+/// Invoice issued for a subscription.
+#[derive(Debug, Clone)]
+pub struct SubscriptionInvoice {
+    /// Invoice as issued through the invoice provider.
+    pub invoice:             PaymentInvoice,
+    /// What the invoice pays for.
+    pub purpose:             InvoicePurpose,
+    /// Tier being paid for.
+    pub tier:                SubscriptionTier,
+    /// Account credit deducted from the amount, in satoshis.
+    pub credit_applied_sats: u64,
+    /// Unix time the invoice was issued.
+    pub issued_at:           u64,
+    /// Payment state.
+    pub status:              SubscriptionInvoiceStatus,
+}
+
+/// A customer's subscription.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    /// Customer identifier.
+    pub customer_id:  String,
+    /// Subscribed tier.
+    pub tier:         SubscriptionTier,
+    /// Billing state.
+    pub status:       SubscriptionStatus,
+    /// Start of the current period, unix seconds.
+    pub period_start: u64,
+    /// End of the current period, unix seconds.
+    pub period_end:   u64,
+    /// Tier taking over at the end of the current period.
+    pub pending_tier: Option<SubscriptionTier>,
+    /// Account credit deducted from future invoices, in satoshis.
+    pub credit_sats:  u64,
+    /// Every invoice issued, oldest first.
+    pub invoices:     Vec<SubscriptionInvoice>,
+}
+
+impl Subscription {
+    /// Tier whose features the customer gets right now.
+    #[must_use]
+    pub fn effective_tier(&self) -> SubscriptionTier {
+        match self.status {
+            SubscriptionStatus::AwaitingPayment => SubscriptionTier::Free,
+            SubscriptionStatus::Active | SubscriptionStatus::GracePeriod => self.tier,
+        }
+    }
+
+    /// Invoice still waiting for payment.
+    #[must_use]
+    pub fn open_invoice(&self) -> Option<&SubscriptionInvoice> {
+        self.invoices.iter().find(|invoice| invoice.status == SubscriptionInvoiceStatus::Open)
+    }
+
+    fn has_open(&self, purpose: InvoicePurpose) -> bool {
+        self.invoices.iter().any(|invoice| {
+            invoice.purpose == purpose && invoice.status == SubscriptionInvoiceStatus::Open
+        })
+    }
+
+    /// Void open invoices matching `filter`, returning their credit to the account.
+    fn void_invoices(&mut self, filter: impl Fn(&SubscriptionInvoice) -> bool) {
+        for invoice in &mut self.invoices {
+            if invoice.status == SubscriptionInvoiceStatus::Open && filter(invoice) {
+                invoice.status = SubscriptionInvoiceStatus::Void;
+                self.credit_sats = self.credit_sats.saturating_add(invoice.credit_applied_sats);
+            }
+        }
+    }
+}
+
+/// Something billing did to a subscription.
+#[derive(Debug, Clone)]
+pub enum BillingEvent {
+    /// Invoice issued to the customer.
+    InvoiceIssued {
+        /// Customer billed.
+        customer_id: String,
+        /// Invoice to pay.
+        invoice:     PaymentInvoice,
+    },
+    /// Payment received; the subscription is active until `period_end`.
+    Activated {
+        /// Customer who paid.
+        customer_id: String,
+        /// End of the paid period, unix seconds.
+        period_end:  u64,
+    },
+    /// Period ended unpaid; the tier lapses at `grace_ends`.
+    GraceStarted {
+        /// Customer whose renewal is overdue.
+        customer_id: String,
+        /// Unix time of the downgrade if still unpaid.
+        grace_ends:  u64,
+    },
+    /// Scheduled tier change took effect without an invoice.
+    TierChanged {
+        /// Customer whose tier changed.
+        customer_id: String,
+        /// Previous tier.
+        from:        SubscriptionTier,
+        /// New tier.
+        to:          SubscriptionTier,
+    },
+    /// AI operations beyond the quota were invoiced.
+    OverageInvoiced {
+        /// Customer billed.
+        customer_id: String,
+        /// Invoice to pay.
+        invoice:     PaymentInvoice,
+        /// Operations billed.
+        operations:  u64,
+    },
+    /// Subscription dropped to the free tier for non-payment.
+    Downgraded {
+        /// Customer downgraded.
+        customer_id: String,
+        /// Tier the customer had.
+        from:        SubscriptionTier,
+    },
+}
+
+/// Subscription billing engine.
+#[derive(Debug)]
+pub struct SubscriptionManager {
+    clock:         Arc<dyn Clock>,
+    policy:        BillingPolicy,
+    subscriptions: HashMap<String, Subscription>,
+}
+
+impl SubscriptionManager {
+    /// Create an empty manager reading time from `clock`.
+    #[must_use]
+    pub fn new(clock: Arc<dyn Clock>, policy: BillingPolicy) -> Self {
+        Self { clock, policy, subscriptions: HashMap::new() }
+    }
+
+    /// Billing timing in use.
+    #[must_use]
+    pub fn policy(&self) -> &BillingPolicy {
+        &self.policy
+    }
+
+    /// Current time according to the injected clock.
+    #[must_use]
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Look up a customer's subscription.
+    #[must_use]
+    pub fn subscription(&self, customer_id: &str) -> Option<&Subscription> {
+        self.subscriptions.get(customer_id)
+    }
+
+    /// Tier whose features the customer gets right now; unknown customers are free.
+    #[must_use]
+    pub fn effective_tier(&self, customer_id: &str) -> SubscriptionTier {
+        self.subscription(customer_id).map_or(SubscriptionTier::Free, Subscription::effective_tier)
+    }
+
+    /// Billing period usage is counted in.
+    ///
+    /// Paid subscriptions use their current period; everyone else uses fixed
+    /// windows of the policy's period length.
+    #[must_use]
+    pub fn current_period(&self, customer_id: &str) -> (u64, u64) {
+        match self.subscription(customer_id) {
+            Some(subscription)
+                if subscription.effective_tier() != SubscriptionTier::Free
+                    && subscription.period_end > subscription.period_start =>
+            {
+                (subscription.period_start, subscription.period_end)
+            },
+            _ => {
+                let now = self.now();
+                let length = self.policy.period_secs.max(1);
+                let start = now - now % length;
+                (start, start.saturating_add(length))
+            },
+        }
+    }
+
+    /// Subscribe a customer to `tier`.
+    ///
+    /// The free tier is active at once. Paid tiers issue their first invoice,
+    /// which is returned, and become active when it is paid.
+    pub fn subscribe(
+        &mut self, customer_id: &str, tier: SubscriptionTier, invoices: &dyn InvoiceProvider,
+    ) -> PaymentResult<Option<PaymentInvoice>> {
+        if customer_id.is_empty() {
+            return Err(SubscriptionError::EmptyCustomerId.into());
+        }
+        if self.effective_tier(customer_id) != SubscriptionTier::Free
+            || self.subscription(customer_id).and_then(Subscription::open_invoice).is_some()
+        {
+            return Err(SubscriptionError::AlreadySubscribed.into());
+        }
+
+        let now = self.now();
+        let mut subscription = Subscription {
+            customer_id: customer_id.to_string(),
+            tier,
+            status: SubscriptionStatus::Active,
+            period_start: now,
+            period_end: now,
+            pending_tier: None,
+            credit_sats: self.subscription(customer_id).map_or(0, |previous| previous.credit_sats),
+            invoices: Vec::new(),
+        };
+        let invoice = if tier == SubscriptionTier::Free {
+            None
+        } else {
+            subscription.status = SubscriptionStatus::AwaitingPayment;
+            let price = tier.monthly_price_sats();
+            Some(issue_invoice(
+                &mut subscription,
+                InvoicePurpose::Initial,
+                tier,
+                price,
+                invoices,
+                now,
+            )?)
+        };
+        self.subscriptions.insert(customer_id.to_string(), subscription);
+        Ok(invoice)
+    }
+
+    /// Record payment of a subscription invoice.
+    ///
+    /// An initial payment starts the first period now; a renewal extends the
+    /// current period, even when paid during the grace period. An invoice paid
+    /// after its expiry is rejected; billing reissues it.
+    pub fn record_payment(&mut self, payment_hash: &[u8; 32]) -> PaymentResult<BillingEvent> {
+        let now = self.now();
+        let period_secs = self.policy.period_secs;
+        let subscription = self
+            .subscriptions
+            .values_mut()
+            .find(|subscription| {
+                subscription.invoices.iter().any(|invoice| {
+                    invoice.invoice.payment_hash == *payment_hash
+                        && invoice.status == SubscriptionInvoiceStatus::Open
+                })
+            })
+            .ok_or(SubscriptionError::UnknownInvoice)?;
+        let invoice = subscription
+            .invoices
+            .iter_mut()
+            .find(|invoice| invoice.invoice.payment_hash == *payment_hash)
+            .ok_or(SubscriptionError::UnknownInvoice)?;
+        if invoice.invoice.expiry <= now {
+            return Err(SubscriptionError::InvoiceExpired.into());
+        }
+        invoice.status = SubscriptionInvoiceStatus::Paid(now);
+        let (purpose, tier) = (invoice.purpose, invoice.tier);
+
+        match purpose {
+            InvoicePurpose::Initial => {
+                subscription.period_start = now;
+                subscription.period_end = now.saturating_add(period_secs);
+            },
+            InvoicePurpose::Renewal => {
+                subscription.period_start = subscription.period_end;
+                subscription.period_end = subscription.period_end.saturating_add(period_secs);
+                subscription.pending_tier = None;
+            },
+            InvoicePurpose::Proration => {
+                // A renewal issued for the old tier is reissued for the new one.
+                subscription.void_invoices(|open| open.purpose == InvoicePurpose::Renewal);
+                subscription.pending_tier = None;
+            },
+        }
+        subscription.tier = tier;
+        subscription.status = SubscriptionStatus::Active;
+        Ok(BillingEvent::Activated {
+            customer_id: subscription.customer_id.clone(),
+            period_end:  subscription.period_end,
+        })
+    }
+
+    /// Move a customer to another paid or free tier.
+    ///
+    /// An immediate change is prorated against the current period, net of any
+    /// account credit: a remaining charge is invoiced and the change applies
+    /// once it is paid, otherwise the change applies now and the difference is
+    /// credited. A change at period end is billed by the next renewal.
+    pub fn change_tier(
+        &mut self, customer_id: &str, tier: SubscriptionTier, timing: TierChangeTiming,
+        invoices: &dyn InvoiceProvider,
+    ) -> PaymentResult<TierChange> {
+        let now = self.now();
+        let subscription =
+            self.subscriptions.get_mut(customer_id).ok_or(SubscriptionError::UnknownCustomer)?;
+        if subscription.status != SubscriptionStatus::Active
+            || subscription.tier == SubscriptionTier::Free
+        {
+            return Err(SubscriptionError::NotActive.into());
+        }
+        if subscription.tier == tier {
+            return Err(SubscriptionError::SameTier.into());
+        }
+        if subscription.has_open(InvoicePurpose::Proration) {
+            return Err(SubscriptionError::ChangePending.into());
+        }
+
+        if timing == TierChangeTiming::PeriodEnd {
+            subscription.pending_tier = Some(tier);
+            subscription.void_invoices(|open| open.purpose == InvoicePurpose::Renewal);
+            return Ok(TierChange::Scheduled { effective_at: subscription.period_end });
+        }
+
+        let proration = Proration::compute(
+            subscription.period_start,
+            subscription.period_end,
+            now,
+            subscription.tier,
+            tier,
+        );
+        match proration.outcome_with_credit(subscription.credit_sats) {
+            ProrationOutcome::Charge(amount_sats) => {
+                let invoice = issue_invoice(
+                    subscription,
+                    InvoicePurpose::Proration,
+                    tier,
+                    amount_sats,
+                    invoices,
+                    now,
+                )?;
+                // The charge nets out all account credit; voiding the invoice returns it.
+                if let Some(issued) = subscription.invoices.last_mut() {
+                    issued.credit_applied_sats = subscription.credit_sats;
+                }
+                subscription.credit_sats = 0;
+                Ok(TierChange::Invoiced { invoice, proration })
+            },
+            outcome => {
+                subscription.credit_sats = match outcome {
+                    ProrationOutcome::Credit(credit_sats) => credit_sats,
+                    _ => 0,
+                };
+                subscription.tier = tier;
+                subscription.pending_tier = None;
+                subscription.void_invoices(|open| open.purpose == InvoicePurpose::Renewal);
+                Ok(TierChange::Applied { proration, credit_sats: subscription.credit_sats })
+            },
+        }
+    }
+
+    /// Advance every subscription to the current time.
+    ///
+    /// Issues renewal invoices that are due, replacing expired ones, applies
+    /// scheduled tier changes, starts grace periods and downgrades
+    /// subscriptions whose grace period ran out, without reissuing their
+    /// invoices. Account credit is spent on renewals first; a renewal it fully
+    /// covers extends the period at once.
+    pub fn run_billing(
+        &mut self, invoices: &dyn InvoiceProvider,
+    ) -> PaymentResult<Vec<BillingEvent>> {
+        let now = self.now();
+        let policy = self.policy;
+        let mut events = Vec::new();
+
+        let mut customers: Vec<String> = self.subscriptions.keys().cloned().collect();
+        customers.sort();
+        for customer_id in customers {
+            let Some(subscription) = self.subscriptions.get_mut(&customer_id) else { continue };
+            if subscription.tier == SubscriptionTier::Free {
+                continue;
+            }
+
+            subscription.void_invoices(|open| open.invoice.expiry <= now);
+            let next_tier = subscription.pending_tier.unwrap_or(subscription.tier);
+            let lapsed = now >= subscription.period_end.saturating_add(policy.grace_period_secs);
+            if subscription.status == SubscriptionStatus::AwaitingPayment {
+                if !lapsed && !subscription.has_open(InvoicePurpose::Initial) {
+                    let price = subscription.tier.monthly_price_sats();
+                    let invoice = issue_invoice(
+                        subscription,
+                        InvoicePurpose::Initial,
+                        subscription.tier,
+                        price,
+                        invoices,
+                        now,
+                    )?;
+                    events.push(BillingEvent::InvoiceIssued {
+                        customer_id: customer_id.clone(),
+                        invoice,
+                    });
+                }
+            } else if next_tier == SubscriptionTier::Free {
+                if now >= subscription.period_end {
+                    let from = subscription.tier;
+                    subscription.void_invoices(|_| true);
+                    subscription.tier = SubscriptionTier::Free;
+                    subscription.pending_tier = None;
+                    subscription.status = SubscriptionStatus::Active;
+                    subscription.period_start = now;
+                    subscription.period_end = now;
+                    events.push(BillingEvent::TierChanged {
+                        customer_id,
+                        from,
+                        to: SubscriptionTier::Free,
+                    });
+                }
+                continue;
+            } else if now.saturating_add(policy.renewal_lead_secs) >= subscription.period_end
+                && !subscription.has_open(InvoicePurpose::Renewal)
+            {
+                let price = next_tier.monthly_price_sats();
+                let credit = subscription.credit_sats.min(price);
+                if credit == price {
+                    subscription.credit_sats -= credit;
+                    subscription.tier = next_tier;
+                    subscription.pending_tier = None;
+                    subscription.status = SubscriptionStatus::Active;
+                    subscription.period_start = subscription.period_end;
+                    subscription.period_end =
+                        subscription.period_end.saturating_add(policy.period_secs);
+                    events.push(BillingEvent::Activated {
+                        customer_id: customer_id.clone(),
+                        period_end:  subscription.period_end,
+                    });
+                } else if !lapsed {
+                    subscription.credit_sats -= credit;
+                    let invoice = issue_invoice(
+                        subscription,
+                        InvoicePurpose::Renewal,
+                        next_tier,
+                        price - credit,
+                        invoices,
+                        now,
+                    )?;
+                    if let Some(issued) = subscription.invoices.last_mut() {
+                        issued.credit_applied_sats = credit;
+                    }
+                    events.push(BillingEvent::InvoiceIssued {
+                        customer_id: customer_id.clone(),
+                        invoice,
+                    });
+                }
+            }
+
+            let grace_ends = subscription.period_end.saturating_add(policy.grace_period_secs);
+            if subscription.status == SubscriptionStatus::Active && now >= subscription.period_end {
+                subscription.status = SubscriptionStatus::GracePeriod;
+                events.push(BillingEvent::GraceStarted {
+                    customer_id: customer_id.clone(),
+                    grace_ends,
+                });
+            }
+            if subscription.status != SubscriptionStatus::Active && now >= grace_ends {
+                let from = subscription.tier;
+                subscription.void_invoices(|_| true);
+                subscription.tier = SubscriptionTier::Free;
+                subscription.pending_tier = None;
+                subscription.status = SubscriptionStatus::Active;
+                subscription.period_start = now;
+                subscription.period_end = now;
+                events.push(BillingEvent::Downgraded { customer_id, from });
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Issue a subscription invoice for `amount_sats`.
+fn issue_invoice(
+    subscription: &mut Subscription, purpose: InvoicePurpose, tier: SubscriptionTier,
+    amount_sats: u64, invoices: &dyn InvoiceProvider, now: u64,
+) -> PaymentResult<PaymentInvoice> {
+    let description = match purpose {
+        InvoicePurpose::Proration => {
+            format!("Prorated upgrade to {tier:?} for {}", subscription.customer_id)
+        },
+        InvoicePurpose::Initial | InvoicePurpose::Renewal => {
+            format!("{tier:?} subscription for {}", subscription.customer_id)
+        },
+    };
+    let invoice = invoices.generate_invoice(Some(amount_sats), &description)?;
+    subscription.invoices.push(SubscriptionInvoice {
+        invoice: invoice.clone(),
+        purpose,
+        tier,
+        credit_applied_sats: 0,
+        issued_at: now,
+        status: SubscriptionInvoiceStatus::Open,
+    });
+    Ok(invoice)
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::SecretKey,
+        implementation::{InvoiceGenerator, ManualClock, PaymentConfig, DEFAULT_INVOICE_EXPIRY},
+    };
+
+    const T0: u64 = 1_700_000_000;
+    const DAY: u64 = SECS_PER_DAY;
+
+    fn manager() -> (SubscriptionManager, ManualClock, InvoiceGenerator) {
+        let clock = ManualClock::new(T0);
+        let manager = SubscriptionManager::new(Arc::new(clock.clone()), BillingPolicy::default());
+        let invoices = InvoiceGenerator::with_clock(
+            PaymentConfig::default(),
+            SecretKey::new_random(),
+            Arc::new(clock.clone()),
+        );
+        (manager, clock, invoices)
+    }
+
+    fn issued(events: &[BillingEvent]) -> Vec<[u8; 32]> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                BillingEvent::InvoiceIssued { invoice, .. } => Some(invoice.payment_hash),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_renewal_cycle_grace_and_downgrade() {
+        let (mut manager, clock, invoices) = manager();
+        let first = manager.subscribe("carol", SubscriptionTier::Pro, &invoices).unwrap().unwrap();
+        assert_eq!(first.amount, Some(10_000));
+        assert_eq!(manager.effective_tier("carol"), SubscriptionTier::Free);
+        assert_eq!(first.expiry, T0 + DEFAULT_INVOICE_EXPIRY);
+
+        // An expired signup invoice is refused and reissued by billing.
+        clock.advance(DEFAULT_INVOICE_EXPIRY);
+        let err = manager.record_payment(&first.payment_hash).unwrap_err();
+        assert!(err.to_string().contains("expired"), "{err}");
+        let reissued = issued(&manager.run_billing(&invoices).unwrap());
+        assert_eq!(reissued.len(), 1);
+        assert!(manager.run_billing(&invoices).unwrap().is_empty());
+        let start = clock.now();
+        manager.record_payment(&reissued[0]).unwrap();
+        let subscription = manager.subscription("carol").unwrap();
+        assert_eq!(subscription.status, SubscriptionStatus::Active);
+        assert_eq!(subscription.period_end, start + 30 * DAY);
+        assert!(manager.record_payment(&first.payment_hash).is_err());
+        assert!(manager.record_payment(&reissued[0]).is_err());
+        assert!(manager.run_billing(&invoices).unwrap().is_empty());
+
+        // Renewal goes out three days early, once.
+        clock.set(start + 27 * DAY);
+        let renewal = issued(&manager.run_billing(&invoices).unwrap());
+        assert_eq!(renewal.len(), 1);
+        assert!(manager.run_billing(&invoices).unwrap().is_empty());
+        manager.record_payment(&renewal[0]).unwrap();
+        assert_eq!(manager.subscription("carol").unwrap().period_end, start + 60 * DAY);
+
+        // Next renewal is ignored: grace, then downgrade.
+        clock.set(start + 60 * DAY);
+        let events = manager.run_billing(&invoices).unwrap();
+        let unpaid = issued(&events);
+        assert!(matches!(
+            events.last(),
+            Some(BillingEvent::GraceStarted { grace_ends, .. }) if *grace_ends == start + 67 * DAY
+        ));
+        assert_eq!(manager.effective_tier("carol"), SubscriptionTier::Pro);
+        clock.advance(DEFAULT_INVOICE_EXPIRY);
+        let reissued = issued(&manager.run_billing(&invoices).unwrap());
+        assert_eq!(reissued.len(), 1);
+        assert!(manager.record_payment(&unpaid[0]).is_err());
+
+        // The lapsed renewal is voided, not reissued.
+        clock.set(start + 67 * DAY);
+        let events = manager.run_billing(&invoices).unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [BillingEvent::Downgraded { from: SubscriptionTier::Pro, .. }]
+        ));
+        assert_eq!(manager.effective_tier("carol"), SubscriptionTier::Free);
+        assert!(manager.record_payment(&reissued[0]).is_err());
+        assert!(manager.run_billing(&invoices).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_late_renewal_keeps_period_anchor() {
+        let (mut manager, clock, invoices) = manager();
+        let first =
+            manager.subscribe("dave", SubscriptionTier::Enterprise, &invoices).unwrap().unwrap();
+        manager.record_payment(&first.payment_hash).unwrap();
+
+        clock.set(T0 + 32 * DAY);
+        let renewal = issued(&manager.run_billing(&invoices).unwrap());
+        assert_eq!(manager.subscription("dave").unwrap().status, SubscriptionStatus::GracePeriod);
+        manager.record_payment(&renewal[0]).unwrap();
+        let subscription = manager.subscription("dave").unwrap();
+        assert_eq!(subscription.status, SubscriptionStatus::Active);
+        assert_eq!(
+            (subscription.period_start, subscription.period_end),
+            (T0 + 30 * DAY, T0 + 60 * DAY)
+        );
+    }
+
+    #[test]
+    fn test_unpaid_signup_lapses() {
+        let (mut manager, clock, invoices) = manager();
+        assert!(manager.subscribe("", SubscriptionTier::Pro, &invoices).is_err());
+        assert_eq!(
+            manager.subscribe("erin", SubscriptionTier::Free, &invoices).unwrap().map(|i| i.amount),
+            None
+        );
+        assert_eq!(manager.subscription("erin").unwrap().status, SubscriptionStatus::Active);
+
+        manager.subscribe("erin", SubscriptionTier::Pro, &invoices).unwrap().unwrap();
+        assert!(manager.subscribe("erin", SubscriptionTier::Enterprise, &invoices).is_err());
+        clock.advance(7 * DAY);
+        let events = manager.run_billing(&invoices).unwrap();
+        assert!(matches!(events.as_slice(), [BillingEvent::Downgraded { .. }]));
+        let subscription = manager.subscription("erin").unwrap();
+        assert_eq!(subscription.tier, SubscriptionTier::Free);
+        assert_eq!(subscription.invoices[0].status, SubscriptionInvoiceStatus::Void);
+    }
+
+    fn active(
+        manager: &mut SubscriptionManager, invoices: &InvoiceGenerator, tier: SubscriptionTier,
+    ) {
+        let first = manager.subscribe("frank", tier, invoices).unwrap().unwrap();
+        manager.record_payment(&first.payment_hash).unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_is_prorated_and_applies_when_paid() {
+        let (mut manager, clock, invoices) = manager();
+        active(&mut manager, &invoices, SubscriptionTier::Pro);
+        let immediate = TierChangeTiming::Immediate;
+        assert!(manager
+            .change_tier("nobody", SubscriptionTier::Enterprise, immediate, &invoices)
+            .is_err());
+        assert!(manager.change_tier("frank", SubscriptionTier::Pro, immediate, &invoices).is_err());
+
+        clock.advance(15 * DAY);
+        let TierChange::Invoiced { invoice, proration } = manager
+            .change_tier("frank", SubscriptionTier::Enterprise, immediate, &invoices)
+            .unwrap()
+        else {
+            panic!("upgrade should be invoiced")
+        };
+        assert_eq!((proration.credit_sats, proration.charge_sats), (5_000, 50_000));
+        assert_eq!(invoice.amount, Some(45_000));
+        assert!(manager
+            .change_tier("frank", SubscriptionTier::Enterprise, immediate, &invoices)
+            .is_err());
+        assert_eq!(manager.effective_tier("frank"), SubscriptionTier::Pro);
+
+        manager.record_payment(&invoice.payment_hash).unwrap();
+        let subscription = manager.subscription("frank").unwrap();
+        assert_eq!(subscription.tier, SubscriptionTier::Enterprise);
+        assert_eq!(subscription.period_end, T0 + 30 * DAY);
+
+        // The renewal bills the new tier in full.
+        clock.set(T0 + 27 * DAY);
+        let events = manager.run_billing(&invoices).unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [BillingEvent::InvoiceIssued { invoice, .. }] if invoice.amount == Some(100_000)
+        ));
+    }
+
+    #[test]
+    fn test_downgrade_takes_effect_at_period_end() {
+        let (mut manager, clock, invoices) = manager();
+        active(&mut manager, &invoices, SubscriptionTier::Enterprise);
+        clock.set(T0 + 28 * DAY);
+        let renewal = issued(&manager.run_billing(&invoices).unwrap());
+        assert_eq!(renewal.len(), 1);
+
+        let change = manager
+            .change_tier("frank", SubscriptionTier::Pro, TierChangeTiming::PeriodEnd, &invoices)
+            .unwrap();
+        assert!(
+            matches!(change, TierChange::Scheduled { effective_at } if effective_at == T0 + 30 * DAY)
+        );
+        assert_eq!(manager.effective_tier("frank"), SubscriptionTier::Enterprise);
+        // The Enterprise renewal is replaced by a Pro one.
+        assert!(manager.record_payment(&renewal[0]).is_err());
+        let events = manager.run_billing(&invoices).unwrap();
+        let [BillingEvent::InvoiceIssued { invoice, .. }] = events.as_slice() else {
+            panic!("expected a Pro renewal")
+        };
+        assert_eq!(invoice.amount, Some(10_000));
+        manager.record_payment(&invoice.payment_hash).unwrap();
+        let subscription = manager.subscription("frank").unwrap();
+        assert_eq!((subscription.tier, subscription.pending_tier), (SubscriptionTier::Pro, None));
+
+        // Dropping to free needs no invoice.
+        manager
+            .change_tier("frank", SubscriptionTier::Free, TierChangeTiming::PeriodEnd, &invoices)
+            .unwrap();
+        clock.set(T0 + 60 * DAY);
+        let events = manager.run_billing(&invoices).unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [BillingEvent::TierChanged { to: SubscriptionTier::Free, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_immediate_downgrade_credits_next_renewal() {
+        let (mut manager, clock, invoices) = manager();
+        active(&mut manager, &invoices, SubscriptionTier::Enterprise);
+        clock.advance(15 * DAY);
+        let change = manager
+            .change_tier("frank", SubscriptionTier::Pro, TierChangeTiming::Immediate, &invoices)
+            .unwrap();
+        assert!(matches!(change, TierChange::Applied { credit_sats: 45_000, .. }));
+        assert_eq!(manager.effective_tier("frank"), SubscriptionTier::Pro);
+
+        // 45_000 sats of credit cover four Pro renewals, then part of the fifth.
+        clock.set(T0 + 27 * DAY);
+        for period in 1..=4 {
+            let events = manager.run_billing(&invoices).unwrap();
+            assert!(matches!(events.as_slice(), [BillingEvent::Activated { .. }]));
+            clock.set(T0 + (27 + 30 * period) * DAY);
+        }
+        let events = manager.run_billing(&invoices).unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [BillingEvent::InvoiceIssued { invoice, .. }] if invoice.amount == Some(5_000)
+        ));
+        assert_eq!(manager.subscription("frank").unwrap().credit_sats, 0);
+    }
+
+    #[test]
+    fn test_upgrade_spends_account_credit() {
+        let (mut manager, clock, invoices) = manager();
+        active(&mut manager, &invoices, SubscriptionTier::Enterprise);
+        clock.advance(15 * DAY);
+        let immediate = TierChangeTiming::Immediate;
+        manager.change_tier("frank", SubscriptionTier::Pro, immediate, &invoices).unwrap();
+        clock.set(T0 + 27 * DAY);
+        let events = manager.run_billing(&invoices).unwrap();
+        assert!(matches!(events.as_slice(), [BillingEvent::Activated { .. }]));
+        assert_eq!(manager.subscription("frank").unwrap().credit_sats, 35_000);
+
+        // Halfway through the Pro period: 50_000 charged, 5_000 + 35_000 credited.
+        clock.set(T0 + 45 * DAY);
+        let TierChange::Invoiced { invoice, .. } = manager
+            .change_tier("frank", SubscriptionTier::Enterprise, immediate, &invoices)
+            .unwrap()
+        else {
+            panic!("upgrade should be invoiced")
+        };
+        assert_eq!(invoice.amount, Some(10_000));
+        let subscription = manager.subscription("frank").unwrap();
+        assert_eq!(subscription.credit_sats, 0);
+        assert_eq!(subscription.invoices.last().unwrap().credit_applied_sats, 35_000);
+
+        manager.record_payment(&invoice.payment_hash).unwrap();
+        let subscription = manager.subscription("frank").unwrap();
+        assert_eq!(
+            (subscription.tier, subscription.credit_sats),
+            (SubscriptionTier::Enterprise, 0)
+        );
+    }
+}
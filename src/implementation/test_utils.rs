@@ -0,0 +1,33 @@
+//! Helpers shared by the implementation tests.
+
+use crate::{
+    crypto::SecretKey,
+    types::{PaymentRoute, RouteHop},
+};
+
+/// Compressed-looking public key for test node `id`; not a valid curve point.
+pub(crate) fn node(id: u8) -> [u8; 33] {
+    let mut pubkey = [0u8; 33];
+    pubkey[0] = 0x02;
+    pubkey[32] = id;
+    pubkey
+}
+
+/// Secret key with every byte set to `byte`.
+pub(crate) fn secret(byte: u8) -> SecretKey {
+    SecretKey::from_slice(&[byte; 32]).unwrap()
+}
+
+/// Route over the BOLT4 test vector nodes, keys 0x41.. to 0x45.., paying
+/// `amount_msat` with `fee_msat` and a CLTV delta of 40 at every forwarding hop.
+pub(crate) fn bolt4_route(fee_msat: u64, amount_msat: u64) -> PaymentRoute {
+    let hops = (0..5u8)
+        .map(|i| RouteHop {
+            pubkey:            secret(0x41 + i).public_key().serialize(),
+            short_channel_id:  100 + u64::from(i),
+            fee_msat:          if i == 4 { 0 } else { fee_msat },
+            cltv_expiry_delta: if i == 4 { 0 } else { 40 },
+        })
+        .collect();
+    PaymentRoute { hops, total_fees_msat: 4 * fee_msat, total_cltv_delta: 160, amount_msat }
+}
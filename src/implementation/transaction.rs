@@ -0,0 +1,426 @@
+//! Bitcoin transactions.
+//!
+//! Just enough of the transaction format to spend escrow outputs: consensus
+//! serialization with and without witnesses, txids, and BIP 143 signature
+//! hashes for segwit v0 inputs.
+
+use core::fmt;
+
+use crate::{
+    crypto::sha256d,
+    encoding::hex,
+    errors::{ErrorDetail, PaymentError},
+};
+
+/// Sign all inputs and outputs.
+pub const SIGHASH_ALL: u32 = 0x01;
+/// Sign no outputs.
+pub const SIGHASH_NONE: u32 = 0x02;
+/// Sign only the output at the input's index.
+pub const SIGHASH_SINGLE: u32 = 0x03;
+/// Sign only the current input.
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+/// Sequence number that opts out of relative locktime and replacement signalling.
+pub const SEQUENCE_FINAL: u32 = 0xffff_ffff;
+
+/// Reasons a transaction fails to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    /// Input ended early.
+    Truncated,
+    /// Bytes left after the transaction.
+    TrailingBytes,
+    /// Segwit marker present but no witness data.
+    EmptyWitness,
+    /// Length prefix is not minimally encoded.
+    NonMinimalCompactSize,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "transaction is truncated"),
+            Self::TrailingBytes => write!(f, "unexpected bytes after transaction"),
+            Self::EmptyWitness => write!(f, "segwit transaction carries no witness"),
+            Self::NonMinimalCompactSize => write!(f, "length prefix is not minimally encoded"),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+impl From<TransactionError> for PaymentError {
+    fn from(err: TransactionError) -> Self {
+        PaymentError::Escrow(ErrorDetail::caused_by(format!("Transaction: {err}"), err))
+    }
+}
+
+/// Reference to a transaction output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    /// Transaction id in internal byte order (reversed from its hex display).
+    pub txid: [u8; 32],
+    /// Output index.
+    pub vout: u32,
+}
+
+impl OutPoint {
+    /// Build an outpoint from a txid as displayed by wallets and explorers.
+    pub fn from_display_txid(txid: &str, vout: u32) -> Option<Self> {
+        let mut bytes: [u8; 32] = hex::decode(txid)?.try_into().ok()?;
+        bytes.reverse();
+        Some(Self { txid: bytes, vout })
+    }
+}
+
+/// Transaction input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxIn {
+    /// Output being spent.
+    pub previous_output: OutPoint,
+    /// Legacy unlocking script, empty for segwit spends.
+    pub script_sig:      Vec<u8>,
+    /// Sequence number.
+    pub sequence:        u32,
+    /// Witness stack.
+    pub witness:         Vec<Vec<u8>>,
+}
+
+/// Transaction output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOut {
+    /// Amount in satoshis.
+    pub value:         u64,
+    /// Locking script.
+    pub script_pubkey: Vec<u8>,
+}
+
+/// Bitcoin transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// Transaction version.
+    pub version:   i32,
+    /// Inputs.
+    pub input:     Vec<TxIn>,
+    /// Outputs.
+    pub output:    Vec<TxOut>,
+    /// Lock time.
+    pub lock_time: u32,
+}
+
+impl Transaction {
+    /// Whether any input carries witness data.
+    #[must_use]
+    pub fn has_witness(&self) -> bool {
+        self.input.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Serialize, including witnesses when present (BIP 144).
+    #[must_use]
+    pub fn serialize(&self) -> Vec<u8> {
+        self.encode(self.has_witness())
+    }
+
+    /// Serialize without witnesses, as hashed for the txid.
+    #[must_use]
+    pub fn serialize_without_witness(&self) -> Vec<u8> {
+        self.encode(false)
+    }
+
+    /// Hex of the full serialization, ready to broadcast.
+    #[must_use]
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.serialize())
+    }
+
+    /// Transaction id in internal byte order.
+    #[must_use]
+    pub fn txid(&self) -> [u8; 32] {
+        sha256d(&self.serialize_without_witness())
+    }
+
+    /// Transaction id as displayed by wallets and explorers.
+    #[must_use]
+    pub fn display_txid(&self) -> String {
+        let mut txid = self.txid();
+        txid.reverse();
+        hex::encode(&txid)
+    }
+
+    /// Parse a serialized transaction, with or without witnesses.
+    pub fn parse(bytes: &[u8]) -> Result<Self, TransactionError> {
+        let mut reader = Cursor { bytes };
+        let tx = Self::read(&mut reader)?;
+        if !reader.bytes.is_empty() {
+            return Err(TransactionError::TrailingBytes);
+        }
+        Ok(tx)
+    }
+
+    /// BIP 143 signature hash for spending segwit v0 input `index`.
+    ///
+    /// `script_code` is the witness script for P2WSH inputs and `value` the
+    /// amount of the output being spent.
+    #[must_use]
+    pub fn segwit_v0_sighash(
+        &self, index: usize, script_code: &[u8], value: u64, sighash_type: u32,
+    ) -> [u8; 32] {
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let base_type = sighash_type & 0x1f;
+
+        let hash_prevouts = if anyone_can_pay {
+            [0u8; 32]
+        } else {
+            let mut data = Vec::with_capacity(36 * self.input.len());
+            for input in &self.input {
+                write_outpoint(&mut data, &input.previous_output);
+            }
+            sha256d(&data)
+        };
+        let hash_sequence =
+            if anyone_can_pay || base_type == SIGHASH_SINGLE || base_type == SIGHASH_NONE {
+                [0u8; 32]
+            } else {
+                let data: Vec<u8> =
+                    self.input.iter().flat_map(|input| input.sequence.to_le_bytes()).collect();
+                sha256d(&data)
+            };
+        let hash_outputs = if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+            let mut data = Vec::new();
+            for output in &self.output {
+                write_txout(&mut data, output);
+            }
+            sha256d(&data)
+        } else if base_type == SIGHASH_SINGLE && index < self.output.len() {
+            let mut data = Vec::new();
+            write_txout(&mut data, &self.output[index]);
+            sha256d(&data)
+        } else {
+            [0u8; 32]
+        };
+
+        let input = &self.input[index];
+        let mut preimage = Vec::with_capacity(156 + script_code.len());
+        preimage.extend_from_slice(&self.version.to_le_bytes());
+        preimage.extend_from_slice(&hash_prevouts);
+        preimage.extend_from_slice(&hash_sequence);
+        write_outpoint(&mut preimage, &input.previous_output);
+        write_var_bytes(&mut preimage, script_code);
+        preimage.extend_from_slice(&value.to_le_bytes());
+        preimage.extend_from_slice(&input.sequence.to_le_bytes());
+        preimage.extend_from_slice(&hash_outputs);
+        preimage.extend_from_slice(&self.lock_time.to_le_bytes());
+        preimage.extend_from_slice(&sighash_type.to_le_bytes());
+        sha256d(&preimage)
+    }
+
+    fn encode(&self, with_witness: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.version.to_le_bytes());
+        if with_witness {
+            out.extend_from_slice(&[0x00, 0x01]);
+        }
+        write_compact_size(&mut out, self.input.len() as u64);
+        for input in &self.input {
+            write_outpoint(&mut out, &input.previous_output);
+            write_var_bytes(&mut out, &input.script_sig);
+            out.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+        write_compact_size(&mut out, self.output.len() as u64);
+        for output in &self.output {
+            write_txout(&mut out, output);
+        }
+        if with_witness {
+            for input in &self.input {
+                write_compact_size(&mut out, input.witness.len() as u64);
+                for item in &input.witness {
+                    write_var_bytes(&mut out, item);
+                }
+            }
+        }
+        out.extend_from_slice(&self.lock_time.to_le_bytes());
+        out
+    }
+
+    fn read(reader: &mut Cursor<'_>) -> Result<Self, TransactionError> {
+        let version = i32::from_le_bytes(reader.array()?);
+
+        let mut input_count = reader.compact_size()?;
+        let segwit = input_count == 0;
+        if segwit {
+            if reader.array::<1>()? != [0x01] {
+                return Err(TransactionError::EmptyWitness);
+            }
+            input_count = reader.compact_size()?;
+        }
+
+        let mut input = Vec::with_capacity(reader.bounded(input_count, 41));
+        for _ in 0..input_count {
+            let previous_output =
+                OutPoint { txid: reader.array()?, vout: u32::from_le_bytes(reader.array()?) };
+            let script_sig = reader.var_bytes()?;
+            let sequence = u32::from_le_bytes(reader.array()?);
+            input.push(TxIn { previous_output, script_sig, sequence, witness: Vec::new() });
+        }
+
+        let output_count = reader.compact_size()?;
+        let mut output = Vec::with_capacity(reader.bounded(output_count, 9));
+        for _ in 0..output_count {
+            let value = u64::from_le_bytes(reader.array()?);
+            output.push(TxOut { value, script_pubkey: reader.var_bytes()? });
+        }
+
+        if segwit {
+            for txin in &mut input {
+                let items = reader.compact_size()?;
+                for _ in 0..items {
+                    txin.witness.push(reader.var_bytes()?);
+                }
+            }
+            if input.iter().all(|txin| txin.witness.is_empty()) {
+                return Err(TransactionError::EmptyWitness);
+            }
+        }
+
+        let lock_time = u32::from_le_bytes(reader.array()?);
+        Ok(Self { version, input, output, lock_time })
+    }
+}
+
+/// Append a Bitcoin compact size integer.
+pub(crate) fn write_compact_size(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => out.push(value as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(value as u16).to_le_bytes());
+        },
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(value as u32).to_le_bytes());
+        },
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&value.to_le_bytes());
+        },
+    }
+}
+
+/// Append bytes prefixed with their compact size length.
+pub(crate) fn write_var_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_compact_size(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_outpoint(out: &mut Vec<u8>, outpoint: &OutPoint) {
+    out.extend_from_slice(&outpoint.txid);
+    out.extend_from_slice(&outpoint.vout.to_le_bytes());
+}
+
+fn write_txout(out: &mut Vec<u8>, output: &TxOut) {
+    out.extend_from_slice(&output.value.to_le_bytes());
+    write_var_bytes(out, &output.script_pubkey);
+}
+
+/// Little-endian reader over consensus-encoded bytes.
+pub(crate) struct Cursor<'a> {
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], TransactionError> {
+        if self.bytes.len() < len {
+            return Err(TransactionError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], TransactionError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    pub(crate) fn compact_size(&mut self) -> Result<u64, TransactionError> {
+        let (value, minimum) = match self.array::<1>()?[0] {
+            0xfd => (u64::from(u16::from_le_bytes(self.array()?)), 0xfd),
+            0xfe => (u64::from(u32::from_le_bytes(self.array()?)), 0x1_0000),
+            0xff => (u64::from_le_bytes(self.array()?), 0x1_0000_0000),
+            byte => return Ok(u64::from(byte)),
+        };
+        if value < minimum {
+            return Err(TransactionError::NonMinimalCompactSize);
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn var_bytes(&mut self) -> Result<Vec<u8>, TransactionError> {
+        let len = self.compact_size()?;
+        let len = usize::try_from(len).map_err(|_| TransactionError::Truncated)?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    /// Capacity hint for `count` items of at least `min_size` bytes each.
+    fn bounded(&self, count: u64, min_size: usize) -> usize {
+        usize::try_from(count).unwrap_or(usize::MAX).min(self.bytes.len() / min_size)
+    }
+}
+
+#[cfg(all(test, feature = "full-tests"))]
+mod tests {
+    use super::*;
+    use crate::crypto::{PublicKey, Signature};
+
+    /// Unsigned transaction from the BIP 143 native P2WPKH example.
+    const BIP143_UNSIGNED: &str = "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000";
+
+    #[test]
+    fn test_roundtrips_serialization() {
+        let bytes = hex::decode(BIP143_UNSIGNED).unwrap();
+        let tx = Transaction::parse(&bytes).unwrap();
+        assert_eq!((tx.version, tx.input.len(), tx.output.len(), tx.lock_time), (1, 2, 2, 17));
+        assert_eq!(tx.input[0].sequence, 0xffff_ffee);
+        assert_eq!(tx.output[0].value, 112_340_000);
+        assert_eq!(tx.serialize(), bytes);
+
+        let mut signed = tx.clone();
+        signed.input[1].witness = vec![vec![0x30; 71], vec![0x02; 33]];
+        let encoded = signed.serialize();
+        assert_eq!(&encoded[4..6], &[0x00, 0x01]);
+        assert_eq!(Transaction::parse(&encoded).unwrap(), signed);
+        assert_eq!(signed.txid(), tx.txid());
+        assert_eq!(Transaction::parse(&bytes[..bytes.len() - 1]), Err(TransactionError::Truncated));
+    }
+
+    #[test]
+    fn test_bip143_sighash_and_der_signature() {
+        let tx = Transaction::parse(&hex::decode(BIP143_UNSIGNED).unwrap()).unwrap();
+        let script_code =
+            hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+        let sighash = tx.segwit_v0_sighash(1, &script_code, 600_000_000, SIGHASH_ALL);
+        assert_eq!(
+            hex::encode(&sighash),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+
+        // The example's signature, without its trailing sighash byte.
+        let der = hex::decode("304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee").unwrap();
+        let signature = Signature::from_der(&der).unwrap();
+        assert_eq!(signature.serialize_der(), der);
+        let pubkey = PublicKey::from_slice(
+            &hex::decode("025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357")
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(pubkey.verify_ecdsa(&sighash, &signature));
+
+        let mut padded = der.clone();
+        padded[1] += 1;
+        padded[3] += 1;
+        padded.insert(4, 0);
+        assert!(Signature::from_der(&padded).is_err());
+    }
+}
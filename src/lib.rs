@@ -1,26 +1,35 @@
 //! Essentia Payment Plugin library.
+//!
+//! Keys, preimages and payment secrets come from the operating system CSPRNG,
+//! which is supported on Unix and Windows. On other targets, operations that
+//! need fresh randomness and return [`PaymentResult`] fail with a
+//! [`PaymentError::Configuration`] error, and constructors that generate a node
+//! key, such as [`PaymentPlugin::new`], panic.
 
 #![allow(dead_code, missing_docs)]
 #![allow(clippy::pedantic)]
 
+pub mod crypto;
 pub mod errors;
 pub mod implementation;
 pub mod traits;
 pub mod types;
+mod encoding;
 mod flexforge;
 
-pub use errors::{PaymentError, PaymentResult};
+pub use errors::{ErrorDetail, PaymentError, PaymentErrorCode, PaymentResult};
 pub use flexforge::PaymentFlexForgeIntegration;
 pub use implementation::{
-    ChannelManager, InvoiceGenerator, LightningNodeImpl, PaymentConfig, PaymentPlugin,
-    PaymentRouter,
+    Bolt11Invoice, ChannelManager, InvoiceGenerator, LightningNodeImpl, PaymentConfig,
+    PaymentPlugin, PaymentRouter,
 };
 pub use traits::{ChannelProvider, InvoiceProvider, PaymentProcessor};
 pub use types::{
-    ChannelState, EscrowStatus, EscrowType, LightningInvoice, LightningNode, PaymentAmount,
-    PaymentChannel, PaymentHash, PaymentInvoice, PaymentRoute, PaymentStatus, RouteHop, Satoshis,
-    SubscriptionTier, TierFeatures,
+    ChannelState, EscrowStatus, EscrowType, Htlc, HtlcDirection, HtlcState, LightningInvoice,
+    LightningNode, Network, PaymentAmount, PaymentChannel, PaymentHash, PaymentInvoice,
+    PaymentPreimage, PaymentRoute, PaymentStatus, RouteHop, Satoshis, SubscriptionTier,
+    TierFeatures,
 };
 
 #[cfg(test)]
-mod tests;
+mod tests;
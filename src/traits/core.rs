@@ -1,8 +1,10 @@
 //! Core payment traits.
 
+use core::fmt;
+
 use crate::{
     errors::PaymentResult,
-    types::{PaymentChannel, PaymentInvoice, PaymentRoute, PaymentStatus},
+    types::{HtlcDirection, PaymentChannel, PaymentInvoice, PaymentRoute, PaymentStatus},
 };
 
 /// Trait for channel management providers.
@@ -16,6 +18,9 @@ pub trait ChannelProvider: Send + Sync {
     /// Get total local balance across all active channels.
     fn total_local_balance(&self) -> u64;
 
+    /// Get the amount locked in pending HTLCs in one direction, in millisatoshis.
+    fn total_in_flight_msat(&self, direction: HtlcDirection) -> u64;
+
     /// Open a new channel with a peer.
     fn open_channel(&mut self, peer_pubkey: [u8; 33], capacity: u64) -> PaymentResult<[u8; 32]>;
 
@@ -45,3 +50,9 @@ pub trait PaymentProcessor: Send + Sync {
     /// Get total spendable balance.
     fn spendable_balance(&self) -> u64;
 }
+
+/// Source of the current time, injectable so time-driven logic can be tested.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Current unix time in seconds.
+    fn now(&self) -> u64;
+}
@@ -4,4 +4,4 @@
 
 mod core;
 
-pub use core::{ChannelProvider, InvoiceProvider, PaymentProcessor};
+pub use core::{ChannelProvider, Clock, InvoiceProvider, PaymentProcessor};
@@ -1,20 +1,104 @@
 //! Core payment types.
 
+use crate::{
+    crypto::{random, sha256},
+    encoding::hex,
+};
+
 /// Payment channel representation.
 #[derive(Debug, Clone)]
 pub struct PaymentChannel {
     /// Channel identifier.
-    pub channel_id:     [u8; 32],
+    pub channel_id:          [u8; 32],
     /// Remote peer public key.
-    pub peer_pubkey:    [u8; 33],
+    pub peer_pubkey:         [u8; 33],
     /// Channel capacity in satoshis.
-    pub capacity:       u64,
-    /// Local balance in satoshis.
-    pub local_balance:  u64,
-    /// Remote balance in satoshis.
-    pub remote_balance: u64,
+    pub capacity:            u64,
+    /// Local balance in millisatoshis, excluding pending HTLCs.
+    pub local_balance_msat:  u64,
+    /// Remote balance in millisatoshis, excluding pending HTLCs.
+    pub remote_balance_msat: u64,
     /// Current channel state.
-    pub state:          ChannelState,
+    pub state:               ChannelState,
+    /// HTLCs added to the channel, including resolved ones.
+    pub htlcs:               Vec<Htlc>,
+}
+
+impl PaymentChannel {
+    /// Short channel id the channel is routed under.
+    ///
+    /// The funding transaction's position in the chain is not tracked, so this
+    /// is an alias taken from the channel id.
+    #[must_use]
+    pub fn short_channel_id(&self) -> u64 {
+        let mut alias = [0u8; 8];
+        alias.copy_from_slice(&self.channel_id[..8]);
+        u64::from_be_bytes(alias)
+    }
+
+    /// Check that local, remote and in-flight amounts add up to the capacity.
+    #[must_use]
+    pub fn balances_consistent(&self) -> bool {
+        let in_flight = self.in_flight_msat(HtlcDirection::Offered)
+            + self.in_flight_msat(HtlcDirection::Received);
+        self.local_balance_msat
+            .checked_add(self.remote_balance_msat)
+            .and_then(|sum| sum.checked_add(in_flight))
+            == self.capacity.checked_mul(1000)
+    }
+
+    /// HTLCs still locking funds.
+    pub fn pending_htlcs(&self) -> impl Iterator<Item = &Htlc> {
+        self.htlcs.iter().filter(|htlc| htlc.state == HtlcState::Pending)
+    }
+
+    /// Amount locked in pending HTLCs in one direction, in millisatoshis.
+    #[must_use]
+    pub fn in_flight_msat(&self, direction: HtlcDirection) -> u64 {
+        self.pending_htlcs()
+            .filter(|htlc| htlc.direction == direction)
+            .map(|htlc| htlc.amount_msat)
+            .sum()
+    }
+}
+
+/// Side of the channel that added an HTLC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HtlcDirection {
+    /// Added by us, spending from the local balance.
+    Offered,
+    /// Added by the peer, spending from the remote balance.
+    Received,
+}
+
+/// HTLC state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtlcState {
+    /// Funds are locked until the HTLC is fulfilled, failed or expires.
+    Pending,
+    /// Preimage revealed; funds moved to the receiving side.
+    Fulfilled,
+    /// Failed back; funds returned to the offering side.
+    Failed,
+    /// CLTV expiry reached while pending; funds returned to the offering side.
+    Expired,
+}
+
+/// Hash time-locked contract on a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Htlc {
+    /// Identifier, unique per channel and direction.
+    pub id:           u64,
+    /// Side that added the HTLC.
+    pub direction:    HtlcDirection,
+    /// Amount in millisatoshis.
+    pub amount_msat:  u64,
+    /// Hash the preimage must match.
+    pub payment_hash: PaymentHash,
+    /// Block height at which the HTLC times out.
+    pub cltv_expiry:  u32,
+    /// Current state.
+    pub state:        HtlcState,
 }
 
 /// Channel state.
@@ -32,6 +116,76 @@ pub enum ChannelState {
     Closed,
 }
 
+impl ChannelState {
+    /// Check whether a channel may move from this state to `next`.
+    ///
+    /// Channels open, run, then close cooperatively or by force:
+    /// `Opening → Active → Closing → Closed` and `Active → ForceClosed → Closed`.
+    #[must_use]
+    pub fn can_transition_to(&self, next: ChannelState) -> bool {
+        matches!(
+            (self, next),
+            (Self::Opening, Self::Active)
+                | (Self::Active, Self::Closing)
+                | (Self::Active, Self::ForceClosed)
+                | (Self::Closing, Self::Closed)
+                | (Self::ForceClosed, Self::Closed)
+        )
+    }
+}
+
+/// Bitcoin network a node or invoice operates on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    /// Bitcoin mainnet.
+    Bitcoin,
+    /// Bitcoin testnet.
+    Testnet,
+    /// Bitcoin signet.
+    Signet,
+    /// Local regression test network.
+    Regtest,
+}
+
+impl Network {
+    /// BOLT11 currency prefix for this network.
+    #[must_use]
+    pub fn bolt11_prefix(&self) -> &'static str {
+        match self {
+            Self::Bitcoin => "bc",
+            Self::Testnet => "tb",
+            Self::Signet => "tbs",
+            Self::Regtest => "bcrt",
+        }
+    }
+
+    /// Human-readable part of segwit addresses on this network.
+    #[must_use]
+    pub fn segwit_hrp(&self) -> &'static str {
+        match self {
+            Self::Bitcoin => "bc",
+            Self::Testnet | Self::Signet => "tb",
+            Self::Regtest => "bcrt",
+        }
+    }
+
+    /// Genesis block hash identifying this chain in Lightning messages.
+    ///
+    /// Bytes are in internal (little-endian) order, as they appear on the wire.
+    #[must_use]
+    pub fn chain_hash(&self) -> [u8; 32] {
+        let genesis = match self {
+            Self::Bitcoin => "6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000",
+            Self::Testnet => "43497fd7f826957108f4a30fd9cec3aeba79972084e90ead01ea330900000000",
+            Self::Signet => "f61eee3b63a380a477a063af32b2bbc97c9ff9f01f2c4225e973988108000000",
+            Self::Regtest => "06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f",
+        };
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hex::decode(genesis).unwrap_or_default());
+        hash
+    }
+}
+
 /// Payment invoice.
 #[derive(Debug, Clone)]
 pub struct PaymentInvoice {
@@ -50,20 +204,33 @@ pub struct PaymentInvoice {
 /// Payment route.
 #[derive(Debug, Clone)]
 pub struct PaymentRoute {
-    /// Route hops.
+    /// Route hops, from the first peer to the destination.
     pub hops:             Vec<RouteHop>,
     /// Total fees in millisatoshis.
     pub total_fees_msat:  u64,
     /// Total time lock delta.
     pub total_cltv_delta: u32,
+    /// Amount delivered to the destination in millisatoshis.
+    pub amount_msat:      u64,
+}
+
+impl PaymentRoute {
+    /// Amount the sender commits to the first hop, including all fees.
+    #[must_use]
+    pub fn total_amount_msat(&self) -> u64 {
+        self.amount_msat + self.total_fees_msat
+    }
 }
 
 /// Single hop in a payment route.
+///
+/// Fee and CLTV delta are those charged by `pubkey` for forwarding to the
+/// next hop; both are zero for the destination.
 #[derive(Debug, Clone)]
 pub struct RouteHop {
     /// Node public key.
     pub pubkey:            [u8; 33],
-    /// Short channel ID.
+    /// Short channel ID of the channel into this node.
     pub short_channel_id:  u64,
     /// Fee in millisatoshis.
     pub fee_msat:          u64,
@@ -155,6 +322,46 @@ impl PaymentHash {
     }
 }
 
+/// Payment preimage; its SHA-256 is the payment hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PaymentPreimage(pub [u8; 32]);
+
+impl PaymentPreimage {
+    /// Create new preimage from bytes
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Generate a preimage from the system CSPRNG, failing if it is unavailable
+    pub fn try_random() -> Result<Self, random::RandomnessError> {
+        Ok(Self(random::try_random_bytes()?))
+    }
+
+    /// Generate a preimage from the system CSPRNG
+    ///
+    /// # Panics
+    ///
+    /// If the operating system CSPRNG is unavailable.
+    pub fn random() -> Self {
+        Self(random::random_bytes())
+    }
+
+    /// Get as byte array
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Payment hash committing to this preimage
+    pub fn payment_hash(&self) -> PaymentHash {
+        PaymentHash(sha256(&self.0))
+    }
+
+    /// Check whether this preimage settles the given payment hash
+    pub fn matches(&self, payment_hash: &PaymentHash) -> bool {
+        self.payment_hash() == *payment_hash
+    }
+}
+
 /// Satoshis wrapper for type safety
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Satoshis(pub u64);
@@ -198,6 +405,20 @@ pub enum EscrowStatus {
     Disputed,
 }
 
+impl EscrowStatus {
+    /// Check whether an escrow may move from this status to `next`
+    ///
+    /// Funded escrows are released, refunded or disputed; disputes end in a
+    /// release or a refund. Released and refunded escrows are final.
+    pub fn can_transition_to(&self, next: EscrowStatus) -> bool {
+        matches!(
+            (self, next),
+            (Self::Funded, Self::Released | Self::Refunded | Self::Disputed)
+                | (Self::Disputed, Self::Released | Self::Refunded)
+        )
+    }
+}
+
 /// Subscription tier for VCS
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubscriptionTier {
@@ -8,7 +8,8 @@
 mod core;
 
 pub use core::{
-    ChannelState, EscrowStatus, EscrowType, LightningInvoice, LightningNode, PaymentAmount,
-    PaymentChannel, PaymentHash, PaymentInvoice, PaymentRoute, PaymentStatus, RouteHop, Satoshis,
-    SubscriptionTier, TierFeatures,
+    ChannelState, EscrowStatus, EscrowType, Htlc, HtlcDirection, HtlcState, LightningInvoice,
+    LightningNode, Network, PaymentAmount, PaymentChannel, PaymentHash, PaymentInvoice,
+    PaymentPreimage, PaymentRoute, PaymentStatus, RouteHop, Satoshis, SubscriptionTier,
+    TierFeatures,
 };